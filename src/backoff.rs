@@ -0,0 +1,83 @@
+//! Per-monitor backoff for the daemon: a monitor whose wallpaper cannot be
+//! picked, or whose renderer keeps exiting, is skipped at rotation for 5s,
+//! then 10s, 20s and so on up to 5 minutes, until a renderer shows a frame on
+//! it again.
+
+use std::time::{Duration, Instant};
+
+const INITIAL_DELAY: Duration = Duration::from_secs(5);
+const MAX_DELAY: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone)]
+pub struct BackoffState {
+    pub consecutive_failures: u32,
+    pub next_retry: Instant,
+}
+
+impl BackoffState {
+    pub fn new() -> Self {
+        Self {
+            consecutive_failures: 0,
+            next_retry: Instant::now(),
+        }
+    }
+
+    pub fn should_retry(&self) -> bool {
+        Instant::now() >= self.next_retry
+    }
+
+    pub fn record_failure(&mut self) {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        self.next_retry = Instant::now() + self.delay();
+    }
+
+    pub fn record_success(&mut self) {
+        self.consecutive_failures = 0;
+        self.next_retry = Instant::now();
+    }
+
+    /// How long the monitor is skipped after the latest failure.
+    pub fn delay(&self) -> Duration {
+        if self.consecutive_failures == 0 {
+            return Duration::ZERO;
+        }
+        let doublings = (self.consecutive_failures - 1).min(16);
+        (INITIAL_DELAY * 2u32.pow(doublings)).min(MAX_DELAY)
+    }
+}
+
+impl Default for BackoffState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BackoffState;
+    use std::time::Duration;
+
+    #[test]
+    fn delay_doubles_from_five_seconds_up_to_five_minutes_and_resets() {
+        let mut backoff = BackoffState::new();
+        assert!(backoff.should_retry());
+        assert_eq!(backoff.delay(), Duration::ZERO);
+
+        let mut delays = Vec::new();
+        for _ in 0..8 {
+            backoff.record_failure();
+            delays.push(backoff.delay().as_secs());
+        }
+        assert_eq!(delays, [5, 10, 20, 40, 80, 160, 300, 300]);
+        assert!(!backoff.should_retry());
+
+        for _ in 0..100 {
+            backoff.record_failure();
+        }
+        assert_eq!(backoff.delay(), Duration::from_secs(300));
+
+        backoff.record_success();
+        assert_eq!(backoff.consecutive_failures, 0);
+        assert!(backoff.should_retry());
+    }
+}
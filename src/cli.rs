@@ -1,6 +1,12 @@
-use clap::{Parser, Subcommand};
-use crate::config::FitMode;
-use std::path::PathBuf;
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use crate::config::{CompositorType, Config, FitMode, ScaleFilter, SurfaceAnchor, Transition};
+use crate::effects::Effect;
+use crate::migrate::WallpaperTool;
+use crate::preview::PreviewMode;
+use crate::transition::EasingCurve;
+use crate::util::parse_size;
+use std::{path::PathBuf, time::Duration};
 
 #[derive(Parser, Debug)]
 #[command(name = "papdieo", version, about = "A Hyprland-compatible wallpaper CLI")]
@@ -8,6 +14,12 @@ pub struct PapdieoArgs {
     #[arg(short, long, help = "Path to config TOML file")]
     pub config: Option<PathBuf>,
 
+    #[arg(short, long, global = true, action = ArgAction::Count, help = "Print debug output (-vv for more)")]
+    pub verbose: u8,
+
+    #[arg(short, long, global = true, conflicts_with = "verbose", help = "Suppress informational output")]
+    pub quiet: bool,
+
     #[command(subcommand)]
     pub command: Option<Command>,
 }
@@ -18,50 +30,182 @@ pub enum Command {
     Daemon {
         #[arg(long, help = "Run daemon in foreground (no detach)")]
         foreground: bool,
+        #[arg(long, help = "Set wallpapers on every monitor once, leave the renderers running and exit")]
+        once: bool,
+        #[command(flatten)]
+        options: DaemonArgs,
     },
 
     #[command(about = "Restart wallpaper daemon service")]
     Restart,
 
+    #[command(about = "Show the wallpaper currently shown on each monitor")]
+    Status {
+        #[arg(long, help = "Print daemon state as JSON")]
+        json: bool,
+    },
+
+    #[command(about = "List connected monitors with resolution, refresh rate and position")]
+    Monitors {
+        #[arg(long, help = "Print the raw monitor data as JSON")]
+        json: bool,
+    },
+
     #[command(about = "Set a specific wallpaper")]
     Set {
-        path: PathBuf,
+        #[arg(required_unless_present_any = ["mirror", "list_gpus", "script"], help = "File path, or an http(s) video, HLS (.m3u8), DASH (.mpd) or rtsp:// URL")]
+        path: Option<PathBuf>,
+        #[arg(long, value_name = "MONITOR", conflicts_with = "path", help = "Show the wallpaper currently on another monitor")]
+        mirror: Option<String>,
+        #[arg(long, value_name = "EXECUTABLE", conflicts_with_all = ["path", "mirror"], help = "Run this and use the wallpaper path it prints")]
+        script: Option<PathBuf>,
         #[arg(long, help = "Target monitor name (example: DP-4)")]
         monitor: Option<String>,
+        #[arg(long, value_name = "M1,M2", value_delimiter = ',', conflicts_with_all = ["monitor", "mirror"], help = "Spread one image across these monitors as they are laid out (Hyprland only)")]
+        span_monitors: Vec<String>,
+        #[arg(long, value_name = "PATTERN", value_parser = parse_monitor_regex, conflicts_with_all = ["monitor", "span_monitors"], help = "Show the wallpaper on every monitor whose name matches this regex")]
+        monitor_regex: Option<String>,
+        #[arg(long, alias = "monitor-wildcard", value_name = "PATTERN", value_parser = parse_monitor_glob, conflicts_with_all = ["monitor", "span_monitors", "monitor_regex"], help = "Show the wallpaper on every monitor whose name matches this shell glob, e.g. 'DP-*'")]
+        monitor_glob: Option<String>,
+        #[arg(long, requires = "monitor_glob", help = "Match --monitor-glob regardless of case")]
+        glob_ignore_case: bool,
         #[arg(long, help = "Video FPS target (default: 60)")]
         fps: Option<u32>,
-        #[arg(long, value_enum, help = "Render mode: stretch|fill|cover|fit|contain|center|scale-down")]
+        #[arg(long, value_enum, help = "Render mode: stretch|fill|cover|fit|contain|center|scale-down|tile")]
         fit: Option<FitMode>,
+        #[arg(long, value_enum, help = "Scaling filter: nearest|triangle|catmull-rom|gaussian|lanczos3")]
+        filter: Option<ScaleFilter>,
+        #[arg(long = "image-offset-x", value_name = "PIXELS", allow_negative_numbers = true, help = "Shift the image horizontally (positive = right)")]
+        offset_x: Option<i32>,
+        #[arg(long = "image-offset-y", value_name = "PIXELS", allow_negative_numbers = true, help = "Shift the image vertically (positive = down)")]
+        offset_y: Option<i32>,
+        #[arg(long, value_name = "X,Y,W,H", value_parser = parse_focus_area, help = "Image region, in source pixels, that cover/fill crops keep visible (overrides default_focus_area)")]
+        focus_area: Option<[f32; 4]>,
+        #[arg(long, requires = "focus_area", help = "Read --focus-area as 0.0-1.0 fractions of the image")]
+        focus_area_relative: bool,
+        #[arg(long, value_name = "WxH", value_parser = parse_tile_size, help = "Scale the image to this tile size for --fit tile; 0 keeps that side in proportion, 0x0 is native (overrides tile_size)")]
+        tile_size: Option<(u32, u32)>,
+        #[arg(long, value_name = "X,Y", value_parser = parse_tile_offset, allow_negative_numbers = true, conflicts_with_all = ["offset_x", "offset_y"], help = "Shift the tile grid's origin (same as --image-offset-x/-y)")]
+        tile_offset: Option<(i32, i32)>,
+        #[arg(long = "layer-namespace", visible_alias = "namespace", value_name = "STRING", help = "wlr-layer-shell namespace for compositor layer rules (default: papdieo)")]
+        layer_namespace: Option<String>,
+        #[arg(long, value_name = "INT", allow_negative_numbers = true, help = "Layer exclusive zone; -1 (default) draws edge-to-edge. Compositors ignore positive values for every --anchor")]
+        exclusive_zone: Option<i32>,
+        #[arg(long, value_enum, help = "Surface placement: all-edges (default)|topleft|topright|bottomleft|bottomright|center")]
+        anchor: Option<SurfaceAnchor>,
+        #[arg(long = "start-time", value_name = "MS", help = "Start a video this many milliseconds in (overrides video_start_time_ms)")]
+        start_time: Option<u64>,
+        #[arg(long, value_name = "MS", help = "Wait this long after the surface is mapped before drawing (overrides startup_delay_ms)")]
+        delay: Option<u64>,
+        #[arg(long = "loop", value_name = "N", default_value_t = 0, help = "Play a video N times, then hold the last frame or show loop_fallback (0 = loop forever)")]
+        loop_count: u32,
+        #[arg(long, value_name = "INDEX", help = "GPU that decodes video, numbered as in --list-gpus (overrides gpu_index)")]
+        gpu: Option<u32>,
+        #[arg(long, help = "List the GPUs that --gpu can select and exit")]
+        list_gpus: bool,
+        #[arg(long, help = "Play no audio from a video wallpaper (overrides mute_audio = false)")]
+        mute: bool,
+        #[arg(long, value_name = "0.0-1.0", value_parser = parse_brightness, help = "Dim the wallpaper by scaling its colours (overrides brightness)")]
+        brightness: Option<f32>,
+        #[arg(long, value_name = "0.0-2.0", value_parser = parse_contrast, help = "Contrast around mid-grey, 1.0 unchanged (overrides contrast)")]
+        contrast: Option<f32>,
+        #[arg(long, value_enum, value_name = "EFFECT", help = "Post-processing effect, repeatable and applied in order: grayscale|sepia|invert|blur (overrides effects)")]
+        effect: Vec<Effect>,
+        #[arg(long, value_enum, value_name = "CURVE", help = "Fade opacity ramp: linear|ease-in|ease-out|ease-in-out (overrides transition_curve)")]
+        opacity_curve: Option<EasingCurve>,
+        #[arg(long, help = "Redraw for the new orientation when the monitor is rotated (overrides auto_rotate = false)")]
+        auto_rotate: bool,
+        #[arg(long, value_name = "IMAGE_PATH", help = "Overlay this logo on the wallpaper, placed as in the watermark config (overrides watermark.path)")]
+        watermark: Option<PathBuf>,
+        #[arg(long, conflicts_with_all = ["monitor", "span_monitors", "monitor_regex", "monitor_glob"], help = "Draw a still image on every monitor from a single renderer")]
+        all_outputs: bool,
+        #[arg(long, value_name = "STRING", help = "Share the scaled image with other renderers given the same key, per output size")]
+        cache_key: Option<String>,
+        #[arg(long, help = "Download an http(s) video to a temp file first so it loops locally")]
+        cache_stream: bool,
         #[arg(long, help = "Run wallpaper renderer in background")]
         detach: bool,
+        #[arg(long, help = "Let the daemon take this monitor back at its next rotation")]
+        unlock: bool,
+    },
+
+    #[command(
+        visible_alias = "restore-session",
+        about = "Start one detached renderer per monitor from a JSON manifest"
+    )]
+    BatchSet {
+        #[arg(help = "JSON array of {monitor, path, fit, fps} entries, `papdieo export-session` output or `papdieo status --json` output")]
+        manifest: PathBuf,
+    },
+
+    #[command(about = "Save the daemon's wallpapers as a manifest for restore-session")]
+    ExportSession {
+        #[arg(long, short, value_name = "FILE", help = "Write the manifest here instead of stdout")]
+        output: Option<PathBuf>,
     },
 
     #[command(about = "Pick a random wallpaper from configured directory")]
     Random {
         #[arg(long, help = "Media directory override")]
         dir: Option<PathBuf>,
+        #[arg(long, help = "Seed for a reproducible random pick")]
+        seed: Option<u64>,
+        #[arg(long, value_name = "EXECUTABLE", conflicts_with_all = ["seed", "tag", "aspect_filter"], help = "Run this and use the wallpaper path it prints")]
+        script: Option<PathBuf>,
+        #[arg(long, help = "Only pick wallpapers with this tag in their sidecar metadata")]
+        tag: Option<String>,
+        #[arg(long, value_name = "RATIO", value_parser = parse_aspect_ratio, help = "Only pick wallpapers with this aspect ratio, e.g. 16:9 or 1.777")]
+        aspect_filter: Option<f64>,
+        #[arg(long, value_name = "PERCENT", default_value_t = 10.0, help = "How far a wallpaper may be off --aspect-filter, in percent")]
+        aspect_tolerance: f64,
+        #[arg(long, conflicts_with = "no_images", help = "Skip video files (overrides allow_video)")]
+        no_video: bool,
+        #[arg(long, help = "Skip image files (overrides allow_images)")]
+        no_images: bool,
         #[arg(long, help = "Target monitor name (example: DP-4)")]
         monitor: Option<String>,
         #[arg(long, help = "Video FPS target (default: 60)")]
         fps: Option<u32>,
-        #[arg(long, value_enum, help = "Render mode: stretch|fill|cover|fit|contain|center|scale-down")]
+        #[arg(long, value_enum, help = "Render mode: stretch|fill|cover|fit|contain|center|scale-down|tile")]
         fit: Option<FitMode>,
+        #[arg(long, value_name = "0.0-1.0", value_parser = parse_brightness, help = "Dim the wallpaper by scaling its colours (overrides brightness)")]
+        brightness: Option<f32>,
+        #[arg(long, value_name = "0.0-2.0", value_parser = parse_contrast, help = "Contrast around mid-grey, 1.0 unchanged (overrides contrast)")]
+        contrast: Option<f32>,
         #[arg(long, help = "Run wallpaper renderer in background")]
         detach: bool,
+        #[arg(long, help = "Let the daemon take this monitor back at its next rotation")]
+        unlock: bool,
     },
 
     #[command(about = "Pick next wallpaper in sorted order")]
     Next {
         #[arg(long, help = "Media directory override")]
         dir: Option<PathBuf>,
+        #[arg(long, help = "Only pick wallpapers with this tag in their sidecar metadata")]
+        tag: Option<String>,
+        #[arg(long, value_name = "RATIO", value_parser = parse_aspect_ratio, help = "Only pick wallpapers with this aspect ratio, e.g. 16:9 or 1.777")]
+        aspect_filter: Option<f64>,
+        #[arg(long, value_name = "PERCENT", default_value_t = 10.0, help = "How far a wallpaper may be off --aspect-filter, in percent")]
+        aspect_tolerance: f64,
+        #[arg(long, conflicts_with = "no_images", help = "Skip video files (overrides allow_video)")]
+        no_video: bool,
+        #[arg(long, help = "Skip image files (overrides allow_images)")]
+        no_images: bool,
         #[arg(long, help = "Target monitor name (example: DP-4)")]
         monitor: Option<String>,
         #[arg(long, help = "Video FPS target (default: 60)")]
         fps: Option<u32>,
-        #[arg(long, value_enum, help = "Render mode: stretch|fill|cover|fit|contain|center|scale-down")]
+        #[arg(long, value_enum, help = "Render mode: stretch|fill|cover|fit|contain|center|scale-down|tile")]
         fit: Option<FitMode>,
+        #[arg(long, value_name = "0.0-1.0", value_parser = parse_brightness, help = "Dim the wallpaper by scaling its colours (overrides brightness)")]
+        brightness: Option<f32>,
+        #[arg(long, value_name = "0.0-2.0", value_parser = parse_contrast, help = "Contrast around mid-grey, 1.0 unchanged (overrides contrast)")]
+        contrast: Option<f32>,
         #[arg(long, help = "Run wallpaper renderer in background")]
         detach: bool,
+        #[arg(long, help = "Let the daemon take this monitor back at its next rotation")]
+        unlock: bool,
     },
 
     #[command(about = "Continuously rotate random wallpapers/videos from a folder")]
@@ -70,16 +214,171 @@ pub enum Command {
         dir: Option<PathBuf>,
         #[arg(long, help = "Target monitor name (example: DP-4)")]
         monitor: Option<String>,
-        #[arg(long, help = "Rotation interval in seconds")]
-        interval: Option<u64>,
+        #[arg(long, value_name = "SECONDS", value_parser = parse_seconds, help = "Rotation interval in seconds, fractions allowed (e.g. 0.5)")]
+        interval: Option<Duration>,
+        #[arg(long, help = "Rotate in sorted order instead of randomly")]
+        sequential: bool,
+        #[arg(long, help = "Never pick a wallpaper another monitor is showing")]
+        no_overlap: bool,
+        #[arg(long, value_name = "EXECUTABLE", conflicts_with_all = ["sequential", "no_overlap"], help = "Run this at each rotation and use the wallpaper path it prints (overrides picker_script)")]
+        script: Option<PathBuf>,
+        #[arg(long, conflicts_with = "no_images", help = "Skip video files (overrides allow_video)")]
+        no_video: bool,
+        #[arg(long, help = "Skip image files (overrides allow_images)")]
+        no_images: bool,
         #[arg(long, help = "Video FPS target (default: 60)")]
         fps: Option<u32>,
-        #[arg(long, value_enum, help = "Render mode: stretch|fill|cover|fit|contain|center|scale-down")]
+        #[arg(long, value_enum, help = "Render mode: stretch|fill|cover|fit|contain|center|scale-down|tile")]
         fit: Option<FitMode>,
     },
 
+    #[command(about = "Pause video wallpapers without stopping their renderers")]
+    Pause {
+        #[arg(long, help = "Only pause this monitor (default: all monitors)")]
+        monitor: Option<String>,
+    },
+
+    #[command(about = "Resume paused video wallpapers")]
+    Resume {
+        #[arg(long, help = "Only resume this monitor (default: all monitors)")]
+        monitor: Option<String>,
+    },
+
+    #[command(about = "Keep the daemon from rotating a monitor's wallpaper")]
+    Pin {
+        #[arg(help = "Monitor name, e.g. DP-1")]
+        monitor: String,
+    },
+
+    #[command(about = "Let the daemon rotate a pinned monitor again")]
+    Unpin {
+        #[arg(help = "Monitor name, e.g. DP-1")]
+        monitor: String,
+    },
+
+    #[command(about = "Keep the daemon off a monitor whose wallpaper was set by hand")]
+    Lock {
+        #[arg(help = "Monitor name, e.g. DP-1")]
+        monitor: String,
+    },
+
+    #[command(about = "Let the daemon rotate a locked monitor again")]
+    Unlock {
+        #[arg(help = "Monitor name, e.g. DP-1")]
+        monitor: String,
+    },
+
+    #[command(about = "Inspect the cron schedule rules from the config")]
+    Scheduler {
+        #[command(subcommand)]
+        command: SchedulerCommand,
+    },
+
     #[command(about = "List discovered wallpapers")]
-    List,
+    List {
+        #[arg(long, value_enum, default_value = "none", help = "Thumbnail under each file: none|sixel|block")]
+        preview: PreviewMode,
+        #[arg(long, help = "Only list wallpapers with this tag in their sidecar metadata")]
+        tag: Option<String>,
+        #[arg(long, value_name = "N", help = "Descend N levels of subdirectories (0: only the wallpaper directory)")]
+        max_depth: Option<usize>,
+        #[arg(long, value_name = "N", help = "Stop after listing N wallpapers")]
+        max_results: Option<usize>,
+        #[arg(long, value_name = "BYTES", value_parser = parse_size, help = "Only list files of at least this size, e.g. 500KB")]
+        min_size: Option<u64>,
+        #[arg(long, value_name = "BYTES", value_parser = parse_size, help = "Only list files of at most this size, e.g. 20MB")]
+        max_size: Option<u64>,
+        #[arg(long, conflicts_with = "no_images", help = "Skip video files (overrides allow_video)")]
+        no_video: bool,
+        #[arg(long, help = "Skip image files (overrides allow_images)")]
+        no_images: bool,
+        #[arg(long, help = "Group the wallpapers under a header per directory (with --max-depth)")]
+        grouped: bool,
+        #[arg(long, help = "Omit near-duplicate images, keeping the largest file of each group")]
+        deduplicate: bool,
+        #[arg(long, value_name = "N", requires = "deduplicate", help = "Perceptual hashes at most N bits apart count as duplicates (default 5)")]
+        deduplicate_threshold: Option<u32>,
+        #[arg(long, help = "Print the list as JSON")]
+        json: bool,
+    },
+
+    #[command(about = "Print groups of near-duplicate wallpapers for review")]
+    Dedup {
+        #[arg(long, value_name = "N", help = "Perceptual hashes at most N bits apart count as duplicates (default 5)")]
+        threshold: Option<u32>,
+        #[arg(long, value_name = "N", help = "Descend N levels of subdirectories (0: only the wallpaper directory)")]
+        max_depth: Option<usize>,
+        #[arg(long, help = "Print the groups as JSON")]
+        json: bool,
+    },
+
+    #[command(about = "Download a wallpaper into the wallpaper directory")]
+    Pull {
+        #[arg(help = "http(s) URL of an image or video")]
+        url: String,
+        #[arg(long, help = "Save into this directory instead of wallpaper_dir")]
+        dir: Option<PathBuf>,
+        #[arg(long, help = "Show the downloaded wallpaper right away (detached)")]
+        set: bool,
+        #[arg(long, requires = "set", help = "Target monitor for --set (example: DP-4)")]
+        monitor: Option<String>,
+    },
+
+    #[command(about = "Edit the tags in a wallpaper's sidecar metadata")]
+    Tag {
+        #[command(subcommand)]
+        action: TagAction,
+    },
+
+    #[command(about = "List, create or switch to config profiles (<config dir>/profiles/<NAME>.toml)")]
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+
+    #[command(about = "Read or change a single config key")]
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    #[command(about = "Create the config file, data directory and wallpaper directory (first-run setup)")]
+    Init {
+        #[arg(long, help = "Replace an existing config file with the default template")]
+        force: bool,
+    },
+
+    #[command(about = "Print a fully commented default config")]
+    ExportConfig {
+        #[arg(long, short, help = "Write to this file instead of stdout")]
+        output: Option<PathBuf>,
+        #[arg(long, help = "Overwrite the output file if it already exists")]
+        force: bool,
+    },
+
+    #[command(about = "Print a shell completion script (set only suggests supported media in fish and zsh)")]
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+
+    #[command(about = "Generate a papdieo config from a swww, swaybg or hyprpaper setup")]
+    Migrate {
+        #[arg(value_enum, help = "Tool to migrate from")]
+        from: WallpaperTool,
+        #[arg(help = "hyprpaper.conf, the file that starts swaybg, or the shell history with swww commands (default: their usual locations)")]
+        config_path: Option<PathBuf>,
+        #[arg(long, help = "Write to --config or the default config path instead of stdout")]
+        write: bool,
+        #[arg(long, requires = "write", help = "Overwrite an existing config file")]
+        force: bool,
+    },
+
+    #[command(about = "Check a config file and report problems with line numbers")]
+    ValidateConfig {
+        #[arg(help = "Config file to check (default: --config or the default config path)")]
+        path: Option<PathBuf>,
+    },
 
     #[command(hide = true)]
     __RunInternal {
@@ -93,16 +392,378 @@ pub enum Command {
         fps: Option<u32>,
         #[arg(long, value_enum)]
         fit: Option<FitMode>,
+        #[arg(long, value_enum)]
+        transition: Option<Transition>,
+        #[arg(long, value_enum)]
+        filter: Option<ScaleFilter>,
+        #[arg(long = "image-offset-x", allow_negative_numbers = true)]
+        offset_x: Option<i32>,
+        #[arg(long = "image-offset-y", allow_negative_numbers = true)]
+        offset_y: Option<i32>,
+        // Always fractions of the image here.
+        #[arg(long, value_parser = parse_focus_area)]
+        focus_area: Option<[f32; 4]>,
+        #[arg(long, value_parser = parse_tile_size)]
+        tile_size: Option<(u32, u32)>,
+        #[arg(long, value_enum)]
+        compositor: Option<CompositorType>,
+        #[arg(long = "layer-namespace")]
+        layer_namespace: Option<String>,
+        #[arg(long, allow_negative_numbers = true)]
+        exclusive_zone: Option<i32>,
+        #[arg(long, value_enum)]
+        anchor: Option<SurfaceAnchor>,
+        #[arg(long = "start-time")]
+        start_time: Option<u64>,
+        #[arg(long)]
+        delay: Option<u64>,
+        #[arg(long = "loop")]
+        loop_count: Option<u32>,
+        #[arg(long)]
+        gpu: Option<u32>,
+        #[arg(long)]
+        mute: bool,
+        #[arg(long)]
+        brightness: Option<f32>,
+        #[arg(long)]
+        contrast: Option<f32>,
+        #[arg(long, value_enum)]
+        effect: Vec<Effect>,
+        #[arg(long)]
+        blur_sigma: Option<f32>,
+        #[arg(long, value_enum)]
+        opacity_curve: Option<EasingCurve>,
+        #[arg(long)]
+        auto_rotate: bool,
+        #[arg(long)]
+        watermark: Option<PathBuf>,
+        #[arg(long, conflicts_with_all = ["monitor", "assignments"])]
+        all_outputs: bool,
+        #[arg(long)]
+        cache_key: Option<String>,
     },
 
     #[command(hide = true)]
-    __DaemonInternal,
+    __DaemonInternal {
+        #[command(flatten)]
+        options: DaemonArgs,
+    },
+
+    #[command(hide = true)]
+    __DaemonWatchdog {
+        #[command(flatten)]
+        options: DaemonArgs,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SchedulerCommand {
+    #[command(about = "Show the active rule and upcoming scheduled changes")]
+    List {
+        #[arg(long, default_value_t = 10, help = "Number of upcoming changes to show")]
+        count: usize,
+    },
+}
+
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    let seconds: f64 = value
+        .parse()
+        .map_err(|_| format!("'{}' is not a number of seconds", value))?;
+    if !seconds.is_finite() || seconds <= 0.0 {
+        return Err(format!("interval must be positive, got {}", value));
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+/// `16:9` or a plain ratio such as `1.777`.
+fn parse_aspect_ratio(value: &str) -> Result<f64, String> {
+    let invalid = || format!("'{}' is not an aspect ratio like 16:9 or 1.777", value);
+    let ratio = match value.split_once(':') {
+        Some((width, height)) => {
+            let width: f64 = width.trim().parse().map_err(|_| invalid())?;
+            let height: f64 = height.trim().parse().map_err(|_| invalid())?;
+            width / height
+        }
+        None => value.trim().parse().map_err(|_| invalid())?,
+    };
+    if !ratio.is_finite() || ratio <= 0.0 {
+        return Err(invalid());
+    }
+    Ok(ratio)
+}
+
+fn parse_monitor_regex(value: &str) -> Result<String, String> {
+    regex::Regex::new(value)
+        .map(|_| value.to_string())
+        .map_err(|error| error.to_string())
+}
+
+fn parse_monitor_glob(value: &str) -> Result<String, String> {
+    glob::Pattern::new(value)
+        .map(|_| value.to_string())
+        .map_err(|error| error.to_string())
+}
+
+fn parse_brightness(value: &str) -> Result<f32, String> {
+    parse_factor(value, 1.0)
+}
+
+fn parse_contrast(value: &str) -> Result<f32, String> {
+    parse_factor(value, 2.0)
+}
+
+fn parse_factor(value: &str, max: f32) -> Result<f32, String> {
+    let factor: f32 = value
+        .trim()
+        .parse()
+        .map_err(|_| format!("'{}' is not a number", value))?;
+    if !(0.0..=max).contains(&factor) {
+        return Err(format!("{} is not between 0.0 and {:.1}", factor, max));
+    }
+    Ok(factor)
+}
+
+/// `WxH` such as `256x256`.
+fn parse_tile_size(value: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("'{}' is not a size like 256x256", value);
+    let (width, height) = value.trim().split_once(['x', 'X']).ok_or_else(invalid)?;
+    let width = width.trim().parse().map_err(|_| invalid())?;
+    let height = height.trim().parse().map_err(|_| invalid())?;
+    Ok((width, height))
+}
+
+/// `X,Y` such as `-16,32`.
+fn parse_tile_offset(value: &str) -> Result<(i32, i32), String> {
+    let invalid = || format!("'{}' is not an offset like -16,32", value);
+    let (x, y) = value.split_once(',').ok_or_else(invalid)?;
+    let x = x.trim().parse().map_err(|_| invalid())?;
+    let y = y.trim().parse().map_err(|_| invalid())?;
+    Ok((x, y))
+}
+
+fn parse_focus_area(value: &str) -> Result<[f32; 4], String> {
+    let invalid = || format!("'{}' is not X,Y,W,H", value);
+    let numbers = value
+        .split(',')
+        .map(|part| part.trim().parse::<f32>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let area: [f32; 4] = numbers.try_into().map_err(|_| invalid())?;
+    if area.iter().any(|v| !v.is_finite() || *v < 0.0) || area[2] <= 0.0 || area[3] <= 0.0 {
+        return Err(format!("'{}' needs a non-negative position and a non-empty size", value));
+    }
+    Ok(area)
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ProfileAction {
+    #[command(about = "List the available profiles")]
+    List,
+    #[command(about = "Create a profile from the current config (or the default template)")]
+    Create { name: String },
+    #[command(about = "Switch the running daemon to a profile")]
+    Activate { name: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TagAction {
+    #[command(about = "Add a tag, creating <PATH>.meta if there is no sidecar yet")]
+    Add { path: PathBuf, tag: String },
+    #[command(about = "Remove a tag")]
+    Remove { path: PathBuf, tag: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    #[command(about = "Print the effective value of KEY, e.g. video_fps or monitor_fit_modes.DP-1")]
+    Get { key: String },
+    #[command(about = "Write KEY = VALUE to the config file, keeping its comments")]
+    Set { key: String, value: String },
+}
+
+/// Daemon flags shared by `daemon` and the hidden `daemon-watchdog` and
+/// `daemon-internal` processes it spawns.
+#[derive(Args, Debug, Clone, Default)]
+pub struct DaemonArgs {
+    #[arg(long, value_name = "SECONDS", help = "Wait before the first wallpaper is set")]
+    pub start_delay: Option<u64>,
+    #[arg(long, help = "Wait (up to 30s) until the compositor reports a monitor")]
+    pub wait_for_compositor: bool,
+    #[arg(long, help = "Reload the config through inotify as soon as it is saved")]
+    pub config_watch: bool,
+    #[arg(long, value_name = "PATH", help = "Daemon pid file (overrides pid_file)")]
+    pub pidfile: Option<PathBuf>,
+    #[arg(long, value_name = "PATH", help = "Daemon lock file (overrides lock_file)")]
+    pub lockfile: Option<PathBuf>,
+    #[arg(long, help = "Print PID=<n> to stdout instead of writing the pid file")]
+    pub pid_stdout: bool,
+    #[arg(long, value_name = "FD", help = "Write a newline to this file descriptor once the first wallpaper is up")]
+    pub ready_fd: Option<i32>,
+    #[arg(long, help = "Skip the running-daemon check and remove the pid file first")]
+    pub force: bool,
+    #[arg(long, value_name = "NAME", help = "Use <config dir>/profiles/<NAME>.toml instead of the config file")]
+    pub profile: Option<String>,
+    #[arg(long, help = "Take over the renderers `set --detach` and friends left running instead of replacing them")]
+    pub watch_pidfile: bool,
+    #[command(flatten)]
+    pub overrides: CommandLineOverrides,
+}
+
+/// Config values replaced for one daemon session; the config file is left untouched.
+#[derive(Args, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandLineOverrides {
+    #[arg(long, help = "Video FPS target (overrides video_fps)")]
+    pub fps: Option<u32>,
+    #[arg(long, value_enum, help = "Fit mode for every monitor (overrides fit_mode and monitor_fit_modes)")]
+    pub fit: Option<FitMode>,
+    #[arg(long, value_name = "SECONDS", help = "Rotation interval (overrides daemon_interval_seconds)")]
+    pub interval: Option<u64>,
+    #[arg(long, help = "Only manage this monitor (overrides monitors)")]
+    pub monitor: Option<String>,
+    #[arg(long, value_name = "NAME", help = "Leave this monitor alone; repeatable (adds to exclude_monitors)")]
+    pub exclude_monitor: Vec<String>,
+    #[arg(long, help = "Give every monitor a different wallpaper (overrides no_overlap)")]
+    pub no_overlap: bool,
+    #[arg(long, value_name = "SOCKET", help = "WAYLAND_DISPLAY for the renderers (overrides wayland_display)")]
+    pub wayland_display: Option<String>,
+    #[arg(long, value_name = "PATH", help = "XDG_RUNTIME_DIR for the renderers (overrides xdg_runtime_dir)")]
+    pub xdg_runtime_dir: Option<PathBuf>,
+    #[arg(long, value_name = "PATH", help = "Read wallpaper paths from this named pipe (overrides fifo_path)")]
+    pub fifo: Option<PathBuf>,
+    #[arg(long, visible_alias = "namespace", value_name = "STRING", help = "wlr-layer-shell namespace for layer rules (overrides layer_namespace)")]
+    pub layer_namespace: Option<String>,
+}
+
+impl CommandLineOverrides {
+    pub fn apply(&self, cfg: &mut Config) {
+        if let Some(fps) = self.fps {
+            cfg.video_fps = Some(fps);
+        }
+        if let Some(fit) = self.fit {
+            cfg.fit_mode = Some(fit);
+            cfg.monitor_fit_modes = None;
+        }
+        if let Some(interval) = self.interval {
+            cfg.daemon_interval_seconds = Some(interval);
+        }
+        if let Some(monitor) = &self.monitor {
+            cfg.monitors = Some(vec![monitor.clone()]);
+        }
+        if !self.exclude_monitor.is_empty() {
+            cfg.exclude_monitors
+                .get_or_insert_with(Vec::new)
+                .extend(self.exclude_monitor.iter().cloned());
+        }
+        if self.no_overlap {
+            cfg.no_overlap = Some(true);
+        }
+        if let Some(display) = &self.wayland_display {
+            cfg.wayland_display = Some(display.clone());
+        }
+        if let Some(dir) = &self.xdg_runtime_dir {
+            cfg.xdg_runtime_dir = Some(dir.clone());
+        }
+        if let Some(path) = &self.fifo {
+            cfg.fifo_path = Some(path.clone());
+        }
+        if let Some(namespace) = &self.layer_namespace {
+            cfg.layer_namespace = Some(namespace.clone());
+        }
+    }
+
+    fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(fps) = self.fps {
+            args.push("--fps".to_string());
+            args.push(fps.to_string());
+        }
+        if let Some(value) = self.fit.and_then(|fit| fit.to_possible_value()) {
+            args.push("--fit".to_string());
+            args.push(value.get_name().to_string());
+        }
+        if let Some(interval) = self.interval {
+            args.push("--interval".to_string());
+            args.push(interval.to_string());
+        }
+        if let Some(monitor) = &self.monitor {
+            args.push("--monitor".to_string());
+            args.push(monitor.clone());
+        }
+        for monitor in &self.exclude_monitor {
+            args.push("--exclude-monitor".to_string());
+            args.push(monitor.clone());
+        }
+        if self.no_overlap {
+            args.push("--no-overlap".to_string());
+        }
+        if let Some(display) = &self.wayland_display {
+            args.push("--wayland-display".to_string());
+            args.push(display.clone());
+        }
+        if let Some(dir) = &self.xdg_runtime_dir {
+            args.push("--xdg-runtime-dir".to_string());
+            args.push(dir.display().to_string());
+        }
+        if let Some(path) = &self.fifo {
+            args.push("--fifo".to_string());
+            args.push(path.display().to_string());
+        }
+        if let Some(namespace) = &self.layer_namespace {
+            args.push("--layer-namespace".to_string());
+            args.push(namespace.clone());
+        }
+        args
+    }
+}
+
+impl DaemonArgs {
+    /// Re-encodes the flags for the detached daemon process.
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(seconds) = self.start_delay {
+            args.push("--start-delay".to_string());
+            args.push(seconds.to_string());
+        }
+        if self.wait_for_compositor {
+            args.push("--wait-for-compositor".to_string());
+        }
+        if self.config_watch {
+            args.push("--config-watch".to_string());
+        }
+        if let Some(path) = &self.pidfile {
+            args.push("--pidfile".to_string());
+            args.push(path.display().to_string());
+        }
+        if let Some(path) = &self.lockfile {
+            args.push("--lockfile".to_string());
+            args.push(path.display().to_string());
+        }
+        // --pid-stdout and --force are not forwarded: the process that spawns the
+        // daemon prints the pid and clears the old files.
+        if let Some(fd) = self.ready_fd {
+            args.push("--ready-fd".to_string());
+            args.push(fd.to_string());
+        }
+        if let Some(profile) = &self.profile {
+            args.push("--profile".to_string());
+            args.push(profile.clone());
+        }
+        if self.watch_pidfile {
+            args.push("--watch-pidfile".to_string());
+        }
+        args.extend(self.overrides.to_args());
+        args
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Command, PapdieoArgs};
+    use super::{
+        parse_focus_area, parse_tile_offset, parse_tile_size, Command, CommandLineOverrides,
+        DaemonArgs, PapdieoArgs,
+    };
+    use crate::config::{FitMode, SurfaceAnchor};
     use clap::Parser;
+    use std::{path::PathBuf, time::Duration};
 
     #[test]
     fn run_internal_accepts_assignments_without_path() {
@@ -141,6 +802,8 @@ mod tests {
             "DP-1",
             "--fps",
             "30",
+            "--anchor",
+            "bottomright",
         ])
         .expect("run-internal with path should parse");
 
@@ -150,14 +813,206 @@ mod tests {
                 assignments,
                 monitor,
                 fps,
+                anchor,
                 ..
             }) => {
                 assert_eq!(path.as_deref().and_then(|p| p.to_str()), Some("/tmp/a.png"));
                 assert!(assignments.is_none());
                 assert_eq!(monitor.as_deref(), Some("DP-1"));
                 assert_eq!(fps, Some(30));
+                assert_eq!(anchor, Some(SurfaceAnchor::BottomRight));
+            }
+            _ => panic!("unexpected command variant"),
+        }
+    }
+
+    #[test]
+    fn verbosity_flags_are_global_and_exclusive() {
+        let args = PapdieoArgs::try_parse_from(["papdieo", "list", "-vv"])
+            .expect("-vv after subcommand should parse");
+        assert_eq!(args.verbose, 2);
+        assert!(!args.quiet);
+
+        let args = PapdieoArgs::try_parse_from(["papdieo", "-q", "list"])
+            .expect("-q before subcommand should parse");
+        assert!(args.quiet);
+
+        assert!(PapdieoArgs::try_parse_from(["papdieo", "-q", "-v", "list"]).is_err());
+    }
+
+    #[test]
+    fn daemon_args_round_trip_through_daemon_internal() {
+        let options = DaemonArgs {
+            start_delay: Some(5),
+            wait_for_compositor: true,
+            config_watch: true,
+            pidfile: Some(PathBuf::from("/run/papdieo/daemon.pid")),
+            lockfile: None,
+            pid_stdout: false,
+            ready_fd: Some(3),
+            force: false,
+            profile: Some("work".to_string()),
+            watch_pidfile: true,
+            overrides: CommandLineOverrides {
+                fps: Some(30),
+                fit: Some(FitMode::ScaleDown),
+                interval: Some(60),
+                monitor: Some("DP-1".to_string()),
+                exclude_monitor: vec!["eDP-1".to_string(), "HDMI".to_string()],
+                no_overlap: true,
+                wayland_display: Some("wayland-1".to_string()),
+                xdg_runtime_dir: Some(PathBuf::from("/run/user/1000")),
+                fifo: Some(PathBuf::from("/run/user/1000/papdieo.fifo")),
+                layer_namespace: Some("papdieo-blur".to_string()),
+            },
+        };
+
+        let mut argv = vec!["papdieo".to_string(), "daemon-internal".to_string()];
+        argv.extend(options.to_args());
+        let args = PapdieoArgs::try_parse_from(argv).expect("forwarded daemon args should parse");
+
+        match args.command {
+            Some(Command::__DaemonInternal { options }) => {
+                assert_eq!(options.start_delay, Some(5));
+                assert!(options.wait_for_compositor);
+                assert!(options.config_watch);
+                assert_eq!(options.pidfile, Some(PathBuf::from("/run/papdieo/daemon.pid")));
+                assert_eq!(options.lockfile, None);
+                assert_eq!(options.ready_fd, Some(3));
+                assert_eq!(options.profile.as_deref(), Some("work"));
+                assert!(options.watch_pidfile);
+                assert_eq!(options.overrides.fps, Some(30));
+                assert_eq!(options.overrides.fit, Some(FitMode::ScaleDown));
+                assert_eq!(options.overrides.interval, Some(60));
+                assert_eq!(options.overrides.monitor.as_deref(), Some("DP-1"));
+                assert_eq!(options.overrides.exclude_monitor, ["eDP-1", "HDMI"]);
+                assert!(options.overrides.no_overlap);
+                assert_eq!(options.overrides.wayland_display.as_deref(), Some("wayland-1"));
+                assert_eq!(options.overrides.xdg_runtime_dir, Some(PathBuf::from("/run/user/1000")));
+                assert_eq!(
+                    options.overrides.fifo,
+                    Some(PathBuf::from("/run/user/1000/papdieo.fifo"))
+                );
+                assert_eq!(options.overrides.layer_namespace.as_deref(), Some("papdieo-blur"));
+            }
+            _ => panic!("unexpected command variant"),
+        }
+    }
+
+    #[test]
+    fn rotate_interval_accepts_fractional_seconds() {
+        let args = PapdieoArgs::try_parse_from(["papdieo", "rotate", "--interval", "0.5"])
+            .expect("fractional interval should parse");
+        match args.command {
+            Some(Command::Rotate { interval, .. }) => {
+                assert_eq!(interval, Some(Duration::from_millis(500)));
             }
             _ => panic!("unexpected command variant"),
         }
+
+        assert!(PapdieoArgs::try_parse_from(["papdieo", "rotate", "--interval", "0"]).is_err());
+        assert!(PapdieoArgs::try_parse_from(["papdieo", "rotate", "--interval", "soon"]).is_err());
+    }
+
+    #[test]
+    fn aspect_filter_accepts_fractions_and_floats() {
+        let args = PapdieoArgs::try_parse_from(["papdieo", "random", "--aspect-filter", "16:9"])
+            .expect("fraction aspect ratio should parse");
+        match args.command {
+            Some(Command::Random {
+                aspect_filter,
+                aspect_tolerance,
+                ..
+            }) => {
+                assert!((aspect_filter.unwrap() - 16.0 / 9.0).abs() < 1e-9);
+                assert_eq!(aspect_tolerance, 10.0);
+            }
+            _ => panic!("unexpected command variant"),
+        }
+
+        let args = PapdieoArgs::try_parse_from(["papdieo", "next", "--aspect-filter", "1.777"])
+            .expect("float aspect ratio should parse");
+        assert!(matches!(
+            args.command,
+            Some(Command::Next { aspect_filter: Some(ratio), .. }) if ratio == 1.777
+        ));
+        for bad in ["16:0", "wide", "-1.5"] {
+            assert!(
+                PapdieoArgs::try_parse_from(["papdieo", "next", "--aspect-filter", bad]).is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn set_takes_either_path_or_mirror() {
+        let args = PapdieoArgs::try_parse_from(["papdieo", "set", "--mirror", "eDP-1"])
+            .expect("set --mirror without a path should parse");
+        match args.command {
+            Some(Command::Set { path, mirror, .. }) => {
+                assert!(path.is_none());
+                assert_eq!(mirror.as_deref(), Some("eDP-1"));
+            }
+            _ => panic!("unexpected command variant"),
+        }
+
+        assert!(PapdieoArgs::try_parse_from(["papdieo", "set"]).is_err());
+        assert!(
+            PapdieoArgs::try_parse_from(["papdieo", "set", "/tmp/a.png", "--mirror", "eDP-1"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn all_outputs_replaces_monitor_selection() {
+        let args = PapdieoArgs::try_parse_from(["papdieo", "set", "/tmp/a.png", "--all-outputs"])
+            .expect("set --all-outputs should parse");
+        assert!(matches!(args.command, Some(Command::Set { all_outputs: true, .. })));
+        for conflicting in [["--monitor", "DP-1"], ["--monitor-regex", "^DP-"]] {
+            let mut argv = vec!["papdieo", "set", "/tmp/a.png", "--all-outputs"];
+            argv.extend(conflicting);
+            assert!(PapdieoArgs::try_parse_from(argv).is_err(), "{:?} parsed", conflicting);
+        }
+        assert!(PapdieoArgs::try_parse_from([
+            "papdieo",
+            "run-internal",
+            "/tmp/a.png",
+            "--all-outputs",
+            "--monitor",
+            "DP-1"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn namespace_is_another_name_for_layer_namespace() {
+        for flag in ["--namespace", "--layer-namespace"] {
+            let args = PapdieoArgs::try_parse_from(["papdieo", "set", "/tmp/a.png", flag, "blur"])
+                .expect("set with a namespace should parse");
+            match args.command {
+                Some(Command::Set { layer_namespace, .. }) => {
+                    assert_eq!(layer_namespace.as_deref(), Some("blur"), "{}", flag)
+                }
+                _ => panic!("unexpected command variant"),
+            }
+        }
+    }
+
+    #[test]
+    fn focus_area_takes_four_numbers_with_a_size() {
+        assert_eq!(parse_focus_area("10, 20,300,400"), Ok([10.0, 20.0, 300.0, 400.0]));
+        assert_eq!(parse_focus_area("0,0.1,1,0.25"), Ok([0.0, 0.1, 1.0, 0.25]));
+        for invalid in ["1,2,3", "1,2,3,4,5", "a,b,c,d", "-1,0,10,10", "0,0,0,10"] {
+            assert!(parse_focus_area(invalid).is_err(), "{:?} parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn tile_size_and_offset_parse() {
+        assert_eq!(parse_tile_size("256x128"), Ok((256, 128)));
+        assert_eq!(parse_tile_size("0X0"), Ok((0, 0)));
+        assert!(parse_tile_size("256").is_err());
+        assert!(parse_tile_size("-1x5").is_err());
+        assert_eq!(parse_tile_offset("-16, 32"), Ok((-16, 32)));
+        assert!(parse_tile_offset("16").is_err());
     }
 }
@@ -0,0 +1,267 @@
+//! YUV to XRGB8888 conversion for decoders that hand us planar frames.
+//!
+//! Output pixels use the Wayland `xrgb8888` memory order (B, G, R, X), the
+//! same layout as GStreamer `BGRx`. Coefficients are BT.601 full range in
+//! 16.16 fixed point.
+
+use image::RgbaImage;
+
+const CR_TO_R: i32 = 91_881; // 1.402
+const CB_TO_G: i32 = 22_554; // 0.344136
+const CR_TO_G: i32 = 46_802; // 0.714136
+const CB_TO_B: i32 = 116_130; // 1.772
+
+/// Converts an NV12 frame: a `stride`-wide Y plane followed directly by an
+/// interleaved UV plane with the same stride. `dst` is tightly packed.
+pub fn convert_nv12_to_xrgb(
+    src: &[u8],
+    stride: usize,
+    width: usize,
+    height: usize,
+    dst: &mut [u8],
+) {
+    let uv_plane = &src[stride * height..];
+    for row in 0..height {
+        let y_row = &src[row * stride..];
+        let uv_row = &uv_plane[(row / 2) * stride..];
+        let dst_row = &mut dst[row * width * 4..(row + 1) * width * 4];
+        for (column, px) in dst_row.chunks_exact_mut(4).enumerate() {
+            let uv = (column / 2) * 2;
+            write_xrgb(px, y_row[column], uv_row[uv], uv_row[uv + 1]);
+        }
+    }
+}
+
+/// Converts an I420 (`YUV420P`) frame: a `stride`-wide Y plane followed by
+/// U and V planes of half height with a stride of `stride / 2`.
+pub fn convert_yuv420p_to_xrgb(
+    src: &[u8],
+    stride: usize,
+    width: usize,
+    height: usize,
+    dst: &mut [u8],
+) {
+    let chroma_stride = stride / 2;
+    let chroma_height = height.div_ceil(2);
+    let u_plane = &src[stride * height..];
+    let v_plane = &u_plane[chroma_stride * chroma_height..];
+    for row in 0..height {
+        let y_row = &src[row * stride..];
+        let u_row = &u_plane[(row / 2) * chroma_stride..];
+        let v_row = &v_plane[(row / 2) * chroma_stride..];
+        let dst_row = &mut dst[row * width * 4..(row + 1) * width * 4];
+        for (column, px) in dst_row.chunks_exact_mut(4).enumerate() {
+            write_xrgb(px, y_row[column], u_row[column / 2], v_row[column / 2]);
+        }
+    }
+}
+
+/// Converts tightly packed RGBA to XRGB8888 once, so frames shown repeatedly
+/// can be copied straight into the shm buffer.
+pub fn rgba_to_xrgb(src: &[u8]) -> Vec<u8> {
+    let mut dst = vec![0u8; src.len()];
+    rgba_to_xrgb_into(src, &mut dst);
+    dst
+}
+
+pub fn rgba_to_xrgb_into(src: &[u8], dst: &mut [u8]) {
+    for (out, px) in dst.chunks_exact_mut(4).zip(src.chunks_exact(4)) {
+        out[0] = px[2];
+        out[1] = px[1];
+        out[2] = px[0];
+        out[3] = 255;
+    }
+}
+
+/// Bytes needed for a frame in the layout the converters expect.
+pub fn nv12_frame_len(stride: usize, height: usize) -> usize {
+    stride * height + stride * height.div_ceil(2)
+}
+
+pub fn yuv420p_frame_len(stride: usize, height: usize) -> usize {
+    stride * height + 2 * (stride / 2) * height.div_ceil(2)
+}
+
+/// `--brightness`/`--contrast`: stretches each channel around mid-grey by
+/// `contrast` (0.0 to 2.0), then scales it by `brightness` (0.0 to 1.0), so 0.0
+/// brightness is black whatever the contrast. Alpha is left alone.
+pub fn apply_image_adjustments(img: &mut RgbaImage, brightness: f32, contrast: f32) {
+    if brightness == 1.0 && contrast == 1.0 {
+        return;
+    }
+    let brightness = brightness.clamp(0.0, 1.0);
+    let table: [u8; 256] = std::array::from_fn(|value| {
+        let stretched = (value as f32 - 128.0) * contrast + 128.0;
+        (stretched * brightness).round().clamp(0.0, 255.0) as u8
+    });
+    for px in img.pixels_mut() {
+        for channel in &mut px.0[..3] {
+            *channel = table[*channel as usize];
+        }
+    }
+}
+
+fn write_xrgb(px: &mut [u8], y: u8, cb: u8, cr: u8) {
+    let y = (y as i32) << 16;
+    let cb = cb as i32 - 128;
+    let cr = cr as i32 - 128;
+    let r = (y + CR_TO_R * cr + 0x8000) >> 16;
+    let g = (y - CB_TO_G * cb - CR_TO_G * cr + 0x8000) >> 16;
+    let b = (y + CB_TO_B * cb + 0x8000) >> 16;
+    px[0] = b.clamp(0, 255) as u8;
+    px[1] = g.clamp(0, 255) as u8;
+    px[2] = r.clamp(0, 255) as u8;
+    px[3] = 255;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        apply_image_adjustments, convert_nv12_to_xrgb, convert_yuv420p_to_xrgb, nv12_frame_len,
+        rgba_to_xrgb, rgba_to_xrgb_into, yuv420p_frame_len,
+    };
+    use image::{Rgba, RgbaImage};
+    use std::{hint::black_box, time::Instant};
+
+    fn reference_bgrx(y: u8, cb: u8, cr: u8) -> [u8; 4] {
+        let y = y as f64;
+        let cb = cb as f64 - 128.0;
+        let cr = cr as f64 - 128.0;
+        let clamp = |v: f64| v.round().clamp(0.0, 255.0) as u8;
+        [
+            clamp(y + 1.772 * cb),
+            clamp(y - 0.344136 * cb - 0.714136 * cr),
+            clamp(y + 1.402 * cr),
+            255,
+        ]
+    }
+
+    fn assert_close(actual: &[u8], expected: [u8; 4]) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(
+                a.abs_diff(e) <= 1,
+                "got {:?}, expected {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    /// 4x2 luma ramp with per-2x2-block chroma, so every chroma sample and a
+    /// few saturating combinations get exercised.
+    const WIDTH: usize = 4;
+    const HEIGHT: usize = 2;
+    const LUMA: [u8; 8] = [0, 60, 128, 255, 16, 90, 200, 235];
+    const CB: [u8; 2] = [40, 240];
+    const CR: [u8; 2] = [220, 10];
+
+    #[test]
+    fn nv12_matches_reference_conversion() {
+        let stride = 6;
+        let mut src = vec![0u8; nv12_frame_len(stride, HEIGHT)];
+        for row in 0..HEIGHT {
+            src[row * stride..row * stride + WIDTH].copy_from_slice(&LUMA[row * WIDTH..][..WIDTH]);
+        }
+        let uv = stride * HEIGHT;
+        src[uv..uv + 4].copy_from_slice(&[CB[0], CR[0], CB[1], CR[1]]);
+
+        let mut dst = vec![0u8; WIDTH * HEIGHT * 4];
+        convert_nv12_to_xrgb(&src, stride, WIDTH, HEIGHT, &mut dst);
+
+        for (i, px) in dst.chunks_exact(4).enumerate() {
+            let block = (i % WIDTH) / 2;
+            assert_close(px, reference_bgrx(LUMA[i], CB[block], CR[block]));
+        }
+    }
+
+    #[test]
+    fn yuv420p_matches_reference_conversion() {
+        let stride = 8;
+        let mut src = vec![0u8; yuv420p_frame_len(stride, HEIGHT)];
+        for row in 0..HEIGHT {
+            src[row * stride..row * stride + WIDTH].copy_from_slice(&LUMA[row * WIDTH..][..WIDTH]);
+        }
+        let u = stride * HEIGHT;
+        let v = u + stride / 2;
+        src[u..u + 2].copy_from_slice(&CB);
+        src[v..v + 2].copy_from_slice(&CR);
+
+        let mut dst = vec![0u8; WIDTH * HEIGHT * 4];
+        convert_yuv420p_to_xrgb(&src, stride, WIDTH, HEIGHT, &mut dst);
+
+        for (i, px) in dst.chunks_exact(4).enumerate() {
+            let block = (i % WIDTH) / 2;
+            assert_close(px, reference_bgrx(LUMA[i], CB[block], CR[block]));
+        }
+    }
+
+    #[test]
+    fn neutral_chroma_gives_gray() {
+        let mut src = vec![128u8; nv12_frame_len(2, 2)];
+        src[..4].copy_from_slice(&[0, 77, 180, 255]);
+
+        let mut dst = vec![0u8; 16];
+        convert_nv12_to_xrgb(&src, 2, 2, 2, &mut dst);
+
+        assert_eq!(
+            dst,
+            [0, 0, 0, 255, 77, 77, 77, 255, 180, 180, 180, 255, 255, 255, 255, 255]
+        );
+    }
+
+    #[test]
+    fn rgba_swaps_to_bgrx_and_drops_alpha() {
+        assert_eq!(
+            rgba_to_xrgb(&[10, 20, 30, 0, 1, 2, 3, 128]),
+            [30, 20, 10, 255, 3, 2, 1, 255]
+        );
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture xrgb_copy`.
+    #[test]
+    #[ignore]
+    fn bench_xrgb_copy_against_per_frame_rgba_conversion() {
+        const FRAMES: u32 = 60;
+        let rgba = vec![0x5au8; 3840 * 2160 * 4];
+        let mut shm = vec![0u8; rgba.len()];
+
+        let started = Instant::now();
+        for _ in 0..FRAMES {
+            rgba_to_xrgb_into(black_box(&rgba), black_box(&mut shm));
+        }
+        let convert = started.elapsed() / FRAMES;
+
+        let xrgb = rgba_to_xrgb(&rgba);
+        let started = Instant::now();
+        for _ in 0..FRAMES {
+            black_box(&mut shm).copy_from_slice(black_box(&xrgb));
+        }
+        let copy = started.elapsed() / FRAMES;
+
+        println!("4K frame: rgba conversion {:?}, xrgb copy {:?}", convert, copy);
+    }
+
+    #[test]
+    fn adjustments_dim_and_stretch_colour_but_keep_alpha() {
+        let pixels = [[0, 64, 128, 255], [192, 255, 100, 40]];
+        let image = || RgbaImage::from_fn(2, 1, |x, _| Rgba(pixels[x as usize]));
+
+        let mut unchanged = image();
+        apply_image_adjustments(&mut unchanged, 1.0, 1.0);
+        assert_eq!(unchanged, image());
+
+        let mut dimmed = image();
+        apply_image_adjustments(&mut dimmed, 0.5, 1.0);
+        assert_eq!(dimmed.get_pixel(0, 0).0, [0, 32, 64, 255]);
+        assert_eq!(dimmed.get_pixel(1, 0).0, [96, 128, 50, 40]);
+
+        let mut contrasted = image();
+        apply_image_adjustments(&mut contrasted, 1.0, 2.0);
+        assert_eq!(contrasted.get_pixel(0, 0).0, [0, 0, 128, 255]);
+        assert_eq!(contrasted.get_pixel(1, 0).0, [255, 255, 72, 40]);
+
+        let mut black = image();
+        apply_image_adjustments(&mut black, 0.0, 1.5);
+        assert!(black.pixels().all(|px| px.0[..3] == [0, 0, 0]));
+    }
+}
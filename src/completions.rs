@@ -0,0 +1,72 @@
+//! `papdieo completions <shell>`: clap_complete scripts, with `set` limited
+//! to supported media files in fish and zsh.
+
+use crate::cli::PapdieoArgs;
+use crate::picker::{SUPPORTED_IMAGE_EXTS, SUPPORTED_VIDEO_EXTS};
+use anyhow::{anyhow, Result};
+use clap::CommandFactory;
+use clap_complete::Shell;
+
+pub fn generate(shell: Shell) -> Result<String> {
+    let mut out = Vec::new();
+    clap_complete::generate(shell, &mut PapdieoArgs::command(), "papdieo", &mut out);
+    let script = String::from_utf8(out).map_err(|_| anyhow!("completion script is not UTF-8"))?;
+
+    Ok(match shell {
+        Shell::Fish => script + &fish_set_path_completion(),
+        Shell::Zsh => zsh_with_set_path_glob(&script)?,
+        _ => script,
+    })
+}
+
+fn media_extensions() -> impl Iterator<Item = &'static str> {
+    SUPPORTED_IMAGE_EXTS.iter().chain(SUPPORTED_VIDEO_EXTS).copied()
+}
+
+fn fish_set_path_completion() -> String {
+    let suffixes: Vec<String> = media_extensions().map(|ext| format!(".{}", ext)).collect();
+    format!(
+        "complete -c papdieo -n \"__fish_papdieo_using_subcommand set\" -k -f -a \"(__fish_complete_suffix {})\"\n",
+        suffixes.join(" ")
+    )
+}
+
+/// Swaps `_files` for a glob on the path argument of the top-level `set`.
+fn zsh_with_set_path_glob(script: &str) -> Result<String> {
+    let missing = || anyhow!("set path argument not found in the zsh completion script");
+    let block = script.find("\n(set)\n").ok_or_else(missing)?;
+    let block_end = block + script[block..].find("\n;;").ok_or_else(missing)?;
+    let path_line = block + script[block..block_end].find("\n'::path").ok_or_else(missing)?;
+    let files = path_line + script[path_line..block_end].find(":_files'").ok_or_else(missing)?;
+
+    let glob = media_extensions().collect::<Vec<_>>().join("|");
+    let mut patched = script.to_string();
+    patched.replace_range(
+        files..files + ":_files'".len(),
+        &format!(":_files -g \"*.({})\"'", glob),
+    );
+    Ok(patched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::generate;
+    use clap_complete::Shell;
+
+    #[test]
+    fn fish_and_zsh_limit_set_to_media_files() {
+        let fish = generate(Shell::Fish).unwrap();
+        let last = fish.lines().last().unwrap();
+        assert!(last.starts_with("complete -c papdieo -n \"__fish_papdieo_using_subcommand set\""));
+        assert!(last.contains("(__fish_complete_suffix .jpg .jpeg .png .webp"));
+        assert!(last.contains(" .mp4 ") && last.ends_with(" .ts)\""));
+
+        let zsh = generate(Shell::Zsh).unwrap();
+        let set = &zsh[zsh.find("\n(set)\n").unwrap()..];
+        let set = &set[..set.find("\n;;").unwrap()];
+        assert!(set.contains(":_files -g \"*.(jpg|jpeg|png|webp"));
+        assert_eq!(zsh.matches("_files -g").count(), 1);
+
+        assert!(!generate(Shell::Bash).unwrap().contains("_files -g"));
+    }
+}
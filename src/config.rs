@@ -1,28 +1,118 @@
+use crate::effects::{self, Effect, WatermarkConfig};
+use crate::output::{debug, warning};
+use crate::transition::EasingCurve;
 use anyhow::{Context, Result};
 use clap::ValueEnum;
+use image::imageops::FilterType;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     env,
     fs,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
-#[derive(Debug, Clone, Deserialize)]
+/// Shortest rotation interval; anything below is raised to this.
+pub const MIN_ROTATION_INTERVAL: Duration = Duration::from_millis(100);
+/// How many levels of `config_includes` are followed; this also ends include cycles.
+const MAX_INCLUDE_DEPTH: usize = 3;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Config {
+    /// Files merged over this one, in order; see [`Config::load_or_default`].
+    pub config_includes: Option<Vec<PathBuf>>,
     #[serde(default = "default_wallpaper_dir")]
     pub wallpaper_dir: PathBuf,
+    pub wallpaper_dirs: Option<Vec<PathBuf>>,
     pub monitor_wallpaper_dirs: Option<HashMap<String, PathBuf>>,
+    pub monitor_wallpaper_dir_lists: Option<HashMap<String, Vec<PathBuf>>>,
+    pub resolution_dirs: Option<Vec<ResolutionRule>>,
+    pub recursive_depth: Option<usize>,
+    pub min_wallpaper_size_bytes: Option<u64>,
+    pub max_wallpaper_size_bytes: Option<u64>,
     pub monitor_fit_modes: Option<HashMap<String, FitMode>>,
     pub monitor: Option<String>,
+    pub monitor_regex: Option<String>,
     pub monitors: Option<Vec<String>>,
+    pub exclude_monitors: Option<Vec<String>>,
+    pub monitor_poll_interval_seconds: Option<u64>,
     pub video_fps: Option<u32>,
+    pub battery_fps: Option<u32>,
+    pub ac_fps: Option<u32>,
+    pub video_start_time_ms: Option<u64>,
+    pub video_retry_count: Option<u32>,
+    pub loop_fallback_path: Option<PathBuf>,
+    pub video_fallback_image: Option<PathBuf>,
+    pub auto_rotate: Option<bool>,
+    pub gpu_index: Option<u32>,
+    pub mute_audio: Option<bool>,
+    pub allow_video: Option<bool>,
+    pub allow_images: Option<bool>,
+    pub brightness: Option<f32>,
+    pub contrast: Option<f32>,
+    pub effects: Option<Vec<Effect>>,
+    pub blur_sigma: Option<f32>,
+    pub watermark: Option<WatermarkConfig>,
     pub rotation_seconds: Option<u64>,
+    pub rotation_ms: Option<u64>,
     pub daemon_interval_seconds: Option<u64>,
     pub fit_mode: Option<FitMode>,
+    pub transition: Option<Transition>,
+    pub monitor_transitions: Option<HashMap<String, Transition>>,
+    pub transition_duration_ms: Option<u64>,
+    pub transition_curve: Option<EasingCurve>,
+    pub scale_filter: Option<ScaleFilter>,
+    pub rotation_mode: Option<RotationMode>,
+    pub picker_script: Option<PathBuf>,
+    pub no_overlap: Option<bool>,
+    pub start_delay_seconds: Option<u64>,
+    pub daemon_startup_delay_ms: Option<u64>,
+    pub startup_delay_ms: Option<u64>,
+    #[serde(alias = "startup_stagger_ms")]
+    pub monitor_start_delay_ms: Option<u64>,
+    pub rotation_stagger_seconds: Option<u64>,
+    pub rotation_jitter_seconds: Option<u64>,
+    pub compositor_type: Option<CompositorType>,
+    pub visibility_hysteresis_ms: Option<u64>,
+    pub default_offset: Option<(i32, i32)>,
+    pub default_focus_area: Option<[f32; 4]>,
+    pub tile_size: Option<(u32, u32)>,
+    pub image_slideshow_delay_ms: Option<u64>,
+    pub config_watch: Option<bool>,
+    pub watch_wallpaper_dirs: Option<bool>,
+    pub fifo_path: Option<PathBuf>,
+    pub workspace_wallpapers: Option<HashMap<String, PathBuf>>,
+    pub workspace_wallpaper_dirs: Option<HashMap<String, PathBuf>>,
+    pub metrics_port: Option<u16>,
+    pub max_pooled_buffers: Option<usize>,
+    pub sd_notify_ready: Option<bool>,
+    pub pid_file: Option<PathBuf>,
+    pub lock_file: Option<PathBuf>,
+    pub wayland_display: Option<String>,
+    pub xdg_runtime_dir: Option<PathBuf>,
+    pub layer_namespace: Option<String>,
+    pub anchor: Option<SurfaceAnchor>,
+    pub startup_wallpaper: Option<PathBuf>,
+    pub startup_fit: Option<FitMode>,
+    pub startup_fps: Option<u32>,
+    pub restore_on_restart: Option<bool>,
+    pub shutdown_wallpaper: Option<PathBuf>,
+    pub on_change_command: Option<String>,
+    #[serde(default = "default_brightness_threshold")]
+    pub brightness_threshold: Option<f32>,
+    pub schedule: Option<Vec<ScheduleRule>>,
+    pub max_renderer_memory_mb: Option<u64>,
+    pub max_renderer_cpu_percent: Option<u32>,
+    pub max_renderer_cpu_percent_sustained: Option<f32>,
+    pub max_renderer_rss_mb: Option<u64>,
+    pub resource_check_interval_seconds: Option<u64>,
+    pub resource_violation_grace_seconds: Option<u64>,
+    pub renderer_nice: Option<i8>,
+    pub renderer_ionice_class: Option<u8>,
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize, ValueEnum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
 #[serde(rename_all = "kebab-case")]
 pub enum FitMode {
     Stretch,
@@ -33,20 +123,447 @@ pub enum FitMode {
     Center,
     #[serde(alias = "scaledown")]
     ScaleDown,
+    Tile,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transition {
+    None,
+    Fade,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScaleFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl ScaleFilter {
+    pub fn filter_type(self) -> FilterType {
+        match self {
+            ScaleFilter::Nearest => FilterType::Nearest,
+            ScaleFilter::Triangle => FilterType::Triangle,
+            ScaleFilter::CatmullRom => FilterType::CatmullRom,
+            ScaleFilter::Gaussian => FilterType::Gaussian,
+            ScaleFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum RotationMode {
+    Random,
+    Sequential,
+    Shuffle,
+    HashShuffle,
+}
+
+/// Layer surface placement. `AllEdges` fills the monitor; the others give the
+/// surface its own size and leave the rest of the monitor uncovered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+#[value(rename_all = "lower")]
+pub enum SurfaceAnchor {
+    #[default]
+    #[serde(rename = "all-edges")]
+    #[value(name = "all-edges")]
+    AllEdges,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+/// Which compositor IPC is used to pause video behind windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompositorType {
+    Auto,
+    Hyprland,
+    Sway,
+}
+
+/// A cron trigger that switches the daemon to `dir` or to the single wallpaper `path`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ScheduleRule {
+    pub cron: String,
+    pub dir: Option<PathBuf>,
+    pub path: Option<PathBuf>,
+}
+
+/// Wallpapers from `dir` for monitors at least `min_width` × `min_height` pixels.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ResolutionRule {
+    pub min_width: u32,
+    pub min_height: u32,
+    pub dir: PathBuf,
+}
+
+/// Every supported key, commented out, with its default or an example value.
+/// Uncommenting any line enables that setting.
+pub const CONFIG_TEMPLATE: &str = r#"# papdieo configuration
+# Default location: ~/.config/papdieo/config.toml (or $XDG_CONFIG_HOME/papdieo/config.toml)
+
+# Files merged over this one, in order (relative paths are next to this file; missing ones
+# are skipped). Their settings win; tables such as monitor_wallpaper_dirs are merged by key.
+# config_includes = ["machine.toml"]
+
+# Directory to pick wallpapers from (default: ~/Pictures/Wallpapers).
+# wallpaper_dir = "/home/youruser/Pictures/Wallpapers"
+
+# Several directories merged into one pool; takes precedence over wallpaper_dir in the daemon.
+# wallpaper_dirs = ["/home/youruser/Wallpapers/Nature", "/home/youruser/Wallpapers/Cities"]
+
+# Per-monitor wallpaper directory.
+# monitor_wallpaper_dirs = { DP-1 = "/home/youruser/Pictures/Walls-Work", HDMI-A-1 = "/home/youruser/Pictures/Walls-Personal" }
+
+# Per-monitor list of directories (takes precedence over monitor_wallpaper_dirs).
+# monitor_wallpaper_dir_lists = { DP-1 = ["/home/youruser/Wallpapers/Nature", "/home/youruser/Wallpapers/Cities"] }
+
+# Directories by monitor resolution (Hyprland), for monitors without a directory of their own.
+# The largest rule the monitor meets applies; smaller monitors use wallpaper_dir.
+# resolution_dirs = [{ min_width = 3840, min_height = 2160, dir = "/home/youruser/Wallpapers/4K" }, { min_width = 2560, min_height = 1440, dir = "/home/youruser/Wallpapers/QHD" }]
+
+# How many subdirectory levels `papdieo list` descends into (0: the directory itself only).
+# recursive_depth = 2
+
+# The daemon skips files smaller or larger than this many bytes when picking wallpapers.
+# min_wallpaper_size_bytes = 10240
+# max_wallpaper_size_bytes = 104857600
+
+# Per-monitor fit mode; other monitors use fit_mode.
+# monitor_fit_modes = { DP-1 = "cover", HDMI-A-1 = "contain" }
+
+# Default monitor for set/random/next/rotate.
+# monitor = "DP-1"
+
+# Default monitors for `set`, as a regex over monitor names (takes precedence over monitor).
+# monitor_regex = "^DP-[0-9]+$"
+
+# Monitors the daemon manages (default: keys of the directory maps, then auto-detected).
+# monitors = ["DP-1", "HDMI-A-1"]
+
+# Monitors the daemon leaves alone; a connector type such as "HDMI" matches every HDMI output.
+# exclude_monitors = ["eDP-1"]
+
+# How often the daemon looks for monitors that were plugged in or removed, in seconds,
+# independent of the rotation interval (0 only checks at each rotation).
+# monitor_poll_interval_seconds = 10
+
+# Video frame rate target.
+# video_fps = 60
+
+# Daemon frame rate cap for videos on battery and on AC power (checked every 10s).
+# battery_fps = 24
+# ac_fps = 60
+
+# Start videos this many milliseconds in; they also loop back to this point.
+# video_start_time_ms = 30000
+
+# How often a video that fails mid-playback is restarted before the next decoder is tried.
+# video_retry_count = 3
+
+# Image shown once a video started with `papdieo set --loop N` has played N times
+# (default: keep the video's last frame).
+# loop_fallback_path = "/home/youruser/Pictures/Wallpapers/still.png"
+
+# Shown when no GStreamer pipeline can play a video, instead of the renderer exiting and
+# being restarted on the same file; unset shows neutral grey.
+# video_fallback_image = "/home/youruser/Pictures/Wallpapers/still.png"
+
+# Redraw when a monitor is rotated to portrait or back; videos restart at the new size.
+# auto_rotate = false
+
+# GPU that decodes video on multi-GPU systems, numbered as in `papdieo set --list-gpus`.
+# gpu_index = 1
+
+# Keep video wallpapers silent; set to false to play a file's audio track as well.
+# mute_audio = true
+
+# Pick only images or only videos, e.g. on hardware that struggles with video decoding.
+# `--no-video`/`--no-images` on random, next, rotate and list do the same for one command.
+# allow_video = true
+# allow_images = true
+
+# Dim wallpapers (0.0 to 1.0, channel multiplier) or change their contrast (0.0 to 2.0),
+# for images and videos alike; `--brightness`/`--contrast` override them.
+# brightness = 1.0
+# contrast = 1.0
+
+# Post-processing effects applied in order after an image is fitted: grayscale, sepia,
+# invert, blur. Videos get GStreamer filters for grayscale, sepia and blur only.
+# `papdieo set --effect` overrides them.
+# effects = ["grayscale", "blur"]
+# Strength of blur: the Gaussian sigma in pixels (0.0 to 20.0), for images and videos.
+# blur_sigma = 8.0
+
+# A logo composited over every wallpaper after the effects. position is top-left, top-right,
+# bottom-left, bottom-right (default) or center; scale is its width as a fraction of the output
+# (default 0.1, 0 keeps its own size); opacity defaults to 0.8. `papdieo set --watermark` sets path.
+# watermark = { path = "/home/youruser/Pictures/logo.png", position = "bottom-right", opacity = 0.8, scale = 0.1 }
+
+# Interval for `papdieo rotate`, in seconds.
+# rotation_seconds = 300
+
+# Same in milliseconds, for sub-second slideshows; wins over rotation_seconds (minimum 100).
+# rotation_ms = 500
+
+# Daemon rotation interval in seconds (falls back to rotation_ms, then rotation_seconds).
+# daemon_interval_seconds = 300
+
+# Seconds the daemon waits before setting the first wallpaper.
+# start_delay_seconds = 0
+# The same in milliseconds; wins over start_delay_seconds, `daemon --start-delay` wins over both.
+# daemon_startup_delay_ms = 0
+
+# Milliseconds `papdieo set` waits between mapping its surface and drawing the first frame,
+# e.g. to avoid a flash when started from exec-once; `set --delay` overrides it.
+# startup_delay_ms = 0
+
+# Milliseconds between starting one monitor's renderer and the next (in monitor name order),
+# so several videos do not all initialize at once; also accepted as startup_stagger_ms.
+# monitor_start_delay_ms = 0
+
+# Spread each daemon rotation instead of switching every monitor at once: monitors rotate
+# rotation_stagger_seconds apart (in name order), each moved by ± rotation_jitter_seconds.
+# rotation_stagger_seconds = 0
+# rotation_jitter_seconds = 0
+
+# Wallpaper the daemon shows on every monitor for its first interval, before rotating.
+# startup_wallpaper = "/home/youruser/Pictures/Wallpapers/splash.png"
+# startup_fit = "cover"
+# startup_fps = 30
+
+# Start with the wallpapers the previous daemon showed (with their fit and fps) and rotate
+# them when their interval runs out, instead of picking new ones; the startup wallpaper
+# is used only on monitors with nothing to restore.
+# restore_on_restart = false
+
+# Wallpaper left on every monitor when the daemon is stopped, until the next one takes over;
+# without it the monitors are kept black instead of showing the bare desktop.
+# shutdown_wallpaper = "/home/youruser/Pictures/Wallpapers/goodbye.png"
+
+# stretch | fill | cover | fit | contain | center | scale-down | tile
+# fit_mode = "cover"
+
+# Image transition: none | fade
+# transition = "none"
+
+# Per-monitor transition override.
+# monitor_transitions = { DP-2 = "none" }
+
+# Length of a fade in milliseconds. The daemon cross-fades old and new wallpaper
+# through the compositor (wp_alpha_modifier_v1) when it supports it.
+# transition_duration_ms = 300
+
+# Shape of the fade: linear | ease-in | ease-out | ease-in-out
+# transition_curve = "ease-in-out"
+
+# Resampling filter: nearest | triangle | catmull-rom | gaussian | lanczos3
+# (unset: lanczos3 for images, triangle for video)
+# scale_filter = "lanczos3"
+
+# Daemon/rotate pick order: random | sequential | shuffle | hash-shuffle
+# rotation_mode = "random"
+
+# Executable the daemon runs at each rotation instead of picking from the directory.
+# It prints the wallpaper path; PAPDIEO_MONITOR, PAPDIEO_WALLPAPER_DIR and
+# PAPDIEO_TIMESTAMP are set for it.
+# picker_script = "/home/youruser/.config/papdieo/pick-by-weather.sh"
+
+# Give every monitor a different wallpaper on each random pick (repeats only
+# when a directory has fewer wallpapers than monitors).
+# no_overlap = false
+
+# Compositor IPC used to pause video behind windows: auto | hyprland | sway
+# compositor_type = "auto"
+# Minimum time between pausing and resuming video as windows come and go (ms)
+# visibility_hysteresis_ms = 1000
+
+# Pan images by [x, y] pixels; positive values move right/down.
+# default_offset = [0, 0]
+
+# Region [x, y, width, height], as fractions of the image, that cover/fill crops keep
+# visible instead of cropping around the center; `papdieo set --focus-area` overrides it.
+# default_focus_area = [0.0, 0.0, 1.0, 0.5]
+
+# Tile size [width, height] in pixels for fit_mode "tile"; the image is scaled to it with
+# scale_filter first. A 0 keeps that side in proportion; [0, 0] tiles at native size.
+# tile_size = [256, 256]
+
+# Milliseconds each page of a multi-page TIFF is shown before the next one (default 5000).
+# image_slideshow_delay_ms = 5000
+
+# Reload this file through inotify as soon as it is saved (default: poll the mtime every second).
+# config_watch = false
+
+# Rotate a monitor through inotify as soon as a wallpaper is added to one of its directories.
+# watch_wallpaper_dirs = false
+
+# Named pipe (created if missing) the daemon reads wallpaper paths from, one per line.
+# Append ?monitor=DP-1 or ?monitor=all to a path; otherwise the first monitor gets it.
+# fifo_path = "/run/user/1000/papdieo.fifo"
+
+# Hyprland: a wallpaper or directory per workspace, keyed by workspace name or ID. The daemon
+# rotates a monitor as soon as it switches workspace; other workspaces use the usual directories.
+# workspace_wallpapers = { "1" = "/home/youruser/Pictures/Walls-Work", music = "/home/youruser/Pictures/vinyl.png" }
+# Directories only, checked after workspace_wallpapers.
+# workspace_wallpaper_dirs = { web = "/home/youruser/Pictures/Walls-Web", gaming = "/home/youruser/Pictures/Walls-Games" }
+
+# Serve Prometheus metrics at http://127.0.0.1:<port>/metrics while the daemon runs.
+# metrics_port = 9489
+
+# Frame buffers the daemon keeps mapped for the next wallpaper of the same size (0 frees them).
+# max_pooled_buffers = 4
+
+# systemd Type=notify: READY=1 once the first wallpaper is shown, WATCHDOG=1 pings when the unit
+# sets WatchdogSec=, and STOPPING=1 on shutdown. Run the unit with `papdieo daemon --foreground`.
+# sd_notify_ready = false
+
+# Daemon pid and lock files (default: /tmp/papdieo-daemon.pid and /tmp/papdieo-daemon.lock).
+# pid_file = "/run/user/1000/papdieo-daemon.pid"
+# lock_file = "/run/user/1000/papdieo-daemon.lock"
+
+# Wayland socket and runtime directory for renderers, when the daemon is started by a
+# service manager without the session's WAYLAND_DISPLAY/XDG_RUNTIME_DIR.
+# wayland_display = "wayland-1"
+# xdg_runtime_dir = "/run/user/1000"
+
+# wlr-layer-shell namespace, for compositor layer rules (blur, animations). Layer
+# surfaces have no app-id, so Hyprland's windowrulev2 never matches them.
+# layer_namespace = "papdieo"
+
+# Where the wallpaper sits: all-edges | topleft | topright | bottomleft | bottomright | center
+# (anything but all-edges is drawn at the image's own size, shrunk to fit the monitor).
+# anchor = "all-edges"
+
+# Shell command run after each new wallpaper is shown, with PAPDIEO_WALLPAPER,
+# PAPDIEO_MONITOR and, for images, PAPDIEO_WALLPAPER_BRIGHTNESS (0.0-1.0) and
+# PAPDIEO_WALLPAPER_THEME (light above brightness_threshold, otherwise dark).
+# on_change_command = "wal -i \"$PAPDIEO_WALLPAPER\" -n"
+# brightness_threshold = 0.5
+
+# Cron rules (minute hour day month weekday) the daemon switches to when they fire;
+# each rule stays in effect until another one fires. Give each rule a dir or a path.
+# schedule = [{ cron = "0 9 * * 1-5", dir = "/home/youruser/Pictures/Wallpapers/Work" }, { cron = "0 9 * * 0,6", dir = "/home/youruser/Pictures/Wallpapers/Relax" }]
+
+# Caps for the daemon and detached renderer processes: address space in MiB (above 100)
+# and CPU time in percent of one core (above 1; needs a delegated cgroup v2 tree).
+# max_renderer_memory_mb = 2048
+# max_renderer_cpu_percent = 50
+
+# Renderer processes the daemon took over (daemon --watch-pidfile; the daemon's own renderers
+# are threads and are not watched) are checked every resource_check_interval_seconds
+# (default 30) and stopped once they have used more CPU than max_renderer_cpu_percent_sustained
+# (percent of one core) or more resident memory than max_renderer_rss_mb (MiB) for longer than
+# resource_violation_grace_seconds (default 60).
+# max_renderer_cpu_percent_sustained = 90.0
+# max_renderer_rss_mb = 1024
+# resource_check_interval_seconds = 30
+# resource_violation_grace_seconds = 60
+
+# Scheduling priority of those processes: nice value (-20 to 19, lower runs first; negative
+# values need CAP_SYS_NICE) and I/O class (1 realtime, 2 best-effort, 3 idle).
+# renderer_nice = 10
+# renderer_ionice_class = 3
+"#;
+
 impl Default for Config {
     fn default() -> Self {
         Self {
+            config_includes: None,
             wallpaper_dir: default_wallpaper_dir(),
+            wallpaper_dirs: None,
             monitor_wallpaper_dirs: None,
+            monitor_wallpaper_dir_lists: None,
+            resolution_dirs: None,
+            recursive_depth: None,
+            min_wallpaper_size_bytes: None,
+            max_wallpaper_size_bytes: None,
             monitor_fit_modes: None,
             monitor: None,
+            monitor_regex: None,
             monitors: None,
+            exclude_monitors: None,
+            monitor_poll_interval_seconds: Some(10),
             video_fps: Some(60),
+            battery_fps: Some(24),
+            ac_fps: Some(60),
+            video_start_time_ms: None,
+            loop_fallback_path: None,
+            video_fallback_image: None,
+            auto_rotate: None,
+            gpu_index: None,
+            mute_audio: Some(true),
+            allow_video: Some(true),
+            allow_images: Some(true),
+            brightness: None,
+            contrast: None,
+            effects: None,
+            blur_sigma: None,
+            watermark: None,
+            video_retry_count: None,
             rotation_seconds: Some(300),
+            rotation_ms: None,
             daemon_interval_seconds: Some(300),
             fit_mode: Some(FitMode::Cover),
+            transition: None,
+            monitor_transitions: None,
+            transition_duration_ms: None,
+            transition_curve: None,
+            scale_filter: None,
+            rotation_mode: None,
+            picker_script: None,
+            no_overlap: None,
+            start_delay_seconds: None,
+            daemon_startup_delay_ms: None,
+            startup_delay_ms: None,
+            monitor_start_delay_ms: None,
+            rotation_stagger_seconds: None,
+            rotation_jitter_seconds: None,
+            compositor_type: None,
+            visibility_hysteresis_ms: None,
+            default_offset: None,
+            default_focus_area: None,
+            tile_size: None,
+            image_slideshow_delay_ms: None,
+            config_watch: None,
+            watch_wallpaper_dirs: None,
+            fifo_path: None,
+            workspace_wallpapers: None,
+            workspace_wallpaper_dirs: None,
+            metrics_port: None,
+            max_pooled_buffers: None,
+            sd_notify_ready: None,
+            pid_file: None,
+            lock_file: None,
+            wayland_display: None,
+            xdg_runtime_dir: None,
+            layer_namespace: None,
+            anchor: None,
+            startup_wallpaper: None,
+            startup_fit: None,
+            startup_fps: None,
+            restore_on_restart: None,
+            shutdown_wallpaper: None,
+            on_change_command: None,
+            brightness_threshold: default_brightness_threshold(),
+            schedule: None,
+            max_renderer_memory_mb: None,
+            max_renderer_cpu_percent: None,
+            max_renderer_cpu_percent_sustained: None,
+            max_renderer_rss_mb: None,
+            resource_check_interval_seconds: None,
+            resource_violation_grace_seconds: None,
+            renderer_nice: Some(10),
+            renderer_ionice_class: Some(3),
         }
     }
 }
@@ -56,7 +573,18 @@ fn default_wallpaper_dir() -> PathBuf {
     PathBuf::from(home).join("Pictures").join("Wallpapers")
 }
 
+fn default_brightness_threshold() -> Option<f32> {
+    Some(crate::hooks::DEFAULT_BRIGHTNESS_THRESHOLD)
+}
+
 impl Config {
+    /// `rotation_ms`, else `rotation_seconds`, not yet clamped to [`MIN_ROTATION_INTERVAL`].
+    pub fn rotation_interval(&self) -> Option<Duration> {
+        self.rotation_ms
+            .map(Duration::from_millis)
+            .or(self.rotation_seconds.map(Duration::from_secs))
+    }
+
     pub fn load_or_default(config_path: Option<&Path>) -> Result<Self> {
         let path = if let Some(path) = config_path {
             path.to_path_buf()
@@ -70,15 +598,365 @@ impl Config {
             return Ok(Self::default());
         };
 
-        let content = fs::read_to_string(&path)
-            .with_context(|| format!("failed to read config file: {}", path.display()))?;
+        let table = load_table_with_includes(&path, 0)?;
+        let cfg: Self = toml::Value::Table(table)
+            .try_into()
+            .with_context(|| format!("failed to parse TOML config: {}", path.display()))?;
+        if let Some(pattern) = &cfg.monitor_regex {
+            regex::Regex::new(pattern).with_context(|| {
+                format!("invalid monitor_regex '{}' in {}", pattern, path.display())
+            })?;
+        }
+        Ok(cfg)
+    }
+}
 
-        toml::from_str(&content)
-            .with_context(|| format!("failed to parse TOML config: {}", path.display()))
+/// `path` with the files in its `config_includes` merged over it, each of them
+/// with its own includes first.
+fn load_table_with_includes(path: &Path, depth: usize) -> Result<toml::Table> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("failed to read config file: {}", path.display()))?;
+    let mut table: toml::Table = toml::from_str(&content)
+        .with_context(|| format!("failed to parse TOML config: {}", path.display()))?;
+
+    let includes: Vec<PathBuf> = match table.get("config_includes") {
+        Some(value) => value.clone().try_into().with_context(|| {
+            format!("config_includes must be a list of paths: {}", path.display())
+        })?,
+        None => return Ok(table),
+    };
+    if depth >= MAX_INCLUDE_DEPTH {
+        warning!(
+            "warning: config_includes nested more than {} levels deep, ignored in {}",
+            MAX_INCLUDE_DEPTH,
+            path.display()
+        );
+        return Ok(table);
     }
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    for include in includes {
+        let include = dir.join(include);
+        if !include.exists() {
+            debug!("skipping missing config include {}", include.display());
+            continue;
+        }
+        merge_tables(&mut table, load_table_with_includes(&include, depth + 1)?);
+    }
+    Ok(table)
+}
+
+/// Values in `overlay` win, except that tables (such as `monitor_wallpaper_dirs`)
+/// are merged key by key.
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => {
+                merge_tables(base_table, overlay_table)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found by `validate-config`, located by 1-based line and column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigIssue {
+    pub severity: Severity,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
 }
 
-fn default_config_path() -> Option<PathBuf> {
+impl ConfigIssue {
+    fn warning(content: &str, key: &str, message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            line: key_line(content, key),
+            column: None,
+            message,
+        }
+    }
+}
+
+/// Parses `content` as a config and reports the parse error, if any, or
+/// every semantic warning.
+pub fn validate_source(content: &str) -> Vec<ConfigIssue> {
+    let cfg: Config = match toml::from_str(content) {
+        Ok(cfg) => cfg,
+        Err(error) => {
+            let position = error.span().map(|span| line_column(content, span.start));
+            return vec![ConfigIssue {
+                severity: Severity::Error,
+                line: position.map(|(line, _)| line),
+                column: position.map(|(_, column)| column),
+                message: error.message().trim().to_string(),
+            }];
+        }
+    };
+
+    let mut issues = Vec::new();
+    for (key, value) in [
+        ("video_fps", cfg.video_fps),
+        ("battery_fps", cfg.battery_fps),
+        ("ac_fps", cfg.ac_fps),
+    ] {
+        if let Some(fps) = value.filter(|fps| !(1..=240).contains(fps)) {
+            issues.push(ConfigIssue::warning(
+                content,
+                key,
+                format!("{} must be between 1 and 240, got {}", key, fps),
+            ));
+        }
+    }
+    if let Some(error) = cfg
+        .monitor_regex
+        .as_deref()
+        .and_then(|pattern| regex::Regex::new(pattern).err())
+    {
+        issues.push(ConfigIssue {
+            severity: Severity::Error,
+            line: key_line(content, "monitor_regex"),
+            column: None,
+            message: format!("invalid monitor_regex: {}", error),
+        });
+    }
+    if let (Some(min), Some(max)) = (cfg.min_wallpaper_size_bytes, cfg.max_wallpaper_size_bytes) {
+        if min > max {
+            issues.push(ConfigIssue::warning(
+                content,
+                "min_wallpaper_size_bytes",
+                format!(
+                    "min_wallpaper_size_bytes ({}) is above max_wallpaper_size_bytes ({})",
+                    min, max
+                ),
+            ));
+        }
+    }
+    if let Some(threshold) = cfg
+        .brightness_threshold
+        .filter(|threshold| !(0.0..=1.0).contains(threshold))
+    {
+        issues.push(ConfigIssue::warning(
+            content,
+            "brightness_threshold",
+            format!("brightness_threshold must be between 0.0 and 1.0, got {}", threshold),
+        ));
+    }
+    for (key, value, range) in [
+        ("brightness", cfg.brightness, 0.0..=1.0),
+        ("contrast", cfg.contrast, 0.0..=2.0),
+        ("blur_sigma", cfg.blur_sigma, 0.0..=effects::MAX_BLUR_SIGMA),
+    ] {
+        if let Some(value) = value.filter(|value| !range.contains(value)) {
+            issues.push(ConfigIssue::warning(
+                content,
+                key,
+                format!(
+                    "{} must be between {:.1} and {:.1}, got {}",
+                    key,
+                    range.start(),
+                    range.end(),
+                    value
+                ),
+            ));
+        }
+    }
+    if let Some(area) = cfg.default_focus_area.filter(|area| !is_valid_focus_area(area)) {
+        issues.push(ConfigIssue::warning(
+            content,
+            "default_focus_area",
+            format!(
+                "default_focus_area {:?} is not an [x, y, width, height] area within 0.0 to 1.0",
+                area
+            ),
+        ));
+    }
+    for (key, value) in [
+        ("rotation_seconds", cfg.rotation_seconds),
+        ("daemon_interval_seconds", cfg.daemon_interval_seconds),
+    ] {
+        if value == Some(0) {
+            issues.push(ConfigIssue::warning(
+                content,
+                key,
+                format!("{} must be at least 1", key),
+            ));
+        }
+    }
+
+    if let Some(ms) = cfg
+        .rotation_ms
+        .filter(|&ms| ms < MIN_ROTATION_INTERVAL.as_millis() as u64)
+    {
+        issues.push(ConfigIssue::warning(
+            content,
+            "rotation_ms",
+            format!("rotation_ms below 100 is raised to 100, got {}", ms),
+        ));
+    }
+
+    let mut dirs: Vec<(&str, &Path)> = Vec::new();
+    // The default wallpaper_dir is only checked when it was set explicitly.
+    if key_line(content, "wallpaper_dir").is_some() {
+        dirs.push(("wallpaper_dir", &cfg.wallpaper_dir));
+    }
+    dirs.extend(
+        cfg.wallpaper_dirs
+            .iter()
+            .flatten()
+            .map(|dir| ("wallpaper_dirs", dir.as_path())),
+    );
+    let mut monitor_dirs: Vec<(&str, &Path)> = cfg
+        .monitor_wallpaper_dirs
+        .iter()
+        .flatten()
+        .map(|(_, dir)| ("monitor_wallpaper_dirs", dir.as_path()))
+        .chain(
+            cfg.monitor_wallpaper_dir_lists
+                .iter()
+                .flatten()
+                .flat_map(|(_, dirs)| dirs)
+                .map(|dir| ("monitor_wallpaper_dir_lists", dir.as_path())),
+        )
+        .collect();
+    monitor_dirs.sort();
+    dirs.extend(monitor_dirs);
+    dirs.extend(
+        cfg.resolution_dirs
+            .iter()
+            .flatten()
+            .map(|rule| ("resolution_dirs", rule.dir.as_path())),
+    );
+    dirs.extend(
+        cfg.schedule
+            .iter()
+            .flatten()
+            .filter_map(|rule| rule.dir.as_deref())
+            .map(|dir| ("schedule", dir)),
+    );
+    for (key, dir) in dirs {
+        if !dir.is_dir() {
+            issues.push(ConfigIssue::warning(
+                content,
+                key,
+                format!("{}: directory does not exist: {}", key, dir.display()),
+            ));
+        }
+    }
+    if let Some(mb) = cfg
+        .max_renderer_memory_mb
+        .filter(|&mb| mb <= crate::limits::MIN_MEMORY_MB)
+    {
+        issues.push(ConfigIssue::warning(
+            content,
+            "max_renderer_memory_mb",
+            format!(
+                "max_renderer_memory_mb must be more than {}, got {}",
+                crate::limits::MIN_MEMORY_MB,
+                mb
+            ),
+        ));
+    }
+    if let Some(percent) = cfg
+        .max_renderer_cpu_percent
+        .filter(|&percent| percent <= crate::limits::MIN_CPU_PERCENT)
+    {
+        issues.push(ConfigIssue::warning(
+            content,
+            "max_renderer_cpu_percent",
+            format!(
+                "max_renderer_cpu_percent must be more than {}, got {}",
+                crate::limits::MIN_CPU_PERCENT,
+                percent
+            ),
+        ));
+    }
+    if let Some(percent) = cfg
+        .max_renderer_cpu_percent_sustained
+        .filter(|&percent| percent.is_nan() || percent <= 0.0)
+    {
+        issues.push(ConfigIssue::warning(
+            content,
+            "max_renderer_cpu_percent_sustained",
+            format!("max_renderer_cpu_percent_sustained must be above 0, got {}", percent),
+        ));
+    }
+    if cfg.max_renderer_rss_mb == Some(0) {
+        issues.push(ConfigIssue::warning(
+            content,
+            "max_renderer_rss_mb",
+            "max_renderer_rss_mb must be above 0".to_string(),
+        ));
+    }
+    if let Some(nice) = cfg
+        .renderer_nice
+        .filter(|nice| !crate::limits::NICE_RANGE.contains(nice))
+    {
+        issues.push(ConfigIssue::warning(
+            content,
+            "renderer_nice",
+            format!("renderer_nice must be between -20 and 19, got {}", nice),
+        ));
+    }
+    if let Some(class) = cfg
+        .renderer_ionice_class
+        .filter(|class| !crate::limits::IONICE_CLASSES.contains(class))
+    {
+        issues.push(ConfigIssue::warning(
+            content,
+            "renderer_ionice_class",
+            format!("renderer_ionice_class must be 1, 2 or 3, got {}", class),
+        ));
+    }
+    for rule in cfg.schedule.iter().flatten() {
+        if let Err(error) = crate::scheduler::CompiledRule::new(rule) {
+            issues.push(ConfigIssue::warning(
+                content,
+                "schedule",
+                format!("schedule rule '{}': {}", rule.cron, error),
+            ));
+        }
+    }
+
+    issues
+}
+
+/// 1-based line and column (in characters) of a byte offset.
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Line where `key` is assigned at the top level or opened as a `[key]` table.
+fn key_line(content: &str, key: &str) -> Option<usize> {
+    content
+        .lines()
+        .position(|line| {
+            let line = line.trim_start();
+            if line.trim_end() == format!("[{}]", key) {
+                return true;
+            }
+            line.strip_prefix(key)
+                .map(|rest| rest.trim_start().starts_with('='))
+                .unwrap_or(false)
+        })
+        .map(|index| index + 1)
+}
+
+pub fn default_config_path() -> Option<PathBuf> {
     let base = env::var("XDG_CONFIG_HOME")
         .ok()
         .map(PathBuf::from)
@@ -86,3 +964,119 @@ fn default_config_path() -> Option<PathBuf> {
 
     Some(base.join("papdieo").join("config.toml"))
 }
+
+/// `$XDG_DATA_HOME/papdieo`, or `~/.local/share/papdieo`.
+pub fn default_data_dir() -> Option<PathBuf> {
+    let base = env::var("XDG_DATA_HOME")
+        .ok()
+        .map(PathBuf::from)
+        .or_else(|| env::var("HOME").ok().map(|h| PathBuf::from(h).join(".local/share")))?;
+
+    Some(base.join("papdieo"))
+}
+
+/// `[x, y, w, h]` fractions with a non-empty area inside the unit square.
+pub fn is_valid_focus_area(area: &[f32; 4]) -> bool {
+    let [x, y, w, h] = *area;
+    // Slack for sums such as 0.1 + 0.9 that land a rounding step above 1.0.
+    let fits = |start: f32, length: f32| length > 0.0 && start + length <= 1.0 + f32::EPSILON;
+    area.iter().all(|v| (0.0..=1.0).contains(v)) && fits(x, w) && fits(y, h)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_source, Config, Severity, CONFIG_TEMPLATE};
+    use std::{fs, path::PathBuf};
+
+    #[test]
+    fn template_parses_with_every_setting_uncommented() {
+        let uncommented: String = CONFIG_TEMPLATE
+            .lines()
+            .map(|line| match line.strip_prefix("# ") {
+                Some(setting) if setting.contains(" = ") => setting,
+                _ => line,
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let cfg: Config = toml::from_str(&uncommented).expect("uncommented template should parse");
+        assert_eq!(cfg.video_fps, Some(60));
+        assert_eq!(cfg.default_offset, Some((0, 0)));
+        assert!(cfg.monitor_wallpaper_dir_lists.is_some());
+    }
+
+    #[test]
+    fn includes_override_settings_and_merge_tables() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let main = dir.join("config.toml");
+        fs::write(
+            &main,
+            "config_includes = [\"machine.toml\", \"missing.toml\"]\n\
+             wallpaper_dir = \"/walls\"\n\
+             video_fps = 30\n\
+             monitor_wallpaper_dirs = { DP-1 = \"/a\", DP-2 = \"/b\" }\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("machine.toml"),
+            "video_fps = 60\n[monitor_wallpaper_dirs]\nDP-2 = \"/c\"\n",
+        )
+        .unwrap();
+        let cyclic = dir.join("cyclic.toml");
+        fs::write(&cyclic, "config_includes = [\"cyclic.toml\"]\nvideo_fps = 24\n").unwrap();
+
+        let cfg = Config::load_or_default(Some(&main)).unwrap();
+        let cyclic_cfg = Config::load_or_default(Some(&cyclic)).unwrap();
+
+        assert_eq!(cfg.wallpaper_dir, PathBuf::from("/walls"));
+        assert_eq!(cfg.video_fps, Some(60));
+        let dirs = cfg.monitor_wallpaper_dirs.unwrap();
+        assert_eq!(dirs["DP-1"], PathBuf::from("/a"));
+        assert_eq!(dirs["DP-2"], PathBuf::from("/c"));
+        assert_eq!(cyclic_cfg.video_fps, Some(24));
+    }
+
+    #[test]
+    fn startup_stagger_ms_is_an_alias_for_monitor_start_delay_ms() {
+        let cfg: Config = toml::from_str("startup_stagger_ms = 250").unwrap();
+        assert_eq!(cfg.monitor_start_delay_ms, Some(250));
+        let cfg: Config = toml::from_str("monitor_start_delay_ms = 400").unwrap();
+        assert_eq!(cfg.monitor_start_delay_ms, Some(400));
+    }
+
+    #[test]
+    fn a_missing_brightness_threshold_matches_the_default() {
+        let cfg: Config = toml::from_str("").unwrap();
+        assert_eq!(cfg.brightness_threshold, Config::default().brightness_threshold);
+        let cfg: Config = toml::from_str("brightness_threshold = 0.7").unwrap();
+        assert_eq!(cfg.brightness_threshold, Some(0.7));
+    }
+
+    #[test]
+    fn validation_locates_type_errors() {
+        let issues = validate_source("video_fps = 30\nfit_mode = \"zoom\"\n");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Error);
+        assert_eq!(issues[0].line, Some(2));
+        assert_eq!(issues[0].column, Some(12));
+    }
+
+    #[test]
+    fn validation_warns_about_ranges_and_missing_dirs() {
+        let content = "rotation_seconds = 0\nvideo_fps = 500\nwallpaper_dir = \"/nonexistent/papdieo\"\n";
+        let issues = validate_source(content);
+        let lines: Vec<_> = issues.iter().map(|issue| issue.line).collect();
+        assert!(issues.iter().all(|issue| issue.severity == Severity::Warning));
+        assert_eq!(lines, vec![Some(2), Some(1), Some(3)]);
+
+        assert!(validate_source("video_fps = 240\n").is_empty());
+        assert_eq!(validate_source("battery_fps = 0\nac_fps = 60\n").len(), 1);
+
+        let issues = validate_source("video_fps = 30\nmonitor_regex = \"^DP-(1\"\n");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Error);
+        assert_eq!(issues[0].line, Some(2));
+        assert!(validate_source("monitor_regex = \"^DP-[0-9]+$\"\n").is_empty());
+    }
+}
@@ -0,0 +1,174 @@
+//! `papdieo config get|set`: read or change one config key from scripts.
+//!
+//! Keys are top-level settings (`video_fps`) or one entry of a per-monitor map
+//! (`monitor_wallpaper_dirs.DP-1`). `set` edits the file through `toml_edit`,
+//! so comments and formatting survive.
+
+use crate::config::{Config, CONFIG_TEMPLATE};
+use anyhow::{anyhow, Context, Result};
+use std::{fs, path::Path};
+use toml_edit::{DocumentMut, InlineTable, Item, Value};
+
+/// Every key the template documents, in template order.
+pub fn valid_keys() -> Vec<&'static str> {
+    CONFIG_TEMPLATE
+        .lines()
+        .filter_map(|line| line.strip_prefix("# ")?.split_once(" = "))
+        .map(|(key, _)| key)
+        .filter(|key| !key.contains(' '))
+        .collect()
+}
+
+/// Effective value of `key`, defaults included. Strings are printed without quotes.
+pub fn get(cfg: &Config, key: &str) -> Result<String> {
+    let (name, entry) = split_key(key)?;
+    let table = toml::Table::try_from(cfg).context("failed to serialize config")?;
+    let value = table
+        .get(name)
+        .and_then(|value| match entry {
+            Some(entry) => value.as_table()?.get(entry),
+            None => Some(value),
+        })
+        .ok_or_else(|| anyhow!("{} is not set", key))?;
+
+    Ok(match value {
+        toml::Value::String(text) => text.clone(),
+        other => other.to_string(),
+    })
+}
+
+/// Writes `key = value` into the config file at `path`, creating it if needed.
+pub fn set(path: &Path, key: &str, value: &str) -> Result<()> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(error) => {
+            return Err(error).with_context(|| format!("failed to read {}", path.display()))
+        }
+    };
+    let updated = set_in_document(&content, key, value)?;
+
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    fs::write(path, updated).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// The value is read as TOML first (`60`, `true`, `[0, 0]`) and as a plain
+/// string otherwise, whichever gives a valid config.
+fn set_in_document(content: &str, key: &str, value: &str) -> Result<String> {
+    let (name, entry) = split_key(key)?;
+    let mut doc: DocumentMut = content
+        .parse()
+        .context("config is not valid TOML, fix it by hand first")?;
+
+    let candidates = value
+        .parse::<Value>()
+        .ok()
+        .into_iter()
+        .chain([Value::from(value)]);
+    let mut last_error = None;
+    for candidate in candidates {
+        let mut attempt = doc.clone();
+        insert(&mut attempt, name, entry, candidate)?;
+        let updated = attempt.to_string();
+        match toml::from_str::<Config>(&updated) {
+            Ok(_) => {
+                doc = attempt;
+                return Ok(doc.to_string());
+            }
+            Err(error) => last_error = Some(error),
+        }
+    }
+
+    let error = last_error.map(|e| e.message().trim().to_string()).unwrap_or_default();
+    Err(anyhow!("invalid value for {}: {}", key, error))
+}
+
+fn insert(doc: &mut DocumentMut, name: &str, entry: Option<&str>, value: Value) -> Result<()> {
+    let Some(entry) = entry else {
+        match doc.get_mut(name) {
+            Some(Item::Value(existing)) => {
+                // Keep the comment that trails the old value.
+                let decor = existing.decor().clone();
+                *existing = value;
+                *existing.decor_mut() = decor;
+            }
+            _ => {
+                doc.insert(name, Item::Value(value));
+            }
+        }
+        return Ok(());
+    };
+
+    let item = doc
+        .entry(name)
+        .or_insert_with(|| Item::Value(Value::InlineTable(InlineTable::new())));
+    let table = item
+        .as_table_like_mut()
+        .ok_or_else(|| anyhow!("{} is not a table in the config", name))?;
+    table.insert(entry, Item::Value(value));
+    Ok(())
+}
+
+/// Splits `name.entry` and rejects keys the config does not have.
+fn split_key(key: &str) -> Result<(&str, Option<&str>)> {
+    let (name, entry) = match key.split_once('.') {
+        Some((name, entry)) => (name, Some(entry)),
+        None => (key, None),
+    };
+    let keys = valid_keys();
+    if !keys.contains(&name) {
+        return Err(anyhow!(
+            "unknown config key '{}'; valid keys: {}",
+            name,
+            keys.join(", ")
+        ));
+    }
+    if entry.is_some_and(|entry| entry.is_empty()) {
+        return Err(anyhow!("missing map entry after '{}.'", name));
+    }
+    Ok((name, entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get, set_in_document, valid_keys};
+    use crate::config::Config;
+
+    #[test]
+    fn get_reads_effective_values_and_map_entries() {
+        assert!(valid_keys().contains(&"monitor_wallpaper_dirs"));
+
+        let cfg: Config = toml::from_str(
+            "monitor_wallpaper_dirs = { DP-1 = \"/walls/work\" }\nfit_mode = \"contain\"\n",
+        )
+        .unwrap();
+        assert_eq!(get(&cfg, "fit_mode").unwrap(), "contain");
+        assert_eq!(get(&cfg, "monitor_wallpaper_dirs.DP-1").unwrap(), "/walls/work");
+        assert!(get(&cfg, "monitor").is_err());
+        assert!(get(&cfg, "wallpaper_folder").unwrap_err().to_string().contains("valid keys"));
+    }
+
+    #[test]
+    fn set_keeps_comments_and_picks_the_valid_type() {
+        let content = "# my wallpapers\nvideo_fps = 60 # smooth\nmonitor_fit_modes = { DP-1 = \"cover\" }\n";
+
+        let updated = set_in_document(content, "video_fps", "30").unwrap();
+        assert_eq!(
+            updated,
+            "# my wallpapers\nvideo_fps = 30 # smooth\nmonitor_fit_modes = { DP-1 = \"cover\" }\n"
+        );
+
+        let updated = set_in_document(&updated, "monitor", "DP-2").unwrap();
+        assert!(updated.ends_with("monitor = \"DP-2\"\n"));
+
+        let updated = set_in_document(&updated, "monitor_fit_modes.HDMI-A-1", "tile").unwrap();
+        let cfg: Config = toml::from_str(&updated).unwrap();
+        assert_eq!(cfg.monitor_fit_modes.unwrap().len(), 2);
+
+        assert!(set_in_document(content, "video_fps", "fast").is_err());
+        assert!(set_in_document(content, "fit_mode", "zoom").is_err());
+    }
+}
@@ -0,0 +1,148 @@
+//! Detects edits to the config file for the daemon.
+//!
+//! With inotify the config's directory is watched for `IN_CLOSE_WRITE` and
+//! `IN_MOVED_TO` on the config file name, which also catches editors that save
+//! by renaming a temp file over the original. Otherwise the mtime is polled
+//! once per second.
+
+use crate::output::warning;
+use inotify::{Inotify, WatchMask};
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Lets editors finish multi-step saves before the config is re-read.
+const SETTLE_DELAY: Duration = Duration::from_millis(100);
+
+pub struct ConfigWatcher {
+    path: Option<PathBuf>,
+    observed_mtime: Option<SystemTime>,
+    events: Option<Receiver<()>>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: Option<&Path>, use_inotify: bool) -> Self {
+        let events = match path {
+            Some(path) if use_inotify => match spawn_inotify_watch(path) {
+                Ok(events) => Some(events),
+                Err(error) => {
+                    warning!(
+                        "warning: inotify config watch unavailable, polling instead: {}",
+                        error
+                    );
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        Self {
+            path: path.map(Path::to_path_buf),
+            observed_mtime: path.and_then(modified_time),
+            events,
+        }
+    }
+
+    /// Sleeps for up to `timeout`, returning `true` as soon as the config changed.
+    pub fn wait(&mut self, timeout: Duration) -> bool {
+        if let Some(events) = &self.events {
+            match events.recv_timeout(timeout) {
+                Ok(()) => {
+                    thread::sleep(SETTLE_DELAY);
+                    while events.try_recv().is_ok() {}
+                    return true;
+                }
+                Err(RecvTimeoutError::Timeout) => return false,
+                Err(RecvTimeoutError::Disconnected) => {
+                    warning!("warning: inotify config watch stopped, polling instead");
+                    self.events = None;
+                    return false;
+                }
+            }
+        }
+
+        let mut elapsed = Duration::ZERO;
+        while elapsed < timeout {
+            let sleep_for = timeout.saturating_sub(elapsed).min(POLL_INTERVAL);
+            thread::sleep(sleep_for);
+            elapsed += sleep_for;
+
+            let Some(path) = self.path.as_deref() else {
+                continue;
+            };
+            let current_mtime = modified_time(path);
+            if current_mtime != self.observed_mtime {
+                self.observed_mtime = current_mtime;
+                return true;
+            }
+        }
+        false
+    }
+}
+
+fn spawn_inotify_watch(path: &Path) -> std::io::Result<Receiver<()>> {
+    let file_name: OsString = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::other("config path has no file name"))?
+        .to_os_string();
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let mut inotify = Inotify::init()?;
+    inotify
+        .watches()
+        .add(&dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)?;
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = [0u8; 4096];
+        loop {
+            let Ok(events) = inotify.read_events_blocking(&mut buffer) else {
+                return;
+            };
+            let touched = events
+                .filter_map(|event| event.name)
+                .any(|name| name == file_name.as_os_str());
+            if touched && sender.send(()).is_err() {
+                return;
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).ok()?.modified().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConfigWatcher;
+    use std::time::Duration;
+
+    #[test]
+    fn inotify_reports_rename_over_config_and_ignores_other_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let config = dir.join("config.toml");
+        std::fs::write(&config, "video_fps = 30\n").unwrap();
+
+        let mut watcher = ConfigWatcher::new(Some(&config), true);
+        assert!(watcher.events.is_some());
+
+        std::fs::write(dir.join("other.toml"), "").unwrap();
+        assert!(!watcher.wait(Duration::from_millis(200)));
+
+        let temp = dir.join("config.toml.tmp");
+        std::fs::write(&temp, "video_fps = 60\n").unwrap();
+        std::fs::rename(&temp, &config).unwrap();
+        assert!(watcher.wait(Duration::from_secs(5)));
+    }
+}
@@ -0,0 +1,357 @@
+//! Zero-copy video presentation through `zwp_linux_dmabuf_v1`.
+//!
+//! When a decoder hands out DMA-BUF backed frames (for example VA-API with
+//! `video/x-raw(memory:DMABuf)` caps), the frame's fd is imported as a
+//! `wl_buffer` and scaled by the compositor through `wp_viewporter`, so no
+//! CPU copy happens. Anything that cannot be imported is reported as
+//! `Unsupported` and the caller falls back to the shm `FrameRenderer`.
+
+use crate::config::FitMode;
+use anyhow::{anyhow, Result};
+use gstreamer as gst;
+use gstreamer_allocators as gst_allocators;
+use gstreamer_video as gst_video;
+use std::{
+    os::fd::BorrowedFd,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use wayland_client::{
+    protocol::{wl_buffer, wl_surface},
+    Dispatch, QueueHandle,
+};
+use wayland_protocols::wp::{
+    linux_dmabuf::zv1::client::{zwp_linux_buffer_params_v1, zwp_linux_dmabuf_v1},
+    viewporter::client::{wp_viewport, wp_viewporter},
+};
+
+const DRM_FORMAT_XRGB8888: u32 = fourcc(b"XR24");
+const DRM_FORMAT_NV12: u32 = fourcc(b"NV12");
+const DRM_FORMAT_MOD_LINEAR: u64 = 0;
+/// Implicit modifier: the driver decides the layout, as legacy DMABuf caps do.
+pub const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+
+/// Frames the compositor may hold before we wait for a release.
+const MAX_IN_FLIGHT: usize = 3;
+
+const fn fourcc(code: &[u8; 4]) -> u32 {
+    u32::from_le_bytes(*code)
+}
+
+pub enum Presented {
+    Frame,
+    /// Every imported buffer is still held by the compositor.
+    Busy,
+    Unsupported,
+}
+
+struct InFlight {
+    buffer: wl_buffer::WlBuffer,
+    busy: Arc<AtomicBool>,
+    // Keeps the decoder's DMA-BUF alive until the compositor releases it.
+    _sample: gst::Sample,
+}
+
+pub struct DmabufRenderer {
+    dmabuf: zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1,
+    viewport: Option<wp_viewport::WpViewport>,
+    formats: Vec<(u32, u64)>,
+    output_size: (u32, u32),
+    fit_mode: FitMode,
+    in_flight: Vec<InFlight>,
+    /// The buffer scale shm frames are drawn at, restored when they take over.
+    shm_scale: i32,
+    /// Whether the surface shows DMA-BUF frames, with their viewport and buffer scale 1.
+    presenting: bool,
+}
+
+impl DmabufRenderer {
+    pub fn new<D>(
+        dmabuf: zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1,
+        viewporter: Option<&wp_viewporter::WpViewporter>,
+        formats: Vec<(u32, u64)>,
+        surface: &wl_surface::WlSurface,
+        (output_size, shm_scale): ((u32, u32), i32),
+        fit_mode: FitMode,
+        qh: &QueueHandle<D>,
+    ) -> Self
+    where
+        D: Dispatch<wp_viewport::WpViewport, ()> + 'static,
+    {
+        Self {
+            dmabuf,
+            viewport: viewporter.map(|v| v.get_viewport(surface, qh, ())),
+            formats,
+            output_size,
+            fit_mode,
+            in_flight: Vec::new(),
+            shm_scale,
+            presenting: false,
+        }
+    }
+
+    /// Whether the pipeline should bother asking the decoder for DMA-BUF output.
+    pub fn can_scale(&self) -> bool {
+        self.viewport.is_some()
+            && matches!(self.fit_mode, FitMode::Stretch | FitMode::Fill | FitMode::Cover)
+    }
+
+    pub fn present_sample<D>(
+        &mut self,
+        sample: &gst::Sample,
+        surface: &wl_surface::WlSurface,
+        qh: &QueueHandle<D>,
+    ) -> Result<Presented>
+    where
+        D: Dispatch<zwp_linux_buffer_params_v1::ZwpLinuxBufferParamsV1, ()>
+            + Dispatch<wl_buffer::WlBuffer, Arc<AtomicBool>>
+            + 'static,
+    {
+        self.reap_released();
+        if self.in_flight.len() >= MAX_IN_FLIGHT {
+            return Ok(Presented::Busy);
+        }
+
+        let Some(frame) = DmabufFrame::from_sample(sample)? else {
+            return Ok(Presented::Unsupported);
+        };
+        let Some(modifier) = self.pick_modifier(frame.fourcc) else {
+            return Ok(Presented::Unsupported);
+        };
+        let source = if frame.size == self.output_size {
+            Some((0.0, 0.0, frame.size.0 as f64, frame.size.1 as f64))
+        } else if self.viewport.is_some() {
+            viewport_source(self.fit_mode, frame.size, self.output_size)
+        } else {
+            None
+        };
+        let Some(source) = source else {
+            return Ok(Presented::Unsupported);
+        };
+
+        let params = self.dmabuf.create_params(qh, ());
+        // SAFETY: the fd belongs to the sample's memory, which `InFlight`
+        // keeps alive until the compositor releases the buffer.
+        let fd = unsafe { BorrowedFd::borrow_raw(frame.fd) };
+        for (plane, (offset, stride)) in frame.planes.iter().enumerate() {
+            params.add(
+                fd,
+                plane as u32,
+                *offset,
+                *stride,
+                (modifier >> 32) as u32,
+                modifier as u32,
+            );
+        }
+        let busy = Arc::new(AtomicBool::new(true));
+        let buffer = params.create_immed(
+            frame.size.0 as i32,
+            frame.size.1 as i32,
+            frame.fourcc,
+            zwp_linux_buffer_params_v1::Flags::empty(),
+            qh,
+            busy.clone(),
+        );
+        params.destroy();
+
+        if !self.presenting && self.shm_scale != 1 {
+            // The viewport source is in buffer pixels only at scale 1.
+            surface.set_buffer_scale(1);
+        }
+        if let Some(viewport) = &self.viewport {
+            let (x, y, w, h) = source;
+            viewport.set_source(x, y, w, h);
+            viewport.set_destination(self.output_size.0 as i32, self.output_size.1 as i32);
+        }
+        self.presenting = true;
+
+        surface.attach(Some(&buffer), 0, 0);
+        surface.damage_buffer(0, 0, frame.size.0 as i32, frame.size.1 as i32);
+        surface.commit();
+
+        self.in_flight.push(InFlight {
+            buffer,
+            busy,
+            _sample: sample.clone(),
+        });
+        Ok(Presented::Frame)
+    }
+
+    /// The surface size, in logical pixels, frames are scaled to after the
+    /// output changed, e.g. was rotated.
+    pub fn set_output_size(&mut self, output_size: (u32, u32)) {
+        self.output_size = output_size;
+    }
+
+    /// Clears the viewport and restores the buffer scale so shm frames
+    /// rendered at the output's physical size display 1:1.
+    pub fn reset_viewport(&mut self, surface: &wl_surface::WlSurface) {
+        if !self.presenting {
+            return;
+        }
+        if let Some(viewport) = &self.viewport {
+            viewport.set_source(-1.0, -1.0, -1.0, -1.0);
+            viewport.set_destination(-1, -1);
+        }
+        if self.shm_scale != 1 {
+            surface.set_buffer_scale(self.shm_scale);
+        }
+        self.presenting = false;
+    }
+
+    fn reap_released(&mut self) {
+        self.in_flight.retain(|frame| {
+            let held = frame.busy.load(Ordering::Acquire);
+            if !held {
+                frame.buffer.destroy();
+            }
+            held
+        });
+    }
+
+    fn pick_modifier(&self, fourcc: u32) -> Option<u64> {
+        [DRM_FORMAT_MOD_INVALID, DRM_FORMAT_MOD_LINEAR]
+            .into_iter()
+            .find(|modifier| self.formats.contains(&(fourcc, *modifier)))
+    }
+}
+
+impl Drop for DmabufRenderer {
+    fn drop(&mut self) {
+        for frame in self.in_flight.drain(..) {
+            frame.buffer.destroy();
+        }
+        if let Some(viewport) = self.viewport.take() {
+            viewport.destroy();
+        }
+    }
+}
+
+struct DmabufFrame {
+    fd: i32,
+    fourcc: u32,
+    size: (u32, u32),
+    planes: Vec<(u32, u32)>,
+}
+
+impl DmabufFrame {
+    /// Single-memory DMA-BUF samples in a format we can name in DRM terms.
+    fn from_sample(sample: &gst::Sample) -> Result<Option<Self>> {
+        let buffer = sample
+            .buffer()
+            .ok_or_else(|| anyhow!("video sample missing buffer"))?;
+        if buffer.n_memory() != 1 {
+            return Ok(None);
+        }
+        let memory = buffer.peek_memory(0);
+        let Some(dmabuf) = memory.downcast_memory_ref::<gst_allocators::DmaBufMemory>() else {
+            return Ok(None);
+        };
+
+        let (format, size, offsets, strides) = match buffer.meta::<gst_video::VideoMeta>() {
+            Some(meta) => (
+                meta.format(),
+                (meta.width(), meta.height()),
+                meta.offset().to_vec(),
+                meta.stride().to_vec(),
+            ),
+            None => {
+                let caps = sample
+                    .caps()
+                    .ok_or_else(|| anyhow!("video sample missing caps"))?;
+                let info = gst_video::VideoInfo::from_caps(caps)
+                    .map_err(|_| anyhow!("failed to parse video caps"))?;
+                (
+                    info.format(),
+                    (info.width(), info.height()),
+                    info.offset().to_vec(),
+                    info.stride().to_vec(),
+                )
+            }
+        };
+
+        let Some(fourcc) = drm_fourcc(format) else {
+            return Ok(None);
+        };
+        let planes = offsets
+            .iter()
+            .zip(strides.iter())
+            .map(|(offset, stride)| ((memory.offset() + offset) as u32, *stride as u32))
+            .collect();
+
+        Ok(Some(Self {
+            fd: dmabuf.fd(),
+            fourcc,
+            size,
+            planes,
+        }))
+    }
+}
+
+fn drm_fourcc(format: gst_video::VideoFormat) -> Option<u32> {
+    match format {
+        gst_video::VideoFormat::Bgrx => Some(DRM_FORMAT_XRGB8888),
+        gst_video::VideoFormat::Nv12 => Some(DRM_FORMAT_NV12),
+        _ => None,
+    }
+}
+
+/// Source rectangle (x, y, w, h) in buffer pixels that the compositor should
+/// scale onto the whole output. Modes that need borders return `None`.
+fn viewport_source(
+    fit_mode: FitMode,
+    frame: (u32, u32),
+    output: (u32, u32),
+) -> Option<(f64, f64, f64, f64)> {
+    let (fw, fh) = (frame.0 as f64, frame.1 as f64);
+    match fit_mode {
+        FitMode::Stretch => Some((0.0, 0.0, fw, fh)),
+        FitMode::Fill | FitMode::Cover => {
+            let output_aspect = output.0 as f64 / output.1 as f64;
+            if fw / fh > output_aspect {
+                let w = fh * output_aspect;
+                Some(((fw - w) / 2.0, 0.0, w, fh))
+            } else {
+                let h = fw / output_aspect;
+                Some((0.0, (fh - h) / 2.0, fw, h))
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{drm_fourcc, viewport_source, DRM_FORMAT_NV12, DRM_FORMAT_XRGB8888};
+    use crate::config::FitMode;
+    use gstreamer_video as gst_video;
+
+    #[test]
+    fn fourcc_codes_match_drm_fourcc_h() {
+        assert_eq!(DRM_FORMAT_XRGB8888, 0x3432_5258);
+        assert_eq!(DRM_FORMAT_NV12, 0x3231_564e);
+        assert_eq!(drm_fourcc(gst_video::VideoFormat::Bgrx), Some(DRM_FORMAT_XRGB8888));
+        assert_eq!(drm_fourcc(gst_video::VideoFormat::I420), None);
+    }
+
+    #[test]
+    fn cover_crops_video_to_output_aspect() {
+        let wide = viewport_source(FitMode::Cover, (3000, 1000), (2000, 1000));
+        assert_eq!(wide, Some((500.0, 0.0, 2000.0, 1000.0)));
+
+        let tall = viewport_source(FitMode::Cover, (1000, 1000), (2000, 1000));
+        assert_eq!(tall, Some((0.0, 250.0, 1000.0, 500.0)));
+    }
+
+    #[test]
+    fn bordered_modes_are_left_to_the_shm_renderer() {
+        assert!(viewport_source(FitMode::Contain, (640, 480), (1920, 1080)).is_none());
+        assert!(viewport_source(FitMode::Center, (640, 480), (1920, 1080)).is_none());
+        assert_eq!(
+            viewport_source(FitMode::Stretch, (640, 480), (1920, 1080)),
+            Some((0.0, 0.0, 640.0, 480.0))
+        );
+    }
+}
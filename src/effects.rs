@@ -0,0 +1,371 @@
+//! `--effect` / `effects`: stylistic filters applied after an image is fitted.
+//!
+//! Images are filtered on the CPU in the order given. Videos get the matching
+//! GStreamer elements instead: `videobalance` for grayscale, `gleffects` for
+//! sepia and `gaussianblur` for blur, which takes the same sigma as images.
+//! GStreamer has no inverting element, so videos skip `invert`.
+//!
+//! `--watermark` / `watermark` composites a logo over the fitted wallpaper last,
+//! after every effect. Videos get a `gdkpixbufoverlay` element for it.
+
+use crate::formats;
+use anyhow::Result;
+use clap::ValueEnum;
+use image::{imageops, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Gaussian sigma of `blur` without `blur_sigma`, in pixels of the fitted
+/// image or video frame.
+pub const DEFAULT_BLUR_SIGMA: f32 = 8.0;
+/// The largest sigma `gaussianblur` accepts; images are held to it as well.
+pub const MAX_BLUR_SIGMA: f32 = 20.0;
+
+/// Gap between a corner watermark and the screen edges, as a fraction of the
+/// shorter side of the output.
+const WATERMARK_MARGIN: f32 = 0.02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Effect {
+    Grayscale,
+    Sepia,
+    Invert,
+    Blur,
+}
+
+impl Effect {
+    pub fn name(self) -> &'static str {
+        match self {
+            Effect::Grayscale => "grayscale",
+            Effect::Sepia => "sepia",
+            Effect::Invert => "invert",
+            Effect::Blur => "blur",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum WatermarkPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+    Center,
+}
+
+impl WatermarkPosition {
+    /// Where the watermark sits as fractions of the free space, horizontally and vertically.
+    fn anchor(self) -> (f32, f32) {
+        match self {
+            WatermarkPosition::TopLeft => (0.0, 0.0),
+            WatermarkPosition::TopRight => (1.0, 0.0),
+            WatermarkPosition::BottomLeft => (0.0, 1.0),
+            WatermarkPosition::BottomRight => (1.0, 1.0),
+            WatermarkPosition::Center => (0.5, 0.5),
+        }
+    }
+}
+
+/// The `[watermark]` table. `scale` is the watermark's width as a fraction of
+/// the output width (0 keeps its own size); `opacity` multiplies its alpha.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct WatermarkConfig {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub position: WatermarkPosition,
+    #[serde(default = "default_watermark_opacity")]
+    pub opacity: f32,
+    #[serde(default = "default_watermark_scale")]
+    pub scale: f32,
+}
+
+fn default_watermark_opacity() -> f32 {
+    0.8
+}
+
+fn default_watermark_scale() -> f32 {
+    0.1
+}
+
+impl WatermarkConfig {
+    /// `--watermark`: `path` with the config's position, opacity and scale, if any.
+    pub fn with_path(path: PathBuf, config: Option<&WatermarkConfig>) -> Self {
+        match config {
+            Some(config) => WatermarkConfig {
+                path,
+                ..config.clone()
+            },
+            None => WatermarkConfig {
+                path,
+                position: WatermarkPosition::default(),
+                opacity: default_watermark_opacity(),
+                scale: default_watermark_scale(),
+            },
+        }
+    }
+
+    /// Size of a `mark_size` watermark on a `canvas_size` output.
+    fn scaled_size(&self, mark_size: (u32, u32), canvas_size: (u32, u32)) -> (u32, u32) {
+        let (width, height) = (mark_size.0.max(1), mark_size.1.max(1));
+        let scale = self.scale.clamp(0.0, 1.0);
+        if scale == 0.0 {
+            return (width, height);
+        }
+        let target = (canvas_size.0 as f32 * scale).round().max(1.0);
+        let target_height = (height as f32 * target / width as f32).round().max(1.0);
+        (target as u32, target_height as u32)
+    }
+
+    /// Top-left corner of a `mark_size` watermark on a `canvas_size` output.
+    fn origin(&self, mark_size: (u32, u32), canvas_size: (u32, u32)) -> (i64, i64) {
+        let (anchor_x, anchor_y) = self.position.anchor();
+        let margin = (canvas_size.0.min(canvas_size.1) as f32 * WATERMARK_MARGIN).round();
+        let place = |canvas: u32, mark: u32, anchor: f32| {
+            let inset = if anchor == 0.5 { 0.0 } else { margin };
+            let free = canvas as f32 - mark as f32 - 2.0 * inset;
+            (inset + free * anchor).round() as i64
+        };
+        (
+            place(canvas_size.0, mark_size.0, anchor_x),
+            place(canvas_size.1, mark_size.1, anchor_y),
+        )
+    }
+}
+
+pub fn apply_effects(img: &mut RgbaImage, effects: &[Effect], blur_sigma: f32) {
+    for effect in effects {
+        match effect {
+            Effect::Grayscale => apply_grayscale(img),
+            Effect::Sepia => apply_sepia(img),
+            Effect::Invert => apply_invert(img),
+            Effect::Blur => apply_blur(img, blur_sigma),
+        }
+    }
+}
+
+/// Replaces each colour with its Rec. 709 luma. Alpha is left alone.
+pub fn apply_grayscale(img: &mut RgbaImage) {
+    for pixel in img.pixels_mut() {
+        let [r, g, b, _] = pixel.0;
+        let luma = 0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32;
+        let luma = luma.round().clamp(0.0, 255.0) as u8;
+        pixel.0[..3].fill(luma);
+    }
+}
+
+pub fn apply_sepia(img: &mut RgbaImage) {
+    const SEPIA: [[f32; 3]; 3] = [
+        [0.393, 0.769, 0.189],
+        [0.349, 0.686, 0.168],
+        [0.272, 0.534, 0.131],
+    ];
+    for pixel in img.pixels_mut() {
+        let rgb = [pixel.0[0] as f32, pixel.0[1] as f32, pixel.0[2] as f32];
+        for (channel, row) in pixel.0.iter_mut().zip(SEPIA) {
+            let value = row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+            *channel = value.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+pub fn apply_invert(img: &mut RgbaImage) {
+    for pixel in img.pixels_mut() {
+        for channel in &mut pixel.0[..3] {
+            *channel = 255 - *channel;
+        }
+    }
+}
+
+pub fn apply_blur(img: &mut RgbaImage, radius: f32) {
+    if radius > 0.0 {
+        *img = imageops::blur(img, radius);
+    }
+}
+
+/// Loads the watermark image at its own size.
+pub fn load_watermark(watermark: &WatermarkConfig) -> Result<RgbaImage> {
+    Ok(formats::open_image(&watermark.path)?.into_rgba8())
+}
+
+/// Scales `mark` for `canvas`, fades it by the opacity and composites it at
+/// the configured position.
+pub fn apply_watermark(canvas: &mut RgbaImage, mark: &RgbaImage, watermark: &WatermarkConfig) {
+    let (width, height) = watermark.scaled_size(mark.dimensions(), canvas.dimensions());
+    let mut mark = if (width, height) == mark.dimensions() {
+        mark.clone()
+    } else {
+        imageops::resize(mark, width, height, imageops::FilterType::Triangle)
+    };
+    let opacity = watermark.opacity.clamp(0.0, 1.0);
+    if opacity < 1.0 {
+        for pixel in mark.pixels_mut() {
+            pixel.0[3] = (pixel.0[3] as f32 * opacity).round() as u8;
+        }
+    }
+    let (x, y) = watermark.origin((width, height), canvas.dimensions());
+    imageops::overlay(canvas, &mark, x, y);
+}
+
+/// A `gdkpixbufoverlay` element drawing the watermark over video frames of
+/// `output` size, for a watermark image of `mark_size`.
+pub fn video_watermark_stage(
+    watermark: &WatermarkConfig,
+    mark_size: (u32, u32),
+    output: (u32, u32),
+) -> String {
+    let (width, height) = watermark.scaled_size(mark_size, output);
+    let (anchor_x, anchor_y) = watermark.position.anchor();
+    let margin = (output.0.min(output.1) as f32 * WATERMARK_MARGIN).round() as i64;
+    let offset = |anchor: f32| {
+        if anchor == 0.0 {
+            margin
+        } else if anchor == 1.0 {
+            -margin
+        } else {
+            0
+        }
+    };
+    let location = watermark
+        .path
+        .to_string_lossy()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"");
+    format!(
+        " ! gdkpixbufoverlay location=\"{}\" positioning-mode=pixels-absolute \
+         relative-x={:.1} relative-y={:.1} coef-x={:.1} coef-y={:.1} offset-x={} offset-y={} \
+         overlay-width={} overlay-height={} alpha={:.3}",
+        location,
+        anchor_x,
+        anchor_y,
+        anchor_x,
+        anchor_y,
+        offset(anchor_x),
+        offset(anchor_y),
+        width,
+        height,
+        watermark.opacity.clamp(0.0, 1.0)
+    )
+}
+
+/// Pipeline elements for `effects` on video, in order, or nothing when none
+/// apply. Consecutive GL effects share one upload to the GPU.
+pub fn video_effect_stage(effects: &[Effect], blur_sigma: f32) -> String {
+    let mut stage = String::new();
+    let mut on_gpu = false;
+    for effect in effects {
+        let cpu_element = match effect {
+            Effect::Grayscale => " ! videobalance saturation=0.0".to_string(),
+            Effect::Blur => format!(
+                " ! videoconvert ! gaussianblur sigma={:.1} ! videoconvert",
+                blur_sigma
+            ),
+            Effect::Sepia => {
+                if !on_gpu {
+                    stage.push_str(" ! glupload");
+                    on_gpu = true;
+                }
+                stage.push_str(" ! gleffects effect=sepia");
+                continue;
+            }
+            Effect::Invert => continue,
+        };
+        if on_gpu {
+            stage.push_str(" ! gldownload ! videoconvert");
+            on_gpu = false;
+        }
+        stage.push_str(&cpu_element);
+    }
+    if on_gpu {
+        stage.push_str(" ! gldownload ! videoconvert");
+    }
+    stage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        apply_effects, apply_watermark, video_effect_stage, video_watermark_stage, Effect,
+        WatermarkConfig, WatermarkPosition, DEFAULT_BLUR_SIGMA,
+    };
+    use image::{Rgba, RgbaImage};
+    use std::path::PathBuf;
+
+    #[test]
+    fn effects_apply_in_order_and_map_to_video_elements() {
+        let image = || RgbaImage::from_pixel(4, 4, Rgba([200, 100, 50, 128]));
+        let apply = |img: &mut RgbaImage, effects: &[Effect]| {
+            apply_effects(img, effects, DEFAULT_BLUR_SIGMA)
+        };
+
+        let mut gray = image();
+        apply(&mut gray, &[Effect::Grayscale]);
+        assert_eq!(gray.get_pixel(0, 0).0, [118, 118, 118, 128]);
+
+        let mut sepia = image();
+        apply(&mut sepia, &[Effect::Sepia]);
+        assert_eq!(sepia.get_pixel(0, 0).0, [165, 147, 114, 128]);
+
+        let mut inverted = image();
+        apply(&mut inverted, &[Effect::Grayscale, Effect::Invert]);
+        assert_eq!(inverted.get_pixel(0, 0).0, [137, 137, 137, 128]);
+
+        let mut blurred = image();
+        blurred.put_pixel(0, 0, Rgba([0, 0, 0, 128]));
+        apply(&mut blurred, &[Effect::Blur]);
+        assert_ne!(blurred.get_pixel(0, 0).0, [0, 0, 0, 128]);
+        let mut unblurred = image();
+        unblurred.put_pixel(0, 0, Rgba([0, 0, 0, 128]));
+        apply_effects(&mut unblurred, &[Effect::Blur], 0.0);
+        assert_eq!(unblurred.get_pixel(0, 0).0, [0, 0, 0, 128]);
+
+        assert_eq!(video_effect_stage(&[Effect::Invert], DEFAULT_BLUR_SIGMA), "");
+        assert_eq!(
+            video_effect_stage(&[Effect::Sepia, Effect::Blur, Effect::Grayscale], 3.5),
+            " ! glupload ! gleffects effect=sepia ! gldownload ! videoconvert \
+             ! videoconvert ! gaussianblur sigma=3.5 ! videoconvert \
+             ! videobalance saturation=0.0"
+        );
+        assert_eq!(
+            video_effect_stage(&[Effect::Grayscale, Effect::Sepia], DEFAULT_BLUR_SIGMA),
+            " ! videobalance saturation=0.0 ! glupload ! gleffects effect=sepia \
+             ! gldownload ! videoconvert"
+        );
+    }
+
+    #[test]
+    fn watermarks_are_scaled_faded_and_placed() {
+        let mark = RgbaImage::from_pixel(20, 10, Rgba([255, 255, 255, 255]));
+        let config = |position| WatermarkConfig {
+            position,
+            opacity: 0.5,
+            scale: 0.2,
+            ..WatermarkConfig::with_path(PathBuf::from("/logos/mark.png"), None)
+        };
+
+        // 0.2 of a 200px wide canvas is 40x20, 2px (2% of 100px) from the corner.
+        let mut canvas = RgbaImage::from_pixel(200, 100, Rgba([0, 0, 0, 255]));
+        apply_watermark(&mut canvas, &mark, &config(WatermarkPosition::BottomRight));
+        assert_eq!(canvas.get_pixel(158, 78).0[..3], [128, 128, 128]);
+        assert_eq!(canvas.get_pixel(197, 97).0[..3], [128, 128, 128]);
+        for (x, y) in [(157, 78), (158, 77), (198, 97), (197, 98)] {
+            assert_eq!(canvas.get_pixel(x, y).0, [0, 0, 0, 255], "({}, {})", x, y);
+        }
+
+        let mut centered = RgbaImage::from_pixel(200, 100, Rgba([0, 0, 0, 255]));
+        apply_watermark(&mut centered, &mark, &config(WatermarkPosition::Center));
+        assert_eq!(centered.get_pixel(80, 40).0[0], 128);
+        assert_eq!(centered.get_pixel(79, 40).0[0], 0);
+
+        let top_right = config(WatermarkPosition::TopRight);
+        let stage = video_watermark_stage(&top_right, (20, 10), (200, 100));
+        assert_eq!(
+            stage,
+            " ! gdkpixbufoverlay location=\"/logos/mark.png\" positioning-mode=pixels-absolute \
+             relative-x=1.0 relative-y=0.0 coef-x=1.0 coef-y=0.0 offset-x=-2 offset-y=2 \
+             overlay-width=40 overlay-height=20 alpha=0.500"
+        );
+    }
+}
@@ -0,0 +1,216 @@
+//! `fifo_path` / `daemon --fifo`: wallpaper paths pushed through a named pipe.
+//!
+//! Each line written to the pipe is one request: a wallpaper path, optionally
+//! followed by `?monitor=NAME` (or `?monitor=all`). Without a monitor the
+//! first of the daemon's monitors gets the wallpaper. A reader thread forwards
+//! the lines to the daemon loop and reopens the pipe whenever the last writer
+//! closes it, until the reader is dropped.
+
+use crate::output::warning;
+use anyhow::{anyhow, Context, Result};
+use nix::{sys::stat::Mode, unistd::mkfifo};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader},
+    os::unix::fs::{FileTypeExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+const REOPEN_DELAY: Duration = Duration::from_secs(1);
+const STOP_CHECKS: u32 = 10;
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FifoTarget {
+    First,
+    All,
+    Monitor(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FifoRequest {
+    pub path: PathBuf,
+    pub target: FifoTarget,
+}
+
+/// Parses one line from the pipe; blank lines give `None`.
+pub fn parse_request(line: &str) -> Option<FifoRequest> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+    let (path, target) = match line.rsplit_once("?monitor=") {
+        Some((path, "all")) => (path, FifoTarget::All),
+        Some((path, monitor)) if !monitor.trim().is_empty() => {
+            (path, FifoTarget::Monitor(monitor.trim().to_string()))
+        }
+        _ => (line, FifoTarget::First),
+    };
+    Some(FifoRequest {
+        path: PathBuf::from(path.trim()),
+        target,
+    })
+}
+
+pub struct FifoReader {
+    path: PathBuf,
+    lines: Receiver<String>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FifoReader {
+    /// Creates the pipe if it does not exist yet and starts reading it.
+    pub fn spawn(path: &Path) -> Result<Self> {
+        match fs::metadata(path) {
+            Ok(meta) if meta.file_type().is_fifo() => {}
+            Ok(_) => return Err(anyhow!("{} exists and is not a named pipe", path.display())),
+            Err(_) => mkfifo(path, Mode::from_bits_truncate(0o600))
+                .with_context(|| format!("failed to create named pipe {}", path.display()))?,
+        }
+
+        let (sender, lines) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let (path, stop) = (path.to_path_buf(), Arc::clone(&stop));
+            thread::spawn(move || read_forever(&path, &sender, &stop))
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            lines,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Lines received since the last call.
+    pub fn take_lines(&self) -> Vec<String> {
+        self.lines.try_iter().collect()
+    }
+}
+
+impl Drop for FifoReader {
+    /// Opening the pipe for writing wakes the reader thread if it is waiting
+    /// for a writer; closing it again gives the thread EOF, so it sees `stop`.
+    /// A thread still reading from another writer is left to exit on its own.
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        for _ in 0..STOP_CHECKS {
+            // Again each time: the thread may have been between two opens.
+            let _ = open_for_writing(&self.path);
+            if thread.is_finished() {
+                let _ = thread.join();
+                return;
+            }
+            thread::sleep(STOP_CHECK_INTERVAL);
+        }
+    }
+}
+
+/// Fails with `ENXIO` while nobody has the pipe open for reading.
+fn open_for_writing(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+}
+
+fn read_forever(path: &Path, sender: &Sender<String>, stop: &AtomicBool) {
+    while !stop.load(Ordering::Relaxed) {
+        // Blocks until a writer opens the pipe; EOF means every writer closed it.
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(error) => {
+                warning!("warning: cannot open {}: {}", path.display(), error);
+                thread::sleep(REOPEN_DELAY);
+                continue;
+            }
+        };
+        for line in BufReader::new(file).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if sender.send(line).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{open_for_writing, parse_request, FifoReader, FifoRequest, FifoTarget};
+    use std::{
+        fs,
+        io::Write,
+        path::PathBuf,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn requests_name_a_monitor_with_a_query_suffix() {
+        assert_eq!(
+            parse_request("  /walls/forest.png\n"),
+            Some(FifoRequest {
+                path: PathBuf::from("/walls/forest.png"),
+                target: FifoTarget::First,
+            })
+        );
+        assert_eq!(
+            parse_request("/walls/what?.png?monitor=DP-1").map(|r| (r.path, r.target)),
+            Some((
+                PathBuf::from("/walls/what?.png"),
+                FifoTarget::Monitor("DP-1".to_string())
+            ))
+        );
+        assert_eq!(
+            parse_request("/walls/forest.png?monitor=all").map(|r| r.target),
+            Some(FifoTarget::All)
+        );
+        assert_eq!(parse_request("   "), None);
+    }
+
+    #[test]
+    fn reader_creates_the_pipe_and_survives_writers_closing_it() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let path = dir.join("papdieo.fifo");
+        let reader = FifoReader::spawn(&path).unwrap();
+
+        let mut received = Vec::new();
+        for line in ["/walls/a.png", "/walls/b.png?monitor=DP-2"] {
+            let mut writer = fs::OpenOptions::new().write(true).open(&path).unwrap();
+            writeln!(writer, "{}", line).unwrap();
+            drop(writer);
+
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while received.last().map(String::as_str) != Some(line) && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(20));
+                received.extend(reader.take_lines());
+            }
+        }
+        assert_eq!(received, ["/walls/a.png", "/walls/b.png?monitor=DP-2"]);
+
+        // Dropping the reader ends its thread, so nobody reads the pipe anymore.
+        drop(reader);
+        assert!(open_for_writing(&path).is_err());
+
+        fs::write(dir.join("plain"), "").unwrap();
+        assert!(FifoReader::spawn(&dir.join("plain")).is_err());
+    }
+}
@@ -0,0 +1,265 @@
+//! Image formats the `image` crate cannot decode on its own.
+//!
+//! JPEG XL (`.jxl`) is decoded with `jxl-oxide` when papdieo is built with the
+//! `jxl` feature; without it, `.jxl` files are skipped when picking and
+//! rejected by `set`. The `image` crate only reads the first page of a TIFF, so
+//! multi-page TIFFs are read with the `tiff` crate and shown as a slideshow.
+
+use anyhow::{anyhow, Context, Result};
+use image::{
+    DynamicImage, GrayAlphaImage, GrayImage, ImageBuffer, LumaA, Rgb, RgbImage, Rgba, RgbaImage,
+};
+use std::{fs::File, io::BufReader, path::Path, time::Duration};
+use tiff::{
+    decoder::{Decoder as TiffDecoder, DecodingResult},
+    ColorType as TiffColorType,
+};
+
+/// How long each page of a multi-page TIFF stays up when
+/// `image_slideshow_delay_ms` is not set.
+pub const DEFAULT_SLIDESHOW_DELAY: Duration = Duration::from_millis(5000);
+
+#[cfg(feature = "jxl")]
+pub use jxl::{decode_jxl_animated, decode_jxl_static, jxl_dimensions, jxl_is_animated};
+
+pub fn is_jxl(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("jxl"))
+        .unwrap_or(false)
+}
+
+pub fn is_tiff(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("tif") || ext.eq_ignore_ascii_case("tiff"))
+        .unwrap_or(false)
+}
+
+/// Every page of a multi-page TIFF, each shown for `page_delay`. Single-page
+/// files return `None` and stay on the still-image path.
+pub fn decode_tiff_pages(
+    path: &Path,
+    page_delay: Duration,
+) -> Result<Option<Vec<(RgbaImage, Duration)>>> {
+    let file = File::open(path)
+        .with_context(|| format!("failed to open image: {}", path.display()))?;
+    let mut decoder = TiffDecoder::new(BufReader::new(file))
+        .with_context(|| format!("failed to decode TIFF: {}", path.display()))?;
+    if !decoder.more_images() {
+        return Ok(None);
+    }
+
+    let mut pages = Vec::new();
+    loop {
+        let page = tiff_page(&mut decoder).with_context(|| {
+            format!("failed to decode page {} of {}", pages.len() + 1, path.display())
+        })?;
+        pages.push((page, page_delay));
+        if !decoder.more_images() {
+            break;
+        }
+        decoder
+            .next_image()
+            .with_context(|| format!("failed to read TIFF directory: {}", path.display()))?;
+    }
+    Ok(Some(pages))
+}
+
+fn tiff_page(decoder: &mut TiffDecoder<BufReader<File>>) -> Result<RgbaImage> {
+    let (width, height) = decoder.dimensions()?;
+    let color = decoder.colortype()?;
+    let image = match (color, decoder.read_image()?) {
+        (TiffColorType::Gray(8), DecodingResult::U8(buf)) => {
+            GrayImage::from_raw(width, height, buf).map(DynamicImage::ImageLuma8)
+        }
+        (TiffColorType::GrayA(8), DecodingResult::U8(buf)) => {
+            GrayAlphaImage::from_raw(width, height, buf).map(DynamicImage::ImageLumaA8)
+        }
+        (TiffColorType::RGB(8), DecodingResult::U8(buf)) => {
+            RgbImage::from_raw(width, height, buf).map(DynamicImage::ImageRgb8)
+        }
+        (TiffColorType::RGBA(8), DecodingResult::U8(buf)) => {
+            RgbaImage::from_raw(width, height, buf).map(DynamicImage::ImageRgba8)
+        }
+        (TiffColorType::Gray(16), DecodingResult::U16(buf)) => {
+            ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageLuma16)
+        }
+        (TiffColorType::GrayA(16), DecodingResult::U16(buf)) => {
+            ImageBuffer::<LumaA<u16>, _>::from_raw(width, height, buf)
+                .map(DynamicImage::ImageLumaA16)
+        }
+        (TiffColorType::RGB(16), DecodingResult::U16(buf)) => {
+            ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, buf).map(DynamicImage::ImageRgb16)
+        }
+        (TiffColorType::RGBA(16), DecodingResult::U16(buf)) => {
+            ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, buf)
+                .map(DynamicImage::ImageRgba16)
+        }
+        (color, _) => return Err(anyhow!("unsupported TIFF colour type {:?}", color)),
+    };
+    image
+        .map(DynamicImage::into_rgba8)
+        .ok_or_else(|| anyhow!("TIFF page buffer has the wrong size"))
+}
+
+/// `image::open`, plus JPEG XL when that feature is enabled.
+pub fn open_image(path: &Path) -> Result<DynamicImage> {
+    if is_jxl(path) {
+        #[cfg(feature = "jxl")]
+        return decode_jxl_static(path);
+        #[cfg(not(feature = "jxl"))]
+        return Err(anyhow::anyhow!(
+            "{} is JPEG XL; rebuild papdieo with `--features jxl` to show it",
+            path.display()
+        ));
+    }
+    image::open(path).with_context(|| format!("failed to load image: {}", path.display()))
+}
+
+/// Width and height from the file header, JPEG XL included.
+pub fn image_dimensions(path: &Path) -> Result<(u32, u32)> {
+    if is_jxl(path) {
+        #[cfg(feature = "jxl")]
+        return jxl_dimensions(path);
+        #[cfg(not(feature = "jxl"))]
+        return Err(anyhow::anyhow!(
+            "{} is JPEG XL; rebuild papdieo with `--features jxl` to read it",
+            path.display()
+        ));
+    }
+    image::image_dimensions(path)
+        .with_context(|| format!("failed to read image size: {}", path.display()))
+}
+
+#[cfg(feature = "jxl")]
+mod jxl {
+    use anyhow::{anyhow, Result};
+    use image::{DynamicImage, RgbaImage};
+    use jxl_oxide::{JxlImage, PixelFormat, Render};
+    use std::{path::Path, time::Duration};
+
+    /// Reads the headers only; nothing is decoded.
+    pub fn jxl_is_animated(path: &Path) -> Result<bool> {
+        Ok(open(path)?.image_header().metadata.animation.is_some())
+    }
+
+    pub fn jxl_dimensions(path: &Path) -> Result<(u32, u32)> {
+        let image = open(path)?;
+        Ok((image.width(), image.height()))
+    }
+
+    pub fn decode_jxl_static(path: &Path) -> Result<DynamicImage> {
+        let image = open(path)?;
+        let render = image
+            .render_frame(0)
+            .map_err(|error| anyhow!("failed to decode JPEG XL {}: {}", path.display(), error))?;
+        to_dynamic_image(&render, image.pixel_format())
+    }
+
+    /// Every frame with its display time. Still images come back as one frame.
+    pub fn decode_jxl_animated(path: &Path) -> Result<Vec<(RgbaImage, Duration)>> {
+        let image = open(path)?;
+        let tick = image
+            .image_header()
+            .metadata
+            .animation
+            .as_ref()
+            .filter(|animation| animation.tps_numerator > 0)
+            .map(|animation| {
+                Duration::from_secs_f64(
+                    animation.tps_denominator as f64 / animation.tps_numerator as f64,
+                )
+            })
+            .unwrap_or_default();
+
+        (0..image.num_loaded_keyframes())
+            .map(|index| {
+                let render = image.render_frame(index).map_err(|error| {
+                    anyhow!(
+                        "failed to decode JPEG XL frame {} of {}: {}",
+                        index,
+                        path.display(),
+                        error
+                    )
+                })?;
+                let frame = to_dynamic_image(&render, image.pixel_format())?.into_rgba8();
+                Ok((frame, tick * render.duration()))
+            })
+            .collect()
+    }
+
+    fn open(path: &Path) -> Result<JxlImage> {
+        JxlImage::builder()
+            .open(path)
+            .map_err(|error| anyhow!("failed to open JPEG XL {}: {}", path.display(), error))
+    }
+
+    fn to_dynamic_image(render: &Render, format: PixelFormat) -> Result<DynamicImage> {
+        if format.has_black() {
+            return Err(anyhow!("CMYK JPEG XL images are not supported"));
+        }
+        let mut stream = render.stream();
+        let (width, height) = (stream.width(), stream.height());
+        let mut buf = vec![0u8; width as usize * height as usize * stream.channels() as usize];
+        stream.write_to_buffer(&mut buf);
+
+        let image = match stream.channels() {
+            1 => image::GrayImage::from_raw(width, height, buf).map(DynamicImage::ImageLuma8),
+            2 => image::GrayAlphaImage::from_raw(width, height, buf).map(DynamicImage::ImageLumaA8),
+            3 => image::RgbImage::from_raw(width, height, buf).map(DynamicImage::ImageRgb8),
+            4 => RgbaImage::from_raw(width, height, buf).map(DynamicImage::ImageRgba8),
+            channels => return Err(anyhow!("unsupported JPEG XL channel count: {}", channels)),
+        };
+        image.ok_or_else(|| anyhow!("JPEG XL frame buffer has the wrong size"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_tiff_pages, is_jxl, is_tiff, open_image};
+    use std::{fs::File, path::Path, time::Duration};
+    use tiff::encoder::{colortype, TiffEncoder};
+
+    #[test]
+    fn jxl_is_detected_by_extension() {
+        assert!(is_jxl(Path::new("/walls/aurora.JXL")));
+        assert!(!is_jxl(Path::new("/walls/aurora.jpg")));
+
+        let error = open_image(Path::new("/nonexistent/papdieo.jxl")).unwrap_err();
+        if cfg!(feature = "jxl") {
+            assert!(error.to_string().contains("failed to open JPEG XL"));
+        } else {
+            assert!(error.to_string().contains("--features jxl"));
+        }
+    }
+
+    #[test]
+    fn multi_page_tiffs_decode_every_page() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let write = |name: &str, pages: &[[u8; 4]]| {
+            let path = dir.join(name);
+            let mut encoder = TiffEncoder::new(File::create(&path).unwrap()).unwrap();
+            for pixel in pages {
+                let data: Vec<u8> = pixel.repeat(6);
+                encoder.write_image::<colortype::RGBA8>(3, 2, &data).unwrap();
+            }
+            path
+        };
+        let delay = Duration::from_millis(1500);
+
+        let single = write("single.tif", &[[10, 20, 30, 255]]);
+        assert!(is_tiff(&single));
+        assert!(decode_tiff_pages(&single, delay).unwrap().is_none());
+        assert_eq!(open_image(&single).unwrap().into_rgba8().get_pixel(2, 1).0, [10, 20, 30, 255]);
+
+        let pages = write("pages.TIFF", &[[255, 0, 0, 255], [0, 0, 255, 128]]);
+        let pages = decode_tiff_pages(&pages, delay).unwrap().unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[1].0.dimensions(), (3, 2));
+        assert_eq!(pages[0].0.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(pages[1].0.get_pixel(2, 1).0, [0, 0, 255, 128]);
+        assert!(pages.iter().all(|(_, shown)| *shown == delay));
+    }
+}
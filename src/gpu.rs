@@ -0,0 +1,191 @@
+//! `--gpu`/`gpu_index`: which GPU decodes video when there are several, such
+//! as a laptop's integrated and discrete GPU.
+//!
+//! GPUs are numbered in DRM render node order (`/dev/dri/renderD128` is 0).
+//! VA-API is pointed at the node through `GST_VAAPI_DRM_DEVICE`; NVDEC is
+//! picked by element, since nvcodec registers `nvh264dec` for the first CUDA
+//! device and `nvh264device<N>dec` for the others.
+
+use crate::output::warning;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const SYS_DRM_DIR: &str = "/sys/class/drm";
+const DEV_DRI_DIR: &str = "/dev/dri";
+const RENDER_NODE_PREFIX: &str = "renderD";
+const NVIDIA_VENDOR_ID: u16 = 0x10de;
+const VAAPI_DEVICE_VAR: &str = "GST_VAAPI_DRM_DEVICE";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpuDevice {
+    pub render_node: PathBuf,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    /// PCI address such as `0000:01:00.0`.
+    pub pci_slot: Option<String>,
+}
+
+impl GpuDevice {
+    pub fn is_nvidia(&self) -> bool {
+        self.vendor_id == NVIDIA_VENDOR_ID
+    }
+
+    pub fn vendor_name(&self) -> &'static str {
+        match self.vendor_id {
+            0x8086 => "Intel",
+            0x1002 => "AMD",
+            NVIDIA_VENDOR_ID => "NVIDIA",
+            _ => "unknown vendor",
+        }
+    }
+}
+
+/// Decoder choice for the selected GPU; the default (no `--gpu`) leaves it
+/// to GStreamer. VA-API is set up by [`export_vaapi_device`] instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpuDecoders {
+    /// `None` skips the NVDEC pipeline because the GPU is not an NVIDIA one.
+    pub nvdec_element: Option<String>,
+}
+
+impl Default for GpuDecoders {
+    fn default() -> Self {
+        Self {
+            nvdec_element: Some(nvdec_element(0)),
+        }
+    }
+}
+
+pub fn list_gpus() -> Vec<GpuDevice> {
+    list_gpus_in(Path::new(SYS_DRM_DIR))
+}
+
+/// The render node of GPU number `gpu_index`.
+pub fn resolve_gpu_device(gpu_index: u32) -> Option<String> {
+    list_gpus()
+        .into_iter()
+        .nth(gpu_index as usize)
+        .map(|gpu| gpu.render_node.display().to_string())
+}
+
+/// Points gstreamer-vaapi at GPU `gpu_index` for the whole process. VA-API has
+/// no per-element device, and the variable is read from GStreamer's threads, so
+/// this runs once at startup, before any thread exists.
+pub fn export_vaapi_device(gpu_index: Option<u32>) {
+    if let Some(device) = gpu_index.and_then(resolve_gpu_device) {
+        std::env::set_var(VAAPI_DEVICE_VAR, device);
+    }
+}
+
+pub fn decoders_for(gpu_index: Option<u32>) -> GpuDecoders {
+    let Some(index) = gpu_index else {
+        return GpuDecoders::default();
+    };
+    let gpus = list_gpus();
+    decoders_among(&gpus, index).unwrap_or_else(|| {
+        warning!(
+            "warning: GPU {} not found ({} GPUs; see `papdieo set --list-gpus`), using the default decoders",
+            index,
+            gpus.len()
+        );
+        GpuDecoders::default()
+    })
+}
+
+fn decoders_among(gpus: &[GpuDevice], index: u32) -> Option<GpuDecoders> {
+    let gpu = gpus.get(index as usize)?;
+    // CUDA only counts NVIDIA GPUs; assume it orders them by PCI bus like DRM does.
+    let cuda_index = gpus[..index as usize]
+        .iter()
+        .filter(|other| other.is_nvidia())
+        .count();
+    Some(GpuDecoders {
+        nvdec_element: gpu.is_nvidia().then(|| nvdec_element(cuda_index)),
+    })
+}
+
+fn nvdec_element(cuda_index: usize) -> String {
+    if cuda_index == 0 {
+        "nvh264dec".to_string()
+    } else {
+        format!("nvh264device{}dec", cuda_index)
+    }
+}
+
+fn list_gpus_in(sys_drm: &Path) -> Vec<GpuDevice> {
+    let Ok(entries) = fs::read_dir(sys_drm) else {
+        return Vec::new();
+    };
+    let mut nodes: Vec<(u32, GpuDevice)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let minor: u32 = name.strip_prefix(RENDER_NODE_PREFIX)?.parse().ok()?;
+            let device = entry.path().join("device");
+            let gpu = GpuDevice {
+                render_node: Path::new(DEV_DRI_DIR).join(&name),
+                vendor_id: read_pci_id(&device.join("vendor"))?,
+                device_id: read_pci_id(&device.join("device"))?,
+                pci_slot: fs::read_link(&device)
+                    .ok()
+                    .and_then(|target| Some(target.file_name()?.to_str()?.to_string())),
+            };
+            Some((minor, gpu))
+        })
+        .collect();
+    nodes.sort_by_key(|(minor, _)| *minor);
+    nodes.into_iter().map(|(_, gpu)| gpu).collect()
+}
+
+/// sysfs writes PCI ids as `0x10de`.
+fn read_pci_id(path: &Path) -> Option<u16> {
+    let content = fs::read_to_string(path).ok()?;
+    u16::from_str_radix(content.trim().trim_start_matches("0x"), 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decoders_among, list_gpus_in};
+    use std::{fs, os::unix::fs::symlink, path::PathBuf};
+
+    #[test]
+    fn gpus_are_numbered_by_render_node_and_pick_their_decoders() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let sys_drm = root.join("class/drm");
+        for (node, slot, vendor, device) in [
+            ("renderD129", "0000:01:00.0", "0x10de", "0x2520"),
+            ("renderD128", "0000:00:02.0", "0x8086", "0x46a6"),
+            ("renderD130", "0000:02:00.0", "0x10de", "0x2684"),
+        ] {
+            let pci = root.join("devices").join(slot);
+            fs::create_dir_all(&pci).unwrap();
+            fs::write(pci.join("vendor"), format!("{}\n", vendor)).unwrap();
+            fs::write(pci.join("device"), format!("{}\n", device)).unwrap();
+            fs::create_dir_all(sys_drm.join(node)).unwrap();
+            symlink(&pci, sys_drm.join(node).join("device")).unwrap();
+        }
+        fs::create_dir_all(sys_drm.join("card0")).unwrap();
+
+        let gpus = list_gpus_in(&sys_drm);
+
+        let nodes: Vec<PathBuf> = gpus.iter().map(|gpu| gpu.render_node.clone()).collect();
+        assert_eq!(
+            nodes,
+            ["/dev/dri/renderD128", "/dev/dri/renderD129", "/dev/dri/renderD130"].map(PathBuf::from)
+        );
+        assert_eq!(gpus[0].vendor_name(), "Intel");
+        assert_eq!(gpus[1].device_id, 0x2520);
+        assert_eq!(gpus[1].pci_slot.as_deref(), Some("0000:01:00.0"));
+
+        let intel = decoders_among(&gpus, 0).unwrap();
+        assert_eq!(intel.nvdec_element, None);
+        let first_nvidia = decoders_among(&gpus, 1).unwrap();
+        assert_eq!(first_nvidia.nvdec_element.as_deref(), Some("nvh264dec"));
+        let second_nvidia = decoders_among(&gpus, 2).unwrap();
+        assert_eq!(second_nvidia.nvdec_element.as_deref(), Some("nvh264device1dec"));
+        assert!(decoders_among(&gpus, 3).is_none());
+    }
+}
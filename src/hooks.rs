@@ -0,0 +1,85 @@
+//! `on_change_command`: a shell command run whenever a new wallpaper is shown.
+//!
+//! The command sees `PAPDIEO_WALLPAPER` and `PAPDIEO_MONITOR` (unset when the
+//! wallpaper covers every output). For still images it also gets
+//! `PAPDIEO_WALLPAPER_BRIGHTNESS` and `PAPDIEO_WALLPAPER_THEME=light|dark`.
+
+use crate::{
+    config::Config, formats, output::warning, palette, source::WallpaperSource, wallpaper,
+};
+use std::{
+    path::{Path, PathBuf},
+    process::{Child, Command},
+    thread,
+};
+
+pub const DEFAULT_BRIGHTNESS_THRESHOLD: f32 = 0.5;
+
+#[derive(Debug, Clone)]
+pub struct OnChangeHook {
+    command: String,
+    brightness_threshold: f32,
+}
+
+impl OnChangeHook {
+    pub fn from_config(cfg: &Config) -> Option<Self> {
+        let command = cfg.on_change_command.clone()?;
+        Some(Self {
+            command,
+            brightness_threshold: cfg
+                .brightness_threshold
+                .unwrap_or(DEFAULT_BRIGHTNESS_THRESHOLD),
+        })
+    }
+
+    /// Starts the command and returns without waiting for it.
+    fn spawn(&self, monitor: Option<&str>, path: &Path) -> Option<Child> {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(&self.command)
+            .env("PAPDIEO_WALLPAPER", path);
+        match monitor {
+            Some(monitor) => command.env("PAPDIEO_MONITOR", monitor),
+            None => command.env_remove("PAPDIEO_MONITOR"),
+        };
+
+        let is_stream = WallpaperSource::parse(path).is_ok_and(|source| source.is_stream());
+        if !is_stream && !wallpaper::is_video_file(path) {
+            match formats::open_image(path) {
+                Ok(img) => {
+                    let luminance = palette::compute_average_luminance(&img);
+                    command
+                        .env("PAPDIEO_WALLPAPER_BRIGHTNESS", format!("{:.3}", luminance))
+                        .env(
+                            "PAPDIEO_WALLPAPER_THEME",
+                            palette::theme_for_luminance(luminance, self.brightness_threshold),
+                        );
+                }
+                Err(error) => warning!(
+                    "warning: could not measure brightness of {}: {:#}",
+                    path.display(),
+                    error
+                ),
+            }
+        }
+
+        match command.spawn() {
+            Ok(child) => Some(child),
+            Err(error) => {
+                warning!("warning: failed to run on_change_command: {}", error);
+                None
+            }
+        }
+    }
+
+    /// Like [`spawn`](Self::spawn), but off the calling thread and reaping the child.
+    pub fn spawn_detached(&self, monitor: Option<String>, path: PathBuf) {
+        let hook = self.clone();
+        thread::spawn(move || {
+            if let Some(mut child) = hook.spawn(monitor.as_deref(), &path) {
+                let _ = child.wait();
+            }
+        });
+    }
+}
@@ -0,0 +1,675 @@
+//! Compositor IPC used to pause video while windows cover the wallpaper:
+//! Hyprland's request socket (or `hyprctl` when it cannot be reached) and the
+//! i3-ipc socket for Sway. Hyprland's event socket also drives
+//! `workspace_wallpapers`.
+
+use crate::{config::CompositorType, hysteresis::HysteresisFilter, output::debug};
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::{
+    cell::RefCell,
+    env,
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+const HYPRLAND_IPC_TIMEOUT: Duration = Duration::from_millis(500);
+const I3_IPC_MAGIC: &[u8; 6] = b"i3-ipc";
+const I3_IPC_GET_WORKSPACES: u32 = 1;
+const I3_IPC_GET_TREE: u32 = 4;
+const I3_IPC_TIMEOUT: Duration = Duration::from_millis(500);
+/// Wait before reconnecting to the event socket, e.g. while Hyprland restarts.
+const EVENT_RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Answers "is the desktop behind this monitor visible?" for the running compositor.
+pub enum CompositorVisibility {
+    Hyprland(HyprlandVisibility),
+    Sway(SwayVisibility),
+}
+
+impl CompositorVisibility {
+    /// A new answer only takes effect after two checks in a row and at least
+    /// `hysteresis` after the previous change.
+    pub fn new(
+        compositor: CompositorType,
+        target_monitor_name: Option<&str>,
+        hysteresis: Duration,
+    ) -> Option<Self> {
+        match resolve_compositor(compositor) {
+            CompositorType::Sway => {
+                SwayVisibility::new(target_monitor_name, hysteresis).map(Self::Sway)
+            }
+            _ => HyprlandVisibility::new(target_monitor_name, hysteresis).map(Self::Hyprland),
+        }
+    }
+
+    pub fn should_render(&self) -> bool {
+        match self {
+            Self::Hyprland(v) => v.should_render(),
+            Self::Sway(v) => v.should_render(),
+        }
+    }
+
+    pub fn refresh_now(&self) {
+        match self {
+            Self::Hyprland(v) => v.refresh_now(),
+            Self::Sway(v) => v.refresh_now(),
+        }
+    }
+}
+
+/// `Auto` picks from the session environment, defaulting to Hyprland.
+fn resolve_compositor(compositor: CompositorType) -> CompositorType {
+    if compositor != CompositorType::Auto {
+        return compositor;
+    }
+    if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        CompositorType::Hyprland
+    } else if env::var_os("SWAYSOCK").is_some() {
+        CompositorType::Sway
+    } else {
+        CompositorType::Hyprland
+    }
+}
+
+pub struct HyprlandVisibility {
+    should_render: Arc<AtomicBool>,
+    filter: Mutex<HysteresisFilter<bool>>,
+    target_monitor_id: Option<i64>,
+}
+
+impl HyprlandVisibility {
+    pub fn new(target_monitor_name: Option<&str>, hysteresis: Duration) -> Option<Self> {
+        let target_monitor_id = resolve_monitor_id(target_monitor_name);
+        let initial_should_render = query_should_render(target_monitor_id).unwrap_or(true);
+
+        Some(Self {
+            should_render: Arc::new(AtomicBool::new(initial_should_render)),
+            filter: Mutex::new(HysteresisFilter::new(
+                initial_should_render,
+                hysteresis,
+                Instant::now(),
+            )),
+            target_monitor_id,
+        })
+    }
+
+    fn should_render(&self) -> bool {
+        self.should_render.load(Ordering::Relaxed)
+    }
+
+    fn refresh_now(&self) {
+        if let Some(observed) = query_should_render(self.target_monitor_id) {
+            apply_filtered(&self.filter, &self.should_render, observed);
+        }
+    }
+}
+
+fn apply_filtered(filter: &Mutex<HysteresisFilter<bool>>, target: &AtomicBool, observed: bool) {
+    let mut filter = filter.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(should_render) = filter.update(observed, Instant::now()) {
+        debug!("desktop {}", if should_render { "visible" } else { "covered" });
+        target.store(should_render, Ordering::Relaxed);
+    }
+}
+
+/// Hyprland's request socket, the one `hyprctl` talks to. Hyprland answers a
+/// single request per connection and then closes it, so what is kept between
+/// calls is the resolved socket path, not the connection.
+pub struct HyprlandIpcClient {
+    socket_path: PathBuf,
+}
+
+impl HyprlandIpcClient {
+    pub fn connect() -> Result<Self> {
+        let socket_path = hyprland_socket_path(".socket.sock")
+            .ok_or_else(|| anyhow!("Hyprland IPC socket not found"))?;
+        Ok(Self { socket_path })
+    }
+
+    /// The reply of `hyprctl -j <command>`.
+    pub fn request_json(&mut self, command: &str) -> Result<Value> {
+        let mut stream = UnixStream::connect(&self.socket_path)
+            .with_context(|| format!("failed to connect to {}", self.socket_path.display()))?;
+        stream.set_read_timeout(Some(HYPRLAND_IPC_TIMEOUT))?;
+        stream.set_write_timeout(Some(HYPRLAND_IPC_TIMEOUT))?;
+        stream.write_all(format!("j/{}", command).as_bytes())?;
+
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply)?;
+        serde_json::from_slice(&reply)
+            .with_context(|| format!("invalid reply to Hyprland request '{}'", command))
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/hypr/<signature>/<name>`, or `/tmp/hypr/...` before Hyprland 0.40.
+fn hyprland_socket_path(name: &str) -> Option<PathBuf> {
+    let signature = env::var_os("HYPRLAND_INSTANCE_SIGNATURE")?;
+    let runtime_dir = env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join("hypr"));
+    runtime_dir
+        .into_iter()
+        .chain([PathBuf::from("/tmp/hypr")])
+        .map(|base| base.join(&signature).join(name))
+        .find(|path| path.exists())
+}
+
+/// A monitor switched to another workspace. `monitor` is the focused monitor
+/// when the event arrived, `None` if it is not known yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceEvent {
+    pub monitor: Option<String>,
+    pub id: Option<String>,
+    pub name: String,
+}
+
+/// One line of Hyprland's event socket that matters for workspace wallpapers.
+#[derive(Debug, PartialEq, Eq)]
+enum HyprlandEvent {
+    Workspace { id: Option<String>, name: String },
+    FocusedMonitor(String),
+}
+
+/// Parses `workspace>>NAME`, `workspacev2>>ID,NAME` and `focusedmon>>MONITOR,WORKSPACE`.
+fn parse_event(line: &str) -> Option<HyprlandEvent> {
+    let (event, data) = line.trim_end().split_once(">>")?;
+    match event {
+        "workspace" => Some(HyprlandEvent::Workspace {
+            id: None,
+            name: data.to_string(),
+        }),
+        "workspacev2" => {
+            let (id, name) = data.split_once(',')?;
+            Some(HyprlandEvent::Workspace {
+                id: Some(id.to_string()),
+                name: name.to_string(),
+            })
+        }
+        "focusedmon" => {
+            let (monitor, _) = data.split_once(',')?;
+            Some(HyprlandEvent::FocusedMonitor(monitor.to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// Follows `.socket2.sock` on a background thread and forwards workspace
+/// switches, reconnecting when Hyprland closes the socket.
+pub struct HyprlandEventListener {
+    events: Receiver<Vec<WorkspaceEvent>>,
+}
+
+impl HyprlandEventListener {
+    pub fn spawn() -> Result<Self> {
+        let socket_path = hyprland_socket_path(".socket2.sock")
+            .ok_or_else(|| anyhow!("Hyprland event socket not found"))?;
+        let (sender, events) = mpsc::channel();
+        thread::spawn(move || {
+            // Hyprland only names the monitor when focus moves, so start from the focused one.
+            let mut focused = focused_monitor();
+            loop {
+                match UnixStream::connect(&socket_path) {
+                    Ok(stream) => {
+                        if !forward_events(stream, &mut focused, &sender, active_workspaces) {
+                            return;
+                        }
+                    }
+                    Err(error) => debug!("hyprland events: {}", error),
+                }
+                thread::sleep(EVENT_RECONNECT_DELAY);
+            }
+        });
+        Ok(Self { events })
+    }
+
+    /// Workspace switches since the last call. Never blocks: the listener
+    /// thread has already resolved them.
+    pub fn take_events(&self) -> Vec<WorkspaceEvent> {
+        self.events.try_iter().flatten().collect()
+    }
+}
+
+/// Each monitor's active workspace, as `hyprctl -j monitors` reports it.
+fn active_workspaces() -> Vec<WorkspaceEvent> {
+    hyprctl_json("monitors")
+        .map(|monitors| active_workspaces_from(&monitors))
+        .unwrap_or_default()
+}
+
+fn active_workspaces_from(monitors: &Value) -> Vec<WorkspaceEvent> {
+    let monitors = monitors.as_array().map(Vec::as_slice).unwrap_or_default();
+    monitors
+        .iter()
+        .filter_map(|monitor| {
+            let workspace = monitor.get("activeWorkspace")?;
+            Some(WorkspaceEvent {
+                monitor: Some(monitor.get("name")?.as_str()?.to_string()),
+                id: workspace.get("id").and_then(Value::as_i64).map(|id| id.to_string()),
+                name: workspace.get("name")?.as_str()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Reads events until the socket closes; false once the receiver is gone.
+/// After a switch every monitor's active workspace is read back through
+/// `active` (`hyprctl monitors`), since a workspace can appear on a monitor
+/// without focus moving there; the event credited to the focused monitor is
+/// only sent when that query fails. The query runs here, on the listener
+/// thread, so a slow or hung Hyprland never holds up the daemon loop.
+fn forward_events(
+    stream: impl Read,
+    focused: &mut Option<String>,
+    sender: &Sender<Vec<WorkspaceEvent>>,
+    active: impl Fn() -> Vec<WorkspaceEvent>,
+) -> bool {
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let event = match parse_event(&line) {
+            Some(HyprlandEvent::FocusedMonitor(monitor)) => {
+                *focused = Some(monitor);
+                continue;
+            }
+            Some(HyprlandEvent::Workspace { id, name }) => WorkspaceEvent {
+                monitor: focused.clone(),
+                id,
+                name,
+            },
+            None => continue,
+        };
+        let active = active();
+        let events = if active.is_empty() {
+            vec![event]
+        } else {
+            active
+        };
+        if sender.send(events).is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+fn focused_monitor() -> Option<String> {
+    let value = hyprctl_json("monitors")?;
+    value
+        .as_array()?
+        .iter()
+        .find(|m| m.get("focused").and_then(Value::as_bool) == Some(true))
+        .and_then(|m| m.get("name").and_then(Value::as_str))
+        .map(str::to_string)
+}
+
+/// The client is not shared between threads; each renderer thread polling
+/// visibility gets its own.
+#[derive(Default)]
+struct IpcClientPool {
+    client: Option<HyprlandIpcClient>,
+}
+
+impl IpcClientPool {
+    fn get_or_connect(&mut self) -> Result<&mut HyprlandIpcClient> {
+        if self.client.is_none() {
+            self.client = Some(HyprlandIpcClient::connect()?);
+        }
+        Ok(self.client.as_mut().expect("client was just connected"))
+    }
+}
+
+thread_local! {
+    static IPC_CLIENT_POOL: RefCell<IpcClientPool> = RefCell::default();
+}
+
+/// `hyprctl -j <command>` through this thread's socket client; a failed request
+/// drops the client (it reconnects on the next call) and falls back to `hyprctl`.
+pub fn hyprctl_json(command: &str) -> Option<Value> {
+    let reply = IPC_CLIENT_POOL.with(|pool| {
+        let mut pool = pool.borrow_mut();
+        let reply = pool
+            .get_or_connect()
+            .and_then(|client| client.request_json(command));
+        if reply.is_err() {
+            pool.client = None;
+        }
+        reply
+    });
+    match reply {
+        Ok(value) => Some(value),
+        Err(error) => {
+            debug!("hyprland ipc: {:#}; running hyprctl instead", error);
+            hyprctl_command_json(command)
+        }
+    }
+}
+
+fn hyprctl_command_json(command: &str) -> Option<Value> {
+    let output = Command::new("hyprctl").args(["-j", command]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    serde_json::from_slice(&output.stdout).ok()
+}
+
+fn resolve_monitor_id(target_monitor_name: Option<&str>) -> Option<i64> {
+    let name = target_monitor_name?;
+    let value = hyprctl_json("monitors")?;
+    value
+        .as_array()?
+        .iter()
+        .find(|m| m.get("name").and_then(|v| v.as_str()) == Some(name))
+        .and_then(|m| m.get("id").and_then(|v| v.as_i64()))
+}
+
+fn query_should_render(target_monitor_id: Option<i64>) -> Option<bool> {
+    let active_workspace_id = active_workspace_id(target_monitor_id)?;
+
+    let value = hyprctl_json("clients")?;
+    let clients = value.as_array()?;
+
+    let has_window_on_active_workspace = clients.iter().any(|client| {
+        let mapped = client
+            .get("mapped")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let hidden = client
+            .get("hidden")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        if !mapped || hidden {
+            return false;
+        }
+
+        let workspace_id = client
+            .get("workspace")
+            .and_then(|ws| ws.get("id"))
+            .and_then(|id| id.as_i64());
+
+        workspace_id == Some(active_workspace_id)
+    });
+
+    Some(!has_window_on_active_workspace)
+}
+
+fn active_workspace_id(target_monitor_id: Option<i64>) -> Option<i64> {
+    let value = hyprctl_json("monitors")?;
+    let monitors = value.as_array()?;
+
+    let monitor = if let Some(target_id) = target_monitor_id {
+        monitors
+            .iter()
+            .find(|m| m.get("id").and_then(|v| v.as_i64()) == Some(target_id))
+    } else {
+        monitors
+            .iter()
+            .find(|m| m.get("focused").and_then(|v| v.as_bool()) == Some(true))
+            .or_else(|| monitors.first())
+    }?;
+
+    monitor
+        .get("activeWorkspace")
+        .and_then(|ws| ws.get("id"))
+        .and_then(|id| id.as_i64())
+}
+
+pub struct SwayVisibility {
+    should_render: AtomicBool,
+    filter: Mutex<HysteresisFilter<bool>>,
+    socket_path: String,
+    target_output: Option<String>,
+}
+
+impl SwayVisibility {
+    pub fn new(target_output: Option<&str>, hysteresis: Duration) -> Option<Self> {
+        let socket_path = env::var("SWAYSOCK").ok()?;
+        let target_output = target_output.map(str::to_string);
+        let initial_should_render =
+            query_sway_should_render(&socket_path, target_output.as_deref()).unwrap_or(true);
+        Some(Self {
+            should_render: AtomicBool::new(initial_should_render),
+            filter: Mutex::new(HysteresisFilter::new(
+                initial_should_render,
+                hysteresis,
+                Instant::now(),
+            )),
+            socket_path,
+            target_output,
+        })
+    }
+
+    fn should_render(&self) -> bool {
+        self.should_render.load(Ordering::Relaxed)
+    }
+
+    fn refresh_now(&self) {
+        let observed = query_sway_should_render(&self.socket_path, self.target_output.as_deref());
+        if let Some(observed) = observed {
+            apply_filtered(&self.filter, &self.should_render, observed);
+        }
+    }
+}
+
+fn query_sway_should_render(socket_path: &str, target_output: Option<&str>) -> Option<bool> {
+    let workspaces = i3_ipc_request(socket_path, I3_IPC_GET_WORKSPACES)?;
+    let tree = i3_ipc_request(socket_path, I3_IPC_GET_TREE)?;
+    let has_windows = sway_workspace_has_windows(&workspaces, &tree, target_output)?;
+    Some(!has_windows)
+}
+
+/// One request/reply round on a fresh connection to the sway socket.
+fn i3_ipc_request(socket_path: &str, message_type: u32) -> Option<Value> {
+    let mut stream = UnixStream::connect(socket_path).ok()?;
+    stream.set_read_timeout(Some(I3_IPC_TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(I3_IPC_TIMEOUT)).ok()?;
+
+    let mut request = I3_IPC_MAGIC.to_vec();
+    request.extend_from_slice(&0u32.to_ne_bytes());
+    request.extend_from_slice(&message_type.to_ne_bytes());
+    stream.write_all(&request).ok()?;
+
+    let mut header = [0u8; 14];
+    stream.read_exact(&mut header).ok()?;
+    if &header[..6] != I3_IPC_MAGIC {
+        return None;
+    }
+    let length = u32::from_ne_bytes(header[6..10].try_into().ok()?) as usize;
+    let mut payload = vec![0u8; length];
+    stream.read_exact(&mut payload).ok()?;
+    serde_json::from_slice(&payload).ok()
+}
+
+/// Looks up the visible workspace on the target output (or the focused one)
+/// and reports whether it contains any visible leaf window.
+fn sway_workspace_has_windows(
+    workspaces: &Value,
+    tree: &Value,
+    target_output: Option<&str>,
+) -> Option<bool> {
+    let workspaces = workspaces.as_array()?;
+    let workspace = match target_output {
+        Some(output) => workspaces.iter().find(|ws| {
+            ws.get("output").and_then(Value::as_str) == Some(output)
+                && ws.get("visible").and_then(Value::as_bool) == Some(true)
+        }),
+        None => workspaces
+            .iter()
+            .find(|ws| ws.get("focused").and_then(Value::as_bool) == Some(true)),
+    }?;
+    let name = workspace.get("name").and_then(Value::as_str)?;
+
+    let node = find_workspace_node(tree, name)?;
+    Some(has_visible_leaf(node))
+}
+
+fn find_workspace_node<'a>(node: &'a Value, name: &str) -> Option<&'a Value> {
+    if node.get("type").and_then(Value::as_str) == Some("workspace")
+        && node.get("name").and_then(Value::as_str) == Some(name)
+    {
+        return Some(node);
+    }
+    child_nodes(node).find_map(|child| find_workspace_node(child, name))
+}
+
+fn has_visible_leaf(node: &Value) -> bool {
+    let mut children = child_nodes(node).peekable();
+    if children.peek().is_none() {
+        let is_window = matches!(
+            node.get("type").and_then(Value::as_str),
+            Some("con") | Some("floating_con")
+        );
+        let visible = ["visible", "mapped"]
+            .iter()
+            .any(|key| node.get(*key).and_then(Value::as_bool) == Some(true));
+        return is_window && visible;
+    }
+    children.any(has_visible_leaf)
+}
+
+fn child_nodes(node: &Value) -> impl Iterator<Item = &Value> {
+    ["nodes", "floating_nodes"]
+        .into_iter()
+        .filter_map(|key| node.get(key).and_then(Value::as_array))
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        active_workspaces_from, forward_events, parse_event, sway_workspace_has_windows,
+        HyprlandEvent, HyprlandIpcClient, WorkspaceEvent,
+    };
+    use serde_json::json;
+    use std::{
+        io::{Read, Write},
+        os::unix::net::UnixListener,
+        sync::mpsc,
+    };
+
+    #[test]
+    fn hyprland_requests_are_sent_as_json_commands() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let socket_path = dir.join(".socket.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0u8; 64];
+                let len = stream.read(&mut request).unwrap();
+                requests.push(String::from_utf8_lossy(&request[..len]).into_owned());
+                stream.write_all(br#"[{"id": 0, "name": "DP-1"}]"#).unwrap();
+            }
+            requests
+        });
+
+        let mut client = HyprlandIpcClient { socket_path };
+        let first = client.request_json("monitors").unwrap();
+        let second = client.request_json("clients").unwrap();
+        let requests = server.join().unwrap();
+
+        assert_eq!(first, json!([{"id": 0, "name": "DP-1"}]));
+        assert_eq!(second, first);
+        assert_eq!(requests, ["j/monitors", "j/clients"]);
+    }
+
+    fn sway_tree(windows_on_2: serde_json::Value) -> serde_json::Value {
+        json!({
+            "type": "root",
+            "nodes": [{
+                "type": "output",
+                "name": "DP-1",
+                "nodes": [
+                    { "type": "workspace", "name": "1", "nodes": [
+                        { "type": "con", "visible": true, "nodes": [], "floating_nodes": [] }
+                    ], "floating_nodes": [] },
+                    { "type": "workspace", "name": "2", "nodes": windows_on_2, "floating_nodes": [] }
+                ]
+            }]
+        })
+    }
+
+    #[test]
+    fn sway_visible_workspace_on_output_is_checked_for_windows() {
+        let workspaces = json!([
+            { "name": "1", "output": "DP-1", "visible": false, "focused": false },
+            { "name": "2", "output": "DP-1", "visible": true, "focused": true }
+        ]);
+
+        let empty = sway_tree(json!([]));
+        assert_eq!(sway_workspace_has_windows(&workspaces, &empty, Some("DP-1")), Some(false));
+
+        let split = sway_tree(json!([{ "type": "con", "nodes": [
+            { "type": "con", "visible": true, "nodes": [], "floating_nodes": [] }
+        ], "floating_nodes": [] }]));
+        assert_eq!(sway_workspace_has_windows(&workspaces, &split, None), Some(true));
+    }
+
+    #[test]
+    fn sway_unknown_output_yields_no_answer() {
+        let workspaces = json!([{ "name": "1", "output": "DP-1", "visible": true }]);
+        let tree = sway_tree(json!([]));
+        assert_eq!(sway_workspace_has_windows(&workspaces, &tree, Some("HDMI-A-1")), None);
+    }
+
+    #[test]
+    fn workspace_events_carry_the_focused_monitor() {
+        assert_eq!(
+            parse_event("workspacev2>>3,code\n"),
+            Some(HyprlandEvent::Workspace {
+                id: Some("3".to_string()),
+                name: "code".to_string(),
+            })
+        );
+        assert_eq!(parse_event("activewindow>>kitty,~"), None);
+        assert_eq!(parse_event("garbage"), None);
+
+        let socket = "workspace>>1\nfocusedmon>>HDMI-A-1,2\nopenwindow>>a,2,kitty\nworkspace>>2\n";
+        let (sender, events) = mpsc::channel();
+        let mut focused = Some("DP-1".to_string());
+        assert!(forward_events(socket.as_bytes(), &mut focused, &sender, Vec::new));
+        let event = |monitor: &str, name: &str| WorkspaceEvent {
+            monitor: Some(monitor.to_string()),
+            id: None,
+            name: name.to_string(),
+        };
+        let received: Vec<WorkspaceEvent> = events.try_iter().flatten().collect();
+        assert_eq!(received, [event("DP-1", "1"), event("HDMI-A-1", "2")]);
+        assert_eq!(focused.as_deref(), Some("HDMI-A-1"));
+
+        // With `hyprctl monitors` answering, its view replaces the focused guess.
+        let active = || vec![event("DP-1", "2"), event("HDMI-A-1", "5")];
+        assert!(forward_events("workspace>>2\n".as_bytes(), &mut focused, &sender, active));
+        let received: Vec<WorkspaceEvent> = events.try_iter().flatten().collect();
+        assert_eq!(received, active());
+    }
+
+    #[test]
+    fn active_workspaces_are_read_per_monitor() {
+        let monitors = json!([
+            { "name": "DP-1", "activeWorkspace": { "id": 3, "name": "web" } },
+            { "name": "HDMI-A-1", "activeWorkspace": { "id": -98, "name": "special:scratch" } },
+            { "name": "eDP-1" }
+        ]);
+        let active = active_workspaces_from(&monitors);
+        let event = |monitor: &str, id: &str, name: &str| WorkspaceEvent {
+            monitor: Some(monitor.to_string()),
+            id: Some(id.to_string()),
+            name: name.to_string(),
+        };
+        assert_eq!(
+            active,
+            [event("DP-1", "3", "web"), event("HDMI-A-1", "-98", "special:scratch")]
+        );
+        assert!(active_workspaces_from(&json!({})).is_empty());
+    }
+}
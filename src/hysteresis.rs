@@ -0,0 +1,76 @@
+//! Debouncing for the "should video render" check: the desktop behind a
+//! monitor flips between covered and visible while the user flicks through
+//! workspaces, and following every flip would stop and restart frames several
+//! times a second.
+
+use std::time::{Duration, Instant};
+
+/// Default `visibility_hysteresis_ms`.
+pub const DEFAULT_VISIBILITY_HYSTERESIS: Duration = Duration::from_millis(1000);
+/// Checks in a row a new value must be seen in before it is applied.
+const CONFIRMATIONS: u32 = 2;
+
+#[derive(Debug)]
+pub struct HysteresisFilter<T: PartialEq> {
+    current: T,
+    candidate: Option<(T, u32)>,
+    last_change: Instant,
+    min_interval: Duration,
+}
+
+impl<T: PartialEq + Clone> HysteresisFilter<T> {
+    pub fn new(initial: T, min_interval: Duration, now: Instant) -> Self {
+        Self {
+            current: initial,
+            candidate: None,
+            last_change: now,
+            min_interval,
+        }
+    }
+
+    /// Feeds one observation. Returns the new value once it has been seen in
+    /// two checks in a row and `min_interval` has passed since the last change.
+    pub fn update(&mut self, value: T, now: Instant) -> Option<T> {
+        if value == self.current {
+            self.candidate = None;
+            return None;
+        }
+        let seen = match self.candidate.take() {
+            Some((candidate, seen)) if candidate == value => seen + 1,
+            _ => 1,
+        };
+        if seen < CONFIRMATIONS || now.duration_since(self.last_change) < self.min_interval {
+            self.candidate = Some((value, seen));
+            return None;
+        }
+        self.current = value.clone();
+        self.last_change = now;
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HysteresisFilter;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn changes_need_two_checks_and_the_minimum_interval() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut filter = HysteresisFilter::new(true, Duration::from_millis(1000), start);
+
+        // Too soon after the last change, however often it is seen.
+        assert_eq!(filter.update(false, at(500)), None);
+        assert_eq!(filter.update(false, at(900)), None);
+        assert_eq!(filter.update(false, at(1000)), Some(false));
+        assert_eq!(filter.update(false, at(1100)), None);
+
+        // A flip back that does not persist is dropped.
+        assert_eq!(filter.update(true, at(2500)), None);
+        assert_eq!(filter.update(false, at(3000)), None);
+        assert_eq!(filter.update(true, at(3500)), None);
+        assert_eq!(filter.update(true, at(4000)), Some(true));
+        assert_eq!(filter.update(true, at(4500)), None);
+    }
+}
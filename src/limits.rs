@@ -0,0 +1,262 @@
+//! Optional memory and CPU caps and scheduling priorities for renderer processes.
+//!
+//! Memory is capped with `RLIMIT_AS`, set in the child between fork and exec,
+//! as are the nice value and the I/O scheduling class (`ioprio_set`).
+//! CPU is capped by moving the child into its own cgroup v2 group next to
+//! ours and writing `cpu.max`; that only works where the session's cgroup
+//! tree is delegated to the user (systemd user sessions), so failures are
+//! reported as warnings.
+
+use crate::config::Config;
+use crate::output::{debug, warning};
+use anyhow::{anyhow, Context, Result};
+use nix::sys::resource::{setrlimit, Resource};
+use std::{
+    fs,
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Lower bounds below which a limit would stop any renderer from working.
+pub const MIN_MEMORY_MB: u64 = 100;
+pub const MIN_CPU_PERCENT: u32 = 1;
+pub const NICE_RANGE: std::ops::RangeInclusive<i8> = -20..=19;
+pub const IONICE_CLASSES: std::ops::RangeInclusive<u8> = 1..=3;
+
+const IONICE_CLASS_REALTIME: u8 = 1;
+/// Priority within the best-effort and realtime classes, the `ionice` default.
+const IONICE_LEVEL: u32 = 4;
+const IOPRIO_CLASS_SHIFT: u32 = 13;
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+const CAP_SYS_NICE: u32 = 23;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const CGROUP_PREFIX: &str = "papdieo-renderer-";
+const CPU_PERIOD_US: u64 = 100_000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    pub memory_mb: Option<u64>,
+    pub cpu_percent: Option<u32>,
+    pub nice: Option<i8>,
+    pub ionice_class: Option<u8>,
+}
+
+impl ResourceLimits {
+    /// Limits from the config; values failing the sanity check are dropped with a warning.
+    pub fn from_config(cfg: &Config) -> Self {
+        let memory_mb = cfg.max_renderer_memory_mb.filter(|&mb| {
+            let sane = mb > MIN_MEMORY_MB;
+            if !sane {
+                warning!(
+                    "warning: ignoring max_renderer_memory_mb = {}, must be more than {}",
+                    mb,
+                    MIN_MEMORY_MB
+                );
+            }
+            sane
+        });
+        let cpu_percent = cfg.max_renderer_cpu_percent.filter(|&percent| {
+            let sane = percent > MIN_CPU_PERCENT;
+            if !sane {
+                warning!(
+                    "warning: ignoring max_renderer_cpu_percent = {}, must be more than {}",
+                    percent,
+                    MIN_CPU_PERCENT
+                );
+            }
+            sane
+        });
+        let can_raise_priority = has_cap_sys_nice();
+        let nice = cfg.renderer_nice.filter(|&nice| {
+            if !NICE_RANGE.contains(&nice) {
+                warning!("warning: ignoring renderer_nice = {}, must be -20 to 19", nice);
+                return false;
+            }
+            if nice < 0 && !can_raise_priority {
+                warning!(
+                    "warning: ignoring renderer_nice = {}, negative values need CAP_SYS_NICE",
+                    nice
+                );
+                return false;
+            }
+            true
+        });
+        let ionice_class = cfg.renderer_ionice_class.filter(|&class| {
+            if !IONICE_CLASSES.contains(&class) {
+                warning!("warning: ignoring renderer_ionice_class = {}, must be 1 to 3", class);
+                return false;
+            }
+            if class == IONICE_CLASS_REALTIME && !can_raise_priority {
+                warning!("warning: ignoring renderer_ionice_class = 1, it needs CAP_SYS_NICE");
+                return false;
+            }
+            true
+        });
+        Self {
+            memory_mb,
+            cpu_percent,
+            nice,
+            ionice_class,
+        }
+    }
+
+    /// Caps the address space of the process `command` is about to start and
+    /// sets its CPU and I/O priority.
+    pub fn apply_before_exec(&self, command: &mut Command) {
+        let memory = self.memory_mb.map(|mb| mb.saturating_mul(1024 * 1024));
+        let nice = self.nice;
+        let ioprio = self.ionice_class.map(ioprio_value);
+        if memory.is_none() && nice.is_none() && ioprio.is_none() {
+            return;
+        }
+        // SAFETY: setrlimit, setpriority and ioprio_set are async-signal-safe
+        // and the closure allocates nothing.
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(bytes) = memory {
+                    setrlimit(Resource::RLIMIT_AS, bytes, bytes).map_err(std::io::Error::from)?;
+                }
+                // A priority the kernel refuses leaves the renderer at the default.
+                if let Some(nice) = nice {
+                    libc::setpriority(libc::PRIO_PROCESS, 0, libc::c_int::from(nice));
+                }
+                if let Some(ioprio) = ioprio {
+                    libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio);
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// Moves the started process into a CPU-limited cgroup.
+    pub fn apply_after_spawn(&self, pid: u32) {
+        let Some(percent) = self.cpu_percent else {
+            return;
+        };
+        match limit_cpu(pid, percent) {
+            Ok(group) => debug!(
+                "pid {} limited to {}% CPU in {}",
+                pid,
+                percent,
+                group.display()
+            ),
+            Err(error) => warning!(
+                "warning: could not apply max_renderer_cpu_percent: {:#}",
+                error
+            ),
+        }
+    }
+}
+
+fn limit_cpu(pid: u32, percent: u32) -> Result<PathBuf> {
+    let own = fs::read_to_string("/proc/self/cgroup").context("failed to read /proc/self/cgroup")?;
+    let own = own
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .ok_or_else(|| anyhow!("cgroup v2 is not mounted"))?;
+    let own = Path::new(CGROUP_ROOT).join(own.trim().trim_start_matches('/'));
+    let parent = own.parent().filter(|p| p.starts_with(CGROUP_ROOT)).unwrap_or(&own);
+
+    let controllers = fs::read_to_string(parent.join("cgroup.subtree_control")).unwrap_or_default();
+    if !controllers.split_whitespace().any(|c| c == "cpu") {
+        return Err(anyhow!("cpu controller is not enabled in {}", parent.display()));
+    }
+
+    remove_stale_groups(parent);
+    let group = parent.join(format!("{}{}", CGROUP_PREFIX, pid));
+    fs::create_dir(&group).with_context(|| format!("failed to create {}", group.display()))?;
+    fs::write(group.join("cpu.max"), cpu_max(percent))
+        .with_context(|| format!("failed to write {}/cpu.max", group.display()))?;
+    fs::write(group.join("cgroup.procs"), pid.to_string())
+        .with_context(|| format!("failed to move pid {} into {}", pid, group.display()))?;
+    Ok(group)
+}
+
+/// Groups of renderers that have exited are empty and can be removed.
+fn remove_stale_groups(parent: &Path) {
+    let Ok(entries) = fs::read_dir(parent) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let is_ours = entry
+            .file_name()
+            .to_str()
+            .map(|name| name.starts_with(CGROUP_PREFIX))
+            .unwrap_or(false);
+        if is_ours {
+            let _ = fs::remove_dir(entry.path());
+        }
+    }
+}
+
+/// The `ioprio_set` value for an I/O scheduling class; idle has no levels.
+fn ioprio_value(class: u8) -> libc::c_int {
+    let level = if class == 3 { 0 } else { IONICE_LEVEL };
+    ((u32::from(class) << IOPRIO_CLASS_SHIFT) | level) as libc::c_int
+}
+
+fn has_cap_sys_nice() -> bool {
+    fs::read_to_string("/proc/self/status")
+        .map(|status| status_has_cap_sys_nice(&status))
+        .unwrap_or(false)
+}
+
+/// Whether the effective capabilities in `/proc/<pid>/status` include CAP_SYS_NICE.
+fn status_has_cap_sys_nice(status: &str) -> bool {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+        .is_some_and(|caps| caps & (1 << CAP_SYS_NICE) != 0)
+}
+
+/// `cpu.max` contents: quota and period in microseconds; 100% is one full core.
+fn cpu_max(percent: u32) -> String {
+    format!("{} {}", percent as u64 * CPU_PERIOD_US / 100, CPU_PERIOD_US)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cpu_max, ioprio_value, status_has_cap_sys_nice, ResourceLimits};
+    use crate::config::Config;
+
+    #[test]
+    fn insane_limits_are_dropped_and_cpu_max_scales_with_cores() {
+        let cfg = Config {
+            max_renderer_memory_mb: Some(64),
+            max_renderer_cpu_percent: Some(150),
+            renderer_nice: Some(20),
+            renderer_ionice_class: Some(0),
+            ..Config::default()
+        };
+        assert_eq!(
+            ResourceLimits::from_config(&cfg),
+            ResourceLimits {
+                memory_mb: None,
+                cpu_percent: Some(150),
+                nice: None,
+                ionice_class: None,
+            }
+        );
+        let defaults = ResourceLimits::from_config(&Config::default());
+        assert_eq!((defaults.nice, defaults.ionice_class), (Some(10), Some(3)));
+
+        assert_eq!(cpu_max(50), "50000 100000");
+        assert_eq!(cpu_max(150), "150000 100000");
+    }
+
+    #[test]
+    fn io_priorities_and_capabilities() {
+        assert_eq!(ioprio_value(1), (1 << 13) | 4);
+        assert_eq!(ioprio_value(2), (2 << 13) | 4);
+        assert_eq!(ioprio_value(3), 3 << 13);
+
+        let status = |caps: &str| format!("Name:\tpapdieo\nCapEff:\t{}\nCapBnd:\t1ff\n", caps);
+        assert!(!status_has_cap_sys_nice(&status("0000000000000000")));
+        assert!(status_has_cap_sys_nice(&status("0000000000800000")));
+        assert!(status_has_cap_sys_nice(&status("000001ffffffffff")));
+        assert!(!status_has_cap_sys_nice("Name:\tpapdieo\n"));
+    }
+}
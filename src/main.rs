@@ -1,44 +1,136 @@
+mod backoff;
 mod cli;
+mod colorspace;
 mod config;
+mod config_edit;
+mod completions;
+mod config_watch;
+mod dmabuf;
+mod effects;
+mod fifo;
+mod formats;
+mod gpu;
+mod hooks;
+mod hyprland_ipc;
+mod hysteresis;
+mod limits;
+mod metrics;
+mod metadata;
+mod migrate;
+mod monitors;
+mod output;
+mod palette;
 mod picker;
+mod preview;
+mod profile;
+mod power;
+mod prepick;
+mod pull;
+mod scheduler;
+mod shm;
+mod shm_cache;
+mod source;
+mod spread;
+mod state;
+mod sysinfo;
+mod systemd;
+mod transition;
+mod util;
 mod wallpaper;
+mod watcher;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use clap::Parser;
 use fs2::FileExt;
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     env,
     fs::File,
     fs::OpenOptions,
-    io,
+    io::{self, Write},
     path::{Path, PathBuf},
-    process::{Command as ProcessCommand, Stdio},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::process::ExitStatusExt,
+    },
+    process::{Child, Command as ProcessCommand, ExitStatus, Stdio},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
     thread,
-    time::{Duration, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
-use cli::{Command, PapdieoArgs};
-use config::FitMode;
+use cli::{
+    Command, ConfigAction, DaemonArgs, PapdieoArgs, ProfileAction, SchedulerCommand, TagAction,
+};
+use config::{CompositorType, FitMode, RotationMode, ScaleFilter, SurfaceAnchor, Transition};
+use effects::WatermarkConfig;
+use output::{debug, info, warning};
+use scheduler::ScheduleTarget;
+use transition::EasingCurve;
+use wallpaper::RenderOptions;
 
 const DAEMON_PID_PATH: &str = "/tmp/papdieo-daemon.pid";
 const DAEMON_LOG_PATH: &str = "/tmp/papdieo-daemon.log";
 const DAEMON_LOCK_PATH: &str = "/tmp/papdieo-daemon.lock";
 const DAEMON_STARTUP_RETRY_SECONDS: u64 = 3;
+/// How long a freshly spawned daemon must survive before it counts as started.
+const DAEMON_STARTUP_CHECK: Duration = Duration::from_millis(350);
+const DAEMON_PID_WAIT: Duration = Duration::from_secs(5);
+const DAEMON_WATCHDOG_INTERVAL: Duration = Duration::from_secs(30);
+const DAEMON_RESTART_LIMIT: u32 = 5;
+const DAEMON_RESTART_WINDOW: Duration = Duration::from_secs(600);
+const MISSING_DIR_INITIAL_WAIT: Duration = Duration::from_secs(5);
+const MISSING_DIR_MAX_WAIT: Duration = Duration::from_secs(60);
+const MISSING_DIR_LOG_INTERVAL: Duration = Duration::from_secs(60);
+const RENDERER_LOG_PATH: &str = "/tmp/papdieo.log";
+/// How long a detached renderer must survive before it counts as started.
+const RENDERER_STARTUP_CHECK: Duration = Duration::from_millis(4000);
+/// Shorter than `RENDERER_STARTUP_CHECK`: `papdieo stop` sends SIGKILL after 2s.
+const SHUTDOWN_HANDOFF: Duration = Duration::from_millis(1500);
+/// How often the daemon checks for AC/battery changes.
+const POWER_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const RENDERER_RESTART_LIMIT: u32 = 3;
+const RENDERER_RESTART_WINDOW: Duration = Duration::from_secs(60);
+/// How long a renderer stopped for its resource use has to exit before SIGKILL.
+const RENDERER_TERM_GRACE: Duration = Duration::from_secs(5);
+const COMPOSITOR_WAIT_MAX: Duration = Duration::from_secs(30);
+const COMPOSITOR_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const ROTATE_SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(200);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct MonitorAssignment {
     monitor: String,
     path: PathBuf,
     fit: FitMode,
+    #[serde(default)]
+    transition: Option<Transition>,
+    #[serde(default)]
+    offset: Option<(i32, i32)>,
+}
+
+impl MonitorAssignment {
+    /// `base` carries the settings shared by every monitor.
+    fn render_options(&self, base: &RenderOptions) -> RenderOptions {
+        RenderOptions {
+            fit_mode: self.fit,
+            transition: self.transition.unwrap_or(Transition::None),
+            offset: self.offset.unwrap_or_default(),
+            ..base.clone()
+        }
+    }
 }
 
 fn main() {
     let args = PapdieoArgs::parse();
+    output::set_verbosity(if args.quiet {
+        -1
+    } else {
+        args.verbose.min(i8::MAX as u8) as i8
+    });
     if let Err(error) = run(args) {
         if is_broken_pipe_error(&error) {
             return;
@@ -59,137 +151,991 @@ fn is_broken_pipe_error(error: &anyhow::Error) -> bool {
 }
 
 fn run(args: PapdieoArgs) -> Result<()> {
+    // Handled before loading the config so a broken config can still be replaced.
+    if let Some(Command::ExportConfig { output, force }) = &args.command {
+        return export_config(output.as_deref(), *force);
+    }
+    if let Some(Command::ValidateConfig { path }) = &args.command {
+        let path = path
+            .clone()
+            .or_else(|| args.config.clone())
+            .or_else(config::default_config_path)
+            .ok_or_else(|| anyhow!("could not determine config path"))?;
+        let code = validate_config(&path)?;
+        if code != 0 {
+            std::process::exit(code);
+        }
+        return Ok(());
+    }
+    if let Some(Command::Migrate {
+        from,
+        config_path,
+        write,
+        force,
+    }) = &args.command
+    {
+        let generated = migrate::migrate(*from, config_path.as_deref())?;
+        if !write {
+            print!("{}", generated);
+            return Ok(());
+        }
+        let path = args
+            .config
+            .clone()
+            .or_else(config::default_config_path)
+            .ok_or_else(|| anyhow!("could not determine config path"))?;
+        if path.exists() && !force {
+            return Err(anyhow!("{} already exists; pass --force to overwrite", path.display()));
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        std::fs::write(&path, generated)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        info!("Wrote migrated config to {}", path.display());
+        return Ok(());
+    }
+    if let Some(Command::Init { force }) = &args.command {
+        return init(args.config.as_deref(), *force);
+    }
+    if let Some(Command::Completions { shell }) = &args.command {
+        print!("{}", completions::generate(*shell)?);
+        return Ok(());
+    }
+    if let Some(Command::Config { action }) = &args.command {
+        return edit_config(args.config.as_deref(), action);
+    }
+
     let config = config::Config::load_or_default(args.config.as_deref())?;
-    let default_fps = config.video_fps.unwrap_or(60);
-    let default_fit = config.fit_mode.unwrap_or(FitMode::Cover);
-    let default_interval = config.rotation_seconds.unwrap_or(300);
+    let (no_video, no_images) = match &args.command {
+        Some(
+            Command::Random {
+                no_video,
+                no_images,
+                ..
+            }
+            | Command::Next {
+                no_video,
+                no_images,
+                ..
+            }
+            | Command::Rotate {
+                no_video,
+                no_images,
+                ..
+            }
+            | Command::List {
+                no_video,
+                no_images,
+                ..
+            },
+        ) => (*no_video, *no_images),
+        _ => (false, false),
+    };
+    let media = media_filter(&config, no_video, no_images);
+    // No thread has started yet, so the environment can still be changed safely.
+    let gpu_index = match &args.command {
+        Some(Command::Set { gpu, .. } | Command::__RunInternal { gpu, .. }) => *gpu,
+        _ => None,
+    };
+    gpu::export_vaapi_device(gpu_index.or(config.gpu_index));
+    // Daemon renderers run in this process. They take the socket from their
+    // options, so a reload reaches them; the variables are set for the rest.
+    if let Some(Command::Daemon { options, .. } | Command::__DaemonInternal { options }) =
+        &args.command
+    {
+        let profile_config =
+            profile::resolve_config_path(args.config.as_deref(), options.profile.as_deref())?;
+        let mut daemon_cfg = config::Config::load_or_default(profile_config.as_deref())?;
+        options.overrides.apply(&mut daemon_cfg);
+        for (key, value) in session_environment_overrides(&daemon_cfg) {
+            env::set_var(key, value);
+        }
+    }
+    let base_options = base_render_options(&config);
+    let render_options = |monitor: Option<&str>, fps: Option<u32>, fit: Option<FitMode>| {
+        RenderOptions {
+            fps: fps.unwrap_or(base_options.fps),
+            fit_mode: fit.unwrap_or(base_options.fit_mode),
+            transition: resolve_transition(&config, monitor).unwrap_or(Transition::None),
+            ..base_options.clone()
+        }
+    };
+    let renderer_environment = build_renderer_environment(&config);
+    let default_interval = config
+        .rotation_interval()
+        .unwrap_or(Duration::from_secs(300));
 
     match args.command {
-        None => start_daemon_service(args.config.as_deref()),
-        Some(Command::Daemon { foreground }) => {
-            if foreground {
-                run_daemon_loop(args.config.as_deref())
+        None => {
+            let options = DaemonArgs::default();
+            let paths = DaemonPaths::resolve(&options, &config);
+            start_daemon_service(args.config.as_deref(), &options, &paths)
+        }
+        Some(Command::Daemon {
+            foreground,
+            once,
+            options,
+        }) => {
+            let paths = DaemonPaths::resolve(&options, &config);
+            let profile_config =
+                profile::resolve_config_path(args.config.as_deref(), options.profile.as_deref())?;
+            if once {
+                run_daemon_once(profile_config.as_deref(), &options, &paths)
+            } else if foreground {
+                if options.pid_stdout {
+                    println!("PID={}", std::process::id());
+                }
+                run_daemon_loop(args.config.as_deref(), &options, &paths)
             } else {
-                start_daemon_service(args.config.as_deref())
+                start_daemon_service(args.config.as_deref(), &options, &paths)
             }
         }
-        Some(Command::Restart) => restart_daemon_service(args.config.as_deref()),
+        Some(Command::Restart) => restart_daemon_service(
+            args.config.as_deref(),
+            &DaemonPaths::resolve(&DaemonArgs::default(), &config),
+        ),
         Some(Command::Set {
             path,
+            mirror,
+            script,
             monitor,
+            span_monitors,
+            monitor_regex,
+            monitor_glob,
+            glob_ignore_case,
             fps,
             fit,
+            filter,
+            offset_x,
+            offset_y,
+            focus_area,
+            focus_area_relative,
+            tile_size,
+            tile_offset,
+            layer_namespace,
+            exclusive_zone,
+            anchor,
+            start_time,
+            delay,
+            loop_count,
+            gpu,
+            list_gpus,
+            mute,
+            brightness,
+            contrast,
+            effect,
+            opacity_curve,
+            auto_rotate,
+            watermark,
+            all_outputs,
+            cache_key,
+            cache_stream,
             detach,
-        }) => run_renderer(
-            path,
-            monitor.or_else(|| config.monitor.clone()),
-            fps.unwrap_or(default_fps),
-            fit.unwrap_or(default_fit),
-            detach,
-        ),
+            unlock,
+        }) => {
+            if list_gpus {
+                print_gpus();
+                return Ok(());
+            }
+            if let Some(index) = gpu {
+                if gpu::resolve_gpu_device(index).is_none() {
+                    return Err(anyhow!(
+                        "GPU {} not found; `papdieo set --list-gpus` shows the available GPUs",
+                        index
+                    ));
+                }
+            }
+            let monitor_regex = monitor_regex
+                .or_else(|| config.monitor_regex.clone())
+                .filter(|_| {
+                    monitor.is_none()
+                        && span_monitors.is_empty()
+                        && monitor_glob.is_none()
+                        && !all_outputs
+                });
+            let monitor = monitor
+                .or_else(|| config.monitor.clone())
+                .filter(|_| !all_outputs);
+            let (path, fps, fit) = match (path, mirror, script) {
+                (Some(path), _, _) => (path, fps, fit),
+                (None, _, Some(script)) => {
+                    let dir = &config.wallpaper_dir;
+                    (picker::run_picker_script(&script, monitor.as_deref(), dir)?, fps, fit)
+                }
+                (None, Some(source), None) => {
+                    let current = find_wallpaper_for_monitor(&source).ok_or_else(|| {
+                        anyhow!(
+                            "no wallpaper found for monitor '{}' (is the daemon or a renderer running on it?)",
+                            source
+                        )
+                    })?;
+                    debug!("mirroring {} from monitor '{}'", current.path.display(), source);
+                    (current.path, fps.or(current.fps), fit.or(current.fit))
+                }
+                (None, None, None) => unreachable!("clap requires a path, --mirror or --script"),
+            };
+            let path = if cache_stream {
+                cache_stream_source(path)?
+            } else {
+                path
+            };
+            let is_video = wallpaper::is_video_file(&path)
+                || source::WallpaperSource::parse(&path)?.is_stream();
+            if all_outputs && is_video {
+                return Err(anyhow!(
+                    "--all-outputs only draws still images, not {}",
+                    path.display()
+                ));
+            }
+            let mut options = render_options(monitor.as_deref(), fps, fit);
+            options.scale_filter = filter.or(options.scale_filter);
+            options.offset = (
+                offset_x.unwrap_or(options.offset.0),
+                offset_y.unwrap_or(options.offset.1),
+            );
+            if let Some(area) = focus_area {
+                options.focus_area = focus_area_fractions(&path, area, focus_area_relative)?;
+            }
+            options.tile_size = tile_size.or(options.tile_size);
+            options.offset = tile_offset.unwrap_or(options.offset);
+            options.layer_namespace = layer_namespace.unwrap_or(options.layer_namespace);
+            options.exclusive_zone = exclusive_zone.unwrap_or(options.exclusive_zone);
+            options.anchor = anchor.unwrap_or(options.anchor);
+            options.video_start = start_time
+                .map(Duration::from_millis)
+                .unwrap_or(options.video_start);
+            options.startup_delay = delay
+                .or(config.startup_delay_ms)
+                .map(Duration::from_millis)
+                .unwrap_or_default();
+            options.video_loops = loop_count;
+            options.gpu_index = gpu.or(options.gpu_index);
+            options.mute_audio |= mute;
+            options.brightness = brightness.unwrap_or(options.brightness);
+            options.contrast = contrast.unwrap_or(options.contrast);
+            if !effect.is_empty() {
+                options.effects = effect;
+            }
+            options.transition_curve = opacity_curve.unwrap_or(options.transition_curve);
+            options.auto_rotate |= auto_rotate;
+            if let Some(path) = watermark {
+                let watermark = WatermarkConfig::with_path(path, config.watermark.as_ref());
+                options.watermark = Some(watermark);
+            }
+            options.all_outputs = all_outputs;
+            options.frame_cache_key = cache_key;
+            if let Some(pattern) = monitor_glob {
+                let monitors = find_monitors_by_glob(&config, &pattern, glob_ignore_case)?;
+                info!("'{}' matches {}", pattern, monitors.join(", "));
+                return set_on_monitors(
+                    &config,
+                    &path,
+                    &monitors,
+                    options,
+                    &renderer_environment,
+                    detach,
+                    unlock,
+                );
+            }
+            if let Some(pattern) = monitor_regex {
+                let monitors = find_matching_monitors(&config, &pattern)?;
+                return set_on_monitors(
+                    &config,
+                    &path,
+                    &monitors,
+                    options,
+                    &renderer_environment,
+                    detach,
+                    unlock,
+                );
+            }
+            if !span_monitors.is_empty() {
+                return span_wallpaper(
+                    &config,
+                    &path,
+                    &span_monitors,
+                    options,
+                    &renderer_environment,
+                    detach,
+                    unlock,
+                );
+            }
+            let on_shown = wallpaper_shown(&config, monitor.as_deref(), &path, unlock);
+            run_renderer(path, monitor, options, &renderer_environment, detach, on_shown)
+        }
+        Some(Command::BatchSet { manifest }) => {
+            batch_set(&manifest, render_options, &renderer_environment)
+        }
         Some(Command::Random {
             dir,
+            seed,
+            script,
+            tag,
+            aspect_filter,
+            aspect_tolerance,
             monitor,
             fps,
             fit,
+            brightness,
+            contrast,
             detach,
+            unlock,
+            ..
         }) => {
+            let monitor = monitor.or_else(|| config.monitor.clone());
             let media_dir = dir.unwrap_or_else(|| config.wallpaper_dir.clone());
-            let image = picker::pick_random_wallpaper(&media_dir)?;
-            run_renderer(
-                image,
-                monitor.or_else(|| config.monitor.clone()),
-                fps.unwrap_or(default_fps),
-                fit.unwrap_or(default_fit),
-                detach,
-            )
+            let image = match (script, aspect_filter, tag, seed) {
+                (Some(script), ..) => {
+                    picker::run_picker_script(&script, monitor.as_deref(), &media_dir)?
+                }
+                (None, Some(ratio), tag, seed) => picker::pick_random_among(
+                    &aspect_filtered_wallpapers(
+                        &media_dir,
+                        tag.as_deref(),
+                        ratio,
+                        aspect_tolerance,
+                        media,
+                    )?,
+                    seed,
+                )?,
+                (None, None, Some(tag), seed) => {
+                    picker::pick_random_tagged_wallpaper(&media_dir, &tag, seed, media)?
+                }
+                (None, None, None, Some(seed)) => {
+                    picker::pick_random_wallpaper_seeded(&media_dir, seed, media)?
+                }
+                (None, None, None, None) => picker::pick_random_wallpaper(&media_dir, media)?,
+            };
+            let mut options = render_options(monitor.as_deref(), fps, fit);
+            options.brightness = brightness.unwrap_or(options.brightness);
+            options.contrast = contrast.unwrap_or(options.contrast);
+            let on_shown = wallpaper_shown(&config, monitor.as_deref(), &image, unlock);
+            run_renderer(image, monitor, options, &renderer_environment, detach, on_shown)
         }
         Some(Command::Next {
             dir,
+            tag,
+            aspect_filter,
+            aspect_tolerance,
             monitor,
             fps,
             fit,
+            brightness,
+            contrast,
             detach,
+            unlock,
+            ..
         }) => {
+            // A plain `next` while the daemon runs asks it to rotate now instead of
+            // starting a renderer that would fight it for the monitor.
+            let plain = dir.is_none()
+                && tag.is_none()
+                && aspect_filter.is_none()
+                && fps.is_none()
+                && fit.is_none()
+                && brightness.is_none()
+                && contrast.is_none()
+                && !detach;
+            let daemon = DaemonPaths::resolve(&DaemonArgs::default(), &config);
+            if plain && daemon_is_running(&daemon.pid) {
+                let signal = match &monitor {
+                    Some(monitor) => state::RotateSignal::Monitor(monitor.clone()),
+                    None => state::RotateSignal::All,
+                };
+                state::request_rotation(&signal)?;
+                info!(
+                    "Asked the daemon to rotate {}",
+                    describe_rotation(monitor.as_slice())
+                );
+                return Ok(());
+            }
             let media_dir = dir.unwrap_or_else(|| config.wallpaper_dir.clone());
-            let image = picker::pick_next_wallpaper(&media_dir)?;
-            run_renderer(
-                image,
-                monitor.or_else(|| config.monitor.clone()),
-                fps.unwrap_or(default_fps),
-                fit.unwrap_or(default_fit),
-                detach,
-            )
+            let image = match (aspect_filter, tag) {
+                (Some(ratio), tag) => picker::pick_next_among(&aspect_filtered_wallpapers(
+                    &media_dir,
+                    tag.as_deref(),
+                    ratio,
+                    aspect_tolerance,
+                    media,
+                )?)?,
+                (None, Some(tag)) => {
+                    picker::pick_next_tagged_wallpaper(&media_dir, &tag, media)?
+                }
+                (None, None) => picker::pick_next_wallpaper(&media_dir, media)?,
+            };
+            let monitor = monitor.or_else(|| config.monitor.clone());
+            let mut options = render_options(monitor.as_deref(), fps, fit);
+            options.brightness = brightness.unwrap_or(options.brightness);
+            options.contrast = contrast.unwrap_or(options.contrast);
+            let on_shown = wallpaper_shown(&config, monitor.as_deref(), &image, unlock);
+            run_renderer(image, monitor, options, &renderer_environment, detach, on_shown)
         }
         Some(Command::Rotate {
             dir,
             monitor,
             interval,
+            sequential,
+            no_overlap,
+            script,
             fps,
             fit,
-        }) => run_rotate_loop(
-            dir.unwrap_or_else(|| config.wallpaper_dir.clone()),
-            monitor.or_else(|| config.monitor.clone()),
-            interval.unwrap_or(default_interval),
-            fps.unwrap_or(default_fps),
-            fit.unwrap_or(default_fit),
+            ..
+        }) => {
+            let monitor = monitor.or_else(|| config.monitor.clone());
+            let options = render_options(monitor.as_deref(), fps, fit);
+            let mode = if sequential {
+                RotationMode::Sequential
+            } else {
+                config.rotation_mode.unwrap_or(RotationMode::Random)
+            };
+            let other_monitors = match &monitor {
+                Some(monitor) if no_overlap || config.no_overlap.unwrap_or(false) => {
+                    configured_or_detected_monitors(&config)?
+                        .into_iter()
+                        .filter(|other| !other.eq_ignore_ascii_case(monitor))
+                        .collect()
+                }
+                _ => Vec::new(),
+            };
+            run_rotate_loop_tracked(
+                dir.unwrap_or_else(|| config.wallpaper_dir.clone()),
+                script.or_else(|| config.picker_script.clone()),
+                monitor,
+                interval.unwrap_or(default_interval),
+                mode,
+                media,
+                other_monitors,
+                options,
+                &renderer_environment,
+            )
+        }
+        Some(Command::ExportSession { output }) => export_session(output.as_deref()),
+        Some(Command::Status { json }) => print_status(
+            json,
+            &DaemonPaths::resolve(&DaemonArgs::default(), &config),
+        ),
+        Some(Command::Monitors { json }) => list_monitors(&config, json),
+        Some(Command::Pause { monitor }) => set_paused(monitor.as_deref(), true),
+        Some(Command::Resume { monitor }) => set_paused(monitor.as_deref(), false),
+        Some(Command::Pin { monitor }) => {
+            set_pinned(&monitor, true, base_render_options(&config).wayland_socket.as_deref())
+        }
+        Some(Command::Unpin { monitor }) => {
+            set_pinned(&monitor, false, base_render_options(&config).wayland_socket.as_deref())
+        }
+        Some(Command::Lock { monitor }) => {
+            set_locked(&monitor, true, base_render_options(&config).wayland_socket.as_deref())
+        }
+        Some(Command::Unlock { monitor }) => {
+            set_locked(&monitor, false, base_render_options(&config).wayland_socket.as_deref())
+        }
+        Some(Command::Scheduler {
+            command: SchedulerCommand::List { count },
+        }) => list_schedule(&config, count),
+        Some(Command::Pull {
+            url,
+            dir,
+            set,
+            monitor,
+        }) => {
+            let saved = pull::pull(&url, &dir.unwrap_or_else(|| config.wallpaper_dir.clone()))?;
+            println!("{}", saved.display());
+            if !set {
+                return Ok(());
+            }
+            let monitor = monitor.or_else(|| config.monitor.clone());
+            let options = render_options(monitor.as_deref(), None, None);
+            let on_shown = wallpaper_shown(&config, monitor.as_deref(), &saved, false);
+            run_renderer(saved, monitor, options, &renderer_environment, true, on_shown)
+        }
+        Some(Command::Tag { action }) => edit_tag(action),
+        Some(Command::Profile { action }) => manage_profile(
+            action,
+            args.config.as_deref(),
+            &DaemonPaths::resolve(&DaemonArgs::default(), &config),
         ),
-        Some(Command::List) => {
-            let images = picker::list_wallpapers(&config.wallpaper_dir)?;
-            for img in images {
-                println!("{}", img.display());
+        Some(Command::List {
+            preview,
+            tag,
+            max_depth,
+            max_results,
+            min_size,
+            max_size,
+            grouped,
+            deduplicate,
+            deduplicate_threshold,
+            json,
+            ..
+        }) => {
+            let max_depth = max_depth.or(config.recursive_depth).unwrap_or(0);
+            // With a filter the limit can only be applied once it has run.
+            let sized = min_size.is_some() || max_size.is_some();
+            let walk_limit = max_results.filter(|_| tag.is_none() && !sized && !deduplicate);
+            let mut images = picker::list_wallpapers_in_dirs_recursive(
+                &global_media_dirs(&config),
+                Some(max_depth),
+                walk_limit,
+                media,
+            )?;
+            if let Some(tag) = &tag {
+                images.retain(|img| metadata::has_tag(img, tag));
+            }
+            if sized {
+                images.retain(|img| picker::size_in_range(img, min_size, max_size));
+            }
+            if deduplicate {
+                let threshold =
+                    deduplicate_threshold.unwrap_or(palette::DEFAULT_DUPLICATE_THRESHOLD);
+                images = picker::deduplicate(images, threshold);
+            }
+            if let Some(max_results) = max_results {
+                images.truncate(max_results);
+            }
+            // Without subdirectories there is nothing to group.
+            let grouped = grouped && max_depth > 0;
+            if json {
+                let value = if grouped {
+                    picker::group_by_directory(images)
+                        .into_iter()
+                        .map(|(dir, files)| serde_json::json!({"directory": dir, "files": files}))
+                        .collect()
+                } else {
+                    serde_json::to_value(&images)?
+                };
+                println!("{}", serde_json::to_string_pretty(&value)?);
+                return Ok(());
+            }
+
+            let groups = if grouped {
+                picker::group_by_directory(images)
+            } else {
+                vec![(PathBuf::new(), images)]
+            };
+            let preview = preview::resolve_mode(preview);
+            let mut stdout = io::stdout().lock();
+            for (index, (dir, files)) in groups.into_iter().enumerate() {
+                if grouped {
+                    if index > 0 {
+                        writeln!(stdout)?;
+                    }
+                    writeln!(stdout, "=== {} ===", home_relative(&dir))?;
+                }
+                for img in files {
+                    writeln!(stdout, "{}", img.display())?;
+                    if preview == preview::PreviewMode::None {
+                        continue;
+                    }
+                    match preview::render(&img, preview) {
+                        Ok(thumbnail) => write!(stdout, "{}", thumbnail)?,
+                        Err(error) => {
+                            warning!("warning: no preview for {}: {:#}", img.display(), error)
+                        }
+                    }
+                    stdout.flush()?;
+                }
+            }
+            Ok(())
+        }
+        Some(Command::Dedup {
+            threshold,
+            max_depth,
+            json,
+        }) => {
+            let images = picker::list_wallpapers_in_dirs_recursive(
+                &global_media_dirs(&config),
+                Some(max_depth.or(config.recursive_depth).unwrap_or(0)),
+                None,
+                media,
+            )?;
+            let threshold = threshold.unwrap_or(palette::DEFAULT_DUPLICATE_THRESHOLD);
+            let groups = picker::near_duplicate_groups(&images, threshold);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&groups)?);
+                return Ok(());
+            }
+            let mut stdout = io::stdout().lock();
+            for (index, group) in groups.iter().enumerate() {
+                if index > 0 {
+                    writeln!(stdout)?;
+                }
+                for (position, path) in group.iter().enumerate() {
+                    let role = if position == 0 { "keep" } else { "dup " };
+                    writeln!(stdout, "{}  {}", role, path.display())?;
+                }
             }
             Ok(())
         }
+        Some(Command::ExportConfig { .. })
+        | Some(Command::Migrate { .. })
+        | Some(Command::ValidateConfig { .. })
+        | Some(Command::Config { .. })
+        | Some(Command::Init { .. })
+        | Some(Command::Completions { .. }) => {
+            unreachable!("handled before config load")
+        }
         Some(Command::__RunInternal {
             path,
             assignments,
             monitor,
             fps,
             fit,
+            transition,
+            filter,
+            offset_x,
+            offset_y,
+            focus_area,
+            tile_size,
+            compositor,
+            layer_namespace,
+            exclusive_zone,
+            anchor,
+            start_time,
+            delay,
+            loop_count,
+            gpu,
+            mute,
+            brightness,
+            contrast,
+            effect,
+            blur_sigma,
+            opacity_curve,
+            auto_rotate,
+            watermark,
+            all_outputs,
+            cache_key,
         }) => {
-            let resolved_fps = fps.unwrap_or(default_fps);
+            let base = RenderOptions {
+                fps: fps.unwrap_or(base_options.fps),
+                video_start: start_time
+                    .map(Duration::from_millis)
+                    .unwrap_or(base_options.video_start),
+                startup_delay: delay.map(Duration::from_millis).unwrap_or_default(),
+                scale_filter: filter.or(base_options.scale_filter),
+                focus_area: focus_area.or(base_options.focus_area),
+                tile_size: tile_size.or(base_options.tile_size),
+                compositor: compositor.unwrap_or(base_options.compositor),
+                layer_namespace: layer_namespace.unwrap_or(base_options.layer_namespace.clone()),
+                exclusive_zone: exclusive_zone.unwrap_or(base_options.exclusive_zone),
+                anchor: anchor.unwrap_or(base_options.anchor),
+                video_loops: loop_count.unwrap_or(base_options.video_loops),
+                gpu_index: gpu.or(base_options.gpu_index),
+                mute_audio: mute || base_options.mute_audio,
+                brightness: brightness.unwrap_or(base_options.brightness),
+                contrast: contrast.unwrap_or(base_options.contrast),
+                effects: if effect.is_empty() {
+                    base_options.effects.clone()
+                } else {
+                    effect
+                },
+                blur_sigma: blur_sigma.unwrap_or(base_options.blur_sigma),
+                transition_curve: opacity_curve.unwrap_or(base_options.transition_curve),
+                auto_rotate: auto_rotate || base_options.auto_rotate,
+                watermark: watermark
+                    .map(|path| WatermarkConfig::with_path(path, config.watermark.as_ref()))
+                    .or(base_options.watermark.clone()),
+                frame_cache_key: cache_key,
+                ..base_options.clone()
+            };
             if let Some(assignments_json) = assignments {
                 let assignments: Vec<MonitorAssignment> = serde_json::from_str(&assignments_json)
                     .map_err(|e| anyhow!("invalid internal assignments payload: {}", e))?;
-                return run_wallpaper_assignments(assignments, resolved_fps);
+                return run_wallpaper_assignments(assignments, &base, |_, _| {});
             }
 
             let path = path.ok_or_else(|| anyhow!("missing wallpaper path for run-internal"))?;
-            wallpaper::run_wallpaper(
-                path,
-                monitor.as_deref(),
-                resolved_fps,
-                fit.unwrap_or(default_fit),
-            )
+            let options = RenderOptions {
+                fit_mode: fit.unwrap_or(base.fit_mode),
+                transition: transition.unwrap_or(Transition::None),
+                offset: (offset_x.unwrap_or(0), offset_y.unwrap_or(0)),
+                all_outputs,
+                ..base
+            };
+            wallpaper::run_wallpaper(path, monitor.as_deref(), &options)
+        }
+        Some(Command::__DaemonInternal { options }) => {
+            let paths = DaemonPaths::resolve(&options, &config);
+            run_daemon_loop(args.config.as_deref(), &options, &paths)
+        }
+        Some(Command::__DaemonWatchdog { options }) => {
+            let paths = DaemonPaths::resolve(&options, &config);
+            run_daemon_watchdog(args.config.as_deref(), &options, &paths)
+        }
+    }
+}
+
+/// Render settings from the config alone, before per-command or per-monitor overrides.
+fn base_render_options(cfg: &config::Config) -> RenderOptions {
+    RenderOptions {
+        fps: cfg.video_fps.unwrap_or(60),
+        fit_mode: cfg.fit_mode.unwrap_or(FitMode::Cover),
+        transition: Transition::None,
+        scale_filter: cfg.scale_filter,
+        offset: cfg.default_offset.unwrap_or_default(),
+        focus_area: cfg.default_focus_area.filter(config::is_valid_focus_area),
+        tile_size: cfg.tile_size,
+        compositor: cfg.compositor_type.unwrap_or(CompositorType::Auto),
+        layer_namespace: cfg
+            .layer_namespace
+            .clone()
+            .unwrap_or_else(|| wallpaper::DEFAULT_LAYER_NAMESPACE.to_string()),
+        exclusive_zone: wallpaper::DEFAULT_EXCLUSIVE_ZONE,
+        anchor: cfg.anchor.unwrap_or_default(),
+        resource_limits: limits::ResourceLimits::from_config(cfg),
+        video_start: Duration::from_millis(cfg.video_start_time_ms.unwrap_or(0)),
+        // Only `set` waits; daemon renderers would pause at every rotation.
+        startup_delay: Duration::ZERO,
+        video_retries: cfg
+            .video_retry_count
+            .unwrap_or(wallpaper::DEFAULT_VIDEO_RETRIES),
+        video_loops: 0,
+        loop_fallback: cfg.loop_fallback_path.clone(),
+        video_fallback: cfg.video_fallback_image.clone(),
+        auto_rotate: cfg.auto_rotate.unwrap_or(false),
+        watermark: cfg.watermark.clone(),
+        gpu_index: cfg.gpu_index,
+        mute_audio: cfg.mute_audio.unwrap_or(true),
+        brightness: cfg.brightness.unwrap_or(1.0).clamp(0.0, 1.0),
+        contrast: cfg.contrast.unwrap_or(1.0).clamp(0.0, 2.0),
+        effects: cfg.effects.clone().unwrap_or_default(),
+        blur_sigma: cfg
+            .blur_sigma
+            .unwrap_or(effects::DEFAULT_BLUR_SIGMA)
+            .clamp(0.0, effects::MAX_BLUR_SIGMA),
+        transition_duration: cfg
+            .transition_duration_ms
+            .map(Duration::from_millis)
+            .unwrap_or(wallpaper::DEFAULT_TRANSITION_DURATION),
+        transition_curve: cfg.transition_curve.unwrap_or_default(),
+        slideshow_delay: cfg
+            .image_slideshow_delay_ms
+            .map(Duration::from_millis)
+            .unwrap_or(formats::DEFAULT_SLIDESHOW_DELAY),
+        all_outputs: false,
+        visibility_hysteresis: cfg
+            .visibility_hysteresis_ms
+            .map(Duration::from_millis)
+            .unwrap_or(hysteresis::DEFAULT_VISIBILITY_HYSTERESIS),
+        frame_cache_key: None,
+        surface_alpha: None,
+        wayland_socket: wallpaper::wayland_socket_path(
+            cfg.wayland_display.as_deref(),
+            cfg.xdg_runtime_dir.as_deref(),
+        ),
+    }
+}
+
+fn print_gpus() {
+    let gpus = gpu::list_gpus();
+    if gpus.is_empty() {
+        info!("No GPUs found under /sys/class/drm");
+    }
+    for (index, gpu) in gpus.iter().enumerate() {
+        println!(
+            "{}: {} {} [{:04x}:{:04x}]{}",
+            index,
+            gpu.render_node.display(),
+            gpu.vendor_name(),
+            gpu.vendor_id,
+            gpu.device_id,
+            gpu.pci_slot
+                .as_deref()
+                .map(|slot| format!(" at {}", slot))
+                .unwrap_or_default()
+        );
+    }
+}
+
+/// `~/...` for paths under `$HOME`, for output meant to be read.
+fn home_relative(path: &Path) -> String {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    match home.as_deref().map(|home| path.strip_prefix(home)) {
+        Some(Ok(rest)) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(Ok(rest)) => format!("~/{}", rest.display()),
+        _ => path.display().to_string(),
+    }
+}
+
+fn export_config(output: Option<&Path>, force: bool) -> Result<()> {
+    let Some(path) = output else {
+        print!("{}", config::CONFIG_TEMPLATE);
+        return Ok(());
+    };
+
+    if path.exists() && !force {
+        let hint = if config::default_config_path().as_deref() == Some(path) {
+            " (this is your active config)"
+        } else {
+            ""
+        };
+        return Err(anyhow!(
+            "{} already exists{}; pass --force to overwrite",
+            path.display(),
+            hint
+        ));
+    }
+
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    std::fs::write(path, config::CONFIG_TEMPLATE)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    info!("Wrote default config to {}", path.display());
+    Ok(())
+}
+
+/// `papdieo init`: the config file (from the export-config template), the data
+/// directory and the wallpaper directory the config points at.
+fn init(config_path: Option<&Path>, force: bool) -> Result<()> {
+    let config_path = config_path
+        .map(Path::to_path_buf)
+        .or_else(config::default_config_path)
+        .ok_or_else(|| anyhow!("could not determine config path"))?;
+    let data_dir =
+        config::default_data_dir().ok_or_else(|| anyhow!("could not determine data directory"))?;
+
+    let mut steps = Vec::new();
+    if let Some(config_dir) = config_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        steps.push((config_dir.to_path_buf(), create_dir(config_dir)?));
+    }
+    steps.push((config_path.clone(), write_default_config(&config_path, force)?));
+    steps.push((data_dir.clone(), create_dir(&data_dir)?));
+    // A config the user already had may point somewhere else.
+    let wallpaper_dir = config::Config::load_or_default(Some(&config_path))
+        .map(|cfg| cfg.wallpaper_dir)
+        .unwrap_or_else(|_| config::Config::default().wallpaper_dir);
+    steps.push((wallpaper_dir.clone(), create_dir(&wallpaper_dir)?));
+
+    if steps.iter().all(|(_, step)| *step == InitStep::Existed) {
+        info!("already configured.");
+        return Ok(());
+    }
+    for (path, step) in steps {
+        let label = match step {
+            InitStep::Created => "created",
+            InitStep::Overwritten => "overwrote",
+            InitStep::Existed => "exists",
+        };
+        info!("{:<10}{}", label, path.display());
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InitStep {
+    Created,
+    Overwritten,
+    Existed,
+}
+
+fn write_default_config(path: &Path, force: bool) -> Result<InitStep> {
+    let existed = path.exists();
+    if existed && !force {
+        return Ok(InitStep::Existed);
+    }
+    std::fs::write(path, config::CONFIG_TEMPLATE)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(if existed {
+        InitStep::Overwritten
+    } else {
+        InitStep::Created
+    })
+}
+
+fn create_dir(dir: &Path) -> Result<InitStep> {
+    if dir.is_dir() {
+        return Ok(InitStep::Existed);
+    }
+    std::fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    Ok(InitStep::Created)
+}
+
+fn edit_config(config_path: Option<&Path>, action: &ConfigAction) -> Result<()> {
+    match action {
+        ConfigAction::Get { key } => {
+            let cfg = config::Config::load_or_default(config_path)?;
+            println!("{}", config_edit::get(&cfg, key)?);
         }
-        Some(Command::__DaemonInternal) => run_daemon_loop(args.config.as_deref()),
+        ConfigAction::Set { key, value } => {
+            let path = config_path
+                .map(Path::to_path_buf)
+                .or_else(config::default_config_path)
+                .ok_or_else(|| anyhow!("could not determine config path"))?;
+            config_edit::set(&path, key, value)?;
+            info!("Set {} in {}", key, path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Prints every issue in `path` and returns the exit code: 0 when valid,
+/// 1 for parse errors, 2 when only warnings were found.
+fn validate_config(path: &Path) -> Result<i32> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read config file: {}", path.display()))?;
+    let issues = config::validate_source(&content);
+
+    for issue in &issues {
+        let location = match (issue.line, issue.column) {
+            (Some(line), Some(column)) => format!("{}:{}:{}", path.display(), line, column),
+            (Some(line), None) => format!("{}:{}", path.display(), line),
+            _ => path.display().to_string(),
+        };
+        let label = match issue.severity {
+            config::Severity::Error => "error",
+            config::Severity::Warning => "warning",
+        };
+        println!("{}: {}: {}", location, label, issue.message);
     }
+
+    if issues.iter().any(|i| i.severity == config::Severity::Error) {
+        return Ok(1);
+    }
+    if !issues.is_empty() {
+        return Ok(2);
+    }
+    info!("{} is valid", path.display());
+    Ok(0)
 }
 
-fn run_wallpaper_assignments(assignments: Vec<MonitorAssignment>, fps: u32) -> Result<()> {
-    run_wallpaper_assignments_cancellable(assignments, fps, None)
+/// `on_shown` gets each monitor, by the name of the output its renderer picked,
+/// and this process's pid once its first frame is up.
+fn run_wallpaper_assignments(
+    assignments: Vec<MonitorAssignment>,
+    base: &RenderOptions,
+    on_shown: impl FnMut(&str, u32),
+) -> Result<()> {
+    run_wallpaper_assignments_cancellable(assignments, base, None, on_shown)
 }
 
 fn run_wallpaper_assignments_cancellable(
     assignments: Vec<MonitorAssignment>,
-    fps: u32,
+    base: &RenderOptions,
     stop_signal: Option<Arc<AtomicBool>>,
+    mut on_shown: impl FnMut(&str, u32),
 ) -> Result<()> {
     if assignments.is_empty() {
         return Err(anyhow!("no monitor assignments provided"));
     }
 
+    let monitors: Vec<String> = assignments.iter().map(|a| a.monitor.clone()).collect();
     let mut workers = Vec::with_capacity(assignments.len());
+    let mut pending_shown = Vec::with_capacity(assignments.len());
     for assignment in assignments {
         let monitor = assignment.monitor.clone();
         let worker_stop = stop_signal.clone();
+        let mut options = assignment.render_options(base);
+        options.mute_audio |= !plays_audio(&monitor, &monitors);
+        let alpha = transition::SurfaceAlpha::new(1.0);
+        options.surface_alpha = Some(Arc::clone(&alpha));
+        pending_shown.push((monitor.clone(), alpha));
         workers.push((monitor, thread::spawn(move || {
             wallpaper::run_wallpaper_with_stop(
                 assignment.path,
                 Some(assignment.monitor.as_str()),
-                fps,
-                assignment.fit,
+                &options,
                 worker_stop.as_deref(),
             )
         })));
@@ -205,6 +1151,14 @@ fn run_wallpaper_assignments_cancellable(
         {
             break;
         }
+        pending_shown.retain(|(monitor, alpha)| {
+            let shown = alpha.is_shown();
+            if shown {
+                let output = alpha.output().unwrap_or_else(|| monitor.clone());
+                on_shown(&output, std::process::id());
+            }
+            !shown
+        });
 
         let mut idx = 0;
         while idx < workers.len() {
@@ -251,40 +1205,131 @@ fn run_wallpaper_assignments_cancellable(
     Ok(())
 }
 
-fn restart_daemon_service(config_path: Option<&Path>) -> Result<()> {
-    stop_daemon_service()?;
-    start_daemon_service(config_path)
+/// Where the daemon keeps its pid and lock files: the command-line flag, then
+/// the config, then the fixed paths under /tmp.
+#[derive(Debug, PartialEq)]
+struct DaemonPaths {
+    pid: PathBuf,
+    lock: PathBuf,
 }
 
-fn start_daemon_service(config_path: Option<&Path>) -> Result<()> {
-    let pid_path = Path::new(DAEMON_PID_PATH);
-    if daemon_is_running(pid_path) {
-        println!("papdieo daemon already running");
+impl DaemonPaths {
+    fn resolve(options: &DaemonArgs, cfg: &config::Config) -> Self {
+        let pick = |flag: &Option<PathBuf>, configured: &Option<PathBuf>, default: &str| {
+            flag.clone()
+                .or_else(|| configured.clone())
+                .unwrap_or_else(|| PathBuf::from(default))
+        };
+        Self {
+            pid: pick(&options.pidfile, &cfg.pid_file, DAEMON_PID_PATH),
+            lock: pick(&options.lockfile, &cfg.lock_file, DAEMON_LOCK_PATH),
+        }
+    }
+}
+
+fn restart_daemon_service(config_path: Option<&Path>, paths: &DaemonPaths) -> Result<()> {
+    stop_daemon_service(&paths.pid)?;
+    start_daemon_service(config_path, &DaemonArgs::default(), paths)
+}
+
+fn start_daemon_service(
+    config_path: Option<&Path>,
+    options: &DaemonArgs,
+    paths: &DaemonPaths,
+) -> Result<()> {
+    let pid_path = paths.pid.as_path();
+    if options.force {
+        remove_daemon_pid_file(paths);
+    } else if daemon_is_running(pid_path) {
+        info!("papdieo daemon already running");
         return Ok(());
     }
 
-    let exe = std::env::current_exe()?;
-    let log_path = DAEMON_LOG_PATH;
     let log_out = OpenOptions::new()
         .create(true)
         .truncate(true)
         .write(true)
-        .open(log_path)?;
+        .open(DAEMON_LOG_PATH)?;
     let log_err = log_out.try_clone()?;
 
-    let mut command = ProcessCommand::new(exe);
-    if let Some(path) = config_path {
-        command.arg("--config").arg(path);
+    if options.pid_stdout {
+        // Supervisors restart the daemon themselves, so it runs without the watchdog.
+        let child = spawn_daemon_internal(
+            config_path,
+            options,
+            Stdio::from(log_out),
+            Stdio::from(log_err),
+        )?;
+        println!("PID={}", child.id());
+        return Ok(());
     }
 
-    let mut child = command
-        .arg("daemon-internal")
-        .stdin(Stdio::null())
+    // The watchdog writes the pid file once its daemon has survived startup.
+    let _ = std::fs::remove_file(pid_path);
+    let mut watchdog = daemon_command(config_path, "daemon-watchdog", options)?
         .stdout(Stdio::from(log_out))
         .stderr(Stdio::from(log_err))
         .spawn()?;
+    let started = Instant::now();
+    let pid = loop {
+        if let Some(status) = watchdog.try_wait()? {
+            return Err(anyhow!(
+                "failed to start daemon (status: {}), see {}",
+                status,
+                DAEMON_LOG_PATH
+            ));
+        }
+        if let Some(pid) = read_pid(pid_path) {
+            break pid;
+        }
+        if started.elapsed() > DAEMON_PID_WAIT {
+            return Err(anyhow!("daemon did not start in time, see {}", DAEMON_LOG_PATH));
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    info!(
+        "Started papdieo daemon (pid: {}, watchdog: {}, log: {})",
+        pid,
+        watchdog.id(),
+        DAEMON_LOG_PATH
+    );
+    Ok(())
+}
+
+/// `papdieo <subcommand>` with the global flags and the daemon flags.
+fn daemon_command(
+    config_path: Option<&Path>,
+    subcommand: &str,
+    options: &DaemonArgs,
+) -> Result<ProcessCommand> {
+    let mut command = ProcessCommand::new(std::env::current_exe()?);
+    if let Some(path) = config_path {
+        command.arg("--config").arg(path);
+    }
+    command
+        .args(verbosity_args())
+        .arg(subcommand)
+        .args(options.to_args())
+        .stdin(Stdio::null());
+    Ok(command)
+}
 
-    thread::sleep(Duration::from_millis(350));
+/// Spawns the daemon and fails if it exits during [`DAEMON_STARTUP_CHECK`].
+fn spawn_daemon_internal(
+    config_path: Option<&Path>,
+    options: &DaemonArgs,
+    stdout: Stdio,
+    stderr: Stdio,
+) -> Result<Child> {
+    let mut command = daemon_command(config_path, "daemon-internal", options)?;
+    // The daemon renders every monitor in-process, so it gets the renderer limits.
+    let resource_limits =
+        limits::ResourceLimits::from_config(&config::Config::load_or_default(config_path)?);
+    resource_limits.apply_before_exec(&mut command);
+    let mut child = command.stdout(stdout).stderr(stderr).spawn()?;
+    resource_limits.apply_after_spawn(child.id());
+
+    thread::sleep(DAEMON_STARTUP_CHECK);
     if let Some(status) = child.try_wait()? {
         return Err(anyhow!(
             "failed to start daemon (status: {}), see {}",
@@ -292,33 +1337,100 @@ fn start_daemon_service(config_path: Option<&Path>) -> Result<()> {
             DAEMON_LOG_PATH
         ));
     }
+    Ok(child)
+}
 
-    std::fs::write(pid_path, child.id().to_string())?;
-    println!(
-        "Started papdieo daemon (pid: {}, log: {})",
-        child.id(),
-        log_path
+/// Runs the daemon as a child and checks on it every 30 seconds. A daemon that
+/// died on its own (a panic, a crash signal) is started again with the same
+/// flags, at most 5 times in 10 minutes; `papdieo stop` ends both. Its own pid
+/// goes on the second line of the pid file, for `stop`.
+fn run_daemon_watchdog(
+    config_path: Option<&Path>,
+    options: &DaemonArgs,
+    paths: &DaemonPaths,
+) -> Result<()> {
+    let mut options = options.clone();
+    let mut child =
+        spawn_daemon_internal(config_path, &options, Stdio::inherit(), Stdio::inherit())?;
+    write_daemon_pid_file(&paths.pid, child.id())?;
+    // Readiness is reported once, by the first daemon.
+    options.ready_fd = None;
+    let mut budget = RestartBudget::with_limit(DAEMON_RESTART_LIMIT, DAEMON_RESTART_WINDOW);
+
+    loop {
+        thread::sleep(DAEMON_WATCHDOG_INTERVAL);
+        let Some(status) = child.try_wait()? else {
+            continue;
+        };
+        if daemon_exit_was_requested(status, read_pid(&paths.pid), child.id()) {
+            debug!("daemon stopped ({}), watchdog exiting", status);
+            return Ok(());
+        }
+        if !budget.try_consume(Instant::now()) {
+            let _ = std::fs::remove_file(&paths.pid);
+            return Err(anyhow!("daemon exited ({}); restart limit reached, giving up", status));
+        }
+        warning!("warning: daemon exited unexpectedly ({}); restarting it", status);
+        child = spawn_daemon_internal(config_path, &options, Stdio::inherit(), Stdio::inherit())?;
+        write_daemon_pid_file(&paths.pid, child.id())?;
+    }
+}
+
+fn write_daemon_pid_file(path: &Path, daemon: u32) -> std::io::Result<()> {
+    std::fs::write(path, format!("{}\n{}\n", daemon, std::process::id()))
+}
+
+/// The watchdog named on the second line of the pid file, if it is still a
+/// `daemon-watchdog` process.
+fn read_watchdog_pid(pid_path: &Path) -> Option<u32> {
+    let content = std::fs::read_to_string(pid_path).ok()?;
+    let pid = content.lines().nth(1)?.trim().parse().ok()?;
+    let cmdline = std::fs::read(format!("/proc/{pid}/cmdline")).ok()?;
+    cmdline
+        .split(|byte| *byte == 0)
+        .any(|arg| arg == b"daemon-watchdog")
+        .then_some(pid)
+}
+
+/// `papdieo stop` sends SIGTERM and removes the pid file; a clean exit is not
+/// a crash either.
+fn daemon_exit_was_requested(status: ExitStatus, pid_file: Option<u32>, pid: u32) -> bool {
+    let stop_signal = matches!(
+        status.signal(),
+        Some(signal_hook::consts::SIGTERM | signal_hook::consts::SIGINT)
     );
-    Ok(())
+    status.success() || stop_signal || pid_file != Some(pid)
 }
 
-fn stop_daemon_service() -> Result<()> {
-    let pid_path = Path::new(DAEMON_PID_PATH);
+fn stop_daemon_service(pid_path: &Path) -> Result<()> {
     let Ok(content) = std::fs::read_to_string(pid_path) else {
         cleanup_renderer_processes();
-        println!("papdieo daemon not running");
+        info!("papdieo daemon not running");
         return Ok(());
     };
 
-    let pid = match content.trim().parse::<u32>() {
+    let pid = match content.lines().next().unwrap_or("").trim().parse::<u32>() {
         Ok(pid) => pid,
         Err(_) => {
             let _ = std::fs::remove_file(pid_path);
             cleanup_renderer_processes();
-            return Err(anyhow!("invalid daemon pid file: {}", DAEMON_PID_PATH));
+            return Err(anyhow!("invalid daemon pid file: {}", pid_path.display()));
         }
     };
 
+    // The watchdog goes first, so it cannot restart a daemon that needs a KILL.
+    if let Some(watchdog) = read_watchdog_pid(pid_path) {
+        let _ = ProcessCommand::new("kill")
+            .args(["-TERM", &watchdog.to_string()])
+            .status();
+        for _ in 0..20 {
+            if !process_is_alive(watchdog) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
     if PathBuf::from(format!("/proc/{pid}")).exists() {
         let status = ProcessCommand::new("kill")
             .args(["-TERM", &pid.to_string()])
@@ -356,7 +1468,7 @@ fn stop_daemon_service() -> Result<()> {
 
     let _ = std::fs::remove_file(pid_path);
     cleanup_renderer_processes();
-    println!("Stopped papdieo daemon");
+    info!("Stopped papdieo daemon");
     Ok(())
 }
 
@@ -366,415 +1478,4015 @@ fn cleanup_renderer_processes() {
         .status();
 }
 
-fn daemon_is_running(pid_path: &Path) -> bool {
-    let Ok(content) = std::fs::read_to_string(pid_path) else {
-        return false;
-    };
-    let Ok(pid) = content.trim().parse::<u32>() else {
-        return false;
-    };
+/// Renderers poll the pause flag files, so this works for the daemon and for
+/// detached `set` renderers alike.
+fn set_paused(monitor: Option<&str>, paused: bool) -> Result<()> {
+    let target = monitor
+        .map(|m| format!("monitor '{}'", m))
+        .unwrap_or_else(|| "all monitors".to_string());
 
-    PathBuf::from(format!("/proc/{pid}")).exists()
-}
+    if paused {
+        let flag = state::pause_flag_path(monitor);
+        File::create(&flag).with_context(|| format!("failed to create {}", flag.display()))?;
+        info!("Paused video on {}", target);
+        return Ok(());
+    }
 
-fn run_daemon_loop(config_path: Option<&Path>) -> Result<()> {
-    let _daemon_lock = acquire_daemon_lock()?;
+    match monitor {
+        Some(monitor) => {
+            let flag = state::pause_flag_path(Some(monitor));
+            if let Err(error) = std::fs::remove_file(&flag) {
+                if error.kind() != io::ErrorKind::NotFound {
+                    return Err(error)
+                        .with_context(|| format!("failed to remove {}", flag.display()));
+                }
+            }
+            if state::pause_flag_path(None).exists() {
+                warning!(
+                    "warning: all monitors are paused; run `papdieo resume` without --monitor"
+                );
+            }
+        }
+        None => state::clear_pause_flags()?,
+    }
+    info!("Resumed video on {}", target);
+    Ok(())
+}
 
-    let watched_config_path = resolve_config_watch_path(config_path);
-    let mut observed_config_mtime = watched_config_path
-        .as_ref()
-        .and_then(|path| config_file_modified_time(path));
+fn list_schedule(cfg: &config::Config, count: usize) -> Result<()> {
+    let rules = scheduler::compile_rules(cfg.schedule.as_deref().unwrap_or_default());
+    if rules.is_empty() {
+        println!("no schedule rules configured");
+        return Ok(());
+    }
 
-    loop {
-        let cfg = config::Config::load_or_default(config_path)?;
-        let fps = cfg.video_fps.unwrap_or(60);
-        let configured_interval_seconds = cfg
+    let now = chrono::Local::now();
+    match scheduler::active_rule(&rules, &now) {
+        Some(rule) => println!("active: {} -> {}", rule.rule.cron, rule.target().describe()),
+        None => println!("active: none (no rule has fired yet)"),
+    }
+    for (at, rule) in scheduler::upcoming(&rules, &now, count) {
+        println!(
+            "{}  {} -> {}",
+            at.format("%Y-%m-%d %H:%M %a"),
+            rule.rule.cron,
+            rule.target().describe()
+        );
+    }
+    Ok(())
+}
+
+/// Uses hyprctl when it answers, otherwise asks the Wayland compositor directly.
+fn list_monitors(cfg: &config::Config, json: bool) -> Result<()> {
+    let (detected, hyprctl_json) = match monitors::hyprland_monitors_json() {
+        Some(value) => (monitors::parse_hyprland_monitors(&value), Some(value)),
+        None => (monitors::wayland_monitors()?, None),
+    };
+
+    if json {
+        let value = match hyprctl_json {
+            Some(value) => value,
+            None => serde_json::to_value(&detected)?,
+        };
+        println!("{}", serde_json::to_string_pretty(&value)?);
+    } else {
+        print!("{}", monitors::format_table(&detected));
+    }
+
+    let names: Vec<String> = detected.iter().filter_map(|m| m.name.clone()).collect();
+    warn_unknown_monitor_map_keys(cfg, &names);
+    Ok(())
+}
+
+/// What `random`/`next --aspect-filter` choose from; `tolerance_percent` is
+/// `--aspect-tolerance`.
+fn aspect_filtered_wallpapers(
+    dir: &Path,
+    tag: Option<&str>,
+    ratio: f64,
+    tolerance_percent: f64,
+    media: picker::MediaFilter,
+) -> Result<Vec<PathBuf>> {
+    let images = match tag {
+        Some(tag) => picker::list_wallpapers_filtered_by_tag(dir, tag, media)?,
+        None => picker::list_wallpapers(dir, media)?,
+    };
+    picker::filter_by_aspect_ratio(&images, ratio, tolerance_percent / 100.0)
+}
+
+fn edit_tag(action: TagAction) -> Result<()> {
+    match action {
+        TagAction::Add { path, tag } => {
+            if metadata::add_tag(&path, &tag)? {
+                info!("Tagged {} with '{}'", path.display(), tag.trim());
+            } else {
+                info!("{} is already tagged '{}'", path.display(), tag.trim());
+            }
+        }
+        TagAction::Remove { path, tag } => {
+            if metadata::remove_tag(&path, &tag)? {
+                info!("Removed tag '{}' from {}", tag.trim(), path.display());
+            } else {
+                info!("{} is not tagged '{}'", path.display(), tag.trim());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn manage_profile(
+    action: ProfileAction,
+    config_path: Option<&Path>,
+    paths: &DaemonPaths,
+) -> Result<()> {
+    match action {
+        ProfileAction::List => {
+            let active = state::DaemonState::load()
+                .filter(|_| daemon_is_running(&paths.pid))
+                .and_then(|daemon_state| daemon_state.profile);
+            let names = profile::list(config_path)?;
+            if names.is_empty() {
+                info!("No profiles; create one with `papdieo profile create <NAME>`");
+            }
+            for name in names {
+                let marker = if active.as_deref() == Some(name.as_str()) { "*" } else { " " };
+                println!("{} {}", marker, name);
+            }
+        }
+        ProfileAction::Create { name } => {
+            let source = config_path
+                .map(Path::to_path_buf)
+                .or_else(config::default_config_path);
+            let path = profile::create(&name, source.as_deref())?;
+            info!("Created profile '{}': {}", name, path.display());
+        }
+        ProfileAction::Activate { name } => {
+            profile::resolve_config_path(config_path, Some(&name))?;
+            if !daemon_is_running(&paths.pid) {
+                return Err(anyhow!(
+                    "papdieo daemon is not running; start it with `papdieo daemon --profile {}`",
+                    name
+                ));
+            }
+            state::request_profile(&name)?;
+            info!("Switching the daemon to profile '{}'", name);
+        }
+    }
+    Ok(())
+}
+
+/// The daemon re-reads the pinned set before every rotation.
+fn set_pinned(monitor: &str, pinned: bool, socket: Option<&Path>) -> Result<()> {
+    let monitor = &output_name(monitor.trim(), socket);
+    let mut pinned_monitors = state::load_pinned();
+    let changed = if pinned {
+        !state::is_pinned(&pinned_monitors, monitor)
+            && pinned_monitors.insert(monitor.to_string())
+    } else {
+        let before = pinned_monitors.len();
+        pinned_monitors.retain(|name| !name.eq_ignore_ascii_case(monitor));
+        pinned_monitors.len() != before
+    };
+    if changed {
+        state::save_pinned(&pinned_monitors)?;
+    }
+
+    match (pinned, changed) {
+        (true, true) => info!("Pinned monitor '{}'", monitor),
+        (true, false) => info!("Monitor '{}' is already pinned", monitor),
+        (false, true) => info!("Unpinned monitor '{}'", monitor),
+        (false, false) => info!("Monitor '{}' is not pinned", monitor),
+    }
+    Ok(())
+}
+
+fn set_locked(monitor: &str, locked: bool, socket: Option<&Path>) -> Result<()> {
+    let monitor = &output_name(monitor.trim(), socket);
+    let mut locks = state::MonitorLocks::load();
+    let changed = if locked {
+        locks.lock(monitor, false, None)
+    } else {
+        locks.unlock(monitor)
+    };
+    if changed {
+        locks.save()?;
+    }
+
+    match (locked, changed) {
+        (true, true) => info!("Locked monitor '{}'", monitor),
+        (true, false) => info!("Monitor '{}' is already locked", monitor),
+        (false, true) => info!("Unlocked monitor '{}'", monitor),
+        (false, false) => info!("Monitor '{}' is not locked", monitor),
+    }
+    Ok(())
+}
+
+/// The connector name `requested` stands for, resolved like `--monitor` (a
+/// name in any case, a description or an index); `requested` itself when no
+/// output matches or the compositor cannot be reached.
+fn output_name(requested: &str, socket: Option<&Path>) -> String {
+    match wallpaper::resolve_output_name(requested, socket) {
+        Ok(Some(name)) => name,
+        Ok(None) => requested.to_string(),
+        Err(error) => {
+            debug!("cannot resolve monitor '{}': {:#}", requested, error);
+            requested.to_string()
+        }
+    }
+}
+
+/// Runs `on_change_command` and locks the monitor once `set`, `random` or
+/// `next` shows `path` on it, under the name of the output the renderer
+/// picked when it reports one.
+fn wallpaper_shown<'a>(
+    cfg: &'a config::Config,
+    monitor: Option<&str>,
+    path: &Path,
+    unlock: bool,
+) -> impl FnOnce(u32, Option<String>) + Send + 'a {
+    let (monitor, path) = (monitor.map(str::to_string), path.to_path_buf());
+    move |pid, output| {
+        let monitor = monitor.map(|requested| output.unwrap_or(requested));
+        run_on_change_command(cfg, monitor.as_deref(), &path);
+        lock_set_monitor(monitor.as_deref(), unlock, pid);
+    }
+}
+
+/// `set`, `random` and `next` lock their monitor once `renderer` shows the
+/// wallpaper, so the daemon stops its own renderer there and leaves the
+/// monitor alone until `renderer` exits; with `--unlock` only until its next
+/// rotation.
+fn lock_set_monitor(monitor: Option<&str>, unlock: bool, renderer: u32) {
+    let Some(monitor) = monitor.map(str::trim).filter(|m| !m.is_empty()) else {
+        return;
+    };
+    let mut locks = state::MonitorLocks::load();
+    if locks.lock(monitor, unlock, Some(renderer)) {
+        if let Err(error) = locks.save() {
+            warning!("warning: {:#}", error);
+        }
+    }
+}
+
+/// Writes the daemon's current wallpapers as a manifest for `restore-session`.
+fn export_session(output: Option<&Path>) -> Result<()> {
+    let daemon_state = state::DaemonState::load()
+        .ok_or_else(|| anyhow!("no daemon state recorded; is the daemon running?"))?;
+    let generated_at = chrono::Local::now().to_rfc3339();
+    let manifest = state::SessionManifest::from_state(&daemon_state, generated_at);
+    let content = serde_json::to_string_pretty(&manifest)?;
+    match output {
+        Some(path) => {
+            std::fs::write(path, content + "\n")
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            info!("Saved {} monitors to {}", manifest.monitors.len(), path.display());
+        }
+        None => println!("{}", content),
+    }
+    Ok(())
+}
+
+fn print_status(json: bool, paths: &DaemonPaths) -> Result<()> {
+    let daemon_state = state::DaemonState::load();
+    let pinned = state::load_pinned();
+    let locks = state::MonitorLocks::load();
+    if json {
+        let value = match daemon_state {
+            Some(daemon_state) => {
+                let mut value = serde_json::to_value(daemon_state)?;
+                value["pinned"] = serde_json::to_value(&pinned)?;
+                value["locked"] = serde_json::to_value(&locks.locked)?;
+                value
+            }
+            None => serde_json::Value::Null,
+        };
+        println!("{}", serde_json::to_string_pretty(&value)?);
+        return Ok(());
+    }
+
+    if daemon_is_running(&paths.pid) {
+        println!("papdieo daemon: running");
+    } else {
+        println!("papdieo daemon: not running");
+    }
+
+    let Some(daemon_state) = daemon_state else {
+        println!("no wallpaper state recorded");
+        if !pinned.is_empty() {
+            println!("pinned: {}", pinned.iter().cloned().collect::<Vec<_>>().join(", "));
+        }
+        return Ok(());
+    };
+
+    if let Some(profile) = &daemon_state.profile {
+        println!("profile: {}", profile);
+    }
+    let now = state::unix_now();
+    let mut monitors: Vec<_> = daemon_state.monitors.iter().collect();
+    monitors.sort_by(|a, b| a.0.cmp(b.0));
+    for (monitor, monitor_state) in monitors {
+        println!(
+            "{}: {} (fit: {}, fps: {}, changed {}s ago){}{}",
+            monitor,
+            monitor_state.wallpaper.display(),
+            fit_mode_arg(monitor_state.fit),
+            monitor_state.fps,
+            now.saturating_sub(monitor_state.changed_unix),
+            if state::is_pinned(&pinned, monitor) { " [pinned]" } else { "" },
+            if locks.is_locked(monitor) { " [locked]" } else { "" },
+        );
+    }
+    let idle_pins: Vec<&str> = pinned
+        .iter()
+        .filter(|pin| !daemon_state.monitors.keys().any(|m| m.eq_ignore_ascii_case(pin)))
+        .map(String::as_str)
+        .collect();
+    if !idle_pins.is_empty() {
+        println!("pinned (no wallpaper recorded): {}", idle_pins.join(", "));
+    }
+    Ok(())
+}
+
+fn daemon_is_running(pid_path: &Path) -> bool {
+    read_pid(pid_path).is_some_and(process_is_alive)
+}
+
+/// The first line: the daemon's pid file also names its watchdog on the second.
+fn read_pid(path: &Path) -> Option<u32> {
+    std::fs::read_to_string(path).ok()?.lines().next()?.trim().parse().ok()
+}
+
+fn process_is_alive(pid: u32) -> bool {
+    PathBuf::from(format!("/proc/{pid}")).exists()
+}
+
+/// `--force`: forgets the pid file. The lock file stays, since deleting one
+/// that is held would let a second daemon lock a fresh file next to it.
+fn remove_daemon_pid_file(paths: &DaemonPaths) {
+    match std::fs::remove_file(&paths.pid) {
+        Ok(()) => debug!("removed {}", paths.pid.display()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => warning!("warning: failed to remove {}: {}", paths.pid.display(), error),
+    }
+}
+
+fn run_daemon_loop(
+    config_path: Option<&Path>,
+    options: &DaemonArgs,
+    paths: &DaemonPaths,
+) -> Result<()> {
+    let mut ready_fd = options.ready_fd.map(take_ready_fd).transpose()?;
+    let _daemon_lock = acquire_daemon_lock(paths, options.force)?;
+    let previous_state = state::DaemonState::load();
+    let mut daemon_state = state::DaemonState::new(std::process::id());
+    let mut active_config = profile::resolve_config_path(config_path, options.profile.as_deref())?;
+    daemon_state.profile = options.profile.clone();
+    // Left over from an earlier daemon; this one starts with the profile it was given.
+    let _ = state::take_profile_request();
+
+    let mut startup_cfg = config::Config::load_or_default(active_config.as_deref())?;
+    options.overrides.apply(&mut startup_cfg);
+    wait_before_first_wallpaper(&startup_cfg, options);
+
+    let watch_config = options.config_watch || startup_cfg.config_watch.unwrap_or(false);
+    let mut config_watcher = config_watch::ConfigWatcher::new(
+        resolve_config_watch_path(active_config.as_deref()).as_deref(),
+        watch_config,
+    );
+    let (mut restored, restored_age) = match previous_state {
+        Some(previous) if startup_cfg.restore_on_restart.unwrap_or(false) => {
+            restorable_wallpapers(previous, state::unix_now())
+        }
+        _ => (HashMap::new(), Duration::ZERO),
+    };
+    let mut startup_wallpaper = startup_cfg.startup_wallpaper.clone().filter(|path| {
+        let exists = path.exists();
+        if !exists {
+            warning!("warning: startup_wallpaper does not exist: {}", path.display());
+        }
+        exists
+    });
+    // Workers outlive a cycle only while their monitor is pinned or locked.
+    let mut workers: HashMap<String, MonitorWorker> = HashMap::new();
+    // Rotated out at the end of a cycle, kept on screen until the replacement
+    // has faded in over them.
+    let mut outgoing: HashMap<String, MonitorWorker> = HashMap::new();
+    let mut transitions: HashMap<String, transition::TransitionCoordinator> = HashMap::new();
+    let mut monitor_wallpapers: HashMap<String, PathBuf> = HashMap::new();
+    let mut dir_wait = MissingDirWait::new();
+    // Monitors whose shutdown renderer from the previous daemon has been replaced.
+    let mut handed_over: HashSet<String> = HashSet::new();
+    // Renderers set by hand with `--unlock`, stopped once the daemon's
+    // replacement is shown.
+    let mut handed_back: HashMap<String, u32> = HashMap::new();
+    let mut backoffs: HashMap<String, backoff::BackoffState> = HashMap::new();
+    let mut dir_watcher: Option<watcher::DirectoryWatcher> = None;
+    // Power source and the cap written for it.
+    let mut power_state: Option<(power::PowerState, u32)> = None;
+    let metrics = Arc::new(metrics::Metrics::default());
+    if let Some(port) = startup_cfg.metrics_port {
+        match metrics::serve(port, Arc::clone(&metrics)) {
+            Ok(()) => info!("metrics on http://127.0.0.1:{}/metrics", port),
+            Err(error) => warning!("warning: {:#}", error),
+        }
+    }
+    let mut last_power_check: Instant;
+    let mut resource_watch = sysinfo::ResourceWatch::default();
+    let mut last_resource_check = Instant::now();
+    // Started, replaced or stopped at each cycle to follow `fifo_path`.
+    let mut fifo_reader = None;
+    // Wallpapers pushed through the FIFO, shown at the next pick instead of a rotation.
+    let mut pushed: HashMap<String, PathBuf> = HashMap::new();
+    // Started, restarted or stopped at each cycle to follow the workspace maps.
+    let mut workspace_listener = None;
+    let mut workspace_maps = WorkspaceMaps::default();
+    // The workspace entry of each monitor's current workspace.
+    let mut workspace_targets: HashMap<String, PathBuf> = HashMap::new();
+    let mut rotation_spread = spread::RotationSpread::default();
+    let mut pre_picker = prepick::PrePicker::default();
+    // Set when the config file or profile changed during the last cycle.
+    let mut config_reloaded = false;
+    let watches_resources = startup_cfg.max_renderer_cpu_percent_sustained.is_some()
+        || startup_cfg.max_renderer_rss_mb.is_some();
+    if watches_resources && !options.watch_pidfile {
+        warning!(
+            "warning: max_renderer_cpu_percent_sustained and max_renderer_rss_mb only apply to renderers adopted with --watch-pidfile"
+        );
+    }
+    if options.watch_pidfile {
+        // Kept like a pinned monitor until the first rotation.
+        for record in adoptable_renderers() {
+            info!(
+                "adopting renderer {} on monitor '{}' ({})",
+                record.pid,
+                record.monitor,
+                record.path.display()
+            );
+            restored.remove(&record.monitor);
+            daemon_state.monitors.insert(
+                record.monitor.clone(),
+                state::MonitorState {
+                    wallpaper: record.path.clone(),
+                    changed_unix: record.spawned_at,
+                    renderer_pid: record.pid,
+                    fps: base_render_options(&startup_cfg).fps,
+                    fit: fit_mode_for_monitor(&startup_cfg, &record.monitor),
+                },
+            );
+            monitor_wallpapers.insert(record.monitor.clone(), record.path.clone());
+            workers.insert(record.monitor.clone(), adopt_renderer(&record));
+        }
+    }
+    // systemd `Type=notify`: READY=1 once something is on screen, then watchdog pings.
+    let sd_notify = startup_cfg.sd_notify_ready.unwrap_or(false);
+    let mut sd_ready_pending = sd_notify;
+    let sd_watchdog = sd_notify.then(systemd::watchdog_interval).flatten();
+    let mut last_sd_watchdog = Instant::now();
+    let terminate = Arc::new(AtomicBool::new(false));
+    for signal in [signal_hook::consts::SIGTERM, signal_hook::consts::SIGINT] {
+        signal_hook::flag::register(signal, Arc::clone(&terminate))
+            .context("failed to install signal handler")?;
+    }
+
+    loop {
+        // Pinned and locked workers outlive the cycle; one may have exited since the last check.
+        release_exited_adopted_workers(&mut workers);
+        let exited = reap_finished_workers(&mut workers);
+        for (monitor, exit_status) in &exited {
+            warning!("warning: renderer for monitor '{}' exited ({})", monitor, exit_status);
+            record_monitor_failure(&mut backoffs, monitor);
+        }
+        record_unexpected_exits(&mut daemon_state, &metrics, &exited);
+
+        if let Some(name) = state::take_profile_request() {
+            match profile::resolve_config_path(config_path, Some(&name)) {
+                Ok(path) => {
+                    info!("switching to profile '{}'", name);
+                    config_watcher = config_watch::ConfigWatcher::new(
+                        resolve_config_watch_path(path.as_deref()).as_deref(),
+                        watch_config,
+                    );
+                    active_config = path;
+                    daemon_state.profile = Some(name);
+                }
+                Err(error) => warning!("warning: {:#}", error),
+            }
+        }
+
+        let mut cfg = config::Config::load_or_default(active_config.as_deref())?;
+        options.overrides.apply(&mut cfg);
+        if std::mem::take(&mut config_reloaded) {
+            // Picked under the old media kinds, directories or filters.
+            pre_picker.clear();
+        }
+        // Monitors may have been plugged in, rotated or changed mode since.
+        let resolutions = monitor_resolutions(&cfg);
+        update_workspace_listener(
+            &mut workspace_listener,
+            &mut workspace_maps,
+            &cfg,
+            &mut workspace_targets,
+        );
+        // A reload can change the cap while the power source stays the same.
+        update_fps_limit(&mut power_state, &cfg);
+        last_power_check = Instant::now();
+        shm::ShmPoolRegistry::global().set_capacity(
+            cfg.max_pooled_buffers.unwrap_or(shm::DEFAULT_MAX_POOLED_BUFFERS),
+        );
+        let mut base_options = base_render_options(&cfg);
+        if terminate.load(Ordering::Relaxed) {
+            if sd_notify {
+                if let Err(error) = systemd::sd_notify("STOPPING=1\n") {
+                    debug!("sd_notify failed: {:#}", error);
+                }
+            }
+            for (_, coordinator) in transitions.drain() {
+                coordinator.finish();
+            }
+            let mut on_screen = std::mem::take(&mut workers);
+            on_screen.extend(outgoing.drain());
+            state::remove_fps_limit();
+            return shutdown_renderer_children(on_screen, &cfg, &base_options);
+        }
+        let interval = cfg
             .daemon_interval_seconds
-            .or(cfg.rotation_seconds)
-            .unwrap_or(300)
-            .max(1);
-        let interval = Duration::from_secs(configured_interval_seconds);
+            .map(Duration::from_secs)
+            .or(cfg.rotation_interval())
+            .unwrap_or(Duration::from_secs(300))
+            .max(config::MIN_ROTATION_INTERVAL);
+        metrics.set_rotation_interval(interval);
+        let schedule = scheduler::compile_rules(cfg.schedule.as_deref().unwrap_or_default());
+        let now = chrono::Local::now();
+        let scheduled = scheduler::active_rule(&schedule, &now).map(|rule| {
+            debug!("schedule rule '{}' is active", rule.rule.cron);
+            rule.target()
+        });
+        // End the cycle early when the next schedule rule fires.
+        let cycle_length = match scheduler::next_trigger(&schedule, &now) {
+            Some(at) => interval.min((at - now).to_std().unwrap_or_default()),
+            None => interval,
+        };
+        debug!(
+            "daemon config: fps={}, interval={:?}, wallpaper_dir={}",
+            base_options.fps,
+            interval,
+            cfg.wallpaper_dir.display()
+        );
+
+        let mut monitors = daemon_monitors(&cfg)?;
+        debug!("daemon monitors: {}", monitors.join(", "));
+        update_directory_watcher(&mut dir_watcher, &cfg, &monitors, &resolutions);
+        update_fifo_reader(&mut fifo_reader, &cfg);
+        warn_unknown_monitor_map_keys(&cfg, &monitors);
+        if monitors.is_empty() {
+            stop_monitor_workers(std::mem::take(&mut workers));
+            stop_monitor_workers(std::mem::take(&mut outgoing));
+            config_watcher.wait(Duration::from_secs(5));
+            continue;
+        }
+        let disconnected: Vec<String> = workers
+            .keys()
+            .filter(|monitor| !monitors.contains(*monitor))
+            .cloned()
+            .collect();
+        stop_monitor_workers(
+            disconnected
+                .iter()
+                .filter_map(|monitor| workers.remove_entry(monitor)),
+        );
+        monitor_wallpapers.retain(|monitor, _| workers.contains_key(monitor));
+
+        // Nothing to pick from yet (e.g. a fresh install without ~/Pictures/Wallpapers):
+        // wait quietly for a directory to be created instead of failing every few seconds.
+        let missing_dirs = missing_wallpaper_dirs(&cfg, &monitors, &resolutions);
+        if startup_wallpaper.is_none()
+            && scheduled.is_none()
+            && restored.is_empty()
+            && !missing_dirs.is_empty()
+        {
+            let wait = dir_wait.next_wait();
+            if dir_wait.should_log(Instant::now()) {
+                let listed: Vec<String> =
+                    missing_dirs.iter().map(|d| d.display().to_string()).collect();
+                warning!(
+                    "warning: waiting for a wallpaper directory to be created: {}",
+                    listed.join(", ")
+                );
+            }
+            wait_for_wallpaper_dirs(&mut config_watcher, &missing_dirs, wait);
+            continue;
+        }
+        dir_wait = MissingDirWait::new();
+
+        // The startup wallpaper replaces the first pick on every monitor.
+        let startup = startup_wallpaper.take();
+        if startup.is_some() {
+            base_options.fps = cfg.startup_fps.unwrap_or(base_options.fps);
+        }
+        // Restored wallpapers keep their fps and rotate when their own interval is up.
+        let restoring = !restored.is_empty();
+        if let Some(previous) = restored.values().next() {
+            base_options.fps = previous.fps;
+        }
+        let cycle_length = if restoring {
+            cycle_length.saturating_sub(restored_age)
+        } else {
+            cycle_length
+        };
+        let fps = base_options.fps;
+
+        let rotation_mode = cfg.rotation_mode.unwrap_or(RotationMode::Random);
+        let mut assignments = Vec::new();
+        // Pinned monitors keep showing their wallpaper, so nobody else gets it.
+        let mut taken = cfg
+            .no_overlap
+            .unwrap_or(false)
+            .then(|| monitor_wallpapers.values().cloned().collect::<HashSet<_>>());
+
+        let set_by_hand = state::MonitorLocks::load();
+        for monitor in monitors.iter() {
+            if let Some(path) = monitor_wallpapers.get(monitor) {
+                debug!("monitor '{}' is pinned or locked, keeping {}", monitor, path.display());
+                continue;
+            }
+            if set_by_hand.renderer(monitor).is_some() {
+                debug!("monitor '{}' shows a wallpaper set by hand, leaving it", monitor);
+                continue;
+            }
+            if let Some(previous) = restored.remove(monitor) {
+                debug!("monitor '{}': restoring {}", monitor, previous.wallpaper.display());
+                assignments.push(MonitorAssignment {
+                    monitor: monitor.clone(),
+                    path: previous.wallpaper,
+                    fit: previous.fit,
+                    transition: transition_for_monitor(&cfg, monitor),
+                    offset: cfg.default_offset,
+                });
+                continue;
+            }
+            if let Some(path) = pushed.remove(monitor) {
+                let assignment = assign_monitor(
+                    &cfg,
+                    monitor,
+                    &resolutions,
+                    Some(&path),
+                    None,
+                    rotation_mode,
+                    None,
+                );
+                assignments.extend(assignment.map(|assignment| MonitorAssignment {
+                    fit: fit_mode_for_monitor(&cfg, monitor),
+                    ..assignment
+                }));
+                continue;
+            }
+            if let Some(backoff) = backoffs.get(monitor).filter(|b| !b.should_retry()) {
+                debug!(
+                    "monitor '{}' is backing off after {} failures",
+                    monitor, backoff.consecutive_failures
+                );
+                continue;
+            }
+            let target = workspace_targets
+                .get(monitor)
+                .map(|path| workspace_target(path))
+                .or(scheduled);
+            let prepicked = (startup.is_none() && target.is_none())
+                .then(|| {
+                    take_prepicked(
+                        &mut pre_picker,
+                        &cfg,
+                        monitor,
+                        &resolutions,
+                        rotation_mode,
+                        taken.as_ref(),
+                    )
+                })
+                .flatten();
+            if let Some(path) = prepicked {
+                let assignment = assign_monitor(
+                    &cfg,
+                    monitor,
+                    &resolutions,
+                    Some(&path),
+                    None,
+                    rotation_mode,
+                    taken.as_mut(),
+                );
+                assignments.extend(assignment.map(|assignment| MonitorAssignment {
+                    fit: fit_mode_for_monitor(&cfg, monitor),
+                    ..assignment
+                }));
+                continue;
+            }
+            match assign_monitor(
+                &cfg,
+                monitor,
+                &resolutions,
+                startup.as_deref(),
+                target,
+                rotation_mode,
+                taken.as_mut(),
+            ) {
+                Some(assignment) => assignments.push(assignment),
+                None => record_monitor_failure(&mut backoffs, monitor),
+            }
+        }
+
+        // Saved for monitors that are gone now; they get fresh picks when they return.
+        restored.clear();
+
+        let kept = keep_unassigned_workers(&assignments, &monitors, &mut outgoing, &mut workers);
+        for monitor in kept {
+            debug!("monitor '{}' got no new wallpaper, keeping the current one", monitor);
+            monitor_wallpapers.insert(monitor.clone(), workers[&monitor].wallpaper.clone());
+        }
+        let unassigned: Vec<String> = outgoing
+            .keys()
+            .filter(|monitor| !assignments.iter().any(|a| &a.monitor == *monitor))
+            .cloned()
+            .collect();
+        stop_monitor_workers(
+            unassigned
+                .iter()
+                .filter_map(|monitor| outgoing.remove_entry(monitor)),
+        );
+        for monitor in keep_unchanged_workers(&mut assignments, &mut outgoing, &mut workers) {
+            debug!("monitor '{}' picked the wallpaper it already shows", monitor);
+            monitor_wallpapers.insert(monitor.clone(), workers[&monitor].wallpaper.clone());
+        }
+
+        if assignments.is_empty() && workers.is_empty() {
+            config_watcher.wait(Duration::from_secs(DAEMON_STARTUP_RETRY_SECONDS));
+            continue;
+        }
+
+        let mut restart_budgets: HashMap<String, RestartBudget> = HashMap::new();
+        daemon_state
+            .monitors
+            .retain(|monitor, _| workers.contains_key(monitor));
+        record_daemon_state(&mut daemon_state, &assignments, fps);
+        let on_change = hooks::OnChangeHook::from_config(&cfg);
+        let start_delay = Duration::from_millis(cfg.monitor_start_delay_ms.unwrap_or(0));
+        assignments.sort_by(|a, b| a.monitor.cmp(&b.monitor));
+        for (index, assignment) in assignments.into_iter().enumerate() {
+            if index > 0 && !start_delay.is_zero() {
+                thread::sleep(start_delay);
+            }
+            if let Some(hook) = &on_change {
+                hook.spawn_detached(Some(assignment.monitor.clone()), assignment.path.clone());
+            }
+            monitor_wallpapers.insert(assignment.monitor.clone(), assignment.path.clone());
+            metrics.record_change(&assignment.monitor, state::unix_now(), std::process::id());
+            let monitor = assignment.monitor.clone();
+            let mut options = assignment.render_options(&base_options);
+            options.mute_audio |= !plays_audio(&monitor, &monitors);
+            // A fade still running here would fight the new one over the same surface.
+            if let Some(previous) = transitions.remove(&monitor) {
+                previous.finish();
+            }
+            let worker = match outgoing.remove(&monitor) {
+                Some(old) if options.transition == Transition::Fade => {
+                    let worker = spawn_monitor_worker(assignment, options, true);
+                    let old_monitor = monitor.clone();
+                    let coordinator = transition::TransitionCoordinator::start(
+                        base_options.transition_duration,
+                        base_options.transition_curve,
+                        Arc::clone(&worker.alpha),
+                        move || stop_monitor_workers([(old_monitor, old)]),
+                    );
+                    transitions.insert(monitor.clone(), coordinator);
+                    worker
+                }
+                old => {
+                    stop_monitor_workers(old.map(|old| (monitor.clone(), old)));
+                    spawn_monitor_worker(assignment, options, false)
+                }
+            };
+            workers.insert(monitor, worker);
+        }
+        if can_prepick(&cfg, rotation_mode) {
+            let jobs = monitors
+                .iter()
+                .map(|monitor| {
+                    let dirs = media_dirs_for_monitor(&cfg, monitor, &resolutions);
+                    (monitor.clone(), dirs)
+                })
+                .collect();
+            let media = picker::MediaFilter::from_config(&cfg);
+            pre_picker.request(jobs, move |dirs| prepick_media(dirs, media));
+        }
+
+        let stagger = Duration::from_secs(cfg.rotation_stagger_seconds.unwrap_or(0));
+        let jitter = Duration::from_secs(cfg.rotation_jitter_seconds.unwrap_or(0));
+        let cycle_length = if stagger.is_zero() && jitter.is_zero() {
+            rotation_spread.clear();
+            cycle_length
+        } else {
+            let now = Instant::now();
+            rotation_spread.plan(
+                &monitors,
+                now,
+                interval,
+                cycle_length,
+                stagger,
+                jitter,
+                &mut rand::rng(),
+            );
+            rotation_spread.until_next(now).unwrap_or(cycle_length)
+        };
+
+        // Wall-clock time, so checks that run long or a suspended machine do not
+        // stretch the cycle past the schedule trigger it was cut to.
+        let cycle_started = SystemTime::now();
+        let mut elapsed = Duration::ZERO;
+        let check_every = Duration::from_secs(1);
+        let monitor_poll = Duration::from_secs(cfg.monitor_poll_interval_seconds.unwrap_or(10));
+        let mut last_monitor_poll = Instant::now();
+        // Set by `papdieo next`: rotate these monitors now, or every monitor when empty.
+        let mut rotate_now: Option<Vec<String>> = None;
+        while elapsed < cycle_length {
+            let remaining = cycle_length.saturating_sub(elapsed);
+            let sleep_for = remaining.min(check_every);
+            let config_changed = config_watcher.wait(sleep_for);
+            elapsed = cycle_started.elapsed().unwrap_or(elapsed + sleep_for);
+            transitions.retain(|_, coordinator| !coordinator.is_finished());
+            if last_power_check.elapsed() >= POWER_CHECK_INTERVAL {
+                last_power_check = Instant::now();
+                update_fps_limit(&mut power_state, &cfg);
+            }
+            let resource_check = cfg
+                .resource_check_interval_seconds
+                .map(Duration::from_secs)
+                .unwrap_or(sysinfo::DEFAULT_CHECK_INTERVAL);
+            if last_resource_check.elapsed() >= resource_check {
+                last_resource_check = Instant::now();
+                check_renderer_resources(&mut resource_watch, &cfg, &workers);
+            }
+            if !monitor_poll.is_zero() && last_monitor_poll.elapsed() >= monitor_poll {
+                last_monitor_poll = Instant::now();
+                let (added, removed) = match daemon_monitors(&cfg) {
+                    Ok(detected) => monitor_changes(&monitors, &detected),
+                    Err(error) => {
+                        debug!("monitor poll failed: {:#}", error);
+                        (Vec::new(), Vec::new())
+                    }
+                };
+                if !removed.is_empty() {
+                    info!("monitor removed: {}", removed.join(", "));
+                    monitors.retain(|monitor| !removed.contains(monitor));
+                    for monitor in &removed {
+                        if let Some(coordinator) = transitions.remove(monitor) {
+                            coordinator.finish();
+                        }
+                        monitor_wallpapers.remove(monitor);
+                        daemon_state.monitors.remove(monitor);
+                    }
+                    stop_monitor_workers(removed.iter().filter_map(|m| workers.remove_entry(m)));
+                    stop_monitor_workers(removed.iter().filter_map(|m| outgoing.remove_entry(m)));
+                    for monitor in &removed {
+                        shm::ShmPoolRegistry::global().forget_monitor(monitor);
+                    }
+                }
+                if !added.is_empty() {
+                    // Only the new monitors get a wallpaper; the others keep theirs.
+                    info!("monitor added: {}", added.join(", "));
+                    rotate_now = Some(added);
+                    break;
+                }
+            }
+            if workers.values().any(|w| w.alpha.is_shown()) {
+                if let Some(fd) = ready_fd.take() {
+                    notify_ready(fd);
+                }
+            }
+            if sd_ready_pending && workers.values().any(|w| w.alpha.is_shown()) {
+                sd_ready_pending = false;
+                if let Err(error) = systemd::sd_notify("READY=1\n") {
+                    warning!("warning: sd_notify: {:#}", error);
+                }
+            }
+            if sd_watchdog.is_some_and(|interval| last_sd_watchdog.elapsed() >= interval) {
+                last_sd_watchdog = Instant::now();
+                if let Err(error) = systemd::sd_notify("WATCHDOG=1\n") {
+                    debug!("sd_notify failed: {:#}", error);
+                }
+            }
+            for (monitor, worker) in &workers {
+                if worker.alpha.is_shown() && handed_over.insert(monitor.clone()) {
+                    stop_leftover_renderers(monitor, worker.adopted_pid);
+                }
+                if !worker.alpha.is_shown() {
+                    continue;
+                }
+                let handed = handed_back
+                    .keys()
+                    .find(|name| name.eq_ignore_ascii_case(monitor))
+                    .cloned()
+                    .and_then(|name| handed_back.remove(&name));
+                if let Some(pid) = handed.filter(|pid| process_is_alive(*pid)) {
+                    terminate_renderer(pid);
+                }
+                if let Some(backoff) = backoffs
+                    .get_mut(monitor)
+                    .filter(|b| b.consecutive_failures > 0 && worker.alpha.is_shown())
+                {
+                    info!("monitor '{}' recovered, backoff reset", monitor);
+                    backoff.record_success();
+                }
+            }
+
+            let released = release_exited_adopted_workers(&mut workers);
+            if !released.is_empty() {
+                rotate_now = Some(released);
+                break;
+            }
+            let mut locks = state::MonitorLocks::load();
+            let unlocked = locks.release_exited_renderers(process_is_alive);
+            if !unlocked.is_empty() {
+                if let Err(error) = locks.save() {
+                    warning!("warning: {:#}", error);
+                }
+                info!("wallpaper set by hand exited, unlocking {}", unlocked.join(", "));
+                rotate_now = Some(unlocked);
+                break;
+            }
+            let set_by_hand: Vec<String> = workers
+                .keys()
+                .chain(outgoing.keys().filter(|monitor| !workers.contains_key(*monitor)))
+                .filter(|monitor| locks.renderer(monitor).is_some())
+                .cloned()
+                .collect();
+            if !set_by_hand.is_empty() {
+                info!("wallpaper set by hand on {}, stopping ours", set_by_hand.join(", "));
+                for monitor in &set_by_hand {
+                    if let Some(coordinator) = transitions.remove(monitor) {
+                        coordinator.finish();
+                    }
+                    monitor_wallpapers.remove(monitor);
+                }
+                stop_monitor_workers(set_by_hand.iter().filter_map(|m| workers.remove_entry(m)));
+                stop_monitor_workers(set_by_hand.iter().filter_map(|m| outgoing.remove_entry(m)));
+            }
+            let exited = reap_finished_workers(&mut workers);
+            record_unexpected_exits(&mut daemon_state, &metrics, &exited);
+            for (monitor, exit_status) in exited {
+                record_monitor_failure(&mut backoffs, &monitor);
+                let Some(path) = monitor_wallpapers.get(&monitor).cloned() else {
+                    continue;
+                };
+
+                let budget = restart_budgets
+                    .entry(monitor.clone())
+                    .or_insert_with(RestartBudget::new);
+                if !budget.try_consume(Instant::now()) {
+                    eprintln!(
+                        "renderer for monitor '{}' exited ({}); restart limit reached, leaving it until next rotation",
+                        monitor, exit_status
+                    );
+                    continue;
+                }
+
+                warning!(
+                    "renderer for monitor '{}' exited ({}); restarting with {}",
+                    monitor,
+                    exit_status,
+                    path.display()
+                );
+                let assignment = MonitorAssignment {
+                    monitor: monitor.clone(),
+                    path,
+                    fit: fit_mode_for_monitor(&cfg, &monitor),
+                    transition: transition_for_monitor(&cfg, &monitor),
+                    offset: cfg.default_offset,
+                };
+                let mut options = assignment.render_options(&base_options);
+                options.mute_audio |= !plays_audio(&monitor, &monitors);
+                workers.insert(monitor, spawn_monitor_worker(assignment, options, false));
+            }
+
+            let requests = state::take_rotate_requests();
+            if !requests.is_empty() {
+                let monitors = if requests.contains(&state::RotateSignal::All) {
+                    Vec::new()
+                } else {
+                    requests
+                        .into_iter()
+                        .filter_map(|request| match request {
+                            state::RotateSignal::Monitor(name) => Some(name),
+                            state::RotateSignal::All => None,
+                        })
+                        .collect()
+                };
+                info!("rotating {} now", describe_rotation(&monitors));
+                rotate_now = Some(monitors);
+                break;
+            }
+            let added = dir_watcher
+                .as_ref()
+                .map(watcher::DirectoryWatcher::take_rotations)
+                .unwrap_or_default();
+            if !added.is_empty() {
+                info!("new wallpaper added, rotating {}", added.join(", "));
+                rotate_now = Some(added);
+                break;
+            }
+            let fifo_lines = fifo_reader
+                .as_ref()
+                .map(fifo::FifoReader::take_lines)
+                .unwrap_or_default();
+            for line in fifo_lines {
+                queue_fifo_request(&line, &cfg, &monitors, &mut pushed);
+            }
+            if !pushed.is_empty() {
+                rotate_now = Some(pushed.keys().cloned().collect());
+                break;
+            }
+            let workspace_events = workspace_listener
+                .as_ref()
+                .map(hyprland_ipc::HyprlandEventListener::take_events)
+                .unwrap_or_default();
+            let switched =
+                apply_workspace_events(&workspace_events, &cfg, &monitors, &mut workspace_targets);
+            if !switched.is_empty() {
+                info!("workspace changed, rotating {}", switched.join(", "));
+                rotate_now = Some(switched);
+                break;
+            }
+
+            if config_changed || state::profile_request_path().exists() {
+                config_reloaded = true;
+                break;
+            }
+            if terminate.load(Ordering::Relaxed) {
+                break;
+            }
+        }
+
+        if rotate_now.is_none() && elapsed >= cycle_length && rotation_spread.is_planned() {
+            // Only the monitors whose turn has come; the rest of the round keeps waiting.
+            let due = rotation_spread.take_due(Instant::now());
+            if due.is_empty() {
+                continue;
+            }
+            rotate_now = Some(due);
+        } else if rotate_now.is_none() {
+            rotation_spread.clear();
+        }
+
+        // Re-read so `pin`/`lock` and friends take effect at this rotation without a restart.
+        let pinned = state::load_pinned();
+        let mut locks = state::MonitorLocks::load();
+        handed_back.extend(locks.release_on_rotate.iter().filter_map(|monitor| {
+            locks.renderers.get(monitor).map(|pid| (monitor.clone(), *pid))
+        }));
+        if locks.release_for_rotation() {
+            if let Err(error) = locks.save() {
+                warning!("warning: {:#}", error);
+            }
+        }
+        let rotating = rotating_monitors(workers.keys(), &pinned, &locks, rotate_now.as_deref());
+        pushed.retain(|monitor, _| rotating.contains(monitor));
+        outgoing.extend(
+            rotating
+                .iter()
+                .filter_map(|monitor| workers.remove_entry(monitor)),
+        );
+    }
+}
+
+/// What the previous daemon showed, for `restore_on_restart`: the monitors
+/// whose wallpaper still exists, and how long ago the oldest of them was set.
+fn restorable_wallpapers(
+    previous: state::DaemonState,
+    now_unix: u64,
+) -> (HashMap<String, state::MonitorState>, Duration) {
+    let restorable: HashMap<String, state::MonitorState> = previous
+        .monitors
+        .into_iter()
+        .filter(|(_, monitor)| monitor.wallpaper.exists())
+        .collect();
+    let oldest = restorable.values().map(|monitor| monitor.changed_unix).min();
+    let age = oldest.map_or(0, |changed| now_unix.saturating_sub(changed));
+    (restorable, Duration::from_secs(age))
+}
+
+/// `--start-delay`, else `daemon_startup_delay_ms`, else `start_delay_seconds`.
+fn daemon_start_delay(cfg: &config::Config, options: &DaemonArgs) -> Duration {
+    options
+        .start_delay
+        .map(Duration::from_secs)
+        .or(cfg.daemon_startup_delay_ms.map(Duration::from_millis))
+        .or(cfg.start_delay_seconds.map(Duration::from_secs))
+        .unwrap_or_default()
+}
+
+/// Caps video renderers at `battery_fps` or `ac_fps` when the power source
+/// changes, or when a reload changes the cap for the current one.
+fn update_fps_limit(power_state: &mut Option<(power::PowerState, u32)>, cfg: &config::Config) {
+    let current = power::PowerState::detect();
+    let fps = match current {
+        power::PowerState::Ac => cfg.ac_fps.unwrap_or(60),
+        power::PowerState::Battery => cfg.battery_fps.unwrap_or(24),
+    };
+    if *power_state == Some((current, fps)) {
+        return;
+    }
+    if power_state.is_some() {
+        info!("now on {:?} power, videos capped at {} fps", current, fps);
+    }
+    match state::set_fps_limit(fps) {
+        Ok(()) => *power_state = Some((current, fps)),
+        Err(error) => warning!("warning: {:#}", error),
+    }
+}
+
+/// Queues the wallpaper in one FIFO line for the monitors it names, ignoring
+/// (with a warning) paths that do not exist and monitors the daemon does not manage.
+fn queue_fifo_request(
+    line: &str,
+    cfg: &config::Config,
+    monitors: &[String],
+    pushed: &mut HashMap<String, PathBuf>,
+) {
+    let Some(request) = fifo::parse_request(line) else {
+        return;
+    };
+    let path = cfg.wallpaper_dir.join(&request.path);
+    if !path.exists() {
+        warning!("warning: ignoring FIFO request, no such file: {}", path.display());
+        return;
+    }
+    let targets: Vec<&String> = match &request.target {
+        fifo::FifoTarget::First => monitors.iter().take(1).collect(),
+        fifo::FifoTarget::All => monitors.iter().collect(),
+        fifo::FifoTarget::Monitor(name) => {
+            let matched: Vec<&String> = monitors
+                .iter()
+                .filter(|monitor| monitor.eq_ignore_ascii_case(name))
+                .collect();
+            if matched.is_empty() {
+                warning!("warning: ignoring FIFO request, monitor '{}' is not managed", name);
+            }
+            matched
+        }
+    };
+    for monitor in targets {
+        info!("monitor '{}': showing {} from the FIFO", monitor, path.display());
+        pushed.insert(monitor.clone(), path.clone());
+    }
+}
+
+/// Records the `workspace_wallpapers` (or else `workspace_wallpaper_dirs`) entry
+/// of each monitor's new workspace and returns the monitors whose entry changed.
+/// Events without a monitor go to the first one.
+fn apply_workspace_events(
+    events: &[hyprland_ipc::WorkspaceEvent],
+    cfg: &config::Config,
+    monitors: &[String],
+    targets: &mut HashMap<String, PathBuf>,
+) -> Vec<String> {
+    let mut switched = Vec::new();
+    for event in events {
+        let monitor = match &event.monitor {
+            Some(name) => monitors.iter().find(|m| m.eq_ignore_ascii_case(name)),
+            None => monitors.first(),
+        };
+        let Some(monitor) = monitor else {
+            continue;
+        };
+        let target = [&cfg.workspace_wallpapers, &cfg.workspace_wallpaper_dirs]
+            .into_iter()
+            .flatten()
+            .find_map(|map| {
+                map.get(&event.name)
+                    .or_else(|| event.id.as_ref().and_then(|id| map.get(id)))
+            });
+        if targets.get(monitor) == target {
+            continue;
+        }
+        debug!("monitor '{}' is on workspace '{}'", monitor, event.name);
+        match target {
+            Some(path) => targets.insert(monitor.clone(), path.clone()),
+            None => targets.remove(monitor),
+        };
+        if !switched.contains(monitor) {
+            switched.push(monitor.clone());
+        }
+    }
+    switched
+}
+
+/// `workspace_wallpapers` and `workspace_wallpaper_dirs` as the workspace
+/// listener was last set up for.
+type WorkspaceMaps = [Option<HashMap<String, PathBuf>>; 2];
+
+/// Restarts the workspace listener when a reload changed the workspace maps,
+/// or stops it when they are now empty. The current targets came from the
+/// old maps, so they are dropped.
+fn update_workspace_listener(
+    listener: &mut Option<hyprland_ipc::HyprlandEventListener>,
+    maps: &mut WorkspaceMaps,
+    cfg: &config::Config,
+    targets: &mut HashMap<String, PathBuf>,
+) {
+    let current = [
+        cfg.workspace_wallpapers.clone(),
+        cfg.workspace_wallpaper_dirs.clone(),
+    ];
+    if *maps == current {
+        return;
+    }
+    targets.clear();
+    *listener = None;
+    if current.iter().flatten().any(|map| !map.is_empty()) {
+        *listener = hyprland_ipc::HyprlandEventListener::spawn()
+            .map_err(|error| warning!("warning: workspace_wallpapers: {:#}", error))
+            .ok();
+    }
+    *maps = current;
+}
+
+/// A `workspace_wallpapers` entry picks from a directory or shows one file.
+fn workspace_target(path: &Path) -> ScheduleTarget<'_> {
+    if path.is_dir() {
+        ScheduleTarget::Dir(path)
+    } else {
+        ScheduleTarget::Path(path)
+    }
+}
+
+/// Keeps `watcher` watching the directories of `monitors` while
+/// `watch_wallpaper_dirs` is on, rebuilding it only when they change.
+fn update_directory_watcher(
+    watcher: &mut Option<watcher::DirectoryWatcher>,
+    cfg: &config::Config,
+    monitors: &[String],
+    resolutions: &MonitorResolutions,
+) {
+    if !cfg.watch_wallpaper_dirs.unwrap_or(false) {
+        *watcher = None;
+        return;
+    }
+    let map = watcher::DirectoryWatchMap::new(
+        monitors
+            .iter()
+            .map(|monitor| (monitor.clone(), media_dirs_for_monitor(cfg, monitor, resolutions))),
+    );
+    if watcher.as_ref().map(watcher::DirectoryWatcher::watches) == Some(&map) {
+        return;
+    }
+    *watcher = None;
+    if map.is_empty() {
+        return;
+    }
+    match watcher::DirectoryWatcher::spawn(map) {
+        Ok(spawned) => *watcher = Some(spawned),
+        Err(error) => warning!("warning: cannot watch the wallpaper directories: {}", error),
+    }
+}
+
+/// Follows `fifo_path` across reloads: a new path replaces the reader, and
+/// removing it stops the reader.
+fn update_fifo_reader(reader: &mut Option<fifo::FifoReader>, cfg: &config::Config) {
+    let current = reader.as_ref().map(fifo::FifoReader::path);
+    if current == cfg.fifo_path.as_deref() {
+        return;
+    }
+    *reader = None;
+    if let Some(path) = &cfg.fifo_path {
+        *reader = fifo::FifoReader::spawn(path)
+            .map_err(|error| warning!("warning: {:#}", error))
+            .ok();
+    }
+}
+
+/// The monitors among `monitors` that rotate now: those neither pinned nor
+/// locked, and named in `only` when that names any. Pins and locks match in
+/// any case, like `--monitor`.
+fn rotating_monitors<'a>(
+    monitors: impl Iterator<Item = &'a String>,
+    pinned: &BTreeSet<String>,
+    locks: &state::MonitorLocks,
+    only: Option<&[String]>,
+) -> Vec<String> {
+    monitors
+        .filter(|monitor| !state::is_pinned(pinned, monitor) && !locks.is_locked(monitor))
+        .filter(|monitor| match only {
+            Some(only) if !only.is_empty() => {
+                only.iter().any(|name| name.eq_ignore_ascii_case(monitor))
+            }
+            _ => true,
+        })
+        .cloned()
+        .collect()
+}
+
+fn describe_rotation(monitors: &[String]) -> String {
+    if monitors.is_empty() {
+        "every monitor".to_string()
+    } else {
+        monitors.join(", ")
+    }
+}
+
+/// Leaves `shutdown_wallpaper` (or black) on every monitor in `workers` with a
+/// detached renderer, then stops the in-process ones. The renderers run until
+/// the next daemon's first frame on their monitor, or `papdieo stop`.
+fn shutdown_renderer_children(
+    workers: HashMap<String, MonitorWorker>,
+    cfg: &config::Config,
+    base_options: &RenderOptions,
+) -> Result<()> {
+    let configured = cfg.shutdown_wallpaper.clone().filter(|path| {
+        let exists = path.exists();
+        if !exists {
+            warning!("warning: shutdown_wallpaper does not exist: {}", path.display());
+        }
+        exists
+    });
+    let (path, black) = match configured {
+        Some(path) => (path, false),
+        None => match picker::black_wallpaper() {
+            Ok(path) => (path, true),
+            Err(error) => {
+                warning!("warning: {:#}", error);
+                stop_monitor_workers(workers);
+                return Ok(());
+            }
+        },
+    };
+    let environment = build_renderer_environment(cfg);
+    let mut children = Vec::new();
+    let mut pids = BTreeMap::new();
+    for monitor in workers.keys() {
+        let assignment = MonitorAssignment {
+            monitor: monitor.clone(),
+            path: path.clone(),
+            fit: if black {
+                FitMode::Stretch
+            } else {
+                fit_mode_for_monitor(cfg, monitor)
+            },
+            transition: None,
+            offset: cfg.default_offset,
+        };
+        let options = assignment.render_options(base_options);
+        match spawn_renderer_child(&assignment.path, Some(monitor), &options, &environment, None) {
+            Ok(child) => {
+                // Only shown until the next daemon starts; never worth adopting.
+                state::WallpaperAdoptionRecord::remove(monitor, child.id());
+                pids.insert(monitor.clone(), child.id());
+                children.push((monitor.clone(), child));
+            }
+            Err(error) => warning!(
+                "warning: failed to start shutdown renderer for monitor '{}': {:#}",
+                monitor,
+                error
+            ),
+        }
+    }
+
+    if let Err(error) = state::save_shutdown_renderers(&pids) {
+        warning!("warning: {:#}", error);
+    }
+    if !children.is_empty() {
+        thread::sleep(SHUTDOWN_HANDOFF);
+    }
+    for (monitor, child) in &mut children {
+        if let Ok(Some(status)) = child.try_wait() {
+            warning!("warning: shutdown renderer for monitor '{}' exited ({})", monitor, status);
+        }
+    }
+    stop_monitor_workers(workers);
+    Ok(())
+}
+
+/// The detached renderer a previous daemon left on `monitor` at shutdown,
+/// unless the daemon adopted it. Only the recorded pid is stopped, and only
+/// while it is still a renderer for `monitor`; renderers started by
+/// `set --detach` or `daemon --once` are left alone.
+fn stop_leftover_renderers(monitor: &str, adopted_pid: Option<u32>) {
+    let Some(pid) = state::take_shutdown_renderer(monitor) else {
+        return;
+    };
+    if Some(pid) == adopted_pid || !renderer_pids_for_monitor(monitor).contains(&pid) {
+        return;
+    }
+    let _ = ProcessCommand::new("kill")
+        .args(["-TERM", &pid.to_string()])
+        .status();
+}
+
+/// Puts the outgoing workers whose monitor was assigned the wallpaper they
+/// already show back into `workers`, and drops those assignments, so that
+/// `hash-shuffle` (or a one-file directory) does not redraw or fade the same
+/// file every interval. Returns the monitors kept.
+fn keep_unchanged_workers(
+    assignments: &mut Vec<MonitorAssignment>,
+    outgoing: &mut HashMap<String, MonitorWorker>,
+    workers: &mut HashMap<String, MonitorWorker>,
+) -> Vec<String> {
+    let mut kept = Vec::new();
+    assignments.retain(|assignment| {
+        let monitor = &assignment.monitor;
+        if outgoing.get(monitor).is_none_or(|w| w.wallpaper != assignment.path) {
+            return true;
+        }
+        if let Some(worker) = outgoing.remove(monitor) {
+            workers.insert(monitor.clone(), worker);
+            kept.push(monitor.clone());
+        }
+        false
+    });
+    kept
+}
+
+/// Puts the outgoing workers of monitors that are still managed but got no
+/// assignment, e.g. because they are backing off, back into `workers`, so the
+/// monitor keeps its wallpaper until a replacement starts. Returns the monitors kept.
+fn keep_unassigned_workers(
+    assignments: &[MonitorAssignment],
+    monitors: &[String],
+    outgoing: &mut HashMap<String, MonitorWorker>,
+    workers: &mut HashMap<String, MonitorWorker>,
+) -> Vec<String> {
+    let kept: Vec<String> = outgoing
+        .keys()
+        .filter(|monitor| monitors.contains(*monitor))
+        .filter(|monitor| !assignments.iter().any(|a| &a.monitor == *monitor))
+        .cloned()
+        .collect();
+    for monitor in &kept {
+        if let Some(worker) = outgoing.remove(monitor) {
+            workers.insert(monitor.clone(), worker);
+        }
+    }
+    kept
+}
+
+/// A video shown on several monitors plays its audio on only one of them, the
+/// first by name.
+fn plays_audio(monitor: &str, monitors: &[String]) -> bool {
+    monitors.iter().min().is_none_or(|first| first == monitor)
+}
+
+fn stop_monitor_workers(workers: impl IntoIterator<Item = (String, MonitorWorker)>) {
+    let workers: Vec<(String, MonitorWorker)> = workers.into_iter().collect();
+    for (_, worker) in &workers {
+        worker.stop_signal.store(true, Ordering::Relaxed);
+    }
+    for (monitor, worker) in workers {
+        match worker.join() {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => {
+                if !is_broken_pipe_error(&error) {
+                    eprintln!("renderer for monitor '{}' failed: {}", monitor, error);
+                }
+            }
+            Err(_) => {
+                eprintln!("renderer for monitor '{}' panicked", monitor);
+            }
+        }
+    }
+}
+
+/// One rotation through separate renderer processes, which keep running after
+/// this returns.
+fn run_daemon_once(
+    config_path: Option<&Path>,
+    options: &DaemonArgs,
+    paths: &DaemonPaths,
+) -> Result<()> {
+    if daemon_is_running(&paths.pid) {
+        return Err(anyhow!(
+            "papdieo daemon is already running; use `papdieo restart` to re-pick wallpapers"
+        ));
+    }
+
+    let mut cfg = config::Config::load_or_default(config_path)?;
+    options.overrides.apply(&mut cfg);
+    wait_before_first_wallpaper(&cfg, options);
+
+    let base_options = base_render_options(&cfg);
+    let schedule = scheduler::compile_rules(cfg.schedule.as_deref().unwrap_or_default());
+    let scheduled = scheduler::active_rule(&schedule, &chrono::Local::now()).map(|r| r.target());
+    let rotation_mode = cfg.rotation_mode.unwrap_or(RotationMode::Random);
+
+    let monitors = daemon_monitors(&cfg)?;
+    warn_unknown_monitor_map_keys(&cfg, &monitors);
+    if monitors.is_empty() {
+        return Err(anyhow!("no monitors found"));
+    }
+
+    let log_path = RENDERER_LOG_PATH;
+    let log_out = OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(log_path)?;
+    let on_change = hooks::OnChangeHook::from_config(&cfg);
+    let environment = build_renderer_environment(&cfg);
+
+    let resolutions = monitor_resolutions(&cfg);
+    let mut taken = cfg.no_overlap.unwrap_or(false).then(HashSet::new);
+    let mut children = Vec::new();
+    for assignment in monitors.iter().filter_map(|monitor| {
+        assign_monitor(&cfg, monitor, &resolutions, None, scheduled, rotation_mode, taken.as_mut())
+    }) {
+        // Renderers left by an earlier `--once` are replaced once the new one is up.
+        let previous = renderer_pids_for_monitor(&assignment.monitor);
+        let mut options = assignment.render_options(&base_options);
+        options.mute_audio |= !plays_audio(&assignment.monitor, &monitors);
+        let spawned = log_out.try_clone().map_err(anyhow::Error::from).and_then(|log| {
+            spawn_renderer_child(
+                &assignment.path,
+                Some(&assignment.monitor),
+                &options,
+                &environment,
+                Some(log),
+            )
+        });
+        match spawned {
+            Ok(child) => children.push((assignment, child, previous)),
+            Err(error) => eprintln!(
+                "failed to start renderer for monitor '{}': {:#}",
+                assignment.monitor, error
+            ),
+        }
+    }
+
+    if !children.is_empty() {
+        thread::sleep(RENDERER_STARTUP_CHECK);
+    }
+    let total = children.len();
+    let mut started = 0;
+    for (assignment, mut child, previous) in children {
+        if let Some(status) = child.try_wait()? {
+            eprintln!(
+                "renderer for monitor '{}' exited early (status: {}), see {}",
+                assignment.monitor, status, log_path
+            );
+            continue;
+        }
+        started += 1;
+        for pid in previous {
+            let _ = ProcessCommand::new("kill")
+                .args(["-TERM", &pid.to_string()])
+                .status();
+        }
+        if let Some(hook) = &on_change {
+            hook.spawn_detached(Some(assignment.monitor.clone()), assignment.path.clone());
+        }
+    }
+
+    if started == 0 {
+        return Err(anyhow!("no renderer could be started, see {}", log_path));
+    }
+    info!("Set wallpapers on {} of {} monitors (log: {})", started, total, log_path);
+    Ok(())
+}
+
+fn wait_before_first_wallpaper(cfg: &config::Config, options: &DaemonArgs) {
+    let start_delay = daemon_start_delay(cfg, options);
+    if !start_delay.is_zero() {
+        debug!("waiting {:?} before first wallpaper", start_delay);
+        thread::sleep(start_delay);
+    }
+    if options.wait_for_compositor {
+        if let Err(error) = wait_for_compositor(COMPOSITOR_WAIT_MAX, COMPOSITOR_POLL_INTERVAL) {
+            warning!("warning: {:#}", error);
+        }
+    }
+}
+
+/// Every wallpaper directory of `monitors`, if none of them exists yet; empty
+/// as soon as one does.
+fn missing_wallpaper_dirs(
+    cfg: &config::Config,
+    monitors: &[String],
+    resolutions: &MonitorResolutions,
+) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = monitors
+        .iter()
+        .flat_map(|monitor| media_dirs_for_monitor(cfg, monitor, resolutions))
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    if dirs.iter().any(|dir| dir.is_dir()) {
+        return Vec::new();
+    }
+    dirs
+}
+
+/// Backoff for the daemon while its wallpaper directories do not exist:
+/// retries start 5s apart and double up to a minute, and the wait is logged at
+/// most once a minute.
+struct MissingDirWait {
+    wait: Duration,
+    last_logged: Option<Instant>,
+}
+
+impl MissingDirWait {
+    fn new() -> Self {
+        Self {
+            wait: MISSING_DIR_INITIAL_WAIT,
+            last_logged: None,
+        }
+    }
+
+    fn next_wait(&mut self) -> Duration {
+        let wait = self.wait;
+        self.wait = (self.wait * 2).min(MISSING_DIR_MAX_WAIT);
+        wait
+    }
+
+    fn should_log(&mut self, now: Instant) -> bool {
+        let due = self
+            .last_logged
+            .is_none_or(|at| now.duration_since(at) >= MISSING_DIR_LOG_INTERVAL);
+        if due {
+            self.last_logged = Some(now);
+        }
+        due
+    }
+}
+
+/// Sleeps for up to `timeout`, checking every second so a newly created
+/// directory (or a config change) starts the rotation right away.
+fn wait_for_wallpaper_dirs(
+    config_watcher: &mut config_watch::ConfigWatcher,
+    dirs: &[PathBuf],
+    timeout: Duration,
+) {
+    let mut elapsed = Duration::ZERO;
+    while elapsed < timeout && !dirs.iter().any(|dir| dir.is_dir()) {
+        let step = timeout.saturating_sub(elapsed).min(Duration::from_secs(1));
+        if config_watcher.wait(step) {
+            return;
+        }
+        elapsed += step;
+    }
+}
+
+/// The startup wallpaper wins, then the active schedule rule, then the
+/// monitor's own directories. With `taken` (`no_overlap`), random picks avoid
+/// the wallpapers already in it and the result is added to it.
+fn assign_monitor(
+    cfg: &config::Config,
+    monitor: &str,
+    resolutions: &MonitorResolutions,
+    startup: Option<&Path>,
+    scheduled: Option<ScheduleTarget>,
+    rotation_mode: RotationMode,
+    mut taken: Option<&mut HashSet<PathBuf>>,
+) -> Option<MonitorAssignment> {
+    let exclude = taken.as_deref();
+    let media = match (startup, scheduled) {
+        (Some(path), _) => path.to_path_buf(),
+        (None, Some(ScheduleTarget::Path(path))) => path.to_path_buf(),
+        (None, Some(ScheduleTarget::Dir(dir))) => pick_media_from_dirs(
+            cfg,
+            monitor,
+            &[dir.to_path_buf()],
+            rotation_mode,
+            exclude,
+        )?,
+        (None, None) => match &cfg.picker_script {
+            Some(script) => {
+                let dirs = media_dirs_for_monitor(cfg, monitor, resolutions);
+                let dir = dirs.first().unwrap_or(&cfg.wallpaper_dir);
+                match picker::run_picker_script(script, Some(monitor), dir) {
+                    Ok(path) => path,
+                    Err(error) => {
+                        warning!("warning: monitor '{}': {:#}", monitor, error);
+                        return None;
+                    }
+                }
+            }
+            None => pick_media_for_monitor(cfg, monitor, resolutions, rotation_mode, exclude)?,
+        },
+    };
+    if let Some(taken) = taken.as_mut() {
+        taken.insert(media.clone());
+    }
+    let fit = startup
+        .and(cfg.startup_fit)
+        .unwrap_or_else(|| fit_mode_for_monitor(cfg, monitor));
+    debug!("monitor '{}': {} (fit: {})", monitor, media.display(), fit_mode_arg(fit));
+    Some(MonitorAssignment {
+        monitor: monitor.to_string(),
+        path: media,
+        fit,
+        transition: transition_for_monitor(cfg, monitor),
+        offset: cfg.default_offset,
+    })
+}
+
+/// Whether the next picks can be made ahead of time. Only plain random picks
+/// are: sequential and shuffle modes would advance their saved position for a
+/// pick that may never be shown, and picker scripts and size limits pick
+/// differently.
+fn can_prepick(cfg: &config::Config, rotation_mode: RotationMode) -> bool {
+    rotation_mode == RotationMode::Random
+        && cfg.picker_script.is_none()
+        && cfg.min_wallpaper_size_bytes.is_none()
+        && cfg.max_wallpaper_size_bytes.is_none()
+}
+
+/// The random pick [`pick_media_from_dirs`] would make without exclusions.
+fn prepick_media(dirs: &[PathBuf], media: picker::MediaFilter) -> Result<PathBuf> {
+    match dirs {
+        [dir] => picker::pick_random_wallpaper(dir, media),
+        dirs => picker::pick_random_from_dirs(dirs, media),
+    }
+}
+
+/// The background pick for `monitor`, unless the config has changed in a way
+/// that makes it stale or another monitor already shows it (`no_overlap`).
+/// A failed pick is reported and the monitor is picked for again right away.
+fn take_prepicked(
+    pre_picker: &mut prepick::PrePicker,
+    cfg: &config::Config,
+    monitor: &str,
+    resolutions: &MonitorResolutions,
+    rotation_mode: RotationMode,
+    taken: Option<&HashSet<PathBuf>>,
+) -> Option<PathBuf> {
+    if !can_prepick(cfg, rotation_mode) {
+        return None;
+    }
+    match pre_picker.take(monitor, &media_dirs_for_monitor(cfg, monitor, resolutions))? {
+        Ok(path) if taken.is_some_and(|taken| taken.contains(&path)) => None,
+        Ok(path) => Some(path),
+        Err(error) => {
+            warning!("warning: monitor '{}': background pick failed: {:#}", monitor, error);
+            None
+        }
+    }
+}
+
+fn record_daemon_state(
+    daemon_state: &mut state::DaemonState,
+    assignments: &[MonitorAssignment],
+    fps: u32,
+) {
+    let changed_unix = state::unix_now();
+    let renderer_pid = daemon_state.pid;
+    daemon_state
+        .monitors
+        .extend(assignments.iter().map(|assignment| {
+            (
+                assignment.monitor.clone(),
+                state::MonitorState {
+                    wallpaper: assignment.path.clone(),
+                    changed_unix,
+                    renderer_pid,
+                    fps,
+                    fit: assignment.fit,
+                },
+            )
+        }));
+
+    if let Err(error) = daemon_state.save() {
+        warning!("warning: {:#}", error);
+    }
+}
+
+struct MonitorWorker {
+    stop_signal: Arc<AtomicBool>,
+    alpha: Arc<transition::SurfaceAlpha>,
+    handle: thread::JoinHandle<Result<()>>,
+    /// The `run-internal` process a `--watch-pidfile` daemon took over.
+    adopted_pid: Option<u32>,
+    wallpaper: PathBuf,
+}
+
+impl MonitorWorker {
+    fn join(self) -> thread::Result<Result<()>> {
+        self.handle.join()
+    }
+}
+
+/// Stops adopted renderer processes that have stayed over
+/// `max_renderer_cpu_percent_sustained` or `max_renderer_rss_mb` for the grace
+/// period. Their monitor is then released like any adopted renderer that
+/// exits, so the kill does not count as a crash or towards backoff.
+fn check_renderer_resources(
+    watch: &mut sysinfo::ResourceWatch,
+    cfg: &config::Config,
+    workers: &HashMap<String, MonitorWorker>,
+) {
+    let caps = sysinfo::ResourceCaps {
+        cpu_percent: cfg.max_renderer_cpu_percent_sustained,
+        memory_mb: cfg.max_renderer_rss_mb,
+    };
+    let watched: Vec<(&String, u32)> = workers
+        .iter()
+        .filter_map(|(monitor, worker)| Some((monitor, worker.adopted_pid?)))
+        .collect();
+    watch.retain(&watched.iter().map(|(_, pid)| *pid).collect::<Vec<_>>());
+    if caps == sysinfo::ResourceCaps::default() {
+        return;
+    }
+    let grace = cfg
+        .resource_violation_grace_seconds
+        .map(Duration::from_secs)
+        .unwrap_or(sysinfo::DEFAULT_VIOLATION_GRACE);
+    for (monitor, pid) in watched {
+        match watch.check(pid, caps, grace) {
+            Ok(Some(violation)) => {
+                warning!(
+                    "warning: renderer for monitor '{}' (pid {}) has used {:.0}% CPU and {} MiB for {}s; restarting it",
+                    monitor,
+                    pid,
+                    violation.cpu_percent,
+                    violation.rss_mb,
+                    violation.over_for.as_secs()
+                );
+                terminate_renderer(pid);
+                state::WallpaperAdoptionRecord::remove(monitor, pid);
+            }
+            Ok(None) => {}
+            Err(error) => debug!("resource check for monitor '{}': {:#}", monitor, error),
+        }
+    }
+}
+
+/// SIGTERM, then SIGKILL if the process is still there after
+/// [`RENDERER_TERM_GRACE`]. The wait runs on its own thread.
+fn terminate_renderer(pid: u32) {
+    let _ = ProcessCommand::new("kill")
+        .args(["-TERM", &pid.to_string()])
+        .status();
+    thread::spawn(move || {
+        let proc_dir = PathBuf::from(format!("/proc/{}", pid));
+        let deadline = Instant::now() + RENDERER_TERM_GRACE;
+        while proc_dir.exists() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(100));
+        }
+        if proc_dir.exists() {
+            let _ = ProcessCommand::new("kill")
+                .args(["-KILL", &pid.to_string()])
+                .status();
+        }
+    });
+}
+
+/// `fade_in` starts the surface transparent for a [`transition::TransitionCoordinator`].
+fn spawn_monitor_worker(
+    assignment: MonitorAssignment,
+    mut options: RenderOptions,
+    fade_in: bool,
+) -> MonitorWorker {
+    let alpha = transition::SurfaceAlpha::new(if fade_in { 0.0 } else { 1.0 });
+    options.surface_alpha = Some(Arc::clone(&alpha));
+    let stop_signal = Arc::new(AtomicBool::new(false));
+    let worker_stop_signal = Arc::clone(&stop_signal);
+    let wallpaper = assignment.path.clone();
+    let handle = thread::spawn(move || {
+        wallpaper::run_wallpaper_with_stop(
+            assignment.path,
+            Some(assignment.monitor.as_str()),
+            &options,
+            Some(worker_stop_signal.as_ref()),
+        )
+    });
+
+    MonitorWorker {
+        stop_signal,
+        alpha,
+        handle,
+        adopted_pid: None,
+        wallpaper,
+    }
+}
+
+/// Watches a renderer process from an earlier `set --detach` (or similar) in
+/// place of a worker thread. Stopping the worker stops the process. If the
+/// process exits on its own, [`release_exited_adopted_workers`] gives the
+/// monitor a new pick.
+fn adopt_renderer(record: &state::WallpaperAdoptionRecord) -> MonitorWorker {
+    let alpha = transition::SurfaceAlpha::new(1.0);
+    alpha.mark_shown();
+    let stop_signal = Arc::new(AtomicBool::new(false));
+    let worker_stop_signal = Arc::clone(&stop_signal);
+    let pid = record.pid;
+    let monitor = record.monitor.clone();
+    let handle = thread::spawn(move || {
+        let proc_dir = PathBuf::from(format!("/proc/{}", pid));
+        while proc_dir.exists() {
+            if worker_stop_signal.load(Ordering::Relaxed) {
+                let _ = ProcessCommand::new("kill")
+                    .args(["-TERM", &pid.to_string()])
+                    .status();
+                state::WallpaperAdoptionRecord::remove(&monitor, pid);
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(250));
+        }
+        state::WallpaperAdoptionRecord::remove(&monitor, pid);
+        Err(anyhow!("adopted renderer (pid {}) exited", pid))
+    });
+
+    MonitorWorker {
+        stop_signal,
+        alpha,
+        handle,
+        adopted_pid: Some(pid),
+        wallpaper: record.path.clone(),
+    }
+}
+
+/// Renderer records whose process is still a `run-internal` renderer showing
+/// the recorded wallpaper on the recorded monitor. Stale records are removed.
+fn adoptable_renderers() -> Vec<state::WallpaperAdoptionRecord> {
+    state::WallpaperAdoptionRecord::load_all()
+        .into_iter()
+        .filter(|record| {
+            let argv: Vec<String> = std::fs::read(format!("/proc/{}/cmdline", record.pid))
+                .map(|cmdline| {
+                    cmdline
+                        .split(|byte| *byte == 0)
+                        .filter(|arg| !arg.is_empty())
+                        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                        .collect()
+                })
+                .unwrap_or_default();
+            let matches = wallpaper_from_renderer_argv(&argv, &record.monitor)
+                .is_some_and(|current| current.path == record.path);
+            if !matches {
+                state::WallpaperAdoptionRecord::remove(&record.monitor, record.pid);
+            }
+            matches
+        })
+        .collect()
+}
+
+/// Removes the workers whose adopted process has exited, by monitor. The
+/// daemon did not start that process, so its exit is neither a crash nor a
+/// reason to back off; the monitor just gets a new pick.
+fn release_exited_adopted_workers(workers: &mut HashMap<String, MonitorWorker>) -> Vec<String> {
+    let exited: Vec<String> = workers
+        .iter()
+        .filter(|(_, worker)| worker.adopted_pid.is_some() && worker.handle.is_finished())
+        .map(|(monitor, _)| monitor.clone())
+        .collect();
+    for monitor in &exited {
+        if let Some(worker) = workers.remove(monitor) {
+            debug!("monitor '{}': {}", monitor, describe_worker_exit(worker.join()));
+        }
+        info!("adopted renderer for monitor '{}' exited, picking a new wallpaper", monitor);
+    }
+    exited
+}
+
+/// Removes and joins the workers whose renderer returned on its own (stopped
+/// workers are taken out of the map first), with how each one ended.
+fn reap_finished_workers(workers: &mut HashMap<String, MonitorWorker>) -> Vec<(String, String)> {
+    let finished: Vec<String> = workers
+        .iter()
+        .filter(|(_, worker)| worker.handle.is_finished())
+        .map(|(monitor, _)| monitor.clone())
+        .collect();
+    finished
+        .into_iter()
+        .filter_map(|monitor| {
+            let worker = workers.remove(&monitor)?;
+            Some((monitor, describe_worker_exit(worker.join())))
+        })
+        .collect()
+}
+
+fn record_unexpected_exits(
+    daemon_state: &mut state::DaemonState,
+    metrics: &metrics::Metrics,
+    exited: &[(String, String)],
+) {
+    if exited.is_empty() {
+        return;
+    }
+    for (monitor, _) in exited {
+        *daemon_state.unexpected_exits.entry(monitor.clone()).or_default() += 1;
+        metrics.record_crash(monitor);
+    }
+    if let Err(error) = daemon_state.save() {
+        warning!("warning: {:#}", error);
+    }
+}
+
+fn describe_worker_exit(result: thread::Result<Result<()>>) -> String {
+    match result {
+        Ok(Ok(())) => "exit status: ok".to_string(),
+        Ok(Err(error)) => format!("exit status: error: {}", error),
+        Err(_) => "exit status: panicked".to_string(),
+    }
+}
+
+/// Backs `monitor` off for longer after each failure in a row.
+fn record_monitor_failure(backoffs: &mut HashMap<String, backoff::BackoffState>, monitor: &str) {
+    let backoff = backoffs.entry(monitor.to_string()).or_default();
+    backoff.record_failure();
+    warning!(
+        "warning: monitor '{}' failed {} time(s) in a row, not rotating it for {}s",
+        monitor,
+        backoff.consecutive_failures,
+        backoff.delay().as_secs()
+    );
+}
+
+/// Caps how often a crashed monitor renderer (or, in the watchdog, the whole
+/// daemon) is restarted so a broken file cannot spin it in a crash loop.
+struct RestartBudget {
+    window_start: Instant,
+    attempts: u32,
+    limit: u32,
+    window: Duration,
+}
+
+impl RestartBudget {
+    fn new() -> Self {
+        Self::with_limit(RENDERER_RESTART_LIMIT, RENDERER_RESTART_WINDOW)
+    }
+
+    fn with_limit(limit: u32, window: Duration) -> Self {
+        Self {
+            window_start: Instant::now(),
+            attempts: 0,
+            limit,
+            window,
+        }
+    }
+
+    fn try_consume(&mut self, now: Instant) -> bool {
+        if now.duration_since(self.window_start) >= self.window {
+            self.window_start = now;
+            self.attempts = 0;
+        }
+
+        if self.attempts >= self.limit {
+            return false;
+        }
+
+        self.attempts += 1;
+        true
+    }
+}
+
+fn warn_unknown_monitor_map_keys(cfg: &config::Config, active_monitors: &[String]) {
+    let active: std::collections::HashSet<&str> = active_monitors.iter().map(String::as_str).collect();
+
+    if let Some(map) = cfg.monitor_wallpaper_dirs.as_ref() {
+        let unknown: Vec<&str> = map
+            .keys()
+            .map(String::as_str)
+            .filter(|k| !active.contains(k.trim()))
+            .collect();
+        if !unknown.is_empty() {
+            warning!(
+                "warning: monitor_wallpaper_dirs has unknown monitor keys: {}",
+                unknown.join(", ")
+            );
+        }
+    }
+
+    if let Some(map) = cfg.monitor_wallpaper_dir_lists.as_ref() {
+        let unknown: Vec<&str> = map
+            .keys()
+            .map(String::as_str)
+            .filter(|k| !active.contains(k.trim()))
+            .collect();
+        if !unknown.is_empty() {
+            warning!(
+                "warning: monitor_wallpaper_dir_lists has unknown monitor keys: {}",
+                unknown.join(", ")
+            );
+        }
+    }
+
+    if let Some(map) = cfg.monitor_fit_modes.as_ref() {
+        let unknown: Vec<&str> = map
+            .keys()
+            .map(String::as_str)
+            .filter(|k| !active.contains(k.trim()))
+            .collect();
+        if !unknown.is_empty() {
+            warning!(
+                "warning: monitor_fit_modes has unknown monitor keys: {}",
+                unknown.join(", ")
+            );
+        }
+    }
+}
+
+fn resolve_config_watch_path(config_path: Option<&Path>) -> Option<PathBuf> {
+    if let Some(path) = config_path {
+        return Some(path.to_path_buf());
+    }
+
+    let base = env::var("XDG_CONFIG_HOME")
+        .ok()
+        .map(PathBuf::from)
+        .or_else(|| env::var("HOME").ok().map(|h| PathBuf::from(h).join(".config")))?;
+
+    Some(base.join("papdieo").join("config.toml"))
+}
+
+/// Takes over `--ready-fd` when the daemon starts. It has to be an open pipe
+/// or socket other than stdio, and is closed on exec so that renderer
+/// processes do not inherit it.
+fn take_ready_fd(fd: i32) -> Result<OwnedFd> {
+    if (0..=2).contains(&fd) {
+        return Err(anyhow!("--ready-fd {} is stdin, stdout or stderr", fd));
+    }
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags < 0 {
+        return Err(anyhow!("--ready-fd {} is not an open file descriptor", fd));
+    }
+    let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+    if unsafe { libc::fstat(fd, stat.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("failed to inspect --ready-fd {}", fd));
+    }
+    let kind = unsafe { stat.assume_init() }.st_mode & libc::S_IFMT;
+    if kind != libc::S_IFIFO && kind != libc::S_IFSOCK {
+        return Err(anyhow!("--ready-fd {} is not a pipe or socket", fd));
+    }
+    unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) };
+    // Open, checked above, and handed to us by the supervisor alone.
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Readiness notification in the s6 style: one newline, then the fd is closed.
+fn notify_ready(fd: OwnedFd) {
+    let raw = fd.as_raw_fd();
+    let mut notify = File::from(fd);
+    match notify.write_all(b"\n") {
+        Ok(()) => debug!("signalled readiness on fd {}", raw),
+        Err(error) => warning!("warning: failed to signal readiness on fd {}: {}", raw, error),
+    }
+}
+
+/// The daemon holds an exclusive `flock` on the lock file for as long as it
+/// runs and writes its pid into it. The kernel drops the lock when a daemon
+/// dies, so a file left behind is simply locked again; a lock that is held is
+/// never removed, not even with `--force`.
+fn acquire_daemon_lock(paths: &DaemonPaths, force: bool) -> Result<File> {
+    if force {
+        remove_daemon_pid_file(paths);
+    }
+    if let Some(lock_file) = try_lock_file(&paths.lock)? {
+        return Ok(lock_file);
+    }
+    match read_pid(&paths.lock).or_else(|| read_pid(&paths.pid)) {
+        Some(pid) => Err(anyhow!(
+            "papdieo daemon is already running (pid {} holds {})",
+            pid,
+            paths.lock.display()
+        )),
+        None => Err(anyhow!(
+            "papdieo daemon is already running ({} is locked)",
+            paths.lock.display()
+        )),
+    }
+}
+
+fn try_lock_file(lock_path: &Path) -> Result<Option<File>> {
+    let mut lock_file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(lock_path)
+        .with_context(|| format!("failed to open daemon lock file {}", lock_path.display()))?;
+
+    if lock_file.try_lock_exclusive().is_err() {
+        return Ok(None);
+    }
+    lock_file.set_len(0)?;
+    write!(lock_file, "{}", std::process::id())?;
+    Ok(Some(lock_file))
+}
+
+/// `--focus-area` as fractions of the image; pixel areas are divided by its size.
+/// Videos and streams are always center-cropped, so they get no focus area.
+fn focus_area_fractions(path: &Path, area: [f32; 4], relative: bool) -> Result<Option<[f32; 4]>> {
+    let is_file = matches!(source::WallpaperSource::parse(path)?, source::WallpaperSource::File(_));
+    if !is_file || wallpaper::is_video_file(path) {
+        warning!(
+            "warning: --focus-area only applies to images; {} is cropped around its center",
+            path.display()
+        );
+        return Ok(None);
+    }
+    let fractions = if relative {
+        area
+    } else {
+        let (width, height) = formats::image_dimensions(path)?;
+        let (width, height) = (width as f32, height as f32);
+        [area[0] / width, area[1] / height, area[2] / width, area[3] / height]
+    };
+    if !config::is_valid_focus_area(&fractions) {
+        return Err(anyhow!(
+            "--focus-area {:?} does not lie within the image ({:?} as fractions)",
+            area,
+            fractions
+        ));
+    }
+    Ok(Some(fractions))
+}
+
+/// `allow_video`/`allow_images`, narrowed by `--no-video`/`--no-images`.
+fn media_filter(cfg: &config::Config, no_video: bool, no_images: bool) -> picker::MediaFilter {
+    let allowed = picker::MediaFilter::from_config(cfg);
+    picker::MediaFilter {
+        video: allowed.video && !no_video,
+        images: allowed.images && !no_images,
+    }
+}
+
+/// The monitors the daemon manages: configured or detected, minus `exclude_monitors`.
+fn daemon_monitors(cfg: &config::Config) -> Result<Vec<String>> {
+    Ok(filter_excluded_monitors(
+        configured_or_detected_monitors(cfg)?,
+        cfg.exclude_monitors.as_deref().unwrap_or_default(),
+    ))
+}
+
+/// Monitors in `detected` but not in `known`, and the other way round.
+fn monitor_changes(known: &[String], detected: &[String]) -> (Vec<String>, Vec<String>) {
+    let added = detected
+        .iter()
+        .filter(|monitor| !known.contains(monitor))
+        .cloned()
+        .collect();
+    let removed = known
+        .iter()
+        .filter(|monitor| !detected.contains(monitor))
+        .cloned()
+        .collect();
+    (added, removed)
+}
+
+/// Drops the monitors named in `exclude`. Like `--monitor`, a name matches
+/// case-insensitively; a connector type ("HDMI", "DP") matches every output of that type.
+fn filter_excluded_monitors(monitors: Vec<String>, exclude: &[String]) -> Vec<String> {
+    let exclude: Vec<String> = exclude
+        .iter()
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect();
+    monitors
+        .into_iter()
+        .filter(|monitor| {
+            let monitor = monitor.to_ascii_lowercase();
+            !exclude.iter().any(|name| monitor.starts_with(name.as_str()))
+        })
+        .collect()
+}
+
+fn configured_or_detected_monitors(cfg: &config::Config) -> Result<Vec<String>> {
+    if let Some(monitors) = cfg.monitors.as_ref().filter(|m| !m.is_empty()) {
+        let cleaned: Vec<String> = monitors
+            .iter()
+            .map(|m| m.trim())
+            .filter(|m| !m.is_empty())
+            .map(|m| m.to_string())
+            .collect();
+        if !cleaned.is_empty() {
+            return Ok(cleaned);
+        }
+    }
+
+    let dir_map_keys: Vec<&String> = cfg
+        .monitor_wallpaper_dirs
+        .iter()
+        .flat_map(|map| map.keys())
+        .chain(cfg.monitor_wallpaper_dir_lists.iter().flat_map(|map| map.keys()))
+        .collect();
+    if !dir_map_keys.is_empty() {
+        let mut from_map: Vec<String> = dir_map_keys
+            .into_iter()
+            .map(|m| m.trim())
+            .filter(|m| !m.is_empty())
+            .map(|m| m.to_string())
+            .collect();
+        from_map.sort();
+        from_map.dedup();
+        if !from_map.is_empty() {
+            let detected = detect_monitors()?;
+            if !detected.is_empty() {
+                let detected_set: std::collections::HashSet<&str> =
+                    detected.iter().map(String::as_str).collect();
+                let mut intersection: Vec<String> = from_map
+                    .iter()
+                    .filter(|m| detected_set.contains(m.as_str()))
+                    .cloned()
+                    .collect();
+
+                if intersection.is_empty() {
+                    warning!(
+                        "warning: monitor_wallpaper_dirs keys do not match detected monitors; using detected monitors instead"
+                    );
+                    return Ok(detected);
+                }
+
+                intersection.sort();
+                intersection.dedup();
+                return Ok(intersection);
+            }
+
+            return Ok(from_map);
+        }
+    }
+
+    if let Some(single) = cfg
+        .monitor
+        .as_ref()
+        .map(|m| m.trim().to_string())
+        .filter(|m| !m.is_empty())
+    {
+        return Ok(vec![single]);
+    }
+
+    let detected = detect_monitors()?;
+    if !detected.is_empty() {
+        return Ok(detected);
+    }
+
+    Ok(Vec::new())
+}
+
+fn wait_for_compositor(max_wait: Duration, poll_interval: Duration) -> Result<()> {
+    let started = Instant::now();
+    loop {
+        if matches!(detect_monitors(), Ok(monitors) if !monitors.is_empty()) {
+            debug!("compositor ready after {:?}", started.elapsed());
+            return Ok(());
+        }
+
+        if started.elapsed() >= max_wait {
+            return Err(anyhow!(
+                "compositor reported no monitors within {}s",
+                max_wait.as_secs()
+            ));
+        }
+        thread::sleep(poll_interval);
+    }
+}
+
+/// `hyprctl` first, then the `wl_output` globals so other compositors work too.
+fn detect_monitors() -> Result<Vec<String>> {
+    if let Some(value) = monitors::hyprland_monitors_json() {
+        let names = monitor_names(monitors::parse_hyprland_monitors(&value));
+        if !names.is_empty() {
+            return Ok(names);
+        }
+    }
+    detect_monitors_wayland()
+}
+
+fn detect_monitors_wayland() -> Result<Vec<String>> {
+    let outputs = monitors::wayland_monitors_with_roundtrips(monitors::NAME_ROUNDTRIPS)?;
+    let names = monitor_names(outputs);
+    if names.is_empty() {
+        warning!(
+            "warning: the compositor does not report output names (wl_output v4); set `monitors` in the config"
+        );
+    }
+    Ok(names)
+}
+
+fn monitor_names(monitors: Vec<monitors::MonitorInfo>) -> Vec<String> {
+    let mut names: Vec<String> = monitors.into_iter().filter_map(|m| m.name).collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn pick_media_for_monitor(
+    cfg: &config::Config,
+    monitor: &str,
+    resolutions: &MonitorResolutions,
+    rotation_mode: RotationMode,
+    exclude: Option<&HashSet<PathBuf>>,
+) -> Option<PathBuf> {
+    pick_media_from_dirs(
+        cfg,
+        monitor,
+        &media_dirs_for_monitor(cfg, monitor, resolutions),
+        rotation_mode,
+        exclude,
+    )
+}
+
+/// `exclude` only applies to random picks; sequential and shuffle keep their order.
+fn pick_media_from_dirs(
+    cfg: &config::Config,
+    monitor: &str,
+    media_dirs: &[PathBuf],
+    rotation_mode: RotationMode,
+    exclude: Option<&HashSet<PathBuf>>,
+) -> Option<PathBuf> {
+    let size_range = (cfg.min_wallpaper_size_bytes, cfg.max_wallpaper_size_bytes);
+    let media = picker::MediaFilter::from_config(cfg);
+    let picked = match (media_dirs, exclude) {
+        _ if size_range != (None, None) => pick_media_within_size(
+            monitor,
+            media_dirs,
+            size_range,
+            rotation_mode,
+            exclude,
+            media,
+        ),
+        ([media_dir], Some(exclude)) if rotation_mode == RotationMode::Random => {
+            picker::pick_random_excluding(media_dir, exclude, media)
+        }
+        ([media_dir], _) => {
+            picker::pick_wallpaper(media_dir, rotation_mode, Some(monitor), media)
+        }
+        (dirs, Some(exclude)) => picker::pick_random_from_dirs_excluding(dirs, exclude, media),
+        (dirs, None) => picker::pick_random_from_dirs(dirs, media),
+    };
+    match picked {
+        Ok(media) => Some(media),
+        // The directories exist but hold nothing papdieo can show.
+        Err(error) if media_dirs.iter().any(|dir| dir.is_dir()) => {
+            match picker::placeholder_wallpaper() {
+                Ok(placeholder) => {
+                    warning!(
+                        "warning: monitor '{}': {}; showing the built-in placeholder",
+                        monitor,
+                        error
+                    );
+                    Some(placeholder)
+                }
+                Err(placeholder_error) => {
+                    eprintln!(
+                        "failed to pick wallpaper for monitor '{}': {} ({:#})",
+                        monitor, error, placeholder_error
+                    );
+                    None
+                }
+            }
+        }
+        Err(error) => {
+            let dirs: Vec<String> = media_dirs.iter().map(|d| d.display().to_string()).collect();
+            eprintln!(
+                "failed to pick wallpaper for monitor '{}' from '{}': {}",
+                monitor,
+                dirs.join(", "),
+                error
+            );
+            None
+        }
+    }
+}
+
+/// The picks of [`pick_media_from_dirs`] among the wallpapers within
+/// `min_wallpaper_size_bytes`/`max_wallpaper_size_bytes`.
+fn pick_media_within_size(
+    monitor: &str,
+    media_dirs: &[PathBuf],
+    (min, max): (Option<u64>, Option<u64>),
+    rotation_mode: RotationMode,
+    exclude: Option<&HashSet<PathBuf>>,
+    media: picker::MediaFilter,
+) -> Result<PathBuf> {
+    let images: Vec<PathBuf> = picker::list_wallpapers_in_dirs(media_dirs, media)?
+        .into_iter()
+        .filter(|image| picker::size_in_range(image, min, max))
+        .collect();
+    if images.is_empty() {
+        let range = match (min, max) {
+            (Some(min), Some(max)) => format!("between {} and {} bytes", min, max),
+            (Some(min), None) => format!("of at least {} bytes", min),
+            (None, _) => format!("of at most {} bytes", max.unwrap_or(u64::MAX)),
+        };
+        return Err(anyhow!("no wallpapers {}", range));
+    }
+    match (media_dirs, exclude) {
+        ([_], Some(exclude)) if rotation_mode == RotationMode::Random => {
+            picker::pick_random_among_excluding(&images, exclude)
+        }
+        ([media_dir], _) => {
+            picker::pick_wallpaper_among(&images, media_dir, rotation_mode, Some(monitor))
+        }
+        (_, Some(exclude)) => picker::pick_random_among_excluding(&images, exclude),
+        (_, None) => picker::pick_random_among(&images, None),
+    }
+}
+
+/// Directories to pick from for a monitor. More than one entry means the
+/// daemon merges them and picks randomly regardless of `rotation_mode`.
+fn media_dirs_for_monitor(
+    cfg: &config::Config,
+    monitor: &str,
+    resolutions: &MonitorResolutions,
+) -> Vec<PathBuf> {
+    if let Some(dirs) = cfg
+        .monitor_wallpaper_dir_lists
+        .as_ref()
+        .and_then(|map| map.get(monitor))
+        .filter(|dirs| !dirs.is_empty())
+    {
+        return dirs.clone();
+    }
+
+    if let Some(dir) = cfg
+        .monitor_wallpaper_dirs
+        .as_ref()
+        .and_then(|map| map.get(monitor))
+    {
+        return vec![dir.clone()];
+    }
+
+    if let Some(dir) = cfg
+        .resolution_dirs
+        .as_deref()
+        .filter(|rules| !rules.is_empty())
+        .and_then(|rules| resolution_dir(rules, *resolutions.get(monitor)?))
+    {
+        return vec![dir.to_path_buf()];
+    }
+
+    global_media_dirs(cfg)
+}
+
+/// `resolution_dirs`: the directory of the largest rule `resolution` meets.
+fn resolution_dir(rules: &[config::ResolutionRule], (width, height): (u32, u32)) -> Option<&Path> {
+    let mut rules: Vec<&config::ResolutionRule> = rules.iter().collect();
+    rules.sort_by_key(|rule| std::cmp::Reverse((rule.min_width, rule.min_height)));
+    rules
+        .into_iter()
+        .find(|rule| width >= rule.min_width && height >= rule.min_height)
+        .map(|rule| rule.dir.as_path())
+}
+
+/// Pixel size of each monitor by name, as the wallpaper sees it (portrait on
+/// rotated outputs), for `resolution_dirs`.
+type MonitorResolutions = HashMap<String, (u32, u32)>;
+
+/// One `hyprctl -j monitors` for the cycle, skipped when no `resolution_dirs`
+/// rule needs it; empty outside Hyprland.
+fn monitor_resolutions(cfg: &config::Config) -> MonitorResolutions {
+    if cfg.resolution_dirs.as_deref().is_none_or(<[_]>::is_empty) {
+        return MonitorResolutions::new();
+    }
+    monitors::hyprland_monitors_json()
+        .map(|value| monitors::parse_hyprland_monitors(&value))
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|monitor| {
+            let resolution = monitor.oriented_resolution()?;
+            Some((monitor.name?, resolution))
+        })
+        .collect()
+}
+
+fn global_media_dirs(cfg: &config::Config) -> Vec<PathBuf> {
+    cfg.wallpaper_dirs
+        .clone()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| vec![cfg.wallpaper_dir.clone()])
+}
+
+fn fit_mode_for_monitor(cfg: &config::Config, monitor: &str) -> FitMode {
+    cfg.monitor_fit_modes
+        .as_ref()
+        .and_then(|map| map.get(monitor).copied())
+        .or(cfg.fit_mode)
+        .unwrap_or(FitMode::Cover)
+}
+
+fn transition_for_monitor(cfg: &config::Config, monitor: &str) -> Option<Transition> {
+    cfg.monitor_transitions
+        .as_ref()
+        .and_then(|map| map.get(monitor).copied())
+        .or(cfg.transition)
+}
+
+fn resolve_transition(cfg: &config::Config, monitor: Option<&str>) -> Option<Transition> {
+    match monitor {
+        Some(monitor) => transition_for_monitor(cfg, monitor),
+        None => cfg.transition,
+    }
+}
+
+fn fit_mode_arg(fit: FitMode) -> &'static str {
+    match fit {
+        FitMode::Stretch => "stretch",
+        FitMode::Fill => "fill",
+        FitMode::Cover => "cover",
+        FitMode::Fit => "fit",
+        FitMode::Contain => "contain",
+        FitMode::Center => "center",
+        FitMode::ScaleDown => "scale-down",
+        FitMode::Tile => "tile",
+    }
+}
+
+fn verbosity_args() -> Vec<&'static str> {
+    match output::verbosity() {
+        level if level < 0 => vec!["--quiet"],
+        0 => Vec::new(),
+        1 => vec!["-v"],
+        _ => vec!["-vv"],
+    }
+}
+
+fn transition_arg(transition: Transition) -> &'static str {
+    match transition {
+        Transition::None => "none",
+        Transition::Fade => "fade",
+    }
+}
+
+fn compositor_arg(compositor: CompositorType) -> &'static str {
+    match compositor {
+        CompositorType::Auto => "auto",
+        CompositorType::Hyprland => "hyprland",
+        CompositorType::Sway => "sway",
+    }
+}
+
+fn anchor_arg(anchor: SurfaceAnchor) -> &'static str {
+    match anchor {
+        SurfaceAnchor::AllEdges => "all-edges",
+        SurfaceAnchor::TopLeft => "topleft",
+        SurfaceAnchor::TopRight => "topright",
+        SurfaceAnchor::BottomLeft => "bottomleft",
+        SurfaceAnchor::BottomRight => "bottomright",
+        SurfaceAnchor::Center => "center",
+    }
+}
+
+fn scale_filter_arg(filter: ScaleFilter) -> &'static str {
+    match filter {
+        ScaleFilter::Nearest => "nearest",
+        ScaleFilter::Triangle => "triangle",
+        ScaleFilter::CatmullRom => "catmull-rom",
+        ScaleFilter::Gaussian => "gaussian",
+        ScaleFilter::Lanczos3 => "lanczos3",
+    }
+}
+
+/// `WAYLAND_DISPLAY`/`XDG_RUNTIME_DIR` from the config, for a daemon whose service
+/// manager does not pass on the session environment.
+fn session_environment_overrides(cfg: &config::Config) -> Vec<(&'static str, String)> {
+    let mut overrides = Vec::new();
+    if let Some(display) = &cfg.wayland_display {
+        overrides.push(("WAYLAND_DISPLAY", display.clone()));
+    }
+    if let Some(dir) = &cfg.xdg_runtime_dir {
+        overrides.push(("XDG_RUNTIME_DIR", dir.display().to_string()));
+    }
+    overrides
+}
+
+/// The current environment with the config's session overrides applied.
+fn build_renderer_environment(cfg: &config::Config) -> HashMap<String, String> {
+    let mut environment: HashMap<String, String> = env::vars_os()
+        .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+        .collect();
+    environment.extend(
+        session_environment_overrides(cfg)
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value)),
+    );
+    environment
+}
+
+fn spawn_renderer_child(
+    path: &Path,
+    monitor: Option<&str>,
+    options: &RenderOptions,
+    environment: &HashMap<String, String>,
+    log: Option<File>,
+) -> Result<Child> {
+    let exe = std::env::current_exe()?;
+    let mut command = ProcessCommand::new(exe);
+    command
+        .envs(environment)
+        .args(verbosity_args())
+        .arg("run-internal")
+        .arg(path)
+        .args(monitor.map(|m| vec!["--monitor", m]).unwrap_or_default())
+        .arg("--fps")
+        .arg(options.fps.to_string())
+        .arg("--fit")
+        .arg(fit_mode_arg(options.fit_mode));
+    if options.transition != Transition::None {
+        command
+            .arg("--transition")
+            .arg(transition_arg(options.transition));
+    }
+    if let Some(filter) = options.scale_filter {
+        command.arg("--filter").arg(scale_filter_arg(filter));
+    }
+    if options.compositor != CompositorType::Auto {
+        command
+            .arg("--compositor")
+            .arg(compositor_arg(options.compositor));
+    }
+    if options.offset.0 != 0 {
+        command
+            .arg("--image-offset-x")
+            .arg(options.offset.0.to_string());
+    }
+    if options.offset.1 != 0 {
+        command
+            .arg("--image-offset-y")
+            .arg(options.offset.1.to_string());
+    }
+    if let Some((width, height)) = options.tile_size {
+        command
+            .arg("--tile-size")
+            .arg(format!("{}x{}", width, height));
+    }
+    if let Some(area) = options.focus_area {
+        let area = area.map(|v| v.to_string()).join(",");
+        command.arg("--focus-area").arg(area);
+    }
+    if options.layer_namespace != wallpaper::DEFAULT_LAYER_NAMESPACE {
+        command
+            .arg("--layer-namespace")
+            .arg(&options.layer_namespace);
+    }
+    if options.exclusive_zone != wallpaper::DEFAULT_EXCLUSIVE_ZONE {
+        command
+            .arg("--exclusive-zone")
+            .arg(options.exclusive_zone.to_string());
+    }
+    if options.anchor != SurfaceAnchor::AllEdges {
+        command.arg("--anchor").arg(anchor_arg(options.anchor));
+    }
+    if !options.video_start.is_zero() {
+        command
+            .arg("--start-time")
+            .arg(options.video_start.as_millis().to_string());
+    }
+    if !options.startup_delay.is_zero() {
+        command
+            .arg("--delay")
+            .arg(options.startup_delay.as_millis().to_string());
+    }
+    if options.video_loops != 0 {
+        command.arg("--loop").arg(options.video_loops.to_string());
+    }
+    if let Some(index) = options.gpu_index {
+        command.arg("--gpu").arg(index.to_string());
+    }
+    if options.mute_audio {
+        command.arg("--mute");
+    }
+    if options.brightness != 1.0 {
+        command.arg("--brightness").arg(options.brightness.to_string());
+    }
+    if options.contrast != 1.0 {
+        command.arg("--contrast").arg(options.contrast.to_string());
+    }
+    for effect in &options.effects {
+        command.arg("--effect").arg(effect.name());
+    }
+    if options.blur_sigma != effects::DEFAULT_BLUR_SIGMA {
+        command.arg("--blur-sigma").arg(options.blur_sigma.to_string());
+    }
+    if options.transition_curve != EasingCurve::default() {
+        command
+            .arg("--opacity-curve")
+            .arg(options.transition_curve.name());
+    }
+    if options.auto_rotate {
+        command.arg("--auto-rotate");
+    }
+    if let Some(watermark) = &options.watermark {
+        command.arg("--watermark").arg(&watermark.path);
+    }
+    if options.all_outputs {
+        command.arg("--all-outputs");
+    }
+    if let Some(key) = &options.frame_cache_key {
+        command.arg("--cache-key").arg(key);
+    }
+
+    let (stdout, stderr) = match log {
+        Some(log_out) => {
+            let log_err = log_out.try_clone()?;
+            (Stdio::from(log_out), Stdio::from(log_err))
+        }
+        None => (Stdio::null(), Stdio::null()),
+    };
+
+    options.resource_limits.apply_before_exec(&mut command);
+    let child = command
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(stderr)
+        .spawn()?;
+    options.resource_limits.apply_after_spawn(child.id());
+    if let Some(monitor) = monitor {
+        let record = state::WallpaperAdoptionRecord::new(monitor, path, child.id());
+        if let Err(error) = record.save() {
+            debug!("renderer record not written: {:#}", error);
+        }
+    }
+    Ok(child)
+}
+
+fn cache_stream_source(path: PathBuf) -> Result<PathBuf> {
+    match source::WallpaperSource::parse(&path)? {
+        source::WallpaperSource::Http(url) => match source::cache_http_video(&url)? {
+            Some(cached) => {
+                debug!("cached {} to {}", url, cached.display());
+                Ok(cached)
+            }
+            None => {
+                warning!("warning: stream size is unknown or too large to cache; streaming instead");
+                Ok(path)
+            }
+        },
+        source::WallpaperSource::File(_) => Ok(path),
+        _ => {
+            warning!("warning: --cache-stream only applies to http(s) video files; streaming instead");
+            Ok(path)
+        }
+    }
+}
+
+/// What a monitor is currently showing, as far as papdieo can tell.
+#[derive(Debug, PartialEq)]
+struct MonitorWallpaper {
+    path: PathBuf,
+    fit: Option<FitMode>,
+    fps: Option<u32>,
+}
+
+/// Looks the monitor up in the daemon state, or else in the command lines of
+/// running `papdieo run-internal` renderers.
+fn find_wallpaper_for_monitor(monitor: &str) -> Option<MonitorWallpaper> {
+    let from_daemon = state::DaemonState::load()
+        .filter(|daemon| PathBuf::from(format!("/proc/{}", daemon.pid)).exists())
+        .and_then(|daemon| {
+            daemon
+                .monitors
+                .into_iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(monitor))
+        })
+        .map(|(_, current)| MonitorWallpaper {
+            path: current.wallpaper,
+            fit: Some(current.fit),
+            fps: Some(current.fps),
+        });
+    if from_daemon.is_some() {
+        return from_daemon;
+    }
+
+    other_process_argvs().find_map(|(_, argv)| wallpaper_from_renderer_argv(&argv, monitor))
+}
+
+fn renderer_pids_for_monitor(monitor: &str) -> Vec<u32> {
+    other_process_argvs()
+        .filter(|(_, argv)| wallpaper_from_renderer_argv(argv, monitor).is_some())
+        .map(|(pid, _)| pid)
+        .collect()
+}
+
+/// Pid and argv of every process except this one, from `/proc/*/cmdline`.
+fn other_process_argvs() -> impl Iterator<Item = (u32, Vec<String>)> {
+    let own_pid = std::process::id();
+    std::fs::read_dir("/proc")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok().map(|pid| (pid, entry)))
+        .filter(move |(pid, _)| *pid != own_pid)
+        .filter_map(|(pid, entry)| {
+            let cmdline = std::fs::read(entry.path().join("cmdline")).ok()?;
+            let argv = cmdline
+                .split(|byte| *byte == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                .collect();
+            Some((pid, argv))
+        })
+}
+
+fn wallpaper_from_renderer_argv(argv: &[String], monitor: &str) -> Option<MonitorWallpaper> {
+    if !argv.iter().any(|arg| arg == "run-internal") {
+        return None;
+    }
+    let Ok(PapdieoArgs {
+        command:
+            Some(Command::__RunInternal {
+                path,
+                assignments,
+                monitor: renderer_monitor,
+                fps,
+                fit,
+                ..
+            }),
+        ..
+    }) = PapdieoArgs::try_parse_from(argv)
+    else {
+        return None;
+    };
+
+    if let Some(assignments) = assignments {
+        let assignments: Vec<MonitorAssignment> = serde_json::from_str(&assignments).ok()?;
+        return assignments
+            .into_iter()
+            .find(|a| a.monitor.eq_ignore_ascii_case(monitor))
+            .map(|a| MonitorWallpaper {
+                path: a.path,
+                fit: Some(a.fit),
+                fps,
+            });
+    }
+
+    renderer_monitor
+        .filter(|name| name.eq_ignore_ascii_case(monitor))
+        .and(path)
+        .map(|path| MonitorWallpaper { path, fit, fps })
+}
+
+fn run_on_change_command(cfg: &config::Config, monitor: Option<&str>, path: &Path) {
+    if let Some(hook) = hooks::OnChangeHook::from_config(cfg) {
+        hook.spawn_detached(monitor.map(str::to_string), path.to_path_buf());
+    }
+}
+
+/// Entries that fail validation or exit right away are reported and skipped.
+fn batch_set(
+    manifest: &Path,
+    render_options: impl Fn(Option<&str>, Option<u32>, Option<FitMode>) -> RenderOptions,
+    environment: &HashMap<String, String>,
+) -> Result<()> {
+    let content = std::fs::read_to_string(manifest)
+        .with_context(|| format!("failed to read manifest: {}", manifest.display()))?;
+    let entries = state::parse_manifest(&content)
+        .with_context(|| format!("failed to parse manifest: {}", manifest.display()))?;
+
+    let log_path = RENDERER_LOG_PATH;
+    let log_out = OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(log_path)?;
+
+    let mut children = Vec::new();
+    for entry in &entries {
+        let monitor = entry.monitor.trim();
+        if monitor.is_empty() {
+            eprintln!("skipping {}: monitor name is empty", entry.path.display());
+            continue;
+        }
+        if !entry.path.exists() {
+            eprintln!(
+                "skipping monitor '{}': {} does not exist",
+                monitor,
+                entry.path.display()
+            );
+            continue;
+        }
+
+        let options = render_options(Some(monitor), entry.fps, entry.fit);
+        let spawned = log_out
+            .try_clone()
+            .map_err(anyhow::Error::from)
+            .and_then(|log| {
+                spawn_renderer_child(&entry.path, Some(monitor), &options, environment, Some(log))
+            });
+        match spawned {
+            Ok(child) => children.push((monitor, child)),
+            Err(error) => {
+                eprintln!("failed to start renderer for monitor '{}': {:#}", monitor, error)
+            }
+        }
+    }
+
+    if !children.is_empty() {
+        thread::sleep(RENDERER_STARTUP_CHECK);
+    }
+    let mut started = 0;
+    for (monitor, mut child) in children {
+        match child.try_wait()? {
+            Some(status) => eprintln!(
+                "renderer for monitor '{}' exited early (status: {}), see {}",
+                monitor, status, log_path
+            ),
+            None => {
+                debug!("monitor '{}': renderer pid {}", monitor, child.id());
+                started += 1;
+            }
+        }
+    }
+
+    info!(
+        "Started {} of {} renderers (log: {})",
+        started,
+        entries.len(),
+        log_path
+    );
+    if started == 0 && !entries.is_empty() {
+        return Err(anyhow!("no renderer could be started"));
+    }
+    Ok(())
+}
+
+/// `set --span-monitors`: cuts `path` into one part per monitor (in the
+/// runtime dir) and shows each part stretched on its monitor.
+fn span_wallpaper(
+    cfg: &config::Config,
+    path: &Path,
+    span_monitors: &[String],
+    options: RenderOptions,
+    environment: &HashMap<String, String>,
+    detach: bool,
+    unlock: bool,
+) -> Result<()> {
+    if span_monitors.len() < 2 {
+        return Err(anyhow!("--span-monitors needs at least two monitors"));
+    }
+    let source = source::WallpaperSource::parse(path)?;
+    if !matches!(source, source::WallpaperSource::File(_)) || wallpaper::is_video_file(path) {
+        return Err(anyhow!("--span-monitors only works with images"));
+    }
+    let geometries = monitors::hyprland_monitor_geometries(span_monitors)?;
+    let image = formats::open_image(path)?;
+
+    let mut assignments = Vec::new();
+    for (monitor, part) in wallpaper::compute_span_crop(&geometries, &image, options.fit_mode) {
+        let part_path = state::runtime_dir().join(format!("papdieo-span-{}.png", monitor));
+        part.save(&part_path)
+            .with_context(|| format!("failed to write {}", part_path.display()))?;
+        debug!(
+            "monitor '{}': {}x{} part of {}",
+            monitor,
+            part.width(),
+            part.height(),
+            path.display()
+        );
+        assignments.push(MonitorAssignment {
+            monitor,
+            path: part_path,
+            fit: FitMode::Stretch,
+            transition: Some(options.transition),
+            offset: None,
+        });
+    }
+    let on_shown =
+        |monitor: &str, pid| wallpaper_shown(cfg, Some(monitor), path, unlock)(pid, None);
+    if !detach {
+        return run_wallpaper_assignments(assignments, &options, on_shown);
+    }
+    spawn_assignment_renderers(&assignments, &options, environment, on_shown)?;
+    info!(
+        "Spanning {} across {} (log: {})",
+        path.display(),
+        span_monitors.join(", "),
+        RENDERER_LOG_PATH
+    );
+    Ok(())
+}
+
+/// Monitors named by `set --monitor-regex` / `monitor_regex`.
+fn find_matching_monitors(cfg: &config::Config, pattern: &str) -> Result<Vec<String>> {
+    let regex = regex::Regex::new(pattern)
+        .with_context(|| format!("invalid monitor regex '{}'", pattern))?;
+    let matching: Vec<String> = configured_or_detected_monitors(cfg)?
+        .into_iter()
+        .filter(|monitor| regex.is_match(monitor))
+        .collect();
+    if matching.is_empty() {
+        return Err(anyhow!("no monitor name matches '{}'", pattern));
+    }
+    Ok(matching)
+}
+
+/// Monitors named by `set --monitor-glob`, matched like file names: `*` and
+/// `?` wildcards and `[...]` classes against the whole name.
+fn find_monitors_by_glob(
+    cfg: &config::Config,
+    pattern: &str,
+    case_insensitive: bool,
+) -> Result<Vec<String>> {
+    let glob = glob::Pattern::new(pattern)
+        .with_context(|| format!("invalid monitor glob '{}'", pattern))?;
+    let options = glob::MatchOptions {
+        case_sensitive: !case_insensitive,
+        ..glob::MatchOptions::new()
+    };
+    let matching: Vec<String> = configured_or_detected_monitors(cfg)?
+        .into_iter()
+        .filter(|monitor| glob.matches_with(monitor, options))
+        .collect();
+    if matching.is_empty() {
+        return Err(anyhow!("no monitor name matches '{}'", pattern));
+    }
+    Ok(matching)
+}
+
+/// `set --monitor-regex` and `--monitor-glob`: the same wallpaper on each of `monitors`.
+fn set_on_monitors(
+    cfg: &config::Config,
+    path: &Path,
+    monitors: &[String],
+    options: RenderOptions,
+    environment: &HashMap<String, String>,
+    detach: bool,
+    unlock: bool,
+) -> Result<()> {
+    let assignments: Vec<MonitorAssignment> = monitors
+        .iter()
+        .map(|monitor| {
+            MonitorAssignment {
+                monitor: monitor.clone(),
+                path: path.to_path_buf(),
+                fit: options.fit_mode,
+                transition: Some(options.transition),
+                offset: None,
+            }
+        })
+        .collect();
+    let on_shown =
+        |monitor: &str, pid| wallpaper_shown(cfg, Some(monitor), path, unlock)(pid, None);
+    if !detach {
+        return run_wallpaper_assignments(assignments, &options, on_shown);
+    }
+    spawn_assignment_renderers(&assignments, &options, environment, on_shown)?;
+    info!(
+        "Showing {} on {} (log: {})",
+        path.display(),
+        monitors.join(", "),
+        RENDERER_LOG_PATH
+    );
+    Ok(())
+}
+
+/// One detached renderer per assignment, checked like a single `--detach` renderer.
+/// `on_shown` gets each monitor, by its output's name, and its renderer's pid
+/// once that has started.
+fn spawn_assignment_renderers(
+    assignments: &[MonitorAssignment],
+    options: &RenderOptions,
+    environment: &HashMap<String, String>,
+    mut on_shown: impl FnMut(&str, u32),
+) -> Result<()> {
+    let log_path = RENDERER_LOG_PATH;
+    let log_out = OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(log_path)?;
+    let monitors: Vec<String> = assignments.iter().map(|a| a.monitor.clone()).collect();
+    let mut children = Vec::new();
+    for assignment in assignments {
+        let mut options = assignment.render_options(options);
+        options.mute_audio |= !plays_audio(&assignment.monitor, &monitors);
+        let child = spawn_renderer_child(
+            &assignment.path,
+            Some(&assignment.monitor),
+            &options,
+            environment,
+            Some(log_out.try_clone()?),
+        )?;
+        children.push((assignment.monitor.as_str(), child));
+    }
+    thread::sleep(RENDERER_STARTUP_CHECK);
+    for (monitor, child) in &mut children {
+        if let Some(status) = child.try_wait()? {
+            return Err(anyhow!(
+                "renderer for monitor '{}' exited early (status: {}), see {}",
+                monitor,
+                status,
+                log_path
+            ));
+        }
+    }
+    for (monitor, child) in &children {
+        on_shown(&output_name(monitor, options.wayland_socket.as_deref()), child.id());
+    }
+    Ok(())
+}
+
+/// `on_shown` gets the pid of the process showing the wallpaper: this one once
+/// its first frame is up, or a detached renderer once it has started. With a
+/// monitor, it also gets the name of the output that is on.
+fn run_renderer(
+    path: std::path::PathBuf,
+    monitor: Option<String>,
+    mut options: RenderOptions,
+    environment: &HashMap<String, String>,
+    detach: bool,
+    on_shown: impl FnOnce(u32, Option<String>) + Send,
+) -> Result<()> {
+    if !detach {
+        let alpha = transition::SurfaceAlpha::new(1.0);
+        options.surface_alpha = Some(Arc::clone(&alpha));
+        let finished = AtomicBool::new(false);
+        return thread::scope(|scope| {
+            let (alpha, finished) = (&alpha, &finished);
+            scope.spawn(move || {
+                while !alpha.is_shown() {
+                    if finished.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    thread::sleep(Duration::from_millis(100));
+                }
+                on_shown(std::process::id(), alpha.output());
+            });
+            let result = wallpaper::run_wallpaper(path, monitor.as_deref(), &options);
+            finished.store(true, Ordering::Relaxed);
+            result
+        });
+    }
+
+    let log_path = RENDERER_LOG_PATH;
+    let log_out = OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(log_path)?;
+
+    let mut child =
+        spawn_renderer_child(&path, monitor.as_deref(), &options, environment, Some(log_out))?;
+
+    thread::sleep(RENDERER_STARTUP_CHECK);
+    if let Some(status) = child.try_wait()? {
+        return Err(anyhow!(
+            "wallpaper renderer exited early (status: {}), see {}",
+            status,
+            log_path
+        ));
+    }
+
+    let output = monitor
+        .as_deref()
+        .map(|monitor| output_name(monitor, options.wayland_socket.as_deref()));
+    on_shown(child.id(), output);
+    info!(
+        "Started wallpaper renderer in background (pid: {}, log: {})",
+        child.id(),
+        log_path
+    );
+    Ok(())
+}
+
+/// Only the renderer this loop spawned is ever stopped, including when the
+/// loop itself receives SIGTERM or SIGINT. Random picks skip whatever is on
+/// `avoid_monitors` at the time.
+#[allow(clippy::too_many_arguments)]
+fn run_rotate_loop_tracked(
+    media_dir: std::path::PathBuf,
+    script: Option<PathBuf>,
+    monitor: Option<String>,
+    interval: Duration,
+    mode: RotationMode,
+    media: picker::MediaFilter,
+    avoid_monitors: Vec<String>,
+    options: RenderOptions,
+    environment: &HashMap<String, String>,
+) -> Result<()> {
+    let interval = interval.max(config::MIN_ROTATION_INTERVAL);
+    let terminate = Arc::new(AtomicBool::new(false));
+    for signal in [signal_hook::consts::SIGTERM, signal_hook::consts::SIGINT] {
+        signal_hook::flag::register(signal, Arc::clone(&terminate))
+            .context("failed to install signal handler")?;
+    }
+
+    let mut previous_child: Option<Child> = None;
+    let result = loop {
+        if terminate.load(Ordering::Relaxed) {
+            break Ok(());
+        }
+
+        let picked = if let Some(script) = &script {
+            picker::run_picker_script(script, monitor.as_deref(), &media_dir)
+        } else if avoid_monitors.is_empty() || mode != RotationMode::Random {
+            picker::pick_wallpaper(&media_dir, mode, monitor.as_deref(), media)
+        } else {
+            let shown = avoid_monitors
+                .iter()
+                .filter_map(|other| find_wallpaper_for_monitor(other))
+                .map(|current| current.path)
+                .collect();
+            picker::pick_random_excluding(&media_dir, &shown, media)
+        };
+        let media = match picked {
+            Ok(media) => media,
+            Err(error) => break Err(error),
+        };
+        stop_renderer_child(previous_child.take());
+        match spawn_renderer_child(&media, monitor.as_deref(), &options, environment, None) {
+            Ok(child) => previous_child = Some(child),
+            Err(error) => break Err(error),
+        }
+
+        let started = Instant::now();
+        while started.elapsed() < interval && !terminate.load(Ordering::Relaxed) {
+            let remaining = interval.saturating_sub(started.elapsed());
+            thread::sleep(ROTATE_SIGNAL_POLL_INTERVAL.min(remaining));
+        }
+    };
+
+    stop_renderer_child(previous_child.take());
+    result
+}
+
+fn stop_renderer_child(child: Option<Child>) {
+    if let Some(mut child) = child {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        acquire_daemon_lock, apply_workspace_events, build_renderer_environment, create_dir,
+        daemon_exit_was_requested, daemon_start_delay, filter_excluded_monitors,
+        find_matching_monitors, find_monitors_by_glob, focus_area_fractions, global_media_dirs,
+        keep_unassigned_workers, keep_unchanged_workers, media_dirs_for_monitor,
+        missing_wallpaper_dirs, monitor_changes, notify_ready, pick_media_within_size, plays_audio,
+        read_pid, read_watchdog_pid, reap_finished_workers, release_exited_adopted_workers,
+        resolution_dir, restorable_wallpapers, rotating_monitors, take_ready_fd,
+        transition_for_monitor, update_workspace_listener, wallpaper_from_renderer_argv,
+        write_daemon_pid_file, write_default_config, DaemonPaths, InitStep, MissingDirWait,
+        MonitorAssignment, MonitorResolutions, MonitorWallpaper, MonitorWorker, RestartBudget,
+        DAEMON_LOCK_PATH, DAEMON_PID_PATH, DAEMON_RESTART_LIMIT, DAEMON_RESTART_WINDOW,
+        RENDERER_RESTART_WINDOW,
+    };
+    use crate::cli::DaemonArgs;
+    use crate::config::{Config, FitMode, RotationMode, Transition};
+    use crate::hyprland_ipc::WorkspaceEvent;
+    use crate::picker::MediaFilter;
+    use crate::state::{DaemonState, MonitorState};
+    use crate::transition::SurfaceAlpha;
+    use std::{
+        collections::{HashMap, HashSet},
+        fs,
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    const TRANSITION_FIXTURE: &str = r#"
+transition = "fade"
+monitor_transitions = { DP-2 = "none" }
+"#;
+
+    #[test]
+    fn transition_lookup_prefers_monitor_map_then_global() {
+        let cfg: Config = toml::from_str(TRANSITION_FIXTURE).expect("fixture should parse");
 
-        let monitors = configured_or_detected_monitors(&cfg)?;
-        warn_unknown_monitor_map_keys(&cfg, &monitors);
-        if monitors.is_empty() {
-            wait_for_interval_or_config_change(
-                Duration::from_secs(5),
-                watched_config_path.as_deref(),
-                &mut observed_config_mtime,
-            );
-            continue;
-        }
+        assert_eq!(transition_for_monitor(&cfg, "DP-2"), Some(Transition::None));
+        assert_eq!(transition_for_monitor(&cfg, "DP-1"), Some(Transition::Fade));
+    }
 
-        let mut assignments = Vec::new();
+    #[test]
+    fn transition_lookup_is_none_without_config() {
+        let cfg: Config = toml::from_str("").expect("empty config should parse");
 
-        for monitor in monitors.iter() {
-            let media_dir = media_dir_for_monitor(&cfg, monitor);
-            let media = match picker::pick_random_wallpaper(media_dir) {
-                Ok(media) => media,
-                Err(error) => {
-                    eprintln!(
-                        "failed to pick wallpaper for monitor '{}' from '{}': {}",
-                        monitor,
-                        media_dir.display(),
-                        error
-                    );
-                    continue;
-                }
-            };
-            let fit = fit_mode_for_monitor(&cfg, monitor);
-            assignments.push(MonitorAssignment {
-                monitor: monitor.clone(),
-                path: media,
-                fit,
-            });
-        }
+        assert_eq!(transition_for_monitor(&cfg, "DP-1"), None);
+    }
 
-        let mut launched_any_renderer = false;
+    #[test]
+    fn media_dirs_prefer_monitor_list_then_single_dir_then_global_list() {
+        let cfg: Config = toml::from_str(
+            r#"
+wallpaper_dir = "/walls"
+wallpaper_dirs = ["/walls/a", "/walls/b"]
+monitor_wallpaper_dirs = { DP-1 = "/walls/dp1", DP-2 = "/walls/dp2" }
+monitor_wallpaper_dir_lists = { DP-1 = ["/walls/nature", "/walls/cities"] }
+"#,
+        )
+        .expect("fixture should parse");
+        let none = MonitorResolutions::new();
 
-        if !assignments.is_empty() {
-            launched_any_renderer = true;
-            let stop_signal = Arc::new(AtomicBool::new(false));
-            let worker_stop_signal = Arc::clone(&stop_signal);
-            let worker = thread::spawn(move || {
-                run_wallpaper_assignments_cancellable(assignments, fps, Some(worker_stop_signal))
-            });
+        assert_eq!(
+            media_dirs_for_monitor(&cfg, "DP-1", &none),
+            vec![PathBuf::from("/walls/nature"), PathBuf::from("/walls/cities")]
+        );
+        assert_eq!(media_dirs_for_monitor(&cfg, "DP-2", &none), vec![PathBuf::from("/walls/dp2")]);
+        assert_eq!(
+            media_dirs_for_monitor(&cfg, "HDMI-A-1", &none),
+            vec![PathBuf::from("/walls/a"), PathBuf::from("/walls/b")]
+        );
+    }
 
-            let mut elapsed = Duration::ZERO;
-            let check_every = Duration::from_secs(1);
-            while elapsed < interval {
-                if worker.is_finished() {
-                    break;
-                }
+    #[test]
+    fn resolution_dirs_pick_the_largest_rule_a_monitor_meets() {
+        let cfg: Config = toml::from_str(
+            r#"
+resolution_dirs = [
+    { min_width = 2560, min_height = 1440, dir = "/walls/qhd" },
+    { min_width = 3840, min_height = 2160, dir = "/walls/4k" },
+]
+"#,
+        )
+        .expect("fixture should parse");
+        let rules = cfg.resolution_dirs.clone().unwrap();
 
-                let remaining = interval.saturating_sub(elapsed);
-                let sleep_for = remaining.min(check_every);
-                thread::sleep(sleep_for);
-                elapsed += sleep_for;
+        assert_eq!(resolution_dir(&rules, (3840, 2160)), Some(Path::new("/walls/4k")));
+        assert_eq!(resolution_dir(&rules, (5120, 1440)), Some(Path::new("/walls/qhd")));
+        assert_eq!(resolution_dir(&rules, (2560, 1440)), Some(Path::new("/walls/qhd")));
+        assert_eq!(resolution_dir(&rules, (1920, 1080)), None);
 
-                let Some(path) = watched_config_path.as_deref() else {
-                    continue;
-                };
+        let resolutions = MonitorResolutions::from([
+            ("DP-1".to_string(), (3840, 2160)),
+            ("DP-2".to_string(), (2160, 3840)),
+        ]);
+        assert_eq!(
+            media_dirs_for_monitor(&cfg, "DP-1", &resolutions),
+            vec![PathBuf::from("/walls/4k")]
+        );
+        assert_eq!(media_dirs_for_monitor(&cfg, "DP-2", &resolutions), global_media_dirs(&cfg));
+    }
 
-                let current_mtime = config_file_modified_time(path);
-                if current_mtime != observed_config_mtime {
-                    observed_config_mtime = current_mtime;
-                    break;
-                }
-            }
+    #[test]
+    fn restart_budget_allows_three_restarts_per_window() {
+        let start = Instant::now();
+        let mut budget = RestartBudget::new();
 
-            stop_signal.store(true, Ordering::Relaxed);
+        assert!(budget.try_consume(start));
+        assert!(budget.try_consume(start));
+        assert!(budget.try_consume(start));
+        assert!(!budget.try_consume(start + Duration::from_secs(10)));
+        assert!(budget.try_consume(start + RENDERER_RESTART_WINDOW + Duration::from_secs(1)));
+    }
 
-            match worker.join() {
-                Ok(Ok(())) => {}
-                Ok(Err(error)) => {
-                    if !is_broken_pipe_error(&error) {
-                        eprintln!("failed to run renderer assignments in daemon process: {}", error);
-                    }
-                }
-                Err(_) => {
-                    eprintln!("renderer assignment worker panicked");
-                }
-            }
+    #[test]
+    fn streams_and_videos_get_no_focus_area() {
+        let area = [100.0, 100.0, 200.0, 200.0];
+        for source in ["https://example.com/wall.png", "rtsp://camera/live", "clip.mp4"] {
+            assert_eq!(focus_area_fractions(Path::new(source), area, false).unwrap(), None);
         }
+        let relative = [0.25, 0.25, 0.5, 0.5];
+        let image = focus_area_fractions(Path::new("missing.png"), relative, true).unwrap();
+        assert_eq!(image, Some(relative));
+    }
 
-        if !launched_any_renderer {
-            wait_for_interval_or_config_change(
-                Duration::from_secs(DAEMON_STARTUP_RETRY_SECONDS),
-                watched_config_path.as_deref(),
-                &mut observed_config_mtime,
-            );
-        }
+    #[test]
+    fn watchdog_restarts_crashed_daemons_but_not_stopped_ones() {
+        use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+        let exited = |code: i32| ExitStatus::from_raw(code << 8);
+        let killed_by = |signal: i32| ExitStatus::from_raw(signal);
+        assert!(daemon_exit_was_requested(killed_by(signal_hook::consts::SIGTERM), Some(42), 42));
+        assert!(daemon_exit_was_requested(exited(0), Some(42), 42));
+        assert!(daemon_exit_was_requested(killed_by(9), None, 42));
+        assert!(!daemon_exit_was_requested(exited(101), Some(42), 42));
+        assert!(!daemon_exit_was_requested(killed_by(11), Some(42), 42));
+
+        let mut budget = RestartBudget::with_limit(DAEMON_RESTART_LIMIT, DAEMON_RESTART_WINDOW);
+        let start = Instant::now();
+        assert!((0..5).all(|_| budget.try_consume(start)));
+        assert!(!budget.try_consume(start + Duration::from_secs(300)));
+        assert!(budget.try_consume(start + DAEMON_RESTART_WINDOW));
     }
-}
 
-fn warn_unknown_monitor_map_keys(cfg: &config::Config, active_monitors: &[String]) {
-    let active: std::collections::HashSet<&str> = active_monitors.iter().map(String::as_str).collect();
+    #[test]
+    fn pid_file_names_the_daemon_and_its_watchdog() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("daemon.pid");
 
-    if let Some(map) = cfg.monitor_wallpaper_dirs.as_ref() {
-        let unknown: Vec<&str> = map
-            .keys()
-            .map(String::as_str)
-            .filter(|k| !active.contains(k.trim()))
-            .collect();
-        if !unknown.is_empty() {
-            eprintln!(
-                "warning: monitor_wallpaper_dirs has unknown monitor keys: {}",
-                unknown.join(", ")
-            );
-        }
+        write_daemon_pid_file(&path, 42).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content, format!("42\n{}\n", std::process::id()));
+        assert_eq!(read_pid(&path), Some(42));
+        // This test process is not a `daemon-watchdog`, so `stop` leaves it alone.
+        assert_eq!(read_watchdog_pid(&path), None);
+
+        std::fs::write(&path, "42").unwrap();
+        assert_eq!(read_pid(&path), Some(42));
+        assert_eq!(read_watchdog_pid(&path), None);
     }
 
-    if let Some(map) = cfg.monitor_fit_modes.as_ref() {
-        let unknown: Vec<&str> = map
-            .keys()
-            .map(String::as_str)
-            .filter(|k| !active.contains(k.trim()))
-            .collect();
-        if !unknown.is_empty() {
-            eprintln!(
-                "warning: monitor_fit_modes has unknown monitor keys: {}",
-                unknown.join(", ")
-            );
-        }
+    #[test]
+    fn init_steps_are_idempotent_and_force_rewrites_the_config() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let data_dir = root.join("data").join("papdieo");
+        let config_path = root.join("config.toml");
+
+        let created = (create_dir(&data_dir).unwrap(), create_dir(&data_dir).unwrap());
+        let written = write_default_config(&config_path, false).unwrap();
+        std::fs::write(&config_path, "video_fps = 30\n").unwrap();
+        let kept = write_default_config(&config_path, false).unwrap();
+        let kept_content = std::fs::read_to_string(&config_path).unwrap();
+        let forced = write_default_config(&config_path, true).unwrap();
+        let forced_content = std::fs::read_to_string(&config_path).unwrap();
+
+        assert_eq!(created, (InitStep::Created, InitStep::Existed));
+        assert_eq!(
+            (written, kept, forced),
+            (InitStep::Created, InitStep::Existed, InitStep::Overwritten)
+        );
+        assert_eq!(kept_content, "video_fps = 30\n");
+        assert_eq!(forced_content, crate::config::CONFIG_TEMPLATE);
     }
-}
 
-fn wait_for_interval_or_config_change(
-    interval: Duration,
-    config_path: Option<&Path>,
-    observed_mtime: &mut Option<SystemTime>,
-) {
-    let check_every = Duration::from_secs(1);
-    let mut elapsed = Duration::ZERO;
+    #[test]
+    fn missing_wallpaper_dirs_back_off_and_log_once_a_minute() {
+        let cfg: Config = toml::from_str("wallpaper_dir = \"/nonexistent/papdieo-walls\"").unwrap();
+        let monitors = ["DP-1".to_string(), "DP-2".to_string()];
+        assert_eq!(
+            missing_wallpaper_dirs(&cfg, &monitors, &MonitorResolutions::new()),
+            vec![PathBuf::from("/nonexistent/papdieo-walls")]
+        );
+        let cfg: Config = toml::from_str("wallpaper_dir = \"/tmp\"").unwrap();
+        assert!(missing_wallpaper_dirs(&cfg, &monitors, &MonitorResolutions::new()).is_empty());
 
-    while elapsed < interval {
-        let remaining = interval.saturating_sub(elapsed);
-        let sleep_for = remaining.min(check_every);
-        thread::sleep(sleep_for);
-        elapsed += sleep_for;
+        let mut wait = MissingDirWait::new();
+        let waits: Vec<u64> = (0..6).map(|_| wait.next_wait().as_secs()).collect();
+        assert_eq!(waits, vec![5, 10, 20, 40, 60, 60]);
 
-        let Some(path) = config_path else {
-            continue;
+        let start = Instant::now();
+        assert!(wait.should_log(start));
+        assert!(!wait.should_log(start + Duration::from_secs(35)));
+        assert!(wait.should_log(start + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn renderer_command_lines_are_matched_by_monitor() {
+        let argv = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+        let single = argv(&[
+            "/usr/bin/papdieo",
+            "run-internal",
+            "/walls/a.png",
+            "--monitor",
+            "eDP-1",
+            "--fps",
+            "30",
+            "--fit",
+            "contain",
+        ]);
+        assert_eq!(
+            wallpaper_from_renderer_argv(&single, "edp-1"),
+            Some(MonitorWallpaper {
+                path: PathBuf::from("/walls/a.png"),
+                fit: Some(FitMode::Contain),
+                fps: Some(30),
+            })
+        );
+        assert_eq!(wallpaper_from_renderer_argv(&single, "DP-2"), None);
+
+        let assignments = argv(&[
+            "papdieo",
+            "run-internal",
+            "--assignments",
+            r#"[{"monitor":"DP-2","path":"/walls/b.mp4","fit":"cover"}]"#,
+        ]);
+        assert_eq!(
+            wallpaper_from_renderer_argv(&assignments, "DP-2").map(|w| w.path),
+            Some(PathBuf::from("/walls/b.mp4"))
+        );
+
+        assert_eq!(wallpaper_from_renderer_argv(&argv(&["papdieo", "list"]), "DP-2"), None);
+    }
+
+    #[test]
+    fn daemon_paths_prefer_flag_then_config_then_default() {
+        let mut cfg = Config::default();
+        let defaults = DaemonPaths::resolve(&DaemonArgs::default(), &cfg);
+        assert_eq!(defaults.pid, PathBuf::from(DAEMON_PID_PATH));
+        assert_eq!(defaults.lock, PathBuf::from(DAEMON_LOCK_PATH));
+
+        cfg.pid_file = Some(PathBuf::from("/run/user/1000/papdieo.pid"));
+        cfg.lock_file = Some(PathBuf::from("/run/user/1000/papdieo.lock"));
+        let options = DaemonArgs {
+            pidfile: Some(PathBuf::from("/tmp/second.pid")),
+            ..DaemonArgs::default()
         };
+        let paths = DaemonPaths::resolve(&options, &cfg);
+        assert_eq!(paths.pid, PathBuf::from("/tmp/second.pid"));
+        assert_eq!(paths.lock, PathBuf::from("/run/user/1000/papdieo.lock"));
+    }
 
-        let current_mtime = config_file_modified_time(path);
-        if current_mtime != *observed_mtime {
-            *observed_mtime = current_mtime;
-            break;
-        }
+    #[test]
+    fn ready_fd_receives_one_newline_and_is_closed() {
+        use std::{
+            fs::File,
+            io::Read,
+            os::fd::{AsRawFd, IntoRawFd},
+        };
+
+        let temp = tempfile::tempdir().unwrap();
+        let file = File::create(temp.path().join("ready")).unwrap();
+        let error = take_ready_fd(file.as_raw_fd()).unwrap_err();
+        assert!(error.to_string().contains("not a pipe or socket"), "{:#}", error);
+        assert!(take_ready_fd(1).is_err());
+
+        let (mut reader, writer) = std::io::pipe().unwrap();
+        let fd = take_ready_fd(writer.into_raw_fd()).unwrap();
+        let flags = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GETFD) };
+        assert_ne!(flags & libc::FD_CLOEXEC, 0);
+        notify_ready(fd);
+
+        let mut received = String::new();
+        reader.read_to_string(&mut received).unwrap();
+        assert_eq!(received, "\n");
     }
-}
 
-fn config_file_modified_time(path: &Path) -> Option<SystemTime> {
-    std::fs::metadata(path).ok()?.modified().ok()
-}
+    #[test]
+    fn daemon_lock_uses_the_resolved_lock_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let options = DaemonArgs {
+            lockfile: Some(dir.join("daemon.lock")),
+            ..DaemonArgs::default()
+        };
+        let paths = DaemonPaths::resolve(&options, &Config::default());
 
-fn resolve_config_watch_path(config_path: Option<&Path>) -> Option<PathBuf> {
-    if let Some(path) = config_path {
-        return Some(path.to_path_buf());
+        let held = acquire_daemon_lock(&paths, false).unwrap();
+        assert!(dir.join("daemon.lock").exists());
+        assert!(acquire_daemon_lock(&paths, false).is_err());
+        drop(held);
+        assert!(acquire_daemon_lock(&paths, false).is_ok());
     }
 
-    let base = env::var("XDG_CONFIG_HOME")
-        .ok()
-        .map(PathBuf::from)
-        .or_else(|| env::var("HOME").ok().map(|h| PathBuf::from(h).join(".config")))?;
+    #[test]
+    fn held_lock_is_never_replaced() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let paths = DaemonPaths {
+            pid: dir.join("daemon.pid"),
+            lock: dir.join("daemon.lock"),
+        };
 
-    Some(base.join("papdieo").join("config.toml"))
-}
+        let held = acquire_daemon_lock(&paths, false).unwrap();
+        let error = acquire_daemon_lock(&paths, false).unwrap_err().to_string();
+        assert!(error.contains(&format!("pid {}", std::process::id())), "{}", error);
 
-fn acquire_daemon_lock() -> Result<File> {
-    let lock_file = OpenOptions::new()
-        .create(true)
-        .read(true)
-        .write(true)
-        .open(DAEMON_LOCK_PATH)?;
+        // Whatever pids the files name, a held lock stays held, --force or not.
+        std::fs::write(&paths.lock, "999999999").unwrap();
+        std::fs::write(&paths.pid, "999999999").unwrap();
+        assert!(acquire_daemon_lock(&paths, false).is_err());
+        assert!(acquire_daemon_lock(&paths, true).is_err());
+        assert!(!paths.pid.exists());
+        assert!(paths.lock.exists());
 
-    lock_file
-        .try_lock_exclusive()
-        .map_err(|_| anyhow!("papdieo daemon is already running"))?;
+        // Once the holder is gone, the file left behind is locked again.
+        drop(held);
+        let _taken = acquire_daemon_lock(&paths, false).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&paths.lock).unwrap(),
+            std::process::id().to_string()
+        );
+    }
 
-    Ok(lock_file)
-}
+    #[test]
+    fn renderer_environment_inherits_and_applies_session_overrides() {
+        let inherited = build_renderer_environment(&Config::default());
+        assert_eq!(inherited.get("PATH"), std::env::var("PATH").ok().as_ref());
 
-fn configured_or_detected_monitors(cfg: &config::Config) -> Result<Vec<String>> {
-    if let Some(monitors) = cfg.monitors.as_ref().filter(|m| !m.is_empty()) {
-        let cleaned: Vec<String> = monitors
-            .iter()
-            .map(|m| m.trim())
-            .filter(|m| !m.is_empty())
-            .map(|m| m.to_string())
-            .collect();
-        if !cleaned.is_empty() {
-            return Ok(cleaned);
-        }
+        let cfg = Config {
+            wayland_display: Some("wayland-7".to_string()),
+            xdg_runtime_dir: Some(PathBuf::from("/run/user/4242")),
+            ..Config::default()
+        };
+        let environment = build_renderer_environment(&cfg);
+        assert_eq!(environment["WAYLAND_DISPLAY"], "wayland-7");
+        assert_eq!(environment["XDG_RUNTIME_DIR"], "/run/user/4242");
+        assert_eq!(environment.get("PATH"), inherited.get("PATH"));
     }
 
-    if let Some(map) = cfg.monitor_wallpaper_dirs.as_ref() {
-        let mut from_map: Vec<String> = map
-            .keys()
-            .map(|m| m.trim())
-            .filter(|m| !m.is_empty())
-            .map(|m| m.to_string())
-            .collect();
-        from_map.sort();
-        from_map.dedup();
-        if !from_map.is_empty() {
-            let detected = detect_monitors()?;
-            if !detected.is_empty() {
-                let detected_set: std::collections::HashSet<&str> =
-                    detected.iter().map(String::as_str).collect();
-                let mut intersection: Vec<String> = from_map
-                    .iter()
-                    .filter(|m| detected_set.contains(m.as_str()))
-                    .cloned()
-                    .collect();
+    #[test]
+    fn locked_monitors_are_skipped_whatever_their_case() {
+        let monitors = ["DP-1".to_string(), "DP-2".to_string(), "HDMI-A-1".to_string()];
+        let mut locks = crate::state::MonitorLocks::default();
+        locks.lock("dp-1", false, Some(41));
+        let pinned = Default::default();
 
-                if intersection.is_empty() {
-                    eprintln!(
-                        "warning: monitor_wallpaper_dirs keys do not match detected monitors; using detected monitors instead"
-                    );
-                    return Ok(detected);
-                }
+        let rotating = rotating_monitors(monitors.iter(), &pinned, &locks, None);
+        assert_eq!(rotating, ["DP-2", "HDMI-A-1"]);
+        assert_eq!(locks.renderer("DP-1"), Some(41));
 
-                intersection.sort();
-                intersection.dedup();
-                return Ok(intersection);
-            }
+        let only = ["hdmi-a-1".to_string(), "DP-1".to_string()];
+        let rotating = rotating_monitors(monitors.iter(), &pinned, &locks, Some(&only));
+        assert_eq!(rotating, ["HDMI-A-1"]);
+    }
 
-            return Ok(from_map);
+    #[test]
+    fn pinned_monitors_are_skipped_whatever_their_case() {
+        let monitors = ["DP-1".to_string(), "DP-2".to_string()];
+        let pinned = ["dp-2".to_string()].into_iter().collect();
+        let locks = crate::state::MonitorLocks::default();
+
+        let rotating = rotating_monitors(monitors.iter(), &pinned, &locks, None);
+        assert_eq!(rotating, ["DP-1"]);
+        assert!(crate::state::is_pinned(&pinned, "DP-2"));
+    }
+
+    #[test]
+    fn a_pick_of_the_shown_wallpaper_keeps_the_worker() {
+        let worker = |path: &str| MonitorWorker {
+            stop_signal: Arc::new(AtomicBool::new(false)),
+            alpha: SurfaceAlpha::new(1.0),
+            handle: std::thread::spawn(|| Ok(())),
+            adopted_pid: None,
+            wallpaper: PathBuf::from(path),
+        };
+        let assignment = |monitor: &str, path: &str| MonitorAssignment {
+            monitor: monitor.to_string(),
+            path: PathBuf::from(path),
+            fit: FitMode::Cover,
+            transition: None,
+            offset: None,
+        };
+        let mut outgoing = HashMap::from([
+            ("DP-1".to_string(), worker("/walls/a.png")),
+            ("DP-2".to_string(), worker("/walls/b.png")),
+        ]);
+        let mut workers = HashMap::new();
+        let mut assignments = vec![
+            assignment("DP-1", "/walls/a.png"),
+            assignment("DP-2", "/walls/c.png"),
+            assignment("HDMI-A-1", "/walls/a.png"),
+        ];
+
+        let kept = keep_unchanged_workers(&mut assignments, &mut outgoing, &mut workers);
+        assert_eq!(kept, ["DP-1"]);
+        assert!(workers.contains_key("DP-1") && !outgoing.contains_key("DP-1"));
+        assert!(outgoing.contains_key("DP-2"));
+        let monitors: Vec<&str> = assignments.iter().map(|a| a.monitor.as_str()).collect();
+        assert_eq!(monitors, ["DP-2", "HDMI-A-1"]);
+
+        // DP-3 is backing off and got nothing; HDMI-A-2 is no longer managed.
+        outgoing.insert("DP-3".to_string(), worker("/walls/d.png"));
+        outgoing.insert("HDMI-A-2".to_string(), worker("/walls/e.png"));
+        let managed = ["DP-2", "DP-3", "HDMI-A-1"].map(String::from);
+        let kept = keep_unassigned_workers(&assignments, &managed, &mut outgoing, &mut workers);
+        assert_eq!(kept, ["DP-3"]);
+        assert_eq!(workers["DP-3"].wallpaper, PathBuf::from("/walls/d.png"));
+        let mut left: Vec<&String> = outgoing.keys().collect();
+        left.sort();
+        assert_eq!(left, ["DP-2", "HDMI-A-2"]);
+    }
+
+    #[test]
+    fn only_workers_that_returned_are_reaped() {
+        let stop_signal = Arc::new(AtomicBool::new(false));
+        let running_stop = Arc::clone(&stop_signal);
+        let mut workers = HashMap::from([
+            (
+                "DP-1".to_string(),
+                MonitorWorker {
+                    stop_signal: Arc::new(AtomicBool::new(false)),
+                    alpha: SurfaceAlpha::new(1.0),
+                    handle: std::thread::spawn(|| Err(anyhow::anyhow!("decoder crashed"))),
+                    adopted_pid: None,
+                    wallpaper: PathBuf::from("/walls/a.png"),
+                },
+            ),
+            (
+                "DP-2".to_string(),
+                MonitorWorker {
+                    stop_signal: Arc::clone(&stop_signal),
+                    alpha: SurfaceAlpha::new(1.0),
+                    handle: std::thread::spawn(move || {
+                        while !running_stop.load(Ordering::Relaxed) {
+                            std::thread::sleep(Duration::from_millis(5));
+                        }
+                        Ok(())
+                    }),
+                    adopted_pid: None,
+                    wallpaper: PathBuf::from("/walls/a.png"),
+                },
+            ),
+        ]);
+        while !workers["DP-1"].handle.is_finished() {
+            std::thread::sleep(Duration::from_millis(5));
         }
+
+        let reaped = reap_finished_workers(&mut workers);
+        assert_eq!(
+            reaped,
+            [("DP-1".to_string(), "exit status: error: decoder crashed".to_string())]
+        );
+        assert!(workers.contains_key("DP-2"));
+
+        stop_signal.store(true, Ordering::Relaxed);
+        let _ = workers.remove("DP-2").unwrap().join();
     }
 
-    if let Some(single) = cfg
-        .monitor
-        .as_ref()
-        .map(|m| m.trim().to_string())
-        .filter(|m| !m.is_empty())
-    {
-        return Ok(vec![single]);
+    #[test]
+    fn exited_adopted_renderers_are_released_not_counted_as_crashes() {
+        let worker = |adopted_pid| MonitorWorker {
+            stop_signal: Arc::new(AtomicBool::new(false)),
+            alpha: SurfaceAlpha::new(1.0),
+            handle: std::thread::spawn(|| Err(anyhow::anyhow!("renderer exited"))),
+            adopted_pid,
+            wallpaper: PathBuf::from("/walls/a.png"),
+        };
+        let mut workers = HashMap::from([
+            ("DP-1".to_string(), worker(Some(4242))),
+            ("DP-2".to_string(), worker(None)),
+        ]);
+        while !workers.values().all(|worker| worker.handle.is_finished()) {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        assert_eq!(release_exited_adopted_workers(&mut workers), ["DP-1"]);
+        let reaped = reap_finished_workers(&mut workers);
+        assert_eq!(
+            reaped,
+            [("DP-2".to_string(), "exit status: error: renderer exited".to_string())]
+        );
     }
 
-    let detected = detect_monitors()?;
-    if !detected.is_empty() {
-        return Ok(detected);
+    #[test]
+    fn excluded_monitors_match_by_name_or_connector_type() {
+        let monitors = ["DP-1", "DP-2", "HDMI-A-1", "HDMI-A-2", "eDP-1"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            filter_excluded_monitors(monitors.clone(), &["edp-1".to_string(), "HDMI".to_string()]),
+            ["DP-1", "DP-2"]
+        );
+        assert_eq!(
+            filter_excluded_monitors(monitors.clone(), &["DP".to_string()]),
+            ["HDMI-A-1", "HDMI-A-2", "eDP-1"]
+        );
+        assert_eq!(filter_excluded_monitors(monitors.clone(), &[" ".to_string()]), monitors);
+        assert_eq!(filter_excluded_monitors(monitors.clone(), &[]), monitors);
     }
 
-    Ok(Vec::new())
-}
+    #[test]
+    fn monitor_regex_picks_every_matching_monitor() {
+        let cfg = Config {
+            monitors: Some(["DP-1", "eDP-1", "DP-3", "HDMI-A-1"].map(String::from).to_vec()),
+            ..Config::default()
+        };
+        assert_eq!(find_matching_monitors(&cfg, "^DP-[0-9]+$").unwrap(), ["DP-1", "DP-3"]);
+        assert_eq!(find_matching_monitors(&cfg, "DP").unwrap().len(), 3);
+        assert!(find_matching_monitors(&cfg, "^HDMI-B").is_err());
+        assert!(find_matching_monitors(&cfg, "DP-(").is_err());
+    }
 
-fn detect_monitors() -> Result<Vec<String>> {
-    let output = ProcessCommand::new("hyprctl")
-        .args(["-j", "monitors"])
-        .output()?;
-    if !output.status.success() {
-        return Ok(Vec::new());
+    #[test]
+    fn monitor_glob_matches_whole_names() {
+        let cfg = Config {
+            monitors: Some(["DP-1", "eDP-1", "DP-12", "HDMI-A-1"].map(String::from).to_vec()),
+            ..Config::default()
+        };
+        assert_eq!(find_monitors_by_glob(&cfg, "DP-*", false).unwrap(), ["DP-1", "DP-12"]);
+        assert_eq!(find_monitors_by_glob(&cfg, "DP-?", false).unwrap(), ["DP-1"]);
+        assert_eq!(find_monitors_by_glob(&cfg, "hdmi-a-?", true).unwrap(), ["HDMI-A-1"]);
+        assert!(find_monitors_by_glob(&cfg, "hdmi-a-?", false).is_err());
+        assert!(find_monitors_by_glob(&cfg, "[DP", false).is_err());
     }
 
-    let value: serde_json::Value = serde_json::from_slice(&output.stdout)?;
-    let Some(array) = value.as_array() else {
-        return Ok(Vec::new());
-    };
+    #[test]
+    fn restore_keeps_existing_wallpapers_and_measures_from_the_oldest_change() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::write(dir.join("kept.png"), "").unwrap();
+        let monitor = |name: &str, changed_unix| MonitorState {
+            wallpaper: dir.join(name),
+            changed_unix,
+            renderer_pid: 1,
+            fps: 30,
+            fit: FitMode::Contain,
+        };
+        let mut previous = DaemonState::new(1);
+        previous.monitors.insert("DP-1".to_string(), monitor("kept.png", 1_000));
+        previous.monitors.insert("DP-2".to_string(), monitor("kept.png", 1_100));
+        previous.monitors.insert("DP-3".to_string(), monitor("deleted.png", 500));
 
-    let mut monitors: Vec<String> = array
-        .iter()
-        .filter_map(|m| m.get("name").and_then(|v| v.as_str()))
-        .map(|s| s.to_string())
-        .collect();
-    monitors.sort();
-    monitors.dedup();
-    Ok(monitors)
-}
+        let (restored, age) = restorable_wallpapers(previous, 1_120);
+        let mut monitors: Vec<&String> = restored.keys().collect();
+        monitors.sort();
+        assert_eq!(monitors, ["DP-1", "DP-2"]);
+        assert_eq!(restored["DP-1"].fit, FitMode::Contain);
+        assert_eq!(age, Duration::from_secs(120));
 
-fn media_dir_for_monitor<'a>(cfg: &'a config::Config, monitor: &str) -> &'a Path {
-    cfg.monitor_wallpaper_dirs
-        .as_ref()
-        .and_then(|map| map.get(monitor))
-        .map(|path| path.as_path())
-        .unwrap_or(cfg.wallpaper_dir.as_path())
-}
+        let (restored, age) = restorable_wallpapers(DaemonState::new(1), 1_120);
+        assert!(restored.is_empty());
+        assert_eq!(age, Duration::ZERO);
+    }
 
-fn fit_mode_for_monitor(cfg: &config::Config, monitor: &str) -> FitMode {
-    cfg.monitor_fit_modes
-        .as_ref()
-        .and_then(|map| map.get(monitor).copied())
-        .or(cfg.fit_mode)
-        .unwrap_or(FitMode::Cover)
-}
+    #[test]
+    fn monitor_poll_reports_plugged_and_unplugged_monitors() {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        let known = names(&["eDP-1", "DP-1"]);
 
-fn fit_mode_arg(fit: FitMode) -> &'static str {
-    match fit {
-        FitMode::Stretch => "stretch",
-        FitMode::Fill => "fill",
-        FitMode::Cover => "cover",
-        FitMode::Fit => "fit",
-        FitMode::Contain => "contain",
-        FitMode::Center => "center",
-        FitMode::ScaleDown => "scale-down",
+        assert_eq!(monitor_changes(&known, &known), (Vec::new(), Vec::new()));
+        assert_eq!(
+            monitor_changes(&known, &names(&["eDP-1", "DP-1", "DP-5", "DP-6"])),
+            (names(&["DP-5", "DP-6"]), Vec::new())
+        );
+        assert_eq!(
+            monitor_changes(&known, &names(&["eDP-1", "HDMI-A-1"])),
+            (names(&["HDMI-A-1"]), names(&["DP-1"]))
+        );
     }
-}
 
-fn run_renderer(
-    path: std::path::PathBuf,
-    monitor: Option<String>,
-    fps: u32,
-    fit: FitMode,
-    detach: bool,
-) -> Result<()> {
-    if !detach {
-        return wallpaper::run_wallpaper(path, monitor.as_deref(), fps, fit);
+    #[test]
+    fn only_the_first_monitor_by_name_plays_audio() {
+        let monitors = ["HDMI-A-1".to_string(), "DP-2".to_string(), "DP-1".to_string()];
+        assert!(plays_audio("DP-1", &monitors));
+        assert!(!plays_audio("DP-2", &monitors));
+        assert!(!plays_audio("HDMI-A-1", &monitors));
+        assert!(plays_audio("DP-3", &[]));
     }
 
-    let exe = std::env::current_exe()?;
-    let log_path = "/tmp/papdieo.log";
-    let log_out = OpenOptions::new()
-        .create(true)
-        .truncate(true)
-        .write(true)
-        .open(log_path)?;
-    let log_err = log_out.try_clone()?;
+    #[test]
+    fn daemon_start_delay_prefers_the_flag_then_milliseconds() {
+        let mut cfg = Config::default();
+        let mut options = DaemonArgs::default();
+        assert_eq!(daemon_start_delay(&cfg, &options), Duration::ZERO);
+        cfg.start_delay_seconds = Some(2);
+        assert_eq!(daemon_start_delay(&cfg, &options), Duration::from_secs(2));
+        cfg.daemon_startup_delay_ms = Some(750);
+        assert_eq!(daemon_start_delay(&cfg, &options), Duration::from_millis(750));
+        options.start_delay = Some(5);
+        assert_eq!(daemon_start_delay(&cfg, &options), Duration::from_secs(5));
+    }
 
-    let mut child = ProcessCommand::new(exe)
-        .arg("run-internal")
-        .arg(&path)
-        .args(monitor.as_ref().map(|m| vec!["--monitor", m]).unwrap_or_default())
-        .arg("--fps")
-        .arg(fps.to_string())
-        .arg("--fit")
-        .arg(fit_mode_arg(fit))
-        .stdin(Stdio::null())
-        .stdout(Stdio::from(log_out))
-        .stderr(Stdio::from(log_err))
-        .spawn()?;
+    #[test]
+    fn workspace_switches_rotate_monitors_whose_wallpaper_changes() {
+        let cfg: Config = toml::from_str(
+            r#"
+wallpaper_dir = "/walls"
+workspace_wallpapers = { "1" = "/walls/work", music = "/walls/vinyl.png" }
+workspace_wallpaper_dirs = { "1" = "/walls/ignored", web = "/walls/web" }
+"#,
+        )
+        .unwrap();
+        let monitors = vec!["DP-1".to_string(), "HDMI-A-1".to_string()];
+        let event = |monitor: Option<&str>, id: Option<&str>, name: &str| WorkspaceEvent {
+            monitor: monitor.map(str::to_string),
+            id: id.map(str::to_string),
+            name: name.to_string(),
+        };
+        let mut targets = HashMap::new();
 
-    thread::sleep(Duration::from_millis(4000));
-    if let Some(status) = child.try_wait()? {
-        return Err(anyhow!(
-            "wallpaper renderer exited early (status: {}), see {}",
-            status,
-            log_path
-        ));
+        let switched = apply_workspace_events(
+            &[event(Some("hdmi-a-1"), Some("1"), "1"), event(None, Some("8"), "music")],
+            &cfg,
+            &monitors,
+            &mut targets,
+        );
+        assert_eq!(switched, ["HDMI-A-1", "DP-1"]);
+        assert_eq!(targets["HDMI-A-1"], PathBuf::from("/walls/work"));
+        assert_eq!(targets["DP-1"], PathBuf::from("/walls/vinyl.png"));
+
+        // Same entry again (Hyprland sends `workspace` and `workspacev2`): nothing to do.
+        let again = [event(Some("DP-1"), None, "music")];
+        assert!(apply_workspace_events(&again, &cfg, &monitors, &mut targets).is_empty());
+        // Unmapped workspaces and unmanaged monitors.
+        let back = [event(Some("DP-1"), Some("2"), "2"), event(Some("eDP-1"), None, "1")];
+        assert_eq!(apply_workspace_events(&back, &cfg, &monitors, &mut targets), ["DP-1"]);
+        assert!(!targets.contains_key("DP-1"));
+        let web = [event(Some("DP-1"), Some("4"), "web")];
+        assert_eq!(apply_workspace_events(&web, &cfg, &monitors, &mut targets), ["DP-1"]);
+        assert_eq!(targets["DP-1"], PathBuf::from("/walls/web"));
     }
 
-    println!(
-        "Started wallpaper renderer in background (pid: {}, log: {})",
-        child.id(),
-        log_path
-    );
-    Ok(())
-}
+    #[test]
+    fn changed_workspace_maps_drop_the_old_targets() {
+        let cfg: Config =
+            toml::from_str("workspace_wallpapers = { music = \"/walls/vinyl.png\" }").unwrap();
+        let mut maps = [cfg.workspace_wallpapers.clone(), None];
+        let mut targets = HashMap::from([("DP-1".to_string(), PathBuf::from("/walls/vinyl.png"))]);
+        let mut listener = None;
 
-fn run_rotate_loop(
-    media_dir: std::path::PathBuf,
-    monitor: Option<String>,
-    interval_seconds: u64,
-    fps: u32,
-    fit: FitMode,
-) -> Result<()> {
-    let interval = std::time::Duration::from_secs(interval_seconds.max(1));
+        update_workspace_listener(&mut listener, &mut maps, &cfg, &mut targets);
+        assert_eq!(targets.len(), 1);
 
-    loop {
-        let _ = ProcessCommand::new("pkill")
-            .arg("-f")
-            .arg("papdieo run-internal")
-            .status();
+        let reloaded = Config::default();
+        update_workspace_listener(&mut listener, &mut maps, &reloaded, &mut targets);
+        assert!(targets.is_empty());
+        assert!(listener.is_none());
+        assert_eq!(maps, [None, None]);
+    }
 
-        let media = picker::pick_random_wallpaper(&media_dir)?;
-        let exe = std::env::current_exe()?;
-
-        ProcessCommand::new(&exe)
-            .arg("run-internal")
-            .arg(&media)
-            .args(monitor.as_ref().map(|m| vec!["--monitor", m]).unwrap_or_default())
-            .arg("--fps")
-            .arg(fps.to_string())
-            .arg("--fit")
-            .arg(fit_mode_arg(fit))
-            .stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()?;
-
-        thread::sleep(interval);
+    #[test]
+    fn daemon_picks_skip_wallpapers_outside_the_size_limits() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        for (name, size) in [("tiny.png", 10), ("fine.png", 1_000), ("huge.jpg", 100_000)] {
+            fs::write(dir.join(name), vec![0u8; size]).unwrap();
+        }
+        let dirs = [dir.to_path_buf()];
+        let pick = |range, exclude: Option<&HashSet<PathBuf>>| {
+            let media = MediaFilter::default();
+            pick_media_within_size("DP-1", &dirs, range, RotationMode::Random, exclude, media)
+        };
+
+        for _ in 0..10 {
+            assert_eq!(pick((Some(100), Some(10_000)), None).unwrap(), dir.join("fine.png"));
+        }
+        let taken = HashSet::from([dir.join("fine.png")]);
+        assert_eq!(pick((Some(100), Some(10_000)), Some(&taken)).unwrap(), dir.join("fine.png"));
+        assert_eq!(pick((None, Some(100)), Some(&taken)).unwrap(), dir.join("tiny.png"));
+        let error = pick((Some(200_000), None), None).unwrap_err();
+        assert_eq!(error.to_string(), "no wallpapers of at least 200000 bytes");
     }
 }
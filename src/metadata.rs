@@ -0,0 +1,147 @@
+//! Sidecar metadata for wallpapers: `wallpaper.jpg.meta` next to the file, or
+//! `.papdieo-meta/wallpaper.jpg.json` in the same directory, containing e.g.
+//! `{"tags":["nature","4k","dark"],"rating":5}`.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const SIDECAR_EXTENSION: &str = "meta";
+const SIDECAR_DIR: &str = ".papdieo-meta";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WallpaperMeta {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<u8>,
+    /// Keys papdieo does not know about survive a rewrite.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl WallpaperMeta {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.trim().eq_ignore_ascii_case(tag.trim()))
+    }
+}
+
+/// Both sidecar locations, in lookup order.
+pub fn sidecar_paths(wallpaper: &Path) -> Option<[PathBuf; 2]> {
+    let name = wallpaper.file_name()?.to_str()?;
+    let dir = wallpaper.parent().unwrap_or(Path::new(""));
+    Some([
+        dir.join(format!("{}.{}", name, SIDECAR_EXTENSION)),
+        dir.join(SIDECAR_DIR).join(format!("{}.json", name)),
+    ])
+}
+
+/// Metadata from the first sidecar that exists, or `None` without one.
+pub fn load(wallpaper: &Path) -> Result<Option<WallpaperMeta>> {
+    let Some(path) = existing_sidecar(wallpaper) else {
+        return Ok(None);
+    };
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    serde_json::from_str(&content)
+        .map(Some)
+        .with_context(|| format!("invalid metadata in {}", path.display()))
+}
+
+/// Unreadable sidecars count as untagged.
+pub fn has_tag(wallpaper: &Path, tag: &str) -> bool {
+    load(wallpaper)
+        .ok()
+        .flatten()
+        .map(|meta| meta.has_tag(tag))
+        .unwrap_or(false)
+}
+
+/// Returns `false` when the wallpaper already had the tag.
+pub fn add_tag(wallpaper: &Path, tag: &str) -> Result<bool> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        return Err(anyhow!("tag must not be empty"));
+    }
+    update(wallpaper, |meta| {
+        if meta.has_tag(tag) {
+            return false;
+        }
+        meta.tags.push(tag.to_string());
+        true
+    })
+}
+
+/// Returns `false` when the wallpaper did not have the tag.
+pub fn remove_tag(wallpaper: &Path, tag: &str) -> Result<bool> {
+    update(wallpaper, |meta| {
+        let before = meta.tags.len();
+        meta.tags.retain(|t| !t.trim().eq_ignore_ascii_case(tag.trim()));
+        meta.tags.len() != before
+    })
+}
+
+/// Rewrites the existing sidecar, or creates `<file>.meta`, when `change` reports a change.
+fn update(wallpaper: &Path, change: impl FnOnce(&mut WallpaperMeta) -> bool) -> Result<bool> {
+    if !wallpaper.is_file() {
+        return Err(anyhow!("wallpaper does not exist: {}", wallpaper.display()));
+    }
+    let [default_path, _] = sidecar_paths(wallpaper)
+        .ok_or_else(|| anyhow!("invalid wallpaper path: {}", wallpaper.display()))?;
+
+    let mut meta = load(wallpaper)?.unwrap_or_default();
+    if !change(&mut meta) {
+        return Ok(false);
+    }
+
+    let path = existing_sidecar(wallpaper).unwrap_or(default_path);
+    let content = serde_json::to_string_pretty(&meta)?;
+    fs::write(&path, content + "\n")
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(true)
+}
+
+fn existing_sidecar(wallpaper: &Path) -> Option<PathBuf> {
+    sidecar_paths(wallpaper)?.into_iter().find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_tag, has_tag, load, remove_tag};
+    use std::fs;
+
+    #[test]
+    fn tags_are_written_to_existing_sidecar_and_keep_other_keys() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::create_dir_all(dir.join(".papdieo-meta")).unwrap();
+        let hidden = dir.join("forest.jpg");
+        let plain = dir.join("city.png");
+        fs::write(&hidden, b"").unwrap();
+        fs::write(&plain, b"").unwrap();
+        fs::write(
+            dir.join(".papdieo-meta/forest.jpg.json"),
+            r#"{"tags":["nature"],"rating":5,"source":"unsplash"}"#,
+        )
+        .unwrap();
+
+        assert!(has_tag(&hidden, "Nature"));
+        assert!(add_tag(&hidden, "dark").unwrap());
+        assert!(!add_tag(&hidden, "DARK").unwrap());
+        let meta = load(&hidden).unwrap().unwrap();
+        assert_eq!(meta.tags, vec!["nature", "dark"]);
+        assert_eq!(meta.rating, Some(5));
+        assert_eq!(meta.extra["source"], "unsplash");
+        assert!(!dir.join("forest.jpg.meta").exists());
+
+        assert!(!has_tag(&plain, "dark"));
+        assert!(add_tag(&plain, "dark").unwrap());
+        assert!(dir.join("city.png.meta").is_file());
+        assert!(remove_tag(&plain, "dark").unwrap());
+        assert!(!remove_tag(&plain, "dark").unwrap());
+        assert!(!has_tag(&plain, "dark"));
+    }
+}
@@ -0,0 +1,194 @@
+//! `metrics_port`: daemon counters in the Prometheus text format.
+//!
+//! A background thread answers `GET /metrics` on `127.0.0.1:<port>` with a
+//! minimal HTTP/1.1 response; anything else gets a 404. The daemon loop
+//! updates the shared [`Metrics`] as it sets wallpapers and reaps renderers.
+
+use anyhow::{Context, Result};
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Name, type, help text and per-monitor value of one metric family.
+type Family = (&'static str, &'static str, &'static str, fn(&MonitorMetrics) -> Option<u64>);
+
+#[derive(Debug, Default, Clone)]
+struct MonitorMetrics {
+    changes: u64,
+    crashes: u64,
+    last_change_unix: u64,
+    renderer_pid: Option<u32>,
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    monitors: Mutex<BTreeMap<String, MonitorMetrics>>,
+    rotation_interval: Mutex<Duration>,
+}
+
+impl Metrics {
+    pub fn record_change(&self, monitor: &str, changed_unix: u64, renderer_pid: u32) {
+        let mut monitors = self.monitors.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = monitors.entry(monitor.to_string()).or_default();
+        entry.changes += 1;
+        entry.last_change_unix = changed_unix;
+        entry.renderer_pid = Some(renderer_pid);
+    }
+
+    pub fn record_crash(&self, monitor: &str) {
+        let mut monitors = self.monitors.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = monitors.entry(monitor.to_string()).or_default();
+        entry.crashes += 1;
+        entry.renderer_pid = None;
+    }
+
+    pub fn set_rotation_interval(&self, interval: Duration) {
+        *self.rotation_interval.lock().unwrap_or_else(|e| e.into_inner()) = interval;
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let monitors = self.monitors.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let interval = *self.rotation_interval.lock().unwrap_or_else(|e| e.into_inner());
+        let families: [Family; 4] = [
+            (
+                "papdieo_wallpaper_changes_total",
+                "counter",
+                "Wallpapers set on the monitor since the daemon started.",
+                |m| Some(m.changes),
+            ),
+            (
+                "papdieo_renderer_crashes_total",
+                "counter",
+                "Renderers on the monitor that exited without being stopped.",
+                |m| Some(m.crashes),
+            ),
+            (
+                "papdieo_last_wallpaper_change_timestamp",
+                "gauge",
+                "Unix time of the monitor's last wallpaper change.",
+                |m| (m.changes > 0).then_some(m.last_change_unix),
+            ),
+            (
+                "papdieo_renderer_pid",
+                "gauge",
+                "Process rendering the monitor's wallpaper.",
+                |m| m.renderer_pid.map(u64::from),
+            ),
+        ];
+
+        let mut out = String::new();
+        for (name, kind, help, value) in families {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (monitor, metrics) in &monitors {
+                if let Some(value) = value(metrics) {
+                    let label = escape_label(monitor);
+                    let _ = writeln!(out, "{}{{monitor=\"{}\"}} {}", name, label, value);
+                }
+            }
+        }
+        let name = "papdieo_rotation_interval_seconds";
+        let _ = writeln!(out, "# HELP {} Current rotation interval.", name);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        let _ = writeln!(out, "{} {}", name, interval.as_secs_f64());
+        out
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Starts the `/metrics` server on localhost in a background thread.
+pub fn serve(port: u16, metrics: Arc<Metrics>) -> Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .with_context(|| format!("failed to listen on 127.0.0.1:{} for metrics", port))?;
+    thread::spawn(move || serve_on(listener, &metrics));
+    Ok(())
+}
+
+fn serve_on(listener: TcpListener, metrics: &Metrics) {
+    for stream in listener.incoming().flatten() {
+        let _ = respond(stream, metrics);
+    }
+}
+
+fn respond(mut stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics.render()),
+        _ => ("404 Not Found", "not found\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{serve_on, Metrics};
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::Arc,
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn metrics_are_served_in_the_text_format() {
+        let metrics = Arc::new(Metrics::default());
+        metrics.record_change("DP-1", 1_700_000_000, 4242);
+        metrics.record_change("DP-1", 1_700_000_300, 4242);
+        metrics.record_crash("HDMI-\"A\"-1");
+        metrics.set_rotation_interval(Duration::from_secs(300));
+
+        let rendered = metrics.render();
+        for line in [
+            "# TYPE papdieo_wallpaper_changes_total counter",
+            "papdieo_wallpaper_changes_total{monitor=\"DP-1\"} 2",
+            "papdieo_renderer_crashes_total{monitor=\"HDMI-\\\"A\\\"-1\"} 1",
+            "papdieo_last_wallpaper_change_timestamp{monitor=\"DP-1\"} 1700000300",
+            "papdieo_renderer_pid{monitor=\"DP-1\"} 4242",
+            "papdieo_rotation_interval_seconds 300",
+        ] {
+            assert!(rendered.lines().any(|l| l == line), "missing {line:?} in\n{rendered}");
+        }
+        assert!(!rendered.contains("papdieo_renderer_pid{monitor=\"HDMI"));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let served = Arc::clone(&metrics);
+        thread::spawn(move || serve_on(listener, &served));
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(&rendered));
+        assert!(get("/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
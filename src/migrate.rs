@@ -0,0 +1,534 @@
+//! `papdieo migrate`: turns a swww, swaybg or hyprpaper setup into a papdieo
+//! config.
+//!
+//! papdieo rotates through directories rather than showing one file, so each
+//! monitor's wallpaper becomes its directory in `monitor_wallpaper_dirs` (or
+//! `wallpaper_dir` for every monitor) and the file itself is listed in a
+//! comment at the top of the generated config.
+
+use crate::config::FitMode;
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+use toml_edit::{DocumentMut, InlineTable, Item, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum WallpaperTool {
+    Swww,
+    Swaybg,
+    Hyprpaper,
+}
+
+impl WallpaperTool {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Swww => "swww",
+            Self::Swaybg => "swaybg",
+            Self::Hyprpaper => "hyprpaper",
+        }
+    }
+
+    /// Where the tool's setup usually lives: swaybg is started from the
+    /// Hyprland config and swww is only ever run from a shell.
+    fn default_sources(self) -> Vec<PathBuf> {
+        let home = PathBuf::from(env::var("HOME").unwrap_or_else(|_| ".".into()));
+        let hypr = env::var("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| home.join(".config"))
+            .join("hypr");
+        match self {
+            Self::Hyprpaper => vec![hypr.join("hyprpaper.conf")],
+            Self::Swaybg => vec![hypr.join("hyprland.conf")],
+            Self::Swww => vec![home.join(".bash_history"), home.join(".zsh_history")],
+        }
+    }
+}
+
+/// One monitor's wallpaper, or every monitor's when `monitor` is `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigratedWallpaper {
+    pub monitor: Option<String>,
+    pub path: PathBuf,
+    pub fit: Option<FitMode>,
+}
+
+/// Reads `source` (or the tool's usual files) and returns the generated config.
+pub fn migrate(tool: WallpaperTool, source: Option<&Path>) -> Result<String> {
+    let sources = match source {
+        Some(path) => vec![path.to_path_buf()],
+        None => tool.default_sources(),
+    };
+    let mut read = Vec::new();
+    let mut wallpapers = Vec::new();
+    for path in &sources {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound && source.is_none() => {
+                continue
+            }
+            Err(error) => {
+                return Err(error).with_context(|| format!("failed to read {}", path.display()))
+            }
+        };
+        // Shell history is not always valid UTF-8.
+        let content = String::from_utf8_lossy(&bytes);
+        wallpapers.extend(match tool {
+            WallpaperTool::Hyprpaper => parse_hyprpaper(&content),
+            WallpaperTool::Swaybg => parse_swaybg(&content),
+            WallpaperTool::Swww => parse_swww_history(&content),
+        });
+        read.push(path.as_path());
+    }
+    if wallpapers.is_empty() {
+        let searched: Vec<String> = sources.iter().map(|p| p.display().to_string()).collect();
+        return Err(anyhow!(
+            "no {} wallpapers found in {}",
+            tool.name(),
+            searched.join(", ")
+        ));
+    }
+    Ok(to_config_toml(tool, &read, &wallpapers))
+}
+
+/// `wallpaper = MONITOR,[contain:|tile:]PATH` lines, or `wallpaper { ... }`
+/// blocks in hyprpaper 0.8 and later. `preload` lines only count when there
+/// are no `wallpaper` entries.
+pub fn parse_hyprpaper(content: &str) -> Vec<MigratedWallpaper> {
+    let mut wallpapers = Vec::new();
+    let mut preloaded = Vec::new();
+    let mut block: Option<BTreeMap<String, String>> = None;
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if let Some(fields) = block.as_mut() {
+            if line == "}" {
+                let fields = block.take().unwrap_or_default();
+                if let Some(path) = fields.get("path") {
+                    wallpapers.push(MigratedWallpaper {
+                        monitor: fields.get("monitor").filter(|m| !m.is_empty()).cloned(),
+                        path: expand_home(path),
+                        fit: fields.get("fit_mode").and_then(|mode| hyprpaper_fit(mode)),
+                    });
+                }
+            } else if let Some((key, value)) = line.split_once('=') {
+                fields.insert(key.trim().to_string(), value.trim().to_string());
+            }
+            continue;
+        }
+        if line.starts_with("wallpaper") && line.ends_with('{') {
+            block = Some(BTreeMap::new());
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        match key.trim() {
+            "preload" => preloaded.push(expand_home(value.trim())),
+            "wallpaper" => {
+                let Some((monitor, target)) = value.split_once(',') else {
+                    continue;
+                };
+                let target = target.trim();
+                let (fit, path) = match target.split_once(':') {
+                    Some((mode, path)) if hyprpaper_fit(mode).is_some() => {
+                        (hyprpaper_fit(mode), path)
+                    }
+                    _ => (None, target),
+                };
+                wallpapers.push(MigratedWallpaper {
+                    monitor: Some(monitor.trim().to_string()).filter(|m| !m.is_empty()),
+                    path: expand_home(path),
+                    fit,
+                });
+            }
+            _ => {}
+        }
+    }
+    if wallpapers.is_empty() {
+        wallpapers.extend(preloaded.into_iter().take(1).map(|path| MigratedWallpaper {
+            monitor: None,
+            path,
+            fit: None,
+        }));
+    }
+    wallpapers
+}
+
+fn hyprpaper_fit(mode: &str) -> Option<FitMode> {
+    match mode.trim() {
+        "contain" => Some(FitMode::Contain),
+        "cover" => Some(FitMode::Cover),
+        "tile" => Some(FitMode::Tile),
+        "fill" => Some(FitMode::Fill),
+        _ => None,
+    }
+}
+
+/// Every `swaybg` command in `content` (a Hyprland config, a shell script):
+/// `-i` and `-m` apply to the output named by the last `-o`, or to all
+/// outputs before the first one and after `-o '*'`.
+pub fn parse_swaybg(content: &str) -> Vec<MigratedWallpaper> {
+    let mut wallpapers = Vec::new();
+    for line in content.lines() {
+        for args in commands_after(&shell_words(line), &["swaybg"]) {
+            let mut outputs: Vec<(Option<String>, Option<PathBuf>, Option<FitMode>)> =
+                vec![(None, None, None)];
+            let mut args = args.iter();
+            while let Some(arg) = args.next() {
+                let (flag, inline) = match arg.split_once('=') {
+                    Some((flag, value)) if flag.starts_with("--") => {
+                        (flag, Some(value.to_string()))
+                    }
+                    _ => (arg.as_str(), None),
+                };
+                let mut value = || inline.clone().or_else(|| args.next().cloned());
+                match flag {
+                    "-o" | "--output" => {
+                        let output = value().filter(|output| output != "*");
+                        outputs.push((output, None, None));
+                    }
+                    "-i" | "--image" => {
+                        let current = outputs.last_mut().expect("outputs starts non-empty");
+                        current.1 = value().map(|path| expand_home(&path));
+                    }
+                    "-m" | "--mode" => {
+                        let current = outputs.last_mut().expect("outputs starts non-empty");
+                        current.2 = value().as_deref().and_then(swaybg_fit);
+                    }
+                    "-c" | "--color" => {
+                        value();
+                    }
+                    _ => {}
+                }
+            }
+            wallpapers.extend(outputs.into_iter().filter_map(|(monitor, path, fit)| {
+                Some(MigratedWallpaper {
+                    monitor,
+                    path: path?,
+                    fit,
+                })
+            }));
+        }
+    }
+    wallpapers
+}
+
+fn swaybg_fit(mode: &str) -> Option<FitMode> {
+    match mode {
+        "stretch" => Some(FitMode::Stretch),
+        "fit" => Some(FitMode::Fit),
+        "fill" => Some(FitMode::Fill),
+        "center" => Some(FitMode::Center),
+        "tile" => Some(FitMode::Tile),
+        _ => None,
+    }
+}
+
+/// `swww img` commands in bash or zsh history, oldest first, so the last one
+/// for a monitor wins.
+pub fn parse_swww_history(content: &str) -> Vec<MigratedWallpaper> {
+    const FLAGS_WITHOUT_VALUE: &[&str] = &["--no-resize", "--invert-y", "-h", "--help"];
+    let mut wallpapers = Vec::new();
+    for line in content.lines() {
+        for args in commands_after(&shell_words(line), &["swww", "img"]) {
+            let mut path = None;
+            let mut outputs = Vec::new();
+            let mut fit = None;
+            let mut args = args.iter();
+            while let Some(arg) = args.next() {
+                if !arg.starts_with('-') {
+                    path = path.or_else(|| Some(expand_home(arg)));
+                    continue;
+                }
+                let (flag, value) = match arg.split_once('=') {
+                    Some((flag, value)) => (flag, Some(value.to_string())),
+                    None if FLAGS_WITHOUT_VALUE.contains(&arg.as_str()) => (arg.as_str(), None),
+                    None => (arg.as_str(), args.next().cloned()),
+                };
+                match (flag, value.as_deref()) {
+                    ("-o" | "--outputs", Some(list)) => {
+                        outputs = list
+                            .split(',')
+                            .map(str::trim)
+                            .filter(|output| !output.is_empty())
+                            .map(str::to_string)
+                            .collect();
+                    }
+                    ("--resize", Some("crop")) => fit = Some(FitMode::Cover),
+                    ("--resize", Some("fit")) => fit = Some(FitMode::Contain),
+                    ("--resize", Some("no")) | ("--no-resize", _) => fit = Some(FitMode::Center),
+                    _ => {}
+                }
+            }
+            let Some(path) = path else {
+                continue;
+            };
+            if outputs.is_empty() {
+                wallpapers.push(MigratedWallpaper {
+                    monitor: None,
+                    path,
+                    fit,
+                });
+            } else {
+                wallpapers.extend(outputs.into_iter().map(|monitor| MigratedWallpaper {
+                    monitor: Some(monitor),
+                    path: path.clone(),
+                    fit,
+                }));
+            }
+        }
+    }
+    wallpapers
+}
+
+/// The arguments of every command in `words` that starts with `program`
+/// (matched by file name, so `/usr/bin/swaybg` counts), up to the next
+/// `;`, `&` or `|`.
+fn commands_after<'a>(words: &'a [String], program: &[&str]) -> Vec<&'a [String]> {
+    let mut commands = Vec::new();
+    for start in 0..words.len() {
+        let matches = program.iter().enumerate().all(|(offset, expected)| {
+            words.get(start + offset).is_some_and(|word| {
+                let word = if offset == 0 {
+                    word.rsplit('/').next().unwrap_or(word)
+                } else {
+                    word
+                };
+                word == *expected
+            })
+        });
+        if !matches {
+            continue;
+        }
+        let args = &words[start + program.len()..];
+        let end = args
+            .iter()
+            .position(|word| matches!(word.as_str(), ";" | "&" | "&&" | "|" | "||"))
+            .unwrap_or(args.len());
+        commands.push(&args[..end]);
+    }
+    commands
+}
+
+/// Splits a line the way a shell would for simple commands: quotes and
+/// backslashes are honoured, `;`, `&` and `|` become words of their own and
+/// a `#` starting a word ends the line.
+fn shell_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                current.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                in_word = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => current.extend(chars.next()),
+                        c => current.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                current.extend(chars.next());
+            }
+            '#' if !in_word => break,
+            c if c.is_whitespace() || matches!(c, ';' | '&' | '|') => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+                if matches!(c, ';' | '&' | '|') {
+                    let mut separator = c.to_string();
+                    if chars.peek() == Some(&c) && c != ';' {
+                        separator.extend(chars.next());
+                    }
+                    words.push(separator);
+                }
+            }
+            c => {
+                in_word = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(current);
+    }
+    words
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    let home = env::var("HOME").ok();
+    let rest = path
+        .strip_prefix("~/")
+        .or_else(|| path.strip_prefix("$HOME/"))
+        .or_else(|| path.strip_prefix("${HOME}/"));
+    match (rest, home) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn to_config_toml(
+    tool: WallpaperTool,
+    sources: &[&Path],
+    wallpapers: &[MigratedWallpaper],
+) -> String {
+    // Later entries (the newer history line, the later config line) win.
+    let latest: BTreeMap<Option<&str>, &MigratedWallpaper> = wallpapers
+        .iter()
+        .map(|wallpaper| (wallpaper.monitor.as_deref(), wallpaper))
+        .collect();
+
+    let mut header = format!(
+        "# Generated by `papdieo migrate {}` from {}.\n",
+        tool.name(),
+        sources
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    header.push_str("# papdieo rotates through the directories below; the wallpapers were:\n");
+    for (monitor, wallpaper) in &latest {
+        header.push_str(&format!(
+            "#   {}: {}\n",
+            monitor.unwrap_or("all monitors"),
+            wallpaper.path.display()
+        ));
+    }
+
+    let mut doc = DocumentMut::new();
+    let mut dirs = InlineTable::new();
+    let mut fits = InlineTable::new();
+    for (monitor, wallpaper) in &latest {
+        let dir = wallpaper
+            .path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+            .display()
+            .to_string();
+        let fit = wallpaper.fit.and_then(fit_name);
+        match monitor {
+            None => {
+                doc["wallpaper_dir"] = toml_edit::value(dir);
+                if let Some(fit) = fit {
+                    doc["fit_mode"] = toml_edit::value(fit);
+                }
+            }
+            Some(monitor) => {
+                dirs.insert(*monitor, Value::from(dir));
+                if let Some(fit) = fit {
+                    fits.insert(*monitor, Value::from(fit));
+                }
+            }
+        }
+    }
+    if !dirs.is_empty() {
+        doc["monitor_wallpaper_dirs"] = Item::Value(Value::InlineTable(dirs));
+    }
+    if !fits.is_empty() {
+        doc["monitor_fit_modes"] = Item::Value(Value::InlineTable(fits));
+    }
+    header + &doc.to_string()
+}
+
+/// The config spelling of `fit`, e.g. `scale-down`.
+fn fit_name(fit: FitMode) -> Option<String> {
+    toml::Value::try_from(fit).ok()?.as_str().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_hyprpaper, parse_swaybg, parse_swww_history, shell_words, to_config_toml,
+        MigratedWallpaper, WallpaperTool,
+    };
+    use crate::config::{Config, FitMode};
+    use std::path::{Path, PathBuf};
+
+    fn wallpaper(monitor: Option<&str>, path: &str, fit: Option<FitMode>) -> MigratedWallpaper {
+        MigratedWallpaper {
+            monitor: monitor.map(str::to_string),
+            path: PathBuf::from(path),
+            fit,
+        }
+    }
+
+    #[test]
+    fn each_tool_setup_is_parsed_into_wallpapers() {
+        let hyprpaper = "preload = /walls/a.png\n\
+                         wallpaper = DP-1,contain:/walls/a.png # work\n\
+                         wallpaper = ,/walls/b.jpg\n\
+                         wallpaper {\n    monitor = HDMI-A-1\n    path = /walls/c.png\n    fit_mode = tile\n}\n";
+        assert_eq!(
+            parse_hyprpaper(hyprpaper),
+            [
+                wallpaper(Some("DP-1"), "/walls/a.png", Some(FitMode::Contain)),
+                wallpaper(None, "/walls/b.jpg", None),
+                wallpaper(Some("HDMI-A-1"), "/walls/c.png", Some(FitMode::Tile)),
+            ]
+        );
+        assert_eq!(
+            parse_hyprpaper("preload = /walls/a.png\n"),
+            [wallpaper(None, "/walls/a.png", None)]
+        );
+
+        let hyprland = "exec-once = swaybg -m fill -i '/walls/all of them.png' -o DP-1 -i /walls/d.png -m center & waybar\n";
+        assert_eq!(
+            parse_swaybg(hyprland),
+            [
+                wallpaper(None, "/walls/all of them.png", Some(FitMode::Fill)),
+                wallpaper(Some("DP-1"), "/walls/d.png", Some(FitMode::Center)),
+            ]
+        );
+
+        let history = "swww init\n\
+                       : 1700000000:0;swww img /walls/e.png --outputs DP-1,DP-2 --resize fit\n\
+                       swww img --transition-type grow /walls/f.png; ls\n";
+        assert_eq!(
+            parse_swww_history(history),
+            [
+                wallpaper(Some("DP-1"), "/walls/e.png", Some(FitMode::Contain)),
+                wallpaper(Some("DP-2"), "/walls/e.png", Some(FitMode::Contain)),
+                wallpaper(None, "/walls/f.png", None),
+            ]
+        );
+        assert_eq!(shell_words(r#"a "b c" d\ e;f # g"#), ["a", "b c", "d e", ";", "f"]);
+    }
+
+    #[test]
+    fn generated_config_maps_wallpapers_to_their_directories() {
+        let toml = to_config_toml(
+            WallpaperTool::Swaybg,
+            &[Path::new("/home/me/.config/hypr/hyprland.conf")],
+            &[
+                wallpaper(None, "/walls/old/x.png", None),
+                wallpaper(Some("DP-1"), "/walls/work/y.png", Some(FitMode::ScaleDown)),
+                wallpaper(None, "/walls/all/z.png", Some(FitMode::Fill)),
+            ],
+        );
+        assert!(toml.contains("#   DP-1: /walls/work/y.png\n"));
+        assert!(!toml.contains("/walls/old"));
+
+        let cfg: Config = toml::from_str(&toml).unwrap();
+        assert_eq!(cfg.wallpaper_dir, PathBuf::from("/walls/all"));
+        assert_eq!(cfg.fit_mode, Some(FitMode::Fill));
+        assert_eq!(
+            cfg.monitor_wallpaper_dirs.unwrap()["DP-1"],
+            PathBuf::from("/walls/work")
+        );
+        assert_eq!(cfg.monitor_fit_modes.unwrap()["DP-1"], FitMode::ScaleDown);
+    }
+}
@@ -0,0 +1,359 @@
+//! `papdieo monitors`: names and metadata of connected outputs, from
+//! `hyprctl -j monitors` or, elsewhere, straight from the `wl_output` globals.
+
+use crate::hyprland_ipc;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use wayland_client::{
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{wl_output, wl_registry},
+    Connection, Dispatch, QueueHandle, WEnum,
+};
+
+/// Enough roundtrips for every output's initial burst of events to arrive.
+const OUTPUT_ROUNDTRIPS: usize = 3;
+/// `name` is sent right after binding (wl_output v4), so two roundtrips suffice for it.
+pub const NAME_ROUNDTRIPS: usize = 2;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MonitorInfo {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub resolution: Option<(u32, u32)>,
+    pub refresh_hz: Option<f64>,
+    pub position: Option<(i32, i32)>,
+    /// `wl_output` transform, 0-7; the odd ones rotate by 90 or 270 degrees.
+    pub transform: Option<u32>,
+}
+
+impl MonitorInfo {
+    /// `resolution` as the wallpaper sees it: width and height swapped on
+    /// outputs rotated to portrait.
+    pub fn oriented_resolution(&self) -> Option<(u32, u32)> {
+        let (width, height) = self.resolution?;
+        match self.transform {
+            Some(transform) if transform % 2 == 1 => Some((height, width)),
+            _ => Some((width, height)),
+        }
+    }
+}
+
+/// Where a monitor sits in the layout, as `hyprctl -j monitors` reports it:
+/// `width`/`height` in pixels, `x`/`y` in layout coordinates, which are pixels
+/// divided by `scale`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MonitorGeometry {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+impl MonitorGeometry {
+    pub fn layout_size(&self) -> (f64, f64) {
+        let scale = if self.scale > 0.0 { self.scale } else { 1.0 };
+        (self.width as f64 / scale, self.height as f64 / scale)
+    }
+}
+
+/// Geometry of the named monitors, in the order given.
+pub fn hyprland_monitor_geometries(names: &[String]) -> Result<Vec<MonitorGeometry>> {
+    let value = hyprland_monitors_json()
+        .ok_or_else(|| anyhow!("monitor positions are only known on Hyprland (hyprctl monitors)"))?;
+    select_geometries(&value, names)
+}
+
+fn select_geometries(value: &Value, names: &[String]) -> Result<Vec<MonitorGeometry>> {
+    let all: Vec<MonitorGeometry> =
+        serde_json::from_value(value.clone()).context("unexpected hyprctl monitors output")?;
+    names
+        .iter()
+        .map(|name| {
+            all.iter()
+                .find(|geometry| geometry.name.eq_ignore_ascii_case(name))
+                .cloned()
+                .ok_or_else(|| anyhow!("monitor '{}' is not connected", name))
+        })
+        .collect()
+}
+
+/// Raw `hyprctl -j monitors` output, or `None` outside Hyprland.
+pub fn hyprland_monitors_json() -> Option<Value> {
+    hyprland_ipc::hyprctl_json("monitors").filter(Value::is_array)
+}
+
+pub fn parse_hyprland_monitors(value: &Value) -> Vec<MonitorInfo> {
+    let Some(array) = value.as_array() else {
+        return Vec::new();
+    };
+
+    array
+        .iter()
+        .map(|m| {
+            let int = |key: &str| m.get(key).and_then(Value::as_i64);
+            MonitorInfo {
+                name: m.get("name").and_then(Value::as_str).map(str::to_string),
+                description: m
+                    .get("description")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                resolution: int("width")
+                    .zip(int("height"))
+                    .map(|(w, h)| (w as u32, h as u32)),
+                refresh_hz: m.get("refreshRate").and_then(Value::as_f64),
+                position: int("x").zip(int("y")).map(|(x, y)| (x as i32, y as i32)),
+                transform: int("transform").map(|transform| transform as u32),
+            }
+        })
+        .collect()
+}
+
+/// Binds every `wl_output` and collects whatever metadata the compositor sends.
+pub fn wayland_monitors() -> Result<Vec<MonitorInfo>> {
+    wayland_monitors_with_roundtrips(OUTPUT_ROUNDTRIPS)
+}
+
+pub fn wayland_monitors_with_roundtrips(roundtrips: usize) -> Result<Vec<MonitorInfo>> {
+    let connection = Connection::connect_to_env().context("failed to connect to Wayland")?;
+    let (globals, mut event_queue) =
+        registry_queue_init::<OutputQuery>(&connection).context("failed to init globals")?;
+    let qh = event_queue.handle();
+
+    let mut query = OutputQuery::default();
+    for global in globals
+        .contents()
+        .clone_list()
+        .into_iter()
+        .filter(|g| g.interface == "wl_output")
+    {
+        globals.registry().bind::<wl_output::WlOutput, _, _>(
+            global.name,
+            global.version.min(4),
+            &qh,
+            query.monitors.len(),
+        );
+        query.monitors.push(MonitorInfo::default());
+    }
+    if query.monitors.is_empty() {
+        return Err(anyhow!("no wl_output globals found"));
+    }
+
+    for _ in 0..roundtrips {
+        event_queue
+            .roundtrip(&mut query)
+            .context("failed to query wl_output metadata")?;
+    }
+    Ok(query.monitors)
+}
+
+pub fn format_table(monitors: &[MonitorInfo]) -> String {
+    let missing = || "-".to_string();
+    let mut rows = vec![[
+        "Name".to_string(),
+        "Resolution".to_string(),
+        "RefreshRate".to_string(),
+        "Position".to_string(),
+        "Description".to_string(),
+    ]];
+    rows.extend(monitors.iter().map(|m| {
+        [
+            m.name.clone().unwrap_or_else(missing),
+            m.resolution
+                .map(|(w, h)| format!("{}x{}", w, h))
+                .unwrap_or_else(missing),
+            m.refresh_hz
+                .map(|hz| format!("{:.2} Hz", hz))
+                .unwrap_or_else(missing),
+            m.position
+                .map(|(x, y)| format!("{},{}", x, y))
+                .unwrap_or_else(missing),
+            m.description.clone().unwrap_or_else(missing),
+        ]
+    }));
+
+    let mut widths = [0usize; 5];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            format!("{}\n", cells.join(" | ").trim_end())
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct OutputQuery {
+    monitors: Vec<MonitorInfo>,
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for OutputQuery {
+    fn event(
+        _state: &mut Self,
+        _proxy: &wl_registry::WlRegistry,
+        _event: wl_registry::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, usize> for OutputQuery {
+    fn event(
+        state: &mut Self,
+        _proxy: &wl_output::WlOutput,
+        event: wl_output::Event,
+        index: &usize,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let Some(monitor) = state.monitors.get_mut(*index) else {
+            return;
+        };
+        match event {
+            wl_output::Event::Name { name } => monitor.name = Some(name),
+            wl_output::Event::Description { description } => {
+                monitor.description = Some(description)
+            }
+            wl_output::Event::Geometry {
+                x,
+                y,
+                make,
+                model,
+                transform,
+                ..
+            } => {
+                monitor.position = Some((x, y));
+                monitor.transform = transform.into_result().ok().map(|t| t as u32);
+                if monitor.description.is_none() {
+                    let description = format!("{} {}", make.trim(), model.trim());
+                    monitor.description =
+                        Some(description.trim().to_string()).filter(|d| !d.is_empty());
+                }
+            }
+            wl_output::Event::Mode {
+                flags,
+                width,
+                height,
+                refresh,
+            } => {
+                if matches!(flags, WEnum::Value(mode) if mode.contains(wl_output::Mode::Current)) {
+                    monitor.resolution = Some((width as u32, height as u32));
+                    monitor.refresh_hz = (refresh > 0).then(|| refresh as f64 / 1000.0);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_table, parse_hyprland_monitors, select_geometries, MonitorInfo};
+
+    #[test]
+    fn hyprctl_json_is_mapped_to_monitor_info() {
+        let value = serde_json::json!([{
+            "name": "DP-1",
+            "description": "Dell Inc. DELL U2720Q",
+            "width": 3840,
+            "height": 2160,
+            "refreshRate": 59.99700,
+            "x": 1920,
+            "y": 0
+        }]);
+
+        assert_eq!(
+            parse_hyprland_monitors(&value),
+            vec![MonitorInfo {
+                name: Some("DP-1".to_string()),
+                description: Some("Dell Inc. DELL U2720Q".to_string()),
+                resolution: Some((3840, 2160)),
+                refresh_hz: Some(59.997),
+                position: Some((1920, 0)),
+                transform: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn rotated_outputs_swap_width_and_height() {
+        let value = serde_json::json!([
+            { "name": "DP-1", "width": 3840, "height": 2160, "transform": 0 },
+            { "name": "DP-2", "width": 3840, "height": 2160, "transform": 1 },
+            { "name": "DP-3", "width": 3840, "height": 2160, "transform": 6 },
+            { "name": "DP-4", "width": 3840, "height": 2160, "transform": 7 },
+        ]);
+
+        let resolutions: Vec<_> = parse_hyprland_monitors(&value)
+            .iter()
+            .map(MonitorInfo::oriented_resolution)
+            .collect();
+        assert_eq!(
+            resolutions,
+            vec![
+                Some((3840, 2160)),
+                Some((2160, 3840)),
+                Some((3840, 2160)),
+                Some((2160, 3840))
+            ]
+        );
+    }
+
+    #[test]
+    fn table_aligns_columns_and_marks_missing_values() {
+        let monitors = vec![
+            MonitorInfo {
+                name: Some("HDMI-A-1".to_string()),
+                resolution: Some((1920, 1080)),
+                refresh_hz: Some(60.0),
+                position: Some((0, 0)),
+                description: Some("LG".to_string()),
+                transform: None,
+            },
+            MonitorInfo {
+                name: Some("DP-1".to_string()),
+                ..MonitorInfo::default()
+            },
+        ];
+
+        assert_eq!(
+            format_table(&monitors),
+            "Name     | Resolution | RefreshRate | Position | Description\n\
+             HDMI-A-1 | 1920x1080  | 60.00 Hz    | 0,0      | LG\n\
+             DP-1     | -          | -           | -        | -\n"
+        );
+    }
+
+    #[test]
+    fn geometries_are_selected_by_name_with_their_scale() {
+        let value = serde_json::json!([
+            {"name": "DP-1", "width": 3840, "height": 2160, "x": 1920, "y": 0, "scale": 2.0},
+            {"name": "HDMI-A-1", "width": 1920, "height": 1080, "x": 0, "y": 0}
+        ]);
+        let names = ["hdmi-a-1".to_string(), "DP-1".to_string()];
+        let geometries = select_geometries(&value, &names).unwrap();
+
+        assert_eq!(geometries[0].name, "HDMI-A-1");
+        assert_eq!(geometries[0].layout_size(), (1920.0, 1080.0));
+        assert_eq!(geometries[1].layout_size(), (1920.0, 1080.0));
+        assert!(select_geometries(&value, &["DP-3".to_string()]).is_err());
+    }
+}
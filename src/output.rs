@@ -0,0 +1,54 @@
+use std::sync::atomic::{AtomicI8, Ordering};
+
+/// -1 = quiet, 0 = normal, 1+ = verbose (`-v`, `-vv`).
+static VERBOSITY: AtomicI8 = AtomicI8::new(0);
+
+pub fn set_verbosity(level: i8) {
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> i8 {
+    VERBOSITY.load(Ordering::Relaxed)
+}
+
+pub fn is_quiet() -> bool {
+    verbosity() < 0
+}
+
+/// Informational output on stdout, suppressed by `--quiet`.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_quiet() {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Non-fatal warnings on stderr, suppressed by `--quiet`.
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Diagnostics shown with `-v`.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::output::verbosity() >= 1 {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Extra diagnostics shown with `-vv`.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if $crate::output::verbosity() >= 2 {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use {debug, info, trace, warning};
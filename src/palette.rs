@@ -0,0 +1,163 @@
+//! Colour statistics of a wallpaper, used to tell hooks whether it is light or dark,
+//! and the perceptual hashes `list --deduplicate` and `dedup` compare wallpapers by.
+
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+use std::sync::OnceLock;
+
+/// `--deduplicate-threshold` default: hashes at most this many bits apart are duplicates.
+pub const DEFAULT_DUPLICATE_THRESHOLD: u32 = 5;
+
+/// Only every Nth pixel in each dimension is read; plenty for an average.
+const SAMPLE_STRIDE: u32 = 8;
+
+/// Mean relative luminance (Rec. 709 weights, linear light) in `0.0..=1.0`.
+pub fn compute_average_luminance(img: &DynamicImage) -> f32 {
+    let linear = srgb_to_linear_table();
+    let (width, height) = img.dimensions();
+    let mut total = 0.0f64;
+    let mut samples = 0u64;
+
+    for y in (0..height).step_by(SAMPLE_STRIDE as usize) {
+        for x in (0..width).step_by(SAMPLE_STRIDE as usize) {
+            let [r, g, b, _] = img.get_pixel(x, y).0;
+            let luminance = 0.2126 * linear[r as usize]
+                + 0.7152 * linear[g as usize]
+                + 0.0722 * linear[b as usize];
+            total += luminance as f64;
+            samples += 1;
+        }
+    }
+
+    if samples == 0 {
+        return 0.0;
+    }
+    (total / samples as f64) as f32
+}
+
+pub fn theme_for_luminance(luminance: f32, threshold: f32) -> &'static str {
+    if luminance > threshold {
+        "light"
+    } else {
+        "dark"
+    }
+}
+
+/// Difference hash: the image shrunk to 9x8 grayscale, one bit per pair of
+/// horizontal neighbours that gets brighter to the left. Rescaled or
+/// recompressed copies of a wallpaper land a few bits apart at most.
+pub fn compute_dhash(img: &DynamicImage) -> u64 {
+    let small = img.resize_exact(9, 8, FilterType::Triangle).into_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = small.get_pixel(x, y).0[0] > small.get_pixel(x + 1, y).0[0];
+            hash = (hash << 1) | brighter as u64;
+        }
+    }
+    hash
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    u64::count_ones(a ^ b)
+}
+
+/// Indices of `hashes` that are near-duplicates, grouped transitively: two
+/// hashes within `threshold` bits share a group. Groups of one are left out;
+/// groups and their members are in index order.
+pub fn duplicate_groups(hashes: &[u64], threshold: u32) -> Vec<Vec<usize>> {
+    let mut group_of: Vec<usize> = (0..hashes.len()).collect();
+    for (i, &a) in hashes.iter().enumerate() {
+        for (j, &b) in hashes.iter().enumerate().skip(i + 1) {
+            if hamming_distance(a, b) <= threshold {
+                let (from, to) = (group_of[j], group_of[i]);
+                if from != to {
+                    group_of.iter_mut().filter(|g| **g == from).for_each(|g| *g = to);
+                }
+            }
+        }
+    }
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for index in 0..hashes.len() {
+        match groups.iter_mut().find(|group| group_of[group[0]] == group_of[index]) {
+            Some(group) => group.push(index),
+            None => groups.push(vec![index]),
+        }
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+fn srgb_to_linear_table() -> &'static [f32; 256] {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0.0; 256];
+        for (value, linear) in table.iter_mut().enumerate() {
+            let c = value as f32 / 255.0;
+            *linear = if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            };
+        }
+        table
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        compute_average_luminance, compute_dhash, duplicate_groups, hamming_distance,
+        theme_for_luminance,
+    };
+    use image::{imageops::FilterType, DynamicImage, Rgb, RgbImage};
+
+    #[test]
+    fn luminance_is_averaged_in_linear_light() {
+        let solid = |v: u8| DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 64, Rgb([v, v, v])));
+
+        assert_eq!(compute_average_luminance(&solid(0)), 0.0);
+        assert!((compute_average_luminance(&solid(255)) - 1.0).abs() < 1e-4);
+        // sRGB 128 is about 21.6% linear light, not 50%.
+        assert!((compute_average_luminance(&solid(128)) - 0.2158).abs() < 1e-3);
+
+        let half = RgbImage::from_fn(64, 64, |x, _| {
+            if x < 32 {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        });
+        let luminance = compute_average_luminance(&DynamicImage::ImageRgb8(half));
+        assert!((luminance - 0.5).abs() < 1e-4);
+        assert_eq!(theme_for_luminance(luminance, 0.4), "light");
+        assert_eq!(theme_for_luminance(luminance, 0.5), "dark");
+    }
+
+    #[test]
+    fn near_duplicates_hash_within_a_few_bits() {
+        let scene = |width: u32, height: u32, shift: i16| {
+            DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+                let u = x as f32 / width as f32;
+                let v = y as f32 / height as f32;
+                let wave = ((u * 9.0).sin() * (v * 5.0).cos() * 100.0 + 128.0) as i16;
+                let value = (wave + shift).clamp(0, 255) as u8;
+                Rgb([value, (255.0 * v) as u8, (255.0 * u) as u8])
+            }))
+        };
+        let original = compute_dhash(&scene(640, 360, 0));
+        let rescaled = scene(640, 360, 0).resize_exact(320, 180, FilterType::Nearest);
+        let rescaled = compute_dhash(&rescaled);
+        let brighter = compute_dhash(&scene(640, 360, 12));
+        let flipped = compute_dhash(&scene(640, 360, 0).fliph());
+
+        assert!(hamming_distance(original, rescaled) <= 5);
+        assert!(hamming_distance(original, brighter) <= 5);
+        assert!(hamming_distance(original, flipped) > 5);
+        assert_eq!(hamming_distance(0b1011, 0b0110), 3);
+
+        // 0 and 3 are six bits apart, but both are three bits from 2.
+        let hashes = [0b0000, 0xffff_0000, 0b0111, 0b0011_1111, 0xffff_0001];
+        assert_eq!(duplicate_groups(&hashes, 3), vec![vec![0, 2, 3], vec![1, 4]]);
+        assert!(duplicate_groups(&hashes, 0).is_empty());
+    }
+}
@@ -1,13 +1,94 @@
-use anyhow::{anyhow, Result};
-use rand::prelude::IndexedRandom;
+use crate::{
+    config::{Config, RotationMode},
+    formats, metadata,
+    output::{debug, warning},
+    palette, state, wallpaper,
+};
+use anyhow::{anyhow, Context, Result};
+use chrono::{Datelike, Days, NaiveDate};
+use rand::{
+    prelude::{IndexedRandom, SliceRandom},
+    rngs::StdRng,
+    Rng, SeedableRng,
+};
 use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashSet, VecDeque},
     fs,
+    hash::{Hash, Hasher},
+    io::Read,
     path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
 };
 
 const STATE_FILE: &str = "/tmp/papdieo_state";
+const SHUFFLE_STATE_PREFIX: &str = "/tmp/papdieo_shuffle";
+/// 1920×1080 gray gradient shown on monitors whose directories have no media.
+/// How long a picker script may run before it is killed; the daemon waits on
+/// it between rotations.
+const PICKER_SCRIPT_TIMEOUT: Duration = Duration::from_secs(10);
+const PICKER_SCRIPT_POLL: Duration = Duration::from_millis(50);
+const PLACEHOLDER_PNG: &[u8] = include_bytes!("../assets/placeholder.png");
+
+/// Lowercase image extensions papdieo can show; also feeds shell completions.
+#[cfg(not(feature = "jxl"))]
+pub const SUPPORTED_IMAGE_EXTS: &[&str] = &["jpg", "jpeg", "png", "webp", "tif", "tiff"];
+#[cfg(feature = "jxl")]
+pub const SUPPORTED_IMAGE_EXTS: &[&str] = &["jpg", "jpeg", "png", "webp", "tif", "tiff", "jxl"];
+/// Lowercase video extensions. `.ts` files still need the MPEG-TS sync byte,
+/// see [`wallpaper::is_video_file`].
+pub const SUPPORTED_VIDEO_EXTS: &[&str] = &[
+    "mp4", "mkv", "webm", "mov", "avi", "flv", "3gp", "rm", "gifv", "m4v", "ts",
+];
+
+/// The kinds of media listings and picks include: `allow_video`/`allow_images`,
+/// which `--no-video`/`--no-images` switch off for one command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MediaFilter {
+    pub video: bool,
+    pub images: bool,
+}
+
+impl Default for MediaFilter {
+    fn default() -> Self {
+        Self {
+            video: true,
+            images: true,
+        }
+    }
+}
+
+impl MediaFilter {
+    pub fn from_config(cfg: &Config) -> Self {
+        Self {
+            video: cfg.allow_video.unwrap_or(true),
+            images: cfg.allow_images.unwrap_or(true),
+        }
+    }
+
+    fn check(self) -> Result<()> {
+        if !self.video && !self.images {
+            return Err(anyhow!(
+                "no media types enabled; allow_video and allow_images are both off"
+            ));
+        }
+        Ok(())
+    }
 
-pub fn list_wallpapers(dir: &Path) -> Result<Vec<PathBuf>> {
+    /// Supported media of a kind that is not switched off.
+    fn allows(self, path: &Path) -> bool {
+        is_supported_media(path)
+            && if wallpaper::is_video_file(path) {
+                self.video
+            } else {
+                self.images
+            }
+    }
+}
+
+pub fn list_wallpapers(dir: &Path, media: MediaFilter) -> Result<Vec<PathBuf>> {
+    media.check()?;
     if !dir.exists() {
         return Err(anyhow!("wallpaper directory does not exist: {}", dir.display()));
     }
@@ -16,7 +97,7 @@ pub fn list_wallpapers(dir: &Path) -> Result<Vec<PathBuf>> {
     for entry in fs::read_dir(dir)? {
         let entry = entry?;
         let path = entry.path();
-        if path.is_file() && is_supported_media(&path) {
+        if path.is_file() && media.allows(&path) {
             images.push(path);
         }
     }
@@ -29,15 +110,386 @@ pub fn list_wallpapers(dir: &Path) -> Result<Vec<PathBuf>> {
     Ok(images)
 }
 
-pub fn pick_random_wallpaper(dir: &Path) -> Result<PathBuf> {
-    let images = list_wallpapers(dir)?;
-    let mut rng = rand::rng();
+/// Writes the built-in placeholder to the runtime directory (once) and returns
+/// its path, so it can be shown like any other wallpaper.
+pub fn placeholder_wallpaper() -> Result<PathBuf> {
+    let path = state::runtime_dir().join("papdieo-placeholder.png");
+    let current = fs::metadata(&path).map(|meta| meta.len()).ok();
+    if current != Some(PLACEHOLDER_PNG.len() as u64) {
+        fs::write(&path, PLACEHOLDER_PNG)
+            .with_context(|| format!("failed to write placeholder: {}", path.display()))?;
+    }
+    Ok(path)
+}
+
+/// A single black pixel, stretched over a monitor when there is nothing to show.
+pub fn black_wallpaper() -> Result<PathBuf> {
+    solid_wallpaper("papdieo-black.png", [0, 0, 0])
+}
+
+/// A single neutral grey pixel, shown in place of a video nothing could decode.
+pub fn gray_wallpaper() -> Result<PathBuf> {
+    solid_wallpaper("papdieo-gray.png", [128, 128, 128])
+}
+
+fn solid_wallpaper(name: &str, rgb: [u8; 3]) -> Result<PathBuf> {
+    solid_wallpaper_in(&state::runtime_dir(), name, rgb)
+}
+
+fn solid_wallpaper_in(dir: &Path, name: &str, [r, g, b]: [u8; 3]) -> Result<PathBuf> {
+    let path = dir.join(name);
+    if !path.exists() {
+        image::RgbaImage::from_pixel(1, 1, image::Rgba([r, g, b, 255]))
+            .save(&path)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    Ok(path)
+}
+
+/// Wallpapers in `dir` whose sidecar metadata carries `tag` (case-insensitive).
+pub fn list_wallpapers_filtered_by_tag(
+    dir: &Path,
+    tag: &str,
+    media: MediaFilter,
+) -> Result<Vec<PathBuf>> {
+    let images: Vec<PathBuf> = list_wallpapers(dir, media)?
+        .into_iter()
+        .filter(|path| metadata::has_tag(path, tag))
+        .collect();
+    if images.is_empty() {
+        return Err(anyhow!("no wallpapers tagged '{}' in {}", tag, dir.display()));
+    }
+    Ok(images)
+}
+
+/// Random pick among the tagged wallpapers; `seed` works as for the untagged pick.
+pub fn pick_random_tagged_wallpaper(
+    dir: &Path,
+    tag: &str,
+    seed: Option<u64>,
+    media: MediaFilter,
+) -> Result<PathBuf> {
+    pick_random_among(&list_wallpapers_filtered_by_tag(dir, tag, media)?, seed)
+}
+
+/// Random pick from an already filtered list; `seed` works as for the
+/// directory picks.
+pub fn pick_random_among(images: &[PathBuf], seed: Option<u64>) -> Result<PathBuf> {
+    let selected = match seed {
+        Some(seed) => choose_random(images, &mut StdRng::seed_from_u64(seed), false)?,
+        None => choose_random(images, &mut rand::rng(), true)?,
+    };
+    let _ = fs::write(STATE_FILE, selected.to_string_lossy().as_bytes());
+    Ok(selected)
+}
+
+/// Wallpapers whose width/height is within `tolerance` (a fraction, 0.1 for
+/// ±10%) of `target`. Images are measured from their headers and videos with
+/// GStreamer's discoverer; files that cannot be measured are left out.
+pub fn filter_by_aspect_ratio(
+    paths: &[PathBuf],
+    target: f64,
+    tolerance: f64,
+) -> Result<Vec<PathBuf>> {
+    let matching: Vec<PathBuf> = paths
+        .iter()
+        .filter(|path| match media_dimensions(path) {
+            Ok((width, height)) => aspect_ratio_matches(width, height, target, tolerance),
+            Err(error) => {
+                debug!("aspect filter: skipping {}: {:#}", path.display(), error);
+                false
+            }
+        })
+        .cloned()
+        .collect();
+    if matching.is_empty() {
+        return Err(anyhow!(
+            "no wallpapers with an aspect ratio of {:.3} (±{}%)",
+            target,
+            tolerance * 100.0
+        ));
+    }
+    Ok(matching)
+}
+
+/// Whether the file is `min` to `max` bytes long (either bound may be open).
+/// Files whose size cannot be read are out of range.
+pub fn size_in_range(path: &Path, min: Option<u64>, max: Option<u64>) -> bool {
+    fs::metadata(path).is_ok_and(|meta| {
+        min.is_none_or(|min| meta.len() >= min) && max.is_none_or(|max| meta.len() <= max)
+    })
+}
+
+fn media_dimensions(path: &Path) -> Result<(u32, u32)> {
+    if wallpaper::is_video_file(path) {
+        wallpaper::video_dimensions(path)
+    } else {
+        formats::image_dimensions(path)
+    }
+}
+
+fn aspect_ratio_matches(width: u32, height: u32, target: f64, tolerance: f64) -> bool {
+    height > 0 && (width as f64 / height as f64 - target).abs() <= target * tolerance
+}
+
+pub fn pick_next_tagged_wallpaper(dir: &Path, tag: &str, media: MediaFilter) -> Result<PathBuf> {
+    pick_next_among(&list_wallpapers_filtered_by_tag(dir, tag, media)?)
+}
+
+pub fn pick_random_wallpaper(dir: &Path, media: MediaFilter) -> Result<PathBuf> {
+    let images = list_wallpapers(dir, media)?;
+    let selected = choose_random(&images, &mut rand::rng(), true)?;
+    let _ = fs::write(STATE_FILE, selected.to_string_lossy().as_bytes());
+    Ok(selected)
+}
+
+/// Merges the wallpapers of every directory and picks one at random.
+/// Missing or empty directories are skipped as long as one has media.
+pub fn pick_random_from_dirs(dirs: &[PathBuf], media: MediaFilter) -> Result<PathBuf> {
+    let images = list_wallpapers_in_dirs(dirs, media)?;
+    let selected = choose_random(&images, &mut rand::rng(), true)?;
+    let _ = fs::write(STATE_FILE, selected.to_string_lossy().as_bytes());
+    Ok(selected)
+}
+
+/// Random pick that skips `exclude` (e.g. wallpapers already on other monitors).
+/// Repeats are allowed, with a warning, once every wallpaper is excluded.
+pub fn pick_random_excluding(
+    dir: &Path,
+    exclude: &HashSet<PathBuf>,
+    media: MediaFilter,
+) -> Result<PathBuf> {
+    pick_random_among_excluding(&list_wallpapers(dir, media)?, exclude)
+}
+
+pub fn pick_random_from_dirs_excluding(
+    dirs: &[PathBuf],
+    exclude: &HashSet<PathBuf>,
+    media: MediaFilter,
+) -> Result<PathBuf> {
+    pick_random_among_excluding(&list_wallpapers_in_dirs(dirs, media)?, exclude)
+}
+
+pub fn pick_random_among_excluding(
+    images: &[PathBuf],
+    exclude: &HashSet<PathBuf>,
+) -> Result<PathBuf> {
+    let remaining: Vec<PathBuf> = images
+        .iter()
+        .filter(|image| !exclude.contains(*image))
+        .cloned()
+        .collect();
+    let candidates = if remaining.is_empty() {
+        warning!(
+            "warning: only {} wallpaper(s) for {} monitor(s), allowing repeats",
+            images.len(),
+            exclude.len() + 1
+        );
+        images
+    } else {
+        &remaining
+    };
+    let selected = choose_random(candidates, &mut rand::rng(), true)?;
+    let _ = fs::write(STATE_FILE, selected.to_string_lossy().as_bytes());
+    Ok(selected)
+}
+
+pub fn list_wallpapers_in_dirs(dirs: &[PathBuf], media: MediaFilter) -> Result<Vec<PathBuf>> {
+    media.check()?;
+    let mut images: Vec<PathBuf> = dirs
+        .iter()
+        .filter_map(|dir| list_wallpapers(dir, media).ok())
+        .flatten()
+        .collect();
+    images.sort();
+    images.dedup();
+
+    if images.is_empty() {
+        let listed: Vec<String> = dirs.iter().map(|d| d.display().to_string()).collect();
+        return Err(anyhow!("no wallpapers found in {}", listed.join(", ")));
+    }
+
+    Ok(images)
+}
+
+/// Wallpapers in `dir` and its subdirectories, breadth first. `max_depth`
+/// limits how many levels below `dir` are read (`Some(0)` is `dir` alone,
+/// `None` is unlimited); the walk stops once `max_results` files are found.
+/// Symlinked directories are not followed, and a subdirectory that cannot be
+/// read is skipped with a warning.
+pub fn list_wallpapers_recursive(
+    dir: &Path,
+    max_depth: Option<usize>,
+    max_results: Option<usize>,
+    media: MediaFilter,
+) -> Result<Vec<PathBuf>> {
+    media.check()?;
+    if !dir.exists() {
+        return Err(anyhow!("wallpaper directory does not exist: {}", dir.display()));
+    }
+
+    let limit = max_results.unwrap_or(usize::MAX);
+    let mut images = Vec::new();
+    let mut queue = VecDeque::from([(dir.to_path_buf(), 0)]);
+    while let Some((current, depth)) = queue.pop_front() {
+        let read = fs::read_dir(&current).and_then(|entries| entries.collect::<Result<_, _>>());
+        let mut entries: Vec<fs::DirEntry> = match read {
+            Ok(entries) => entries,
+            Err(error) if depth > 0 => {
+                warning!("warning: skipping {}: {}", current.display(), error);
+                continue;
+            }
+            Err(error) => return Err(error.into()),
+        };
+        entries.sort_by_key(|entry| entry.path());
+        for entry in entries {
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                if max_depth.is_none_or(|max| depth < max) {
+                    queue.push_back((path, depth + 1));
+                }
+            } else if path.is_file() && media.allows(&path) {
+                images.push(path);
+                if images.len() >= limit {
+                    return Ok(images);
+                }
+            }
+        }
+    }
+    Ok(images)
+}
+
+/// [`list_wallpapers_in_dirs`] walking each directory with
+/// [`list_wallpapers_recursive`]; `max_results` counts across all of them.
+pub fn list_wallpapers_in_dirs_recursive(
+    dirs: &[PathBuf],
+    max_depth: Option<usize>,
+    max_results: Option<usize>,
+    media: MediaFilter,
+) -> Result<Vec<PathBuf>> {
+    media.check()?;
+    let mut images: Vec<PathBuf> = Vec::new();
+    for dir in dirs {
+        let remaining = max_results.map(|max| max.saturating_sub(images.len()));
+        if remaining == Some(0) {
+            break;
+        }
+        if let Ok(found) = list_wallpapers_recursive(dir, max_depth, remaining, media) {
+            images.extend(found);
+        }
+    }
+    images.sort();
+    images.dedup();
+
+    if images.is_empty() {
+        let listed: Vec<String> = dirs.iter().map(|d| d.display().to_string()).collect();
+        return Err(anyhow!("no wallpapers found in {}", listed.join(", ")));
+    }
+
+    Ok(images)
+}
+
+/// `list --grouped`: one group per parent directory, directories and files in
+/// sorted order. A plain path sort would interleave a directory's files with
+/// its subdirectories.
+pub fn group_by_directory(paths: Vec<PathBuf>) -> Vec<(PathBuf, Vec<PathBuf>)> {
+    let mut groups: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for path in paths {
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        groups.entry(dir).or_default().push(path);
+    }
+    groups
+        .into_iter()
+        .map(|(dir, mut files)| {
+            files.sort();
+            (dir, files)
+        })
+        .collect()
+}
+
+/// `dedup`: near-duplicate images among `paths` by perceptual hash, each group
+/// led by the copy `list --deduplicate` keeps, the largest file (then the
+/// first by name). Videos and unreadable images are never duplicates.
+pub fn near_duplicate_groups(paths: &[PathBuf], threshold: u32) -> Vec<Vec<PathBuf>> {
+    let hashed: Vec<(&PathBuf, u64)> = hash_images(paths);
+    let hashes: Vec<u64> = hashed.iter().map(|(_, hash)| *hash).collect();
+    palette::duplicate_groups(&hashes, threshold)
+        .into_iter()
+        .map(|group| {
+            let mut files: Vec<PathBuf> = group.iter().map(|&i| hashed[i].0.clone()).collect();
+            let size = |path: &PathBuf| fs::metadata(path).map_or(0, |meta| meta.len());
+            files.sort_by(|a, b| size(b).cmp(&size(a)).then_with(|| a.cmp(b)));
+            files
+        })
+        .collect()
+}
+
+/// `list --deduplicate`: `paths` in their order, with only the kept copy of
+/// each group from [`near_duplicate_groups`].
+pub fn deduplicate(paths: Vec<PathBuf>, threshold: u32) -> Vec<PathBuf> {
+    let dropped: HashSet<PathBuf> = near_duplicate_groups(&paths, threshold)
+        .into_iter()
+        .flat_map(|group| group.into_iter().skip(1))
+        .collect();
+    paths.into_iter().filter(|path| !dropped.contains(path)).collect()
+}
+
+/// Decoding dominates, so the images are hashed on every core.
+fn hash_images(paths: &[PathBuf]) -> Vec<(&PathBuf, u64)> {
+    let hash = |path: &PathBuf| {
+        if wallpaper::is_video_file(path) {
+            return None;
+        }
+        match formats::open_image(path) {
+            Ok(image) => Some(palette::compute_dhash(&image)),
+            Err(error) => {
+                debug!("not hashing {}: {:#}", path.display(), error);
+                None
+            }
+        }
+    };
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = paths.len().div_ceil(workers).max(1);
+    thread::scope(|scope| {
+        let handles: Vec<_> = paths
+            .chunks(chunk)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .filter_map(|path| hash(path).map(|hash| (path, hash)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap_or_default())
+            .collect()
+    })
+}
+
+/// Same seed and directory contents always yield the same wallpaper, so the
+/// usual "avoid the previous wallpaper" rule is skipped here.
+pub fn pick_random_wallpaper_seeded(dir: &Path, seed: u64, media: MediaFilter) -> Result<PathBuf> {
+    let images = list_wallpapers(dir, media)?;
+    let mut rng = StdRng::seed_from_u64(seed);
+    let selected = choose_random(&images, &mut rng, false)?;
+    let _ = fs::write(STATE_FILE, selected.to_string_lossy().as_bytes());
+    Ok(selected)
+}
+
+fn choose_random<R: Rng + ?Sized>(
+    images: &[PathBuf],
+    rng: &mut R,
+    avoid_last: bool,
+) -> Result<PathBuf> {
     let mut selected = images
-        .choose(&mut rng)
+        .choose(rng)
         .cloned()
         .ok_or_else(|| anyhow!("no wallpapers available"))?;
 
-    if images.len() > 1 {
+    if avoid_last && images.len() > 1 {
         if let Ok(last_path) = fs::read_to_string(STATE_FILE) {
             let last = last_path.trim();
             if selected.to_string_lossy() == last {
@@ -46,19 +498,23 @@ pub fn pick_random_wallpaper(dir: &Path) -> Result<PathBuf> {
                     .filter(|p| p.to_string_lossy() != last)
                     .cloned()
                     .collect();
-                if let Some(next) = alternatives.choose(&mut rng).cloned() {
+                if let Some(next) = alternatives.choose(rng).cloned() {
                     selected = next;
                 }
             }
         }
     }
 
-    let _ = fs::write(STATE_FILE, selected.to_string_lossy().as_bytes());
     Ok(selected)
 }
 
-pub fn pick_next_wallpaper(dir: &Path) -> Result<PathBuf> {
-    let images = list_wallpapers(dir)?;
+pub fn pick_next_wallpaper(dir: &Path, media: MediaFilter) -> Result<PathBuf> {
+    pick_next_among(&list_wallpapers(dir, media)?)
+}
+
+/// The entry after the last picked wallpaper, wrapping around; the first one
+/// if the last pick is not in `images`.
+pub fn pick_next_among(images: &[PathBuf]) -> Result<PathBuf> {
     let last = fs::read_to_string(STATE_FILE).ok();
 
     let next_index = match last {
@@ -75,22 +531,469 @@ pub fn pick_next_wallpaper(dir: &Path) -> Result<PathBuf> {
     Ok(next)
 }
 
-fn is_supported_media(path: &Path) -> bool {
-    path.extension()
-        .and_then(|e| e.to_str())
-        .map(|e| {
-            matches!(
-                e.to_ascii_lowercase().as_str(),
-                "jpg"
-                    | "jpeg"
-                    | "png"
-                    | "webp"
-                    | "mp4"
-                    | "mkv"
-                    | "webm"
-                    | "mov"
-                    | "avi"
-            )
+/// `monitor` only matters to `hash-shuffle`, which treats no monitor as an
+/// empty name.
+pub fn pick_wallpaper(
+    dir: &Path,
+    mode: RotationMode,
+    monitor: Option<&str>,
+    media: MediaFilter,
+) -> Result<PathBuf> {
+    match mode {
+        RotationMode::Random => pick_random_wallpaper(dir, media),
+        RotationMode::Sequential => pick_next_wallpaper(dir, media),
+        RotationMode::Shuffle => pick_shuffled_wallpaper(dir, media),
+        RotationMode::HashShuffle => {
+            pick_hash_daily_wallpaper(dir, monitor.unwrap_or_default(), media)
+        }
+    }
+}
+
+/// [`pick_wallpaper`] from an already filtered list of `dir`'s wallpapers.
+pub fn pick_wallpaper_among(
+    images: &[PathBuf],
+    dir: &Path,
+    mode: RotationMode,
+    monitor: Option<&str>,
+) -> Result<PathBuf> {
+    match mode {
+        RotationMode::Random => pick_random_among(images, None),
+        RotationMode::Sequential => pick_next_among(images),
+        RotationMode::Shuffle => pick_shuffled_among(images, dir),
+        RotationMode::HashShuffle => pick_hash_daily_among(images, monitor.unwrap_or_default()),
+    }
+}
+
+/// `hash-shuffle`: the same wallpaper all day on a monitor, whatever the
+/// daemon restarts. Days walk a shuffled copy of the directory, so every
+/// wallpaper is shown once before any repeats; each pass is shuffled with a
+/// seed hashed from the monitor and the date the pass began (`DP-1-2025-01-15`).
+pub fn pick_hash_daily_wallpaper(dir: &Path, monitor: &str, media: MediaFilter) -> Result<PathBuf> {
+    pick_hash_daily_among(&list_wallpapers(dir, media)?, monitor)
+}
+
+fn pick_hash_daily_among(images: &[PathBuf], monitor: &str) -> Result<PathBuf> {
+    let selected = hash_daily_choice(images, monitor, chrono::Local::now().date_naive())?;
+    let _ = fs::write(STATE_FILE, selected.to_string_lossy().as_bytes());
+    Ok(selected)
+}
+
+fn hash_daily_choice(images: &[PathBuf], monitor: &str, date: NaiveDate) -> Result<PathBuf> {
+    if images.is_empty() {
+        return Err(anyhow!("no wallpapers available"));
+    }
+    let day = i64::from(date.num_days_from_ce()).rem_euclid(images.len() as i64) as u64;
+    let pass_start = date.checked_sub_days(Days::new(day)).unwrap_or(date);
+    let mut hasher = DefaultHasher::new();
+    format!("{}-{}", monitor, pass_start.format("%Y-%m-%d")).hash(&mut hasher);
+    let mut order = images.to_vec();
+    order.shuffle(&mut StdRng::seed_from_u64(hasher.finish()));
+    Ok(order.swap_remove(day as usize))
+}
+
+/// Walks a shuffled copy of the directory so every wallpaper is shown once
+/// before any repeats. The remaining queue is kept per directory.
+pub fn pick_shuffled_wallpaper(dir: &Path, media: MediaFilter) -> Result<PathBuf> {
+    pick_shuffled_among(&list_wallpapers(dir, media)?, dir)
+}
+
+fn pick_shuffled_among(images: &[PathBuf], dir: &Path) -> Result<PathBuf> {
+    if images.is_empty() {
+        return Err(anyhow!("no wallpapers found in {}", dir.display()));
+    }
+    let queue_path = shuffle_state_path(dir);
+
+    let mut queue: Vec<PathBuf> = fs::read_to_string(&queue_path)
+        .map(|content| {
+            content
+                .lines()
+                .map(PathBuf::from)
+                .filter(|p| images.contains(p))
+                .collect()
         })
-        .unwrap_or(false)
+        .unwrap_or_default();
+
+    if queue.is_empty() {
+        queue = images.to_vec();
+        queue.shuffle(&mut rand::rng());
+
+        // Avoid showing the same wallpaper twice across a reshuffle.
+        if queue.len() > 1 {
+            if let Ok(last_path) = fs::read_to_string(STATE_FILE) {
+                if queue[0].to_string_lossy() == last_path.trim() {
+                    let last = queue.len() - 1;
+                    queue.swap(0, last);
+                }
+            }
+        }
+    }
+
+    let selected = queue.remove(0);
+    let remaining: Vec<String> = queue
+        .iter()
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect();
+    let _ = fs::write(&queue_path, remaining.join("\n"));
+    let _ = fs::write(STATE_FILE, selected.to_string_lossy().as_bytes());
+    Ok(selected)
+}
+
+/// Runs a `picker_script` / `--script` and returns the wallpaper path it prints.
+/// The script gets `PAPDIEO_MONITOR` (empty when no monitor is targeted),
+/// `PAPDIEO_WALLPAPER_DIR` and `PAPDIEO_TIMESTAMP` (Unix seconds); a relative
+/// path is taken relative to the wallpaper directory. A script still running
+/// after 10 seconds is killed and the pick fails.
+pub fn run_picker_script(
+    script: &Path,
+    monitor: Option<&str>,
+    wallpaper_dir: &Path,
+) -> Result<PathBuf> {
+    run_picker_script_with_timeout(script, monitor, wallpaper_dir, PICKER_SCRIPT_TIMEOUT)
+}
+
+fn run_picker_script_with_timeout(
+    script: &Path,
+    monitor: Option<&str>,
+    wallpaper_dir: &Path,
+    timeout: Duration,
+) -> Result<PathBuf> {
+    let mut child = Command::new(script)
+        .env("PAPDIEO_MONITOR", monitor.unwrap_or_default())
+        .env("PAPDIEO_WALLPAPER_DIR", wallpaper_dir)
+        .env("PAPDIEO_TIMESTAMP", state::unix_now().to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run picker script {}", script.display()))?;
+    let read_to_end = |mut pipe: Box<dyn Read + Send>| {
+        thread::spawn(move || {
+            let mut bytes = Vec::new();
+            let _ = pipe.read_to_end(&mut bytes);
+            bytes
+        })
+    };
+    let stdout = read_to_end(Box::new(child.stdout.take().expect("stdout is piped")));
+    let stderr = read_to_end(Box::new(child.stderr.take().expect("stderr is piped")));
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            // The readers are left behind: a process the script started may
+            // still hold the pipes open.
+            return Err(anyhow!(
+                "picker script {} did not finish within {}s",
+                script.display(),
+                timeout.as_secs()
+            ));
+        }
+        thread::sleep(PICKER_SCRIPT_POLL);
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        return Err(anyhow!(
+            "picker script {} failed ({}): {}",
+            script.display(),
+            status,
+            String::from_utf8_lossy(&stderr).trim()
+        ));
+    }
+    let stdout = String::from_utf8_lossy(&stdout);
+    let picked = stdout.trim();
+    if picked.is_empty() {
+        return Err(anyhow!("picker script {} printed no wallpaper path", script.display()));
+    }
+    debug!("picker script chose {}", picked);
+    Ok(wallpaper_dir.join(picked))
+}
+
+fn shuffle_state_path(dir: &Path) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    dir.hash(&mut hasher);
+    PathBuf::from(format!("{}-{:016x}", SHUFFLE_STATE_PREFIX, hasher.finish()))
+}
+
+pub fn is_supported_media(path: &Path) -> bool {
+    let is_image = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| SUPPORTED_IMAGE_EXTS.contains(&e.to_ascii_lowercase().as_str()))
+        .unwrap_or(false);
+    is_image || wallpaper::is_video_file(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        deduplicate, filter_by_aspect_ratio, group_by_directory, hash_daily_choice,
+        list_wallpapers, list_wallpapers_in_dirs, list_wallpapers_recursive, near_duplicate_groups,
+        pick_random_excluding, pick_random_wallpaper_seeded, run_picker_script,
+        run_picker_script_with_timeout, solid_wallpaper_in, MediaFilter, PLACEHOLDER_PNG,
+    };
+    use chrono::{Datelike, Days, NaiveDate};
+    use std::{collections::HashSet, fs, path::PathBuf};
+
+    #[test]
+    fn seeded_pick_is_reproducible() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        for name in ["a.png", "b.png", "c.png", "d.jpg", "e.webp"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let first = pick_random_wallpaper_seeded(dir, 42, MediaFilter::default()).unwrap();
+        let second = pick_random_wallpaper_seeded(dir, 42, MediaFilter::default()).unwrap();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn multiple_dirs_are_merged_and_missing_dirs_skipped() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let nature = root.join("nature");
+        let cities = root.join("cities");
+        fs::create_dir_all(&nature).unwrap();
+        fs::create_dir_all(&cities).unwrap();
+        fs::write(nature.join("forest.png"), b"").unwrap();
+        fs::write(cities.join("tokyo.jpg"), b"").unwrap();
+        fs::write(cities.join("notes.txt"), b"").unwrap();
+
+        let all = MediaFilter::default();
+        let images =
+            list_wallpapers_in_dirs(&[nature.clone(), root.join("missing"), cities.clone()], all);
+        let empty = list_wallpapers_in_dirs(&[root.join("missing")], all);
+
+        assert_eq!(
+            images.unwrap(),
+            vec![cities.join("tokyo.jpg"), nature.join("forest.png")]
+        );
+        assert!(empty.is_err());
+    }
+
+    #[test]
+    fn recursive_listing_honours_max_depth_and_max_results() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let deep = root.join("nature").join("forests");
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(root.join("top.png"), b"").unwrap();
+        fs::write(root.join("nature").join("lake.jpg"), b"").unwrap();
+        fs::write(deep.join("pine.webp"), b"").unwrap();
+
+        let list = |depth, max| list_wallpapers_recursive(root, depth, max, MediaFilter::default());
+        let flat = list(Some(0), None).unwrap();
+        let one = list(Some(1), None).unwrap();
+        let all = list(None, None).unwrap();
+        let first_two = list(None, Some(2)).unwrap();
+
+        assert_eq!(flat, vec![root.join("top.png")]);
+        assert_eq!(one, vec![root.join("top.png"), root.join("nature").join("lake.jpg")]);
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[2], deep.join("pine.webp"));
+        assert_eq!(first_two, one);
+    }
+
+    #[test]
+    fn an_unreadable_subdirectory_is_skipped() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let locked = root.join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::write(root.join("top.png"), b"").unwrap();
+        fs::write(locked.join("hidden.png"), b"").unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        // Root reads the directory anyway, so only the top-level file is certain.
+        let listed = list_wallpapers_recursive(root, None, None, MediaFilter::default());
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert!(listed.unwrap().contains(&root.join("top.png")));
+    }
+
+    #[test]
+    fn aspect_filter_keeps_wallpapers_within_the_tolerance() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        image::RgbImage::new(32, 18).save(dir.join("wide.png")).unwrap();
+        image::RgbImage::new(34, 18).save(dir.join("almost.png")).unwrap();
+        image::RgbImage::new(24, 18).save(dir.join("square.png")).unwrap();
+        fs::write(dir.join("broken.png"), b"").unwrap();
+        let paths: Vec<_> = ["wide.png", "almost.png", "square.png", "broken.png"]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect();
+
+        let sixteen_nine = filter_by_aspect_ratio(&paths, 16.0 / 9.0, 0.1);
+        let strict = filter_by_aspect_ratio(&paths, 16.0 / 9.0, 0.01);
+        let none = filter_by_aspect_ratio(&paths, 21.0 / 9.0, 0.05);
+
+        assert_eq!(sixteen_nine.unwrap(), vec![dir.join("wide.png"), dir.join("almost.png")]);
+        assert_eq!(strict.unwrap(), vec![dir.join("wide.png")]);
+        assert!(none.is_err());
+    }
+
+    #[test]
+    fn placeholder_is_a_full_hd_image() {
+        let placeholder = image::load_from_memory(PLACEHOLDER_PNG).unwrap();
+        assert_eq!((placeholder.width(), placeholder.height()), (1920, 1080));
+    }
+
+    #[test]
+    fn excluded_wallpapers_are_skipped_until_none_are_left() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::write(dir.join("a.png"), b"").unwrap();
+        fs::write(dir.join("b.png"), b"").unwrap();
+
+        let mut exclude = HashSet::from([dir.join("a.png")]);
+        let picks: HashSet<_> = (0..10)
+            .map(|_| pick_random_excluding(dir, &exclude, MediaFilter::default()).unwrap())
+            .collect();
+        exclude.insert(dir.join("b.png"));
+        let repeat = pick_random_excluding(dir, &exclude, MediaFilter::default());
+
+        assert_eq!(picks, HashSet::from([dir.join("b.png")]));
+        assert!(repeat.is_ok());
+    }
+
+    #[test]
+    fn grouping_keeps_a_directory_together_around_its_subdirectories() {
+        let paths: Vec<PathBuf> = ["/w/b.png", "/w/nature/fern.jpg", "/w/a.png", "/w/city/x.mp4"]
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        let groups = group_by_directory(paths);
+        let expected: Vec<(PathBuf, Vec<PathBuf>)> = vec![
+            ("/w".into(), vec!["/w/a.png".into(), "/w/b.png".into()]),
+            ("/w/city".into(), vec!["/w/city/x.mp4".into()]),
+            ("/w/nature".into(), vec!["/w/nature/fern.jpg".into()]),
+        ];
+        assert_eq!(groups, expected);
+    }
+
+    #[test]
+    fn solid_wallpaper_is_one_opaque_pixel() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let black = solid_wallpaper_in(dir, "papdieo-black.png", [0, 0, 0]).unwrap();
+        assert_eq!(black, dir.join("papdieo-black.png"));
+        let black = image::open(black).unwrap().to_rgba8();
+        assert_eq!(black.dimensions(), (1, 1));
+        assert_eq!(black.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        let gray = solid_wallpaper_in(dir, "papdieo-gray.png", [128, 128, 128]).unwrap();
+        let gray = image::open(gray).unwrap().to_rgba8();
+        assert_eq!(gray.get_pixel(0, 0).0, [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn picker_script_output_becomes_the_wallpaper() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let script = dir.join("pick.sh");
+        fs::write(
+            &script,
+            "#!/bin/sh\n[ \"$PAPDIEO_MONITOR\" = DP-1 ] || { echo \"no monitor\" >&2; exit 3; }\n\
+             [ -n \"$PAPDIEO_TIMESTAMP\" ] || exit 4\necho \"  $PAPDIEO_WALLPAPER_DIR/sunny.png\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let picked = run_picker_script(&script, Some("DP-1"), dir).unwrap();
+        assert_eq!(picked, dir.join("sunny.png"));
+        let error = run_picker_script(&script, None, dir).unwrap_err();
+        assert!(format!("{:#}", error).ends_with("no monitor"));
+    }
+
+    #[test]
+    fn a_hanging_picker_script_is_killed() {
+        use std::{
+            os::unix::fs::PermissionsExt,
+            time::{Duration, Instant},
+        };
+
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let script = dir.join("hang.sh");
+        fs::write(&script, "#!/bin/sh\nexec sleep 30\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let started = Instant::now();
+        let timeout = Duration::from_millis(200);
+        let error = run_picker_script_with_timeout(&script, None, dir, timeout).unwrap_err();
+        assert!(error.to_string().contains("did not finish within"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn media_kinds_can_be_switched_off() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::write(dir.join("loop.mp4"), b"").unwrap();
+        fs::write(dir.join("still.png"), b"").unwrap();
+        let list = |video, images| list_wallpapers(dir, MediaFilter { video, images });
+
+        assert_eq!(list(true, true).unwrap(), [dir.join("loop.mp4"), dir.join("still.png")]);
+        assert_eq!(list(true, false).unwrap(), [dir.join("loop.mp4")]);
+        assert_eq!(list(false, true).unwrap(), [dir.join("still.png")]);
+        let error = list(false, false).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "no media types enabled; allow_video and allow_images are both off"
+        );
+        let both_off = MediaFilter {
+            video: false,
+            images: false,
+        };
+        assert!(list_wallpapers_in_dirs(&[dir.to_path_buf()], both_off).is_err());
+    }
+
+    #[test]
+    fn hash_shuffle_keeps_the_day_and_covers_every_wallpaper() {
+        let images: Vec<PathBuf> = (0..5).map(|i| PathBuf::from(format!("{}.png", i))).collect();
+        let mut date = NaiveDate::from_ymd_opt(2025, 1, 15).unwrap();
+        while date.num_days_from_ce() % 5 != 0 {
+            date = date + Days::new(1);
+        }
+
+        let pick = |day: u64| hash_daily_choice(&images, "DP-1", date + Days::new(day)).unwrap();
+        assert_eq!(pick(2), pick(2));
+        let pass: HashSet<PathBuf> = (0..5).map(pick).collect();
+        assert_eq!(pass.len(), 5);
+        assert!(hash_daily_choice(&[], "DP-1", date).is_err());
+    }
+
+    #[test]
+    fn deduplicate_keeps_the_largest_copy() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let stripes = |width: u32, height: u32, flip: bool| {
+            image::RgbImage::from_fn(width, height, |x, _| {
+                let light = (x * 8 / width).is_multiple_of(2);
+                let value = if light != flip { 230 } else { 20 };
+                image::Rgb([value, value, value])
+            })
+        };
+        let small = dir.join("a-small.png");
+        let large = dir.join("b-large.png");
+        let other = dir.join("c-other.png");
+        stripes(64, 36, false).save(&small).unwrap();
+        stripes(256, 144, false).save(&large).unwrap();
+        stripes(256, 144, true).save(&other).unwrap();
+        let video = dir.join("clip.mp4");
+        fs::write(&video, b"not decoded").unwrap();
+
+        let files = vec![small.clone(), large.clone(), other.clone(), video.clone()];
+        assert_eq!(near_duplicate_groups(&files, 5), vec![vec![large.clone(), small]]);
+        assert_eq!(deduplicate(files, 5), vec![large, other, video]);
+    }
 }
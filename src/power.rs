@@ -0,0 +1,65 @@
+//! AC/battery detection for the daemon's `battery_fps` / `ac_fps`.
+//!
+//! A system counts as on battery when it has a mains supply
+//! (`/sys/class/power_supply/*/type` is `Mains`) and none of them is online.
+//! Desktops without a mains entry are always on AC.
+
+use std::{fs, path::Path};
+
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerState {
+    Ac,
+    Battery,
+}
+
+impl PowerState {
+    pub fn detect() -> Self {
+        power_state_from(Path::new(POWER_SUPPLY_DIR))
+    }
+}
+
+fn power_state_from(dir: &Path) -> PowerState {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return PowerState::Ac;
+    };
+    let read = |path: &Path| fs::read_to_string(path).unwrap_or_default();
+    let mains: Vec<bool> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|supply| read(&supply.join("type")).trim() == "Mains")
+        .map(|supply| read(&supply.join("online")).trim() == "1")
+        .collect();
+    if mains.is_empty() || mains.contains(&true) {
+        PowerState::Ac
+    } else {
+        PowerState::Battery
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{power_state_from, PowerState};
+    use std::fs;
+
+    #[test]
+    fn battery_only_when_every_mains_supply_is_offline() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let supply = |name: &str, kind: &str, online: &str| {
+            let path = dir.join(name);
+            fs::create_dir_all(&path).unwrap();
+            fs::write(path.join("type"), format!("{}\n", kind)).unwrap();
+            fs::write(path.join("online"), format!("{}\n", online)).unwrap();
+        };
+
+        assert_eq!(power_state_from(dir), PowerState::Ac);
+        supply("BAT0", "Battery", "0");
+        assert_eq!(power_state_from(dir), PowerState::Ac);
+        supply("AC", "Mains", "0");
+        assert_eq!(power_state_from(dir), PowerState::Battery);
+        supply("ADP1", "Mains", "1");
+        assert_eq!(power_state_from(dir), PowerState::Ac);
+    }
+}
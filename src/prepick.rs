@@ -0,0 +1,161 @@
+//! Picking the daemon's next wallpapers while it sleeps.
+//!
+//! A random pick lists the monitor's directories first, which takes a while on
+//! NFS mounts or spun-down disks. The daemon asks for the next round's picks
+//! right after a rotation; a background thread makes them during the interval
+//! and the next rotation takes whatever has arrived. Monitors whose pick is
+//! not ready yet, whose directories have changed since, or whose picked file
+//! is gone are picked as before, and a config reload drops every pick.
+
+use anyhow::Result;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+};
+
+#[derive(Debug)]
+pub struct AsyncPickResult {
+    pub monitor: String,
+    pub result: Result<PathBuf>,
+    /// [`PrePicker::clear`] count when the pick was requested.
+    pub generation: u64,
+}
+
+pub struct PrePicker {
+    sender: Sender<AsyncPickResult>,
+    receiver: Receiver<AsyncPickResult>,
+    /// Directories each outstanding or arrived pick was made from.
+    requested: HashMap<String, Vec<PathBuf>>,
+    arrived: HashMap<String, Result<PathBuf>>,
+    generation: u64,
+}
+
+impl Default for PrePicker {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender,
+            receiver,
+            requested: HashMap::new(),
+            arrived: HashMap::new(),
+            generation: 0,
+        }
+    }
+}
+
+impl PrePicker {
+    /// Picks for each monitor from its directories on one background thread.
+    /// Monitors that already have a pick outstanding are left alone. Returns
+    /// the thread, if one was started.
+    pub fn request<F>(
+        &mut self,
+        jobs: Vec<(String, Vec<PathBuf>)>,
+        pick: F,
+    ) -> Option<JoinHandle<()>>
+    where
+        F: Fn(&[PathBuf]) -> Result<PathBuf> + Send + 'static,
+    {
+        let jobs: Vec<(String, Vec<PathBuf>)> = jobs
+            .into_iter()
+            .filter(|(monitor, _)| !self.requested.contains_key(monitor))
+            .collect();
+        if jobs.is_empty() {
+            return None;
+        }
+        self.requested.extend(jobs.iter().cloned());
+        let sender = self.sender.clone();
+        let generation = self.generation;
+        Some(thread::spawn(move || {
+            for (monitor, dirs) in jobs {
+                let result = pick(&dirs);
+                let pick = AsyncPickResult {
+                    monitor,
+                    result,
+                    generation,
+                };
+                if sender.send(pick).is_err() {
+                    return;
+                }
+            }
+        }))
+    }
+
+    /// The pick for `monitor`, if it has arrived, was made from `dirs` and
+    /// still exists. Any other pick is dropped.
+    pub fn take(&mut self, monitor: &str, dirs: &[PathBuf]) -> Option<Result<PathBuf>> {
+        let generation = self.generation;
+        self.arrived.extend(
+            self.receiver
+                .try_iter()
+                .filter(|pick| pick.generation == generation)
+                .map(|pick| (pick.monitor, pick.result)),
+        );
+        let result = self.arrived.remove(monitor)?;
+        let requested = self.requested.remove(monitor)?;
+        if requested != dirs {
+            return None;
+        }
+        match result {
+            // Deleted or moved while the daemon slept.
+            Ok(path) if !path.exists() => None,
+            result => Some(result),
+        }
+    }
+
+    /// Drops every pick, including those still being made, after the config
+    /// changed what may be picked.
+    pub fn clear(&mut self) {
+        self.generation += 1;
+        self.requested.clear();
+        self.arrived.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PrePicker;
+    use anyhow::anyhow;
+    use std::{fs, path::PathBuf};
+
+    #[test]
+    fn picks_arrive_per_monitor_for_the_directories_they_were_made_from() {
+        let temp = tempfile::tempdir().unwrap();
+        let walls = vec![temp.path().to_path_buf()];
+        let empty = vec![PathBuf::from("/empty")];
+        fs::write(temp.path().join("forest.png"), b"png").unwrap();
+        let pick = |dirs: &[PathBuf]| match dirs {
+            [dir] if dir.join("forest.png").exists() => Ok(dir.join("forest.png")),
+            _ => Err(anyhow!("no wallpapers")),
+        };
+        let jobs = |monitors: &[&str], dirs: &Vec<PathBuf>| -> Vec<(String, Vec<PathBuf>)> {
+            monitors
+                .iter()
+                .map(|monitor| (monitor.to_string(), dirs.clone()))
+                .collect()
+        };
+        let mut pre_picker = PrePicker::default();
+        let mut picks = jobs(&["DP-1", "HDMI-A-1", "eDP-1"], &walls);
+        picks.extend(jobs(&["DP-2"], &empty));
+        pre_picker.request(picks, pick).unwrap().join().unwrap();
+
+        let forest = temp.path().join("forest.png");
+        assert_eq!(pre_picker.take("DP-1", &walls).unwrap().unwrap(), forest);
+        assert!(pre_picker.take("DP-1", &walls).is_none());
+        assert!(pre_picker.take("DP-2", &empty).unwrap().is_err());
+        assert!(pre_picker.take("HDMI-A-1", &empty).is_none());
+        // The file went away after it was picked.
+        fs::remove_file(&forest).unwrap();
+        assert!(pre_picker.take("eDP-1", &walls).is_none());
+
+        // Picks from before a reload are dropped, even if they arrive after it.
+        fs::write(&forest, b"png").unwrap();
+        let stale = pre_picker.request(jobs(&["DP-1"], &walls), pick).unwrap();
+        pre_picker.clear();
+        stale.join().unwrap();
+        assert!(pre_picker.take("DP-1", &walls).is_none());
+        pre_picker.request(jobs(&["DP-1"], &walls), pick).unwrap().join().unwrap();
+        assert_eq!(pre_picker.take("DP-1", &walls).unwrap().unwrap(), forest);
+    }
+}
@@ -0,0 +1,327 @@
+//! Terminal thumbnails for `papdieo list --preview`.
+//!
+//! Thumbnails are generated on demand, one file at a time as the list is
+//! printed, and cached as PNGs under `$XDG_CACHE_HOME/papdieo/thumbs/`.
+//! Videos use their first decoded frame.
+
+use crate::formats;
+use crate::output::warning;
+use crate::wallpaper::is_video_file;
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_app as gst_app;
+use gstreamer_video as gst_video;
+use image::{imageops, imageops::FilterType, RgbaImage};
+use std::{
+    collections::hash_map::DefaultHasher,
+    env,
+    fmt::Write as _,
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// Cached thumbnails are at most this many pixels on their longer side.
+const THUMBNAIL_SIZE: u32 = 160;
+/// Width of block-character previews, in terminal columns.
+const BLOCK_COLUMNS: u32 = 32;
+const VIDEO_FRAME_TIMEOUT_SECONDS: u64 = 5;
+/// Terminals known to understand sixel graphics, matched against `$TERM` / `$TERM_PROGRAM`.
+const SIXEL_TERMINALS: [&str; 6] = ["foot", "mlterm", "yaft", "contour", "wezterm", "sixel"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PreviewMode {
+    None,
+    Sixel,
+    Block,
+}
+
+/// Falls back to block characters when sixel was requested but the terminal
+/// does not advertise it.
+pub fn resolve_mode(requested: PreviewMode) -> PreviewMode {
+    if requested == PreviewMode::Sixel && !terminal_supports_sixel() {
+        warning!("warning: terminal does not advertise sixel support; using block previews");
+        return PreviewMode::Block;
+    }
+    requested
+}
+
+fn terminal_supports_sixel() -> bool {
+    ["TERM", "TERM_PROGRAM"]
+        .iter()
+        .filter_map(|key| env::var(key).ok())
+        .map(|value| value.to_ascii_lowercase())
+        .any(|value| SIXEL_TERMINALS.iter().any(|known| value.contains(known)))
+}
+
+/// Thumbnail in the requested mode, ready to print after the file name.
+pub fn render(path: &Path, mode: PreviewMode) -> Result<String> {
+    let thumbnail = thumbnail(path)?;
+    Ok(match mode {
+        PreviewMode::None => String::new(),
+        PreviewMode::Sixel => encode_sixel(&thumbnail),
+        PreviewMode::Block => encode_blocks(&thumbnail, BLOCK_COLUMNS),
+    })
+}
+
+fn thumbnail(path: &Path) -> Result<RgbaImage> {
+    let cache_path = cache_dir().map(|dir| dir.join(format!("{:016x}.png", cache_key(path))));
+    if let Some(cached) = cache_path.as_ref().and_then(|p| image::open(p).ok()) {
+        return Ok(cached.to_rgba8());
+    }
+
+    let full = if is_video_file(path) {
+        first_video_frame(path)?
+    } else {
+        formats::open_image(path)?.to_rgba8()
+    };
+    let (width, height) = fit_within(full.width(), full.height(), THUMBNAIL_SIZE);
+    let thumbnail = imageops::resize(&full, width, height, FilterType::Triangle);
+
+    if let Some(cache_path) = cache_path {
+        if let Err(error) = save_thumbnail(&thumbnail, &cache_path) {
+            warning!("warning: failed to cache thumbnail {}: {:#}", cache_path.display(), error);
+        }
+    }
+    Ok(thumbnail)
+}
+
+fn save_thumbnail(thumbnail: &RgbaImage, path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    thumbnail.save(path)?;
+    Ok(())
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    let base = env::var("XDG_CACHE_HOME")
+        .ok()
+        .map(PathBuf::from)
+        .or_else(|| env::var("HOME").ok().map(|h| PathBuf::from(h).join(".cache")))?;
+    Some(base.join("papdieo").join("thumbs"))
+}
+
+/// Changes whenever the file is replaced or edited.
+fn cache_key(path: &Path) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    if let Ok(metadata) = fs::metadata(path) {
+        metadata.len().hash(&mut hasher);
+        metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .hash(&mut hasher);
+    }
+    THUMBNAIL_SIZE.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn fit_within(width: u32, height: u32, max: u32) -> (u32, u32) {
+    let scale = (max as f64 / width.max(height).max(1) as f64).min(1.0);
+    (
+        ((width as f64 * scale).round() as u32).max(1),
+        ((height as f64 * scale).round() as u32).max(1),
+    )
+}
+
+fn first_video_frame(path: &Path) -> Result<RgbaImage> {
+    gst::init().context("failed to initialize gstreamer")?;
+    let location = path
+        .to_str()
+        .ok_or_else(|| anyhow!("video path contains invalid UTF-8"))?
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"");
+    let pipeline = gst::parse::launch(&format!(
+        "filesrc location=\"{}\" ! decodebin ! videoconvert ! video/x-raw,format=RGBA ! appsink name=sink max-buffers=1",
+        location
+    ))
+    .context("failed to build thumbnail pipeline")?
+    .downcast::<gst::Pipeline>()
+    .map_err(|_| anyhow!("gstreamer element is not a pipeline"))?;
+    let sink = pipeline
+        .by_name("sink")
+        .ok_or_else(|| anyhow!("missing appsink in thumbnail pipeline"))?
+        .downcast::<gst_app::AppSink>()
+        .map_err(|_| anyhow!("sink is not an appsink"))?;
+
+    pipeline
+        .set_state(gst::State::Paused)
+        .context("failed to start thumbnail pipeline")?;
+    let sample = sink.try_pull_preroll(gst::ClockTime::from_seconds(VIDEO_FRAME_TIMEOUT_SECONDS));
+    pipeline.set_state(gst::State::Null).ok();
+    let sample = sample.ok_or_else(|| anyhow!("no video frame decoded: {}", path.display()))?;
+
+    let caps = sample
+        .caps()
+        .ok_or_else(|| anyhow!("video sample missing caps"))?;
+    let info = gst_video::VideoInfo::from_caps(caps)
+        .map_err(|_| anyhow!("failed to parse video caps"))?;
+    let buffer = sample
+        .buffer()
+        .ok_or_else(|| anyhow!("video sample missing buffer"))?;
+    let map = buffer
+        .map_readable()
+        .map_err(|_| anyhow!("failed to map video buffer"))?;
+
+    let row_bytes = info.width() as usize * 4;
+    let stride = info.stride()[0] as usize;
+    let mut pixels = Vec::with_capacity(row_bytes * info.height() as usize);
+    for row in 0..info.height() as usize {
+        let start = row * stride;
+        let src = map
+            .get(start..start + row_bytes)
+            .ok_or_else(|| anyhow!("video frame stride exceeds buffer"))?;
+        pixels.extend_from_slice(src);
+    }
+    RgbaImage::from_raw(info.width(), info.height(), pixels)
+        .ok_or_else(|| anyhow!("video frame size mismatch"))
+}
+
+/// Alpha is composited over black; terminals have no transparency here.
+fn opaque_rgb(px: &image::Rgba<u8>) -> [u8; 3] {
+    let alpha = px[3] as u16;
+    [0, 1, 2].map(|c| ((px[c] as u16 * alpha + 127) / 255) as u8)
+}
+
+/// Two image rows per terminal row using the upper half block: the
+/// foreground paints the top pixel, the background the bottom one.
+fn encode_blocks(image: &RgbaImage, columns: u32) -> String {
+    // Terminal cells are about twice as tall as wide, so two pixels per row keep the aspect.
+    let width = image.width().clamp(1, columns);
+    let height = ((image.height() as f64 * width as f64 / image.width().max(1) as f64).round()
+        as u32)
+        .max(1);
+    let small = imageops::resize(image, width, height, FilterType::Triangle);
+
+    let mut out = String::new();
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let [tr, tg, tb] = opaque_rgb(small.get_pixel(x, y));
+            let [br, bg, bb] = if y + 1 < height {
+                opaque_rgb(small.get_pixel(x, y + 1))
+            } else {
+                [0, 0, 0]
+            };
+            let _ = write!(
+                out,
+                "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}",
+                tr, tg, tb, br, bg, bb
+            );
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+/// Sixel image with a fixed 6x6x6 color cube palette.
+fn encode_sixel(image: &RgbaImage) -> String {
+    let (width, height) = image.dimensions();
+    let level = |v: u8| (v as u16 * 5 + 127) / 255;
+    let indices: Vec<u16> = image
+        .pixels()
+        .map(|px| {
+            let [r, g, b] = opaque_rgb(px);
+            level(r) * 36 + level(g) * 6 + level(b)
+        })
+        .collect();
+
+    let mut out = format!("\x1bPq\"1;1;{};{}", width, height);
+    let mut used = [false; 216];
+    for index in &indices {
+        used[*index as usize] = true;
+    }
+    for index in (0..216u16).filter(|i| used[*i as usize]) {
+        let percent = |step: u16| step * 100 / 5;
+        let _ = write!(
+            out,
+            "#{};2;{};{};{}",
+            index,
+            percent(index / 36),
+            percent(index / 6 % 6),
+            percent(index % 6)
+        );
+    }
+
+    for band in (0..height).step_by(6) {
+        let rows = (height - band).min(6);
+        let mut first_color = true;
+        for color in (0..216u16).filter(|i| used[*i as usize]) {
+            let sixels: Vec<u8> = (0..width)
+                .map(|x| {
+                    (0..rows).fold(0u8, |bits, row| {
+                        let index = indices[((band + row) * width + x) as usize];
+                        bits | (((index == color) as u8) << row)
+                    })
+                })
+                .collect();
+            if sixels.iter().all(|bits| *bits == 0) {
+                continue;
+            }
+            if !first_color {
+                out.push('$');
+            }
+            first_color = false;
+            let _ = write!(out, "#{}", color);
+            push_sixel_runs(&mut out, &sixels);
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\\n");
+    out
+}
+
+fn push_sixel_runs(out: &mut String, sixels: &[u8]) {
+    let mut i = 0;
+    while i < sixels.len() {
+        let run = sixels[i..].iter().take_while(|s| **s == sixels[i]).count();
+        let ch = (b'?' + sixels[i]) as char;
+        if run > 3 {
+            let _ = write!(out, "!{}{}", run, ch);
+        } else {
+            out.extend(std::iter::repeat_n(ch, run));
+        }
+        i += run;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_blocks, encode_sixel, fit_within};
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn thumbnails_keep_aspect_and_never_upscale() {
+        assert_eq!(fit_within(1920, 1080, 160), (160, 90));
+        assert_eq!(fit_within(1080, 1920, 160), (90, 160));
+        assert_eq!(fit_within(64, 32, 160), (64, 32));
+    }
+
+    #[test]
+    fn block_art_pairs_rows_with_half_blocks() {
+        let mut image = RgbaImage::new(1, 2);
+        image.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        image.put_pixel(0, 1, Rgba([0, 0, 255, 255]));
+
+        assert_eq!(
+            encode_blocks(&image, 1),
+            "\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m\u{2580}\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn sixel_uses_cube_palette_and_run_lengths() {
+        let mut image = RgbaImage::from_pixel(5, 1, Rgba([255, 0, 0, 255]));
+        image.put_pixel(4, 0, Rgba([0, 0, 255, 0]));
+
+        // Red is cube index 180, the transparent pixel becomes black (0).
+        assert_eq!(
+            encode_sixel(&image),
+            "\x1bPq\"1;1;5;1#0;2;0;0;0#180;2;100;0;0#0!4?@$#180!4@?-\x1b\\\n"
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! `papdieo profile`: alternative config files kept next to the main config as
+//! `profiles/<NAME>.toml`.
+//!
+//! `papdieo daemon --profile <NAME>` runs with one of them instead of the config
+//! file, and `papdieo profile activate <NAME>` leaves a request in the runtime
+//! directory that a running daemon picks up within a second.
+
+use crate::config::{self, CONFIG_TEMPLATE};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const PROFILE_EXTENSION: &str = "toml";
+
+/// `profiles` next to `config_path` (`--config`), or next to the default
+/// config file: `$XDG_CONFIG_HOME/papdieo/profiles` or `~/.config/papdieo/profiles`.
+pub fn profiles_dir(config_path: Option<&Path>) -> Option<PathBuf> {
+    let config_path = config_path
+        .map(Path::to_path_buf)
+        .or_else(config::default_config_path)?;
+    Some(config_path.parent()?.join("profiles"))
+}
+
+pub fn profile_path(config_path: Option<&Path>, name: &str) -> Result<PathBuf> {
+    let dir = profiles_dir(config_path)
+        .ok_or_else(|| anyhow!("could not determine profiles directory"))?;
+    profile_path_in(&dir, name)
+}
+
+/// The config file the daemon should read: the profile's file when one is
+/// given (it must exist), otherwise `config_path`.
+pub fn resolve_config_path(
+    config_path: Option<&Path>,
+    profile: Option<&str>,
+) -> Result<Option<PathBuf>> {
+    let Some(name) = profile else {
+        return Ok(config_path.map(Path::to_path_buf));
+    };
+    let path = profile_path(config_path, name)?;
+    if !path.is_file() {
+        return Err(anyhow!(
+            "profile '{}' does not exist ({}); create it with `papdieo profile create {}`",
+            name,
+            path.display(),
+            name
+        ));
+    }
+    Ok(Some(path))
+}
+
+pub fn list(config_path: Option<&Path>) -> Result<Vec<String>> {
+    match profiles_dir(config_path) {
+        Some(dir) => list_in(&dir),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Copies `source` (the current config) or, without one, the default
+/// template into the profiles directory next to `source`.
+pub fn create(name: &str, source: Option<&Path>) -> Result<PathBuf> {
+    let dir =
+        profiles_dir(source).ok_or_else(|| anyhow!("could not determine profiles directory"))?;
+    create_in(&dir, name, source)
+}
+
+fn profile_path_in(dir: &Path, name: &str) -> Result<PathBuf> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && !name.contains(['/', '\\'])
+        && !name.chars().any(char::is_control);
+    if !valid {
+        return Err(anyhow!("invalid profile name '{}'", name));
+    }
+    Ok(dir.join(format!("{}.{}", name, PROFILE_EXTENSION)))
+}
+
+fn list_in(dir: &Path) -> Result<Vec<String>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => {
+            return Err(error).with_context(|| format!("failed to read {}", dir.display()))
+        }
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| path.extension().and_then(|e| e.to_str()) == Some(PROFILE_EXTENSION))
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+fn create_in(dir: &Path, name: &str, source: Option<&Path>) -> Result<PathBuf> {
+    let path = profile_path_in(dir, name)?;
+    if path.exists() {
+        return Err(anyhow!("profile '{}' already exists: {}", name, path.display()));
+    }
+    let content = match source.filter(|source| source.is_file()) {
+        Some(source) => fs::read_to_string(source)
+            .with_context(|| format!("failed to read {}", source.display()))?,
+        None => CONFIG_TEMPLATE.to_string(),
+    };
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    fs::write(&path, content).with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{create_in, list_in, profile_path_in, profiles_dir, resolve_config_path};
+    use crate::config::CONFIG_TEMPLATE;
+    use std::fs;
+
+    #[test]
+    fn profiles_are_created_from_the_config_and_listed_by_name() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let dir = root.join("profiles");
+        let config = root.join("config.toml");
+        fs::write(&config, "video_fps = 24\n").unwrap();
+
+        let empty = list_in(&dir).unwrap();
+        let work = create_in(&dir, "work", Some(&config)).unwrap();
+        let gaming = create_in(&dir, "gaming", None).unwrap();
+        let duplicate = create_in(&dir, "work", None);
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        let names = list_in(&dir).unwrap();
+        let work_content = fs::read_to_string(&work).unwrap();
+        let gaming_content = fs::read_to_string(&gaming).unwrap();
+
+        assert!(empty.is_empty());
+        assert_eq!(work, dir.join("work.toml"));
+        assert_eq!(work_content, "video_fps = 24\n");
+        assert_eq!(gaming_content, CONFIG_TEMPLATE);
+        assert!(duplicate.is_err());
+        assert_eq!(names, ["gaming", "work"]);
+        for bad in ["", "../work", ".hidden", "a/b"] {
+            assert!(profile_path_in(&dir, bad).is_err(), "{:?} should be rejected", bad);
+        }
+    }
+
+    #[test]
+    fn profiles_live_next_to_the_config_in_use() {
+        let temp = tempfile::tempdir().unwrap();
+        let config = temp.path().join("custom.toml");
+        fs::write(&config, "").unwrap();
+
+        assert_eq!(profiles_dir(Some(&config)), Some(temp.path().join("profiles")));
+        assert!(resolve_config_path(Some(&config), Some("work")).is_err());
+        create_in(&temp.path().join("profiles"), "work", None).unwrap();
+        let resolved = resolve_config_path(Some(&config), Some("work")).unwrap();
+        assert_eq!(resolved, Some(temp.path().join("profiles").join("work.toml")));
+    }
+}
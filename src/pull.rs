@@ -0,0 +1,192 @@
+//! `papdieo pull <URL>`: downloads a wallpaper into the library.
+//!
+//! Like `--cache-stream`, the download goes through curl straight into a
+//! partial file next to the destination, so nothing is buffered in memory.
+//! The file name comes from `Content-Disposition` when the server sends one,
+//! otherwise from the last segment of the URL path.
+
+use crate::{output, picker};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    fs,
+    io::{IsTerminal, Read},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+const DOWNLOAD_TIMEOUT_SECONDS: u32 = 600;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Downloads `url` into `dir` and returns the saved file.
+pub fn pull(url: &str, dir: &Path) -> Result<PathBuf> {
+    let lower = url.to_ascii_lowercase();
+    if !lower.starts_with("http://") && !lower.starts_with("https://") {
+        return Err(anyhow!("only http(s) URLs can be pulled: {}", url));
+    }
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+
+    let partial = dir.join(format!(".papdieo-pull-{}.part", std::process::id()));
+    let result = download(url, &partial).and_then(|headers| {
+        let name = filename_from_content_disposition(&headers)
+            .or_else(|| filename_from_url(url))
+            .ok_or_else(|| anyhow!("could not tell a file name from {}", url))?;
+        let target = dir.join(&name);
+        if !picker::is_supported_media(&target) {
+            return Err(anyhow!("'{}' is not a supported image or video format", name));
+        }
+        if target.exists() {
+            return Err(anyhow!("{} already exists", target.display()));
+        }
+        fs::rename(&partial, &target)
+            .with_context(|| format!("failed to write {}", target.display()))?;
+        Ok(target)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Runs curl with the body going to `partial` and returns the response headers.
+fn download(url: &str, partial: &Path) -> Result<String> {
+    let mut child = Command::new("curl")
+        .args(["-sSfL", "--max-time"])
+        .arg(DOWNLOAD_TIMEOUT_SECONDS.to_string())
+        .args(["-D", "-", "-o"])
+        .arg(partial)
+        .arg(url)
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("failed to run curl to download the wallpaper")?;
+
+    let mut stdout = child.stdout.take().expect("curl stdout is piped");
+    let headers = thread::spawn(move || {
+        let mut headers = String::new();
+        let _ = stdout.read_to_string(&mut headers);
+        headers
+    });
+
+    let show_progress = !output::is_quiet() && std::io::stderr().is_terminal();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if show_progress {
+            let bytes = fs::metadata(partial).map(|meta| meta.len()).unwrap_or(0);
+            eprint!("\rdownloaded {}", format_bytes(bytes));
+        }
+        thread::sleep(PROGRESS_INTERVAL);
+    };
+    if show_progress {
+        let bytes = fs::metadata(partial).map(|meta| meta.len()).unwrap_or(0);
+        eprintln!("\rdownloaded {}", format_bytes(bytes));
+    }
+
+    let headers = headers.join().unwrap_or_default();
+    if !status.success() {
+        return Err(anyhow!("failed to download {} ({})", url, status));
+    }
+    Ok(headers)
+}
+
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1_048_575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+/// The `filename*=` or `filename=` parameter of the final response's
+/// `Content-Disposition`, without any directory part.
+fn filename_from_content_disposition(headers: &str) -> Option<String> {
+    let last_response = headers.rsplit("HTTP/").next().unwrap_or(headers);
+    let value = last_response.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("content-disposition")
+            .then(|| value.trim().to_string())
+    })?;
+
+    let mut plain = None;
+    for parameter in value.split(';').map(str::trim) {
+        let Some((key, raw)) = parameter.split_once('=') else {
+            continue;
+        };
+        match key.trim().to_ascii_lowercase().as_str() {
+            // RFC 5987: charset'language'percent-encoded-name
+            "filename*" => {
+                let encoded = raw.trim().rsplit('\'').next()?;
+                return sanitize_filename(&percent_decode(encoded));
+            }
+            "filename" => plain = Some(raw.trim().trim_matches('"').to_string()),
+            _ => {}
+        }
+    }
+    sanitize_filename(&plain?)
+}
+
+fn filename_from_url(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next()?;
+    let after_scheme = path.split_once("://").map(|(_, rest)| rest)?;
+    let (_, path) = after_scheme.split_once('/')?;
+    sanitize_filename(&percent_decode(path.rsplit('/').next()?))
+}
+
+/// Keeps the last path component only, so a server cannot write outside the library.
+fn sanitize_filename(name: &str) -> Option<String> {
+    let name = Path::new(name.trim()).file_name()?.to_str()?;
+    (!name.starts_with('.')).then(|| name.to_string())
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| value.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{filename_from_content_disposition, filename_from_url};
+
+    #[test]
+    fn file_names_come_from_the_header_or_the_url() {
+        let headers = "HTTP/1.1 302 Found\r\nContent-Disposition: attachment; filename=\"old.png\"\r\n\r\nHTTP/2 200\r\ncontent-disposition: attachment; filename=\"../../night sky.jpg\"\r\n\r\n";
+        assert_eq!(
+            filename_from_content_disposition(headers).as_deref(),
+            Some("night sky.jpg")
+        );
+        let encoded = "HTTP/2 200\r\nContent-Disposition: attachment; filename=\"fallback.png\"; filename*=UTF-8''caf%C3%A9.webp\r\n\r\n";
+        assert_eq!(
+            filename_from_content_disposition(encoded).as_deref(),
+            Some("café.webp")
+        );
+        assert_eq!(filename_from_content_disposition("HTTP/2 200\r\n\r\n"), None);
+
+        assert_eq!(
+            filename_from_url("https://example.com/walls/forest%20mist.png?w=3840#top").as_deref(),
+            Some("forest mist.png")
+        );
+        assert_eq!(filename_from_url("https://example.com/"), None);
+        assert_eq!(filename_from_url("https://example.com"), None);
+    }
+}
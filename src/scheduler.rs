@@ -0,0 +1,249 @@
+//! `schedule` rules: cron expressions that switch the daemon to another
+//! wallpaper directory (or a single wallpaper) when they fire.
+//!
+//! A rule stays in effect from its trigger until another rule fires, so
+//! `0 9 * * 1-5` + `0 9 * * 0,6` means "weekdays from 9:00 on one set, weekends
+//! the other".
+
+use crate::config::ScheduleRule;
+use crate::output::warning;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use cron::Schedule;
+use std::{path::Path, str::FromStr};
+
+/// What a rule switches the daemon to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleTarget<'a> {
+    Dir(&'a Path),
+    Path(&'a Path),
+}
+
+impl ScheduleTarget<'_> {
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Dir(dir) => format!("dir {}", dir.display()),
+            Self::Path(path) => format!("path {}", path.display()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompiledRule {
+    pub rule: ScheduleRule,
+    schedule: Schedule,
+}
+
+impl CompiledRule {
+    pub fn new(rule: &ScheduleRule) -> Result<Self> {
+        rule_target(rule)?;
+        Ok(Self {
+            schedule: parse_cron(&rule.cron)?,
+            rule: rule.clone(),
+        })
+    }
+
+    pub fn target(&self) -> ScheduleTarget<'_> {
+        rule_target(&self.rule).expect("validated in CompiledRule::new")
+    }
+
+    fn previous_trigger(&self, now: &DateTime<Local>) -> Option<DateTime<Local>> {
+        self.schedule.after(now).next_back()
+    }
+}
+
+/// Compiles every valid rule, warning about (and skipping) the rest.
+pub fn compile_rules(rules: &[ScheduleRule]) -> Vec<CompiledRule> {
+    rules
+        .iter()
+        .filter_map(|rule| match CompiledRule::new(rule) {
+            Ok(compiled) => Some(compiled),
+            Err(error) => {
+                warning!("warning: ignoring schedule rule '{}': {}", rule.cron, error);
+                None
+            }
+        })
+        .collect()
+}
+
+/// The rule that fired most recently, if any has fired yet.
+pub fn active_rule<'a>(
+    rules: &'a [CompiledRule],
+    now: &DateTime<Local>,
+) -> Option<&'a CompiledRule> {
+    rules
+        .iter()
+        .filter_map(|rule| rule.previous_trigger(now).map(|at| (at, rule)))
+        .max_by_key(|(at, _)| *at)
+        .map(|(_, rule)| rule)
+}
+
+/// The next `count` triggers across all rules, in time order.
+pub fn upcoming<'a>(
+    rules: &'a [CompiledRule],
+    now: &DateTime<Local>,
+    count: usize,
+) -> Vec<(DateTime<Local>, &'a CompiledRule)> {
+    let mut triggers: Vec<_> = rules
+        .iter()
+        .flat_map(|rule| rule.schedule.after(now).take(count).map(move |at| (at, rule)))
+        .collect();
+    triggers.sort_by_key(|(at, _)| *at);
+    triggers.truncate(count);
+    triggers
+}
+
+pub fn next_trigger(rules: &[CompiledRule], now: &DateTime<Local>) -> Option<DateTime<Local>> {
+    upcoming(rules, now, 1).first().map(|(at, _)| *at)
+}
+
+/// Parses a standard five-field crontab expression (minute hour day month weekday).
+pub fn parse_cron(expression: &str) -> Result<Schedule> {
+    let fields: Vec<&str> = expression.split_whitespace().collect();
+    let [minute, hour, day, month, weekday] = fields.as_slice() else {
+        return Err(anyhow!(
+            "expected 5 fields (minute hour day month weekday), got {}",
+            fields.len()
+        ));
+    };
+
+    // The cron crate has a leading seconds field and numbers weekdays 1 (Sunday)
+    // to 7 (Saturday); crontab uses 0 or 7 for Sunday.
+    let weekday = crontab_weekday_to_cron(weekday)?;
+    Schedule::from_str(&format!(
+        "0 {} {} {} {} {}",
+        minute, hour, day, month, weekday
+    ))
+    .map_err(|error| anyhow!("invalid cron expression '{}': {}", expression, error))
+}
+
+fn crontab_weekday_to_cron(field: &str) -> Result<String> {
+    let shift = |day: &str| -> Result<Option<u8>> {
+        if !day.bytes().all(|b| b.is_ascii_digit()) {
+            return Ok(None);
+        }
+        match day.parse::<u8>() {
+            Ok(day @ 0..=7) => Ok(Some(day % 7 + 1)),
+            _ => Err(anyhow!("weekday must be between 0 and 7, got {}", day)),
+        }
+    };
+
+    let mut items = Vec::new();
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, Some(step)),
+            None => (item, None),
+        };
+
+        match range.split_once('-') {
+            // Spelled out day by day: a range ending on Sunday (7) ends on the
+            // cron crate's day 1, which no cron range can express.
+            Some((start, end)) if shift(start)?.is_some() && shift(end)?.is_some() => {
+                let (start, end): (u8, u8) = (start.parse()?, end.parse()?);
+                if start > end {
+                    return Err(anyhow!("weekday range {} runs backwards", range));
+                }
+                let step = match step.map(str::parse::<usize>) {
+                    None => 1,
+                    Some(Ok(step)) if step > 0 => step,
+                    Some(_) => return Err(anyhow!("invalid weekday step in {}", item)),
+                };
+                for day in (start..=end).step_by(step) {
+                    let day = (day % 7 + 1).to_string();
+                    if !items.contains(&day) {
+                        items.push(day);
+                    }
+                }
+            }
+            Some(_) => items.push(item.to_string()),
+            None => match (shift(range)?, step) {
+                (Some(day), Some(step)) => items.push(format!("{}/{}", day, step)),
+                (Some(day), None) => items.push(day.to_string()),
+                (None, _) => items.push(item.to_string()),
+            },
+        }
+    }
+    Ok(items.join(","))
+}
+
+fn rule_target(rule: &ScheduleRule) -> Result<ScheduleTarget<'_>> {
+    match (&rule.dir, &rule.path) {
+        (Some(dir), None) => Ok(ScheduleTarget::Dir(dir)),
+        (None, Some(path)) => Ok(ScheduleTarget::Path(path)),
+        (Some(_), Some(_)) => Err(anyhow!("set either dir or path, not both")),
+        (None, None) => Err(anyhow!("missing dir or path")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{active_rule, compile_rules, next_trigger, parse_cron, ScheduleTarget};
+    use crate::config::ScheduleRule;
+    use chrono::{Datelike, Local, TimeZone, Timelike, Weekday};
+    use std::path::{Path, PathBuf};
+
+    fn rule(cron: &str, dir: &str) -> ScheduleRule {
+        ScheduleRule {
+            cron: cron.to_string(),
+            dir: Some(PathBuf::from(dir)),
+            path: None,
+        }
+    }
+
+    #[test]
+    fn crontab_weekdays_follow_monday_equals_one() {
+        let weekdays = parse_cron("0 9 * * 1-5").unwrap();
+        // 2026-10-17 is a Saturday.
+        let saturday = Local.with_ymd_and_hms(2026, 10, 17, 12, 0, 0).unwrap();
+        let next = weekdays.after(&saturday).next().unwrap();
+        assert_eq!(next.weekday(), Weekday::Mon);
+        assert_eq!((next.hour(), next.minute()), (9, 0));
+
+        let weekend = parse_cron("30 8 * * 6-7").unwrap();
+        let days: Vec<Weekday> = weekend.after(&saturday).take(3).map(|at| at.weekday()).collect();
+        assert_eq!(days, vec![Weekday::Sun, Weekday::Sat, Weekday::Sun]);
+
+        // Stepped ranges ending on Sunday: Monday, Wednesday, Friday, Sunday.
+        let monday = Local.with_ymd_and_hms(2026, 10, 19, 12, 0, 0).unwrap();
+        let stepped = parse_cron("0 9 * * 1-7/2").unwrap();
+        let days: Vec<Weekday> = stepped.after(&monday).take(4).map(|at| at.weekday()).collect();
+        assert_eq!(days, vec![Weekday::Wed, Weekday::Fri, Weekday::Sun, Weekday::Mon]);
+        let sundays = parse_cron("0 9 * * 0-7/7").unwrap();
+        let days: Vec<Weekday> = sundays.after(&monday).take(2).map(|at| at.weekday()).collect();
+        assert_eq!(days, vec![Weekday::Sun, Weekday::Sun]);
+
+        assert!(parse_cron("0 9 * * 5-1").is_err());
+        assert!(parse_cron("0 9 * * 1-5/0").is_err());
+        assert!(parse_cron("0 9 * *").is_err());
+        assert!(parse_cron("0 9 * * 8").is_err());
+    }
+
+    #[test]
+    fn most_recent_trigger_wins_until_the_next_one() {
+        let rules = compile_rules(&[
+            rule("0 9 * * 1-5", "/walls/work"),
+            rule("0 9 * * 0,6", "/walls/relax"),
+            ScheduleRule {
+                cron: "0 9 * * *".to_string(),
+                dir: None,
+                path: None,
+            },
+        ]);
+        assert_eq!(rules.len(), 2);
+
+        // Friday evening: the weekday rule fired this morning.
+        let friday = Local.with_ymd_and_hms(2026, 10, 16, 18, 0, 0).unwrap();
+        assert_eq!(
+            active_rule(&rules, &friday).unwrap().target(),
+            ScheduleTarget::Dir(Path::new("/walls/work"))
+        );
+        let next = next_trigger(&rules, &friday).unwrap();
+        assert_eq!((next.weekday(), next.hour()), (Weekday::Sat, 9));
+
+        let saturday = Local.with_ymd_and_hms(2026, 10, 17, 9, 30, 0).unwrap();
+        assert_eq!(
+            active_rule(&rules, &saturday).unwrap().target(),
+            ScheduleTarget::Dir(Path::new("/walls/relax"))
+        );
+    }
+}
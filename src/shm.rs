@@ -0,0 +1,211 @@
+//! Shared-memory frame buffers that outlive a single renderer.
+//!
+//! The daemon renders every monitor on its own thread and replaces that
+//! renderer on each rotation. Rather than creating, sizing and mapping fresh
+//! buffer files every time, a finished renderer parks its buffers in
+//! [`ShmPoolRegistry`] and the next renderer of the same frame size, on any
+//! monitor, takes them back. At most `max_pooled_buffers` are kept, and only
+//! buffers the compositor has released are parked.
+
+use anyhow::{Context, Result};
+use memmap2::MmapMut;
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    process,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+/// Default `max_pooled_buffers`: both buffers of two monitors.
+pub const DEFAULT_MAX_POOLED_BUFFERS: usize = 4;
+
+static BUFFER_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// An unlinked temp file and its mapping, ready to back a `wl_shm_pool`.
+pub struct ShmBuffer {
+    pub file: File,
+    pub mmap: MmapMut,
+}
+
+impl ShmBuffer {
+    pub fn create(size: usize) -> Result<Self> {
+        let unique_id = BUFFER_FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let buffer_path =
+            std::env::temp_dir().join(format!("papdieo-buffer-{}-{}", process::id(), unique_id));
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&buffer_path)
+            .context("failed to create shared memory buffer file")?;
+        file.set_len(size as u64)?;
+        let _ = std::fs::remove_file(&buffer_path);
+
+        let mmap = unsafe { MmapMut::map_mut(&file) }.context("failed to map shared memory")?;
+        Ok(Self { file, mmap })
+    }
+}
+
+/// A buffer waiting in the pool, with the monitor whose renderer parked it.
+struct ParkedBuffer {
+    monitor: Option<String>,
+    buffer: ShmBuffer,
+}
+
+/// Parked XRGB8888 frame buffers by `(width, height)`.
+pub struct ShmPoolRegistry {
+    parked: Mutex<HashMap<(u32, u32), Vec<ParkedBuffer>>>,
+    capacity: AtomicUsize,
+}
+
+impl Default for ShmPoolRegistry {
+    fn default() -> Self {
+        Self {
+            parked: Mutex::default(),
+            capacity: AtomicUsize::new(DEFAULT_MAX_POOLED_BUFFERS),
+        }
+    }
+}
+
+impl ShmPoolRegistry {
+    pub fn global() -> &'static Self {
+        static REGISTRY: OnceLock<ShmPoolRegistry> = OnceLock::new();
+        REGISTRY.get_or_init(Self::default)
+    }
+
+    /// `max_pooled_buffers`; 0 frees every buffer as its renderer ends.
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+        let mut parked = self.parked.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        evict(&mut parked, capacity, None);
+    }
+
+    /// `count` frame buffers of `width`×`height`, reusing parked ones of that
+    /// size and creating the rest.
+    pub fn take(&self, (width, height): (u32, u32), count: usize) -> Result<Vec<ShmBuffer>> {
+        let mut buffers = {
+            let mut parked = self.parked.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let reused = parked.get_mut(&(width, height)).map(|buffers| {
+                let keep = buffers.len().saturating_sub(count);
+                buffers.split_off(keep)
+            });
+            parked.retain(|_, buffers| !buffers.is_empty());
+            reused.into_iter().flatten().map(|parked| parked.buffer).collect::<Vec<_>>()
+        };
+        let size = width as usize * height as usize * 4;
+        while buffers.len() < count {
+            buffers.push(ShmBuffer::create(size)?);
+        }
+        Ok(buffers)
+    }
+
+    /// Parks `buffers` of `monitor`'s renderer for the next renderer of
+    /// `width`×`height`. Over the capacity, buffers of other sizes are freed
+    /// first since their resolution is less likely to come back.
+    pub fn park(&self, size: (u32, u32), monitor: Option<&str>, buffers: Vec<ShmBuffer>) {
+        let mut parked = self.parked.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        parked
+            .entry(size)
+            .or_default()
+            .extend(buffers.into_iter().map(|buffer| ParkedBuffer {
+                monitor: monitor.map(str::to_string),
+                buffer,
+            }));
+        evict(&mut parked, self.capacity.load(Ordering::Relaxed), Some(size));
+    }
+
+    /// Frees the buffers parked by `monitor`'s renderers, once the monitor is gone.
+    pub fn forget_monitor(&self, monitor: &str) {
+        let mut parked = self.parked.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        for buffers in parked.values_mut() {
+            buffers.retain(|parked| parked.monitor.as_deref() != Some(monitor));
+        }
+        parked.retain(|_, buffers| !buffers.is_empty());
+    }
+}
+
+/// Frees buffers until at most `capacity` are parked, those of `keep` last.
+fn evict(
+    parked: &mut HashMap<(u32, u32), Vec<ParkedBuffer>>,
+    capacity: usize,
+    keep: Option<(u32, u32)>,
+) {
+    let mut excess = parked.values().map(Vec::len).sum::<usize>().saturating_sub(capacity);
+    let mut sizes: Vec<(u32, u32)> = parked.keys().copied().filter(|s| Some(*s) != keep).collect();
+    sizes.extend(keep);
+    for size in sizes {
+        let Some(buffers) = parked.get_mut(&size) else {
+            continue;
+        };
+        let freed = excess.min(buffers.len());
+        buffers.truncate(buffers.len() - freed);
+        excess -= freed;
+    }
+    parked.retain(|_, buffers| !buffers.is_empty());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ShmPoolRegistry;
+
+    #[test]
+    fn parked_buffers_are_reused_only_at_the_same_size() {
+        let registry = ShmPoolRegistry::default();
+        let mut buffers = registry.take((4, 4), 2).unwrap();
+        buffers[0].mmap[0] = 7;
+        registry.park((4, 4), None, buffers);
+
+        assert_eq!(registry.take((8, 2), 2).unwrap()[0].mmap[0], 0);
+        let reused = registry.take((4, 4), 2).unwrap();
+        assert_eq!(reused.iter().map(|b| b.mmap[0]).max(), Some(7));
+
+        registry.park((4, 4), None, reused);
+        let resized = registry.take((8, 4), 2).unwrap();
+        assert_eq!(resized[0].mmap.len(), 128);
+        assert_eq!(resized[0].mmap[0], 0);
+    }
+
+    #[test]
+    fn the_pool_keeps_at_most_its_capacity() {
+        let registry = ShmPoolRegistry::default();
+        let take_marked = |size, count, mark| {
+            let mut buffers = registry.take(size, count).unwrap();
+            let reused = buffers.iter().filter(|b| b.mmap[0] == mark).count();
+            buffers.iter_mut().for_each(|b| b.mmap[0] = mark);
+            (buffers, reused)
+        };
+        let (small, _) = take_marked((2, 2), 2, 1);
+        registry.park((2, 2), None, small);
+        let (large, _) = take_marked((4, 4), 3, 2);
+        registry.park((4, 4), None, large);
+
+        // Only four fit: a small buffer made room for the third large one.
+        assert_eq!(take_marked((2, 2), 2, 1).1, 1);
+        let (large, reused) = take_marked((4, 4), 3, 2);
+        assert_eq!(reused, 3);
+
+        registry.park((4, 4), None, large);
+        registry.set_capacity(0);
+        assert_eq!(take_marked((4, 4), 1, 2).1, 0);
+    }
+
+    #[test]
+    fn buffers_of_a_removed_monitor_are_freed() {
+        let registry = ShmPoolRegistry::default();
+        let mut buffers = registry.take((4, 4), 2).unwrap();
+        buffers[0].mmap[0] = 1;
+        buffers[1].mmap[0] = 2;
+        let second = buffers.split_off(1);
+        registry.park((4, 4), Some("DP-1"), buffers);
+        registry.park((4, 4), Some("DP-2"), second);
+
+        registry.forget_monitor("DP-1");
+        let left: Vec<u8> = registry.take((4, 4), 2).unwrap().iter().map(|b| b.mmap[0]).collect();
+        assert!(left.contains(&2) && !left.contains(&1), "{:?}", left);
+    }
+}
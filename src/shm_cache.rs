@@ -0,0 +1,186 @@
+//! `set --cache-key`: fitted image frames shared between renderers.
+//!
+//! Renderers started for several monitors with the same wallpaper would each
+//! decode and scale it. With a cache key, the first renderer to finish a frame
+//! writes it to `papdieo-frame-<key>-<width>-<height>-<fit>` in the runtime
+//! directory (tmpfs in a user session), and a renderer for an output of the
+//! same size maps that file instead of decoding. Frames are written to a
+//! temporary file and renamed into place, so a reader never maps a partial one.
+//!
+//! Frames are only shared through a `$XDG_RUNTIME_DIR` that is private to the
+//! user, and only files the user owns are mapped: anyone can create files in
+//! `/tmp` under the names a renderer would look for.
+
+use crate::config::FitMode;
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use image::RgbaImage;
+use memmap2::Mmap;
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::Write,
+    os::unix::fs::{MetadataExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    process,
+};
+
+const MAGIC: &[u8; 8] = b"PAPDFRM1";
+/// Magic, then width and height as little-endian `u32`s.
+const HEADER_LEN: usize = 16;
+
+/// A cached RGBA frame, mapped read-only.
+pub struct CachedFrame {
+    mmap: Mmap,
+}
+
+impl CachedFrame {
+    pub fn rgba(&self) -> &[u8] {
+        &self.mmap[HEADER_LEN..]
+    }
+}
+
+/// The frame cached under `key` for this size and fit, if a valid one exists.
+pub fn load(key: &str, width: u32, height: u32, fit: FitMode) -> Option<CachedFrame> {
+    load_in(&cache_dir()?, key, width, height, fit)
+}
+
+fn load_in(dir: &Path, key: &str, width: u32, height: u32, fit: FitMode) -> Option<CachedFrame> {
+    if !is_private_dir(dir) {
+        return None;
+    }
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(frame_path(dir, key, width, height, fit))
+        .ok()?;
+    let expected_len = HEADER_LEN + width as usize * height as usize * 4;
+    let metadata = file.metadata().ok()?;
+    let trusted = metadata.is_file()
+        && metadata.uid() == current_uid()
+        && metadata.mode() & 0o022 == 0
+        && metadata.len() == expected_len as u64;
+    if !trusted {
+        return None;
+    }
+    // Writers only ever rename a finished file over this one, never change it in place.
+    let mmap = unsafe { Mmap::map(&file) }.ok()?;
+    let valid = mmap.len() == expected_len
+        && &mmap[..8] == MAGIC
+        && mmap[8..12] == width.to_le_bytes()
+        && mmap[12..16] == height.to_le_bytes();
+    valid.then_some(CachedFrame { mmap })
+}
+
+pub fn store(key: &str, fit: FitMode, frame: &RgbaImage) -> Result<()> {
+    let dir = cache_dir().ok_or_else(|| {
+        anyhow!("frames are not cached without a private $XDG_RUNTIME_DIR (0700, owned by you)")
+    })?;
+    store_in(&dir, key, fit, frame)
+}
+
+fn store_in(dir: &Path, key: &str, fit: FitMode, frame: &RgbaImage) -> Result<()> {
+    if !is_private_dir(dir) {
+        return Err(anyhow!("not caching frames in {}: it is not private", dir.display()));
+    }
+    let path = frame_path(dir, key, frame.width(), frame.height(), fit);
+    let tmp_path = path.with_extension(format!("{}.tmp", process::id()));
+    let mut content = Vec::with_capacity(HEADER_LEN + frame.as_raw().len());
+    content.extend_from_slice(MAGIC);
+    content.extend_from_slice(&frame.width().to_le_bytes());
+    content.extend_from_slice(&frame.height().to_le_bytes());
+    content.extend_from_slice(frame.as_raw());
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(&tmp_path)
+        .and_then(|mut file| file.write_all(&content))
+        .with_context(|| format!("failed to write cached frame: {}", tmp_path.display()))?;
+    fs::rename(&tmp_path, &path)
+        .with_context(|| format!("failed to write cached frame: {}", path.display()))?;
+    Ok(())
+}
+
+/// `$XDG_RUNTIME_DIR`, without the usual `/tmp` fallback.
+fn cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from)
+}
+
+/// A real directory (not a symlink) owned by the user, which nobody else can
+/// read, write or enter.
+fn is_private_dir(dir: &Path) -> bool {
+    fs::symlink_metadata(dir).is_ok_and(|metadata| {
+        metadata.is_dir() && metadata.uid() == current_uid() && metadata.mode() & 0o077 == 0
+    })
+}
+
+fn current_uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+fn frame_path(dir: &Path, key: &str, width: u32, height: u32, fit: FitMode) -> PathBuf {
+    let fit = fit
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default();
+    dir.join(format!(
+        "papdieo-frame-{}-{}-{}-{}",
+        key.trim().replace(['/', '.'], "_"),
+        width,
+        height,
+        fit
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{frame_path, load_in, store_in};
+    use crate::config::FitMode;
+    use image::{Rgba, RgbaImage};
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    #[test]
+    fn frames_are_shared_per_key_size_and_fit() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700)).unwrap();
+        let frame = RgbaImage::from_pixel(4, 3, Rgba([10, 20, 30, 255]));
+        store_in(dir, "forest", FitMode::Cover, &frame).unwrap();
+
+        let cached = load_in(dir, "forest", 4, 3, FitMode::Cover).expect("frame is cached");
+        assert_eq!(cached.rgba(), frame.as_raw().as_slice());
+        assert!(load_in(dir, "forest", 3, 4, FitMode::Cover).is_none());
+        assert!(load_in(dir, "forest", 4, 3, FitMode::Contain).is_none());
+        assert!(load_in(dir, "desert", 4, 3, FitMode::Cover).is_none());
+
+        let path = frame_path(dir, "forest", 4, 3, FitMode::Cover);
+        let mut corrupt = fs::read(&path).unwrap();
+        corrupt[0] = b'X';
+        fs::write(&path, &corrupt).unwrap();
+        assert!(load_in(dir, "forest", 4, 3, FitMode::Cover).is_none());
+        fs::write(&path, &corrupt[..20]).unwrap();
+        assert!(load_in(dir, "forest", 4, 3, FitMode::Cover).is_none());
+    }
+
+    #[test]
+    fn frames_are_only_mapped_from_private_files_and_dirs() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700)).unwrap();
+        let frame = RgbaImage::from_pixel(2, 2, Rgba([1, 2, 3, 255]));
+        store_in(dir, "lake", FitMode::Cover, &frame).unwrap();
+        assert!(load_in(dir, "lake", 2, 2, FitMode::Cover).is_some());
+
+        let path = frame_path(dir, "lake", 2, 2, FitMode::Cover);
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o666)).unwrap();
+        assert!(load_in(dir, "lake", 2, 2, FitMode::Cover).is_none());
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(load_in(dir, "lake", 2, 2, FitMode::Cover).is_none());
+        assert!(store_in(dir, "lake", FitMode::Cover, &frame).is_err());
+    }
+}
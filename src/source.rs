@@ -0,0 +1,212 @@
+use crate::state::runtime_dir;
+use anyhow::{anyhow, Context, Result};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const REACHABILITY_TIMEOUT_SECONDS: u32 = 5;
+const STREAM_DOWNLOAD_TIMEOUT_SECONDS: u32 = 600;
+/// Larger (or unknown-size) HTTP videos are streamed instead of cached.
+const STREAM_CACHE_MAX_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Where a wallpaper comes from. Anything that is not a recognised URL is a file path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WallpaperSource {
+    File(PathBuf),
+    /// Progressive HTTP(S) video file.
+    Http(String),
+    Rtsp(String),
+    Hls(String),
+    Dash(String),
+}
+
+impl WallpaperSource {
+    pub fn parse(input: &Path) -> Result<Self> {
+        let Some(text) = input.to_str() else {
+            return Ok(Self::File(input.to_path_buf()));
+        };
+        let lower = text.to_ascii_lowercase();
+
+        if lower.starts_with("file://") {
+            return Err(anyhow!(
+                "file:// URLs are not supported, pass the path directly: {}",
+                &text["file://".len()..]
+            ));
+        }
+        if lower.starts_with("rtsp://") || lower.starts_with("rtsps://") {
+            return Ok(Self::Rtsp(text.to_string()));
+        }
+        if lower.starts_with("http://") || lower.starts_with("https://") {
+            let url_path = lower.split(['?', '#']).next().unwrap_or_default();
+            return Ok(if url_path.ends_with(".m3u8") {
+                Self::Hls(text.to_string())
+            } else if url_path.ends_with(".mpd") {
+                Self::Dash(text.to_string())
+            } else {
+                Self::Http(text.to_string())
+            });
+        }
+
+        Ok(Self::File(input.to_path_buf()))
+    }
+
+    pub fn is_stream(&self) -> bool {
+        !matches!(self, Self::File(_))
+    }
+
+    /// GStreamer elements producing decoded raw video for this stream.
+    pub fn pipeline_source(&self) -> Option<String> {
+        let (element, url, demux) = match self {
+            Self::File(_) => return None,
+            Self::Http(url) => ("souphttpsrc", url, ""),
+            Self::Hls(url) => ("souphttpsrc", url, " ! hlsdemux2"),
+            Self::Dash(url) => ("souphttpsrc", url, " ! dashdemux2"),
+            Self::Rtsp(url) => ("rtspsrc", url, ""),
+        };
+        Some(format!(
+            "{} location=\"{}\"{} ! decodebin",
+            element,
+            escape_pipeline_string(url),
+            demux
+        ))
+    }
+
+    /// Fails early with a clear message when an HTTP source does not answer.
+    pub fn check_reachable(&self) -> Result<()> {
+        match self {
+            Self::Http(url) | Self::Hls(url) | Self::Dash(url) => http_head(url).map(|_| ()),
+            Self::File(_) | Self::Rtsp(_) => Ok(()),
+        }
+    }
+}
+
+/// Downloads an HTTP video into the runtime dir so it can loop like a local
+/// file. Returns `None` when the server reports no size or the file is too large.
+pub fn cache_http_video(url: &str) -> Result<Option<PathBuf>> {
+    let Some(length) = http_head(url)? else {
+        return Ok(None);
+    };
+    if length > STREAM_CACHE_MAX_BYTES {
+        return Ok(None);
+    }
+
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+    let extension = Path::new(url.split(['?', '#']).next().unwrap_or_default())
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("mp4")
+        .to_string();
+    let cache_path = runtime_dir().join(format!(
+        "papdieo-stream-{:016x}.{}",
+        hasher.finish(),
+        extension
+    ));
+    if cache_path.metadata().map(|m| m.len() == length).unwrap_or(false) {
+        return Ok(Some(cache_path));
+    }
+
+    let partial_path = cache_path.with_extension("part");
+    let status = Command::new("curl")
+        .args(["-sSfL", "--max-time"])
+        .arg(STREAM_DOWNLOAD_TIMEOUT_SECONDS.to_string())
+        .arg("-o")
+        .arg(&partial_path)
+        .arg(url)
+        .status()
+        .context("failed to run curl to cache stream")?;
+    if !status.success() {
+        let _ = std::fs::remove_file(&partial_path);
+        return Err(anyhow!("failed to download {} ({})", url, status));
+    }
+    std::fs::rename(&partial_path, &cache_path)
+        .with_context(|| format!("failed to write stream cache: {}", cache_path.display()))?;
+    Ok(Some(cache_path))
+}
+
+/// HEAD request through curl; returns the final response's Content-Length.
+fn http_head(url: &str) -> Result<Option<u64>> {
+    let output = Command::new("curl")
+        .args(["-sSfIL", "--max-time"])
+        .arg(REACHABILITY_TIMEOUT_SECONDS.to_string())
+        .arg(url)
+        .output()
+        .context("failed to run curl to check stream URL")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "stream URL is not reachable: {} ({})",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(content_length(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Content-Length of the last response in a (possibly redirected) header dump.
+fn content_length(headers: &str) -> Option<u64> {
+    let last_response = headers.rsplit("HTTP/").next().unwrap_or(headers);
+    last_response.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("content-length")
+            .then(|| value.trim().parse().ok())
+            .flatten()
+    })
+}
+
+fn escape_pipeline_string(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{content_length, WallpaperSource};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn urls_are_classified_by_scheme_and_extension() {
+        let parse = |s: &str| WallpaperSource::parse(Path::new(s)).unwrap();
+
+        assert_eq!(
+            parse("https://cdn.example/live/index.m3u8?token=1"),
+            WallpaperSource::Hls("https://cdn.example/live/index.m3u8?token=1".to_string())
+        );
+        assert_eq!(
+            parse("http://example/manifest.MPD"),
+            WallpaperSource::Dash("http://example/manifest.MPD".to_string())
+        );
+        assert_eq!(
+            parse("rtsp://camera.local/stream"),
+            WallpaperSource::Rtsp("rtsp://camera.local/stream".to_string())
+        );
+        assert_eq!(
+            parse("https://example/loop.mp4"),
+            WallpaperSource::Http("https://example/loop.mp4".to_string())
+        );
+        assert_eq!(
+            parse("/home/me/Pictures/a.png"),
+            WallpaperSource::File(PathBuf::from("/home/me/Pictures/a.png"))
+        );
+        assert!(WallpaperSource::parse(Path::new("file:///home/me/a.png")).is_err());
+    }
+
+    #[test]
+    fn hls_pipeline_uses_hlsdemux2() {
+        let source = WallpaperSource::Hls("https://example/index.m3u8".to_string());
+        assert_eq!(
+            source.pipeline_source().unwrap(),
+            "souphttpsrc location=\"https://example/index.m3u8\" ! hlsdemux2 ! decodebin"
+        );
+    }
+
+    #[test]
+    fn content_length_reads_final_redirect_response() {
+        let headers = "HTTP/1.1 302 Found\r\nContent-Length: 0\r\nLocation: /b\r\n\r\nHTTP/2 200\r\ncontent-length: 1048576\r\n\r\n";
+        assert_eq!(content_length(headers), Some(1_048_576));
+        assert_eq!(content_length("HTTP/2 200\r\n\r\n"), None);
+    }
+}
@@ -0,0 +1,141 @@
+//! `rotation_stagger_seconds` / `rotation_jitter_seconds`: spreading one
+//! rotation of the daemon over several moments instead of switching every
+//! monitor at once.
+//!
+//! Each round has a boundary, one interval after the previous one. Monitor `i`
+//! in name order rotates `i × stagger` after it, moved by a random amount of
+//! up to ± jitter that is drawn again every round, so monitors never settle
+//! into the same moment. Rounds follow the boundaries, not the last monitor,
+//! so the stagger does not stretch the interval.
+
+use rand::{Rng, RngExt};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Default)]
+pub struct RotationSpread {
+    due: HashMap<String, Instant>,
+    boundary: Option<Instant>,
+}
+
+impl RotationSpread {
+    /// Schedules a round for `monitors` unless one is still running. The round
+    /// ends `interval` after the last one, but no later than `cycle_length`
+    /// from `now` (an upcoming `schedule` rule).
+    #[allow(clippy::too_many_arguments)]
+    pub fn plan<R: Rng + ?Sized>(
+        &mut self,
+        monitors: &[String],
+        now: Instant,
+        interval: Duration,
+        cycle_length: Duration,
+        stagger: Duration,
+        jitter: Duration,
+        rng: &mut R,
+    ) {
+        if !self.due.is_empty() {
+            return;
+        }
+        let latest = now + cycle_length;
+        let boundary = self
+            .boundary
+            .map_or(latest, |previous| (previous + interval).clamp(now, latest));
+        self.boundary = Some(boundary);
+
+        let mut sorted: Vec<&String> = monitors.iter().collect();
+        sorted.sort();
+        let jitter_ms = jitter.as_millis().min(i64::MAX as u128) as i64;
+        for (index, monitor) in sorted.into_iter().enumerate() {
+            let mut at = boundary + stagger * index as u32;
+            if jitter_ms > 0 {
+                let shift = rng.random_range(-jitter_ms..=jitter_ms);
+                let shift_abs = Duration::from_millis(shift.unsigned_abs());
+                at = if shift < 0 {
+                    at.checked_sub(shift_abs).unwrap_or(now).max(now)
+                } else {
+                    at + shift_abs
+                };
+            }
+            self.due.insert(monitor.clone(), at);
+        }
+    }
+
+    /// Time until the next monitor of the round rotates.
+    pub fn until_next(&self, now: Instant) -> Option<Duration> {
+        self.due
+            .values()
+            .min()
+            .map(|at| at.saturating_duration_since(now))
+    }
+
+    pub fn is_planned(&self) -> bool {
+        !self.due.is_empty()
+    }
+
+    /// Monitors whose turn has come, removed from the round.
+    pub fn take_due(&mut self, now: Instant) -> Vec<String> {
+        let mut due: Vec<String> = self
+            .due
+            .iter()
+            .filter(|(_, at)| **at <= now)
+            .map(|(monitor, _)| monitor.clone())
+            .collect();
+        due.sort();
+        self.due.retain(|monitor, _| !due.contains(monitor));
+        due
+    }
+
+    /// Drops the round; the next one starts a full cycle from now.
+    pub fn clear(&mut self) {
+        self.due.clear();
+        self.boundary = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RotationSpread;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn monitors_rotate_staggered_and_jittered_around_each_boundary() {
+        let monitors = ["HDMI-A-1", "DP-2", "DP-1"].map(String::from);
+        let interval = Duration::from_secs(300);
+        let stagger = Duration::from_secs(10);
+        let mut rng = StdRng::seed_from_u64(7);
+        let start = Instant::now();
+
+        let mut spread = RotationSpread::default();
+        spread.plan(&monitors, start, interval, interval, stagger, Duration::ZERO, &mut rng);
+        assert_eq!(spread.until_next(start), Some(interval));
+        let boundary = start + interval;
+        assert!(spread.take_due(boundary - Duration::from_secs(1)).is_empty());
+        assert_eq!(spread.take_due(boundary), ["DP-1"]);
+        assert_eq!(spread.take_due(boundary + stagger * 2), ["DP-2", "HDMI-A-1"]);
+        assert!(!spread.is_planned());
+
+        // The next round keeps to the boundaries even though the last monitor ran late.
+        let late = boundary + stagger * 2;
+        spread.plan(&monitors, late, interval, interval, stagger, Duration::ZERO, &mut rng);
+        assert_eq!(spread.until_next(late), Some(interval - stagger * 2));
+        // A round in progress is not planned again.
+        spread.plan(&monitors, late, interval, interval, Duration::ZERO, Duration::ZERO, &mut rng);
+        assert_eq!(spread.until_next(late), Some(interval - stagger * 2));
+
+        let jitter = Duration::from_secs(20);
+        spread.clear();
+        let mut first = Vec::new();
+        for _ in 0..20 {
+            spread.plan(&monitors, start, interval, interval, stagger, jitter, &mut rng);
+            let offset = spread.until_next(start).unwrap();
+            assert!(offset >= interval - jitter && offset <= interval + jitter, "{:?}", offset);
+            first.push(offset);
+            spread.clear();
+        }
+        first.dedup();
+        assert!(first.len() > 1, "jitter is drawn again every round");
+    }
+}
@@ -0,0 +1,755 @@
+use crate::config::FitMode;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const DAEMON_STATE_FILE: &str = "papdieo-state.json";
+const PAUSE_FLAG_FILE: &str = "papdieo-pause";
+const PINNED_FILE: &str = "papdieo-pinned.json";
+const LOCKED_FILE: &str = "papdieo-locked.json";
+const PROFILE_REQUEST_FILE: &str = "papdieo-profile-request";
+const ROTATE_REQUEST_FILE: &str = "papdieo-rotate-request";
+const FPS_LIMIT_FILE: &str = "papdieo-fps-limit";
+const RENDERER_RECORD_PREFIX: &str = "papdieo-renderer-";
+const SHUTDOWN_RENDERERS_FILE: &str = "papdieo-shutdown-renderers.json";
+
+/// Snapshot of what the daemon is currently showing, shared with client commands.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonState {
+    pub monitors: HashMap<String, MonitorState>,
+    pub pid: u32,
+    pub started_unix: u64,
+    /// Profile the daemon's config comes from (`daemon --profile`, `profile activate`).
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Renderers that exited without being stopped, per monitor, since the daemon started.
+    #[serde(default)]
+    pub unexpected_exits: HashMap<String, u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonitorState {
+    pub wallpaper: PathBuf,
+    pub changed_unix: u64,
+    pub renderer_pid: u32,
+    pub fps: u32,
+    pub fit: FitMode,
+}
+
+impl DaemonState {
+    pub fn new(pid: u32) -> Self {
+        Self {
+            monitors: HashMap::new(),
+            pid,
+            started_unix: unix_now(),
+            profile: None,
+            unexpected_exits: HashMap::new(),
+        }
+    }
+
+    pub fn load() -> Option<Self> {
+        let content = fs::read_to_string(daemon_state_path()).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Writes to a temporary file and renames it so readers never see a partial file.
+    pub fn save(&self) -> Result<()> {
+        let path = daemon_state_path();
+        let tmp_path = path.with_extension("json.tmp");
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(&tmp_path, content)
+            .with_context(|| format!("failed to write daemon state: {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("failed to write daemon state: {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// One monitor's wallpaper in a `batch-set` manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ManifestEntry {
+    pub monitor: String,
+    #[serde(alias = "wallpaper")]
+    pub path: PathBuf,
+    pub fit: Option<FitMode>,
+    pub fps: Option<u32>,
+}
+
+/// `export-session` output. Its `monitors` map is the subset of the daemon
+/// state (`status --json`) that `batch-set` needs, so either can be restored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionManifest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generated_at: Option<String>,
+    pub monitors: BTreeMap<String, SessionMonitor>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionMonitor {
+    pub wallpaper: PathBuf,
+    pub fit: Option<FitMode>,
+    pub fps: Option<u32>,
+}
+
+impl SessionManifest {
+    pub fn from_state(state: &DaemonState, generated_at: String) -> Self {
+        let monitors = state
+            .monitors
+            .iter()
+            .map(|(monitor, state)| {
+                let entry = SessionMonitor {
+                    wallpaper: state.wallpaper.clone(),
+                    fit: Some(state.fit),
+                    fps: Some(state.fps),
+                };
+                (monitor.clone(), entry)
+            })
+            .collect();
+        Self {
+            generated_at: Some(generated_at),
+            monitors,
+        }
+    }
+
+    /// In monitor name order.
+    pub fn entries(self) -> Vec<ManifestEntry> {
+        self.monitors
+            .into_iter()
+            .map(|(monitor, entry)| ManifestEntry {
+                monitor,
+                path: entry.wallpaper,
+                fit: entry.fit,
+                fps: entry.fps,
+            })
+            .collect()
+    }
+}
+
+/// Accepts a JSON array of entries, an `export-session` manifest or the
+/// `status --json` output, so the daemon state can be saved and restored as-is.
+pub fn parse_manifest(content: &str) -> Result<Vec<ManifestEntry>> {
+    let value: serde_json::Value = serde_json::from_str(content).context("invalid JSON")?;
+    if value.is_array() {
+        return serde_json::from_value(value).context("invalid manifest entry");
+    }
+    if !value.get("monitors").is_some_and(serde_json::Value::is_object) {
+        return Err(anyhow!("expected an array of entries or daemon state with \"monitors\""));
+    }
+    let manifest: SessionManifest =
+        serde_json::from_value(value).context("invalid monitor in manifest")?;
+    Ok(manifest.entries())
+}
+
+/// Monitors whose wallpaper was set by hand (`set`, `random`, `next`, `lock`);
+/// the daemon does not rotate them until they are unlocked.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MonitorLocks {
+    #[serde(default)]
+    pub locked: BTreeSet<String>,
+    /// Locks the daemon drops at its next rotation (`--unlock`).
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub release_on_rotate: BTreeSet<String>,
+    /// The process showing each hand-set wallpaper. The daemon stops its own
+    /// renderer on the monitor while that process lives, and drops the lock
+    /// once it has exited. `lock` keeps the daemon's wallpaper and records none.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub renderers: BTreeMap<String, u32>,
+}
+
+impl MonitorLocks {
+    pub fn load() -> Self {
+        fs::read_to_string(locked_path())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writes to a temporary file and renames it so readers never see a partial file.
+    pub fn save(&self) -> Result<()> {
+        let path = locked_path();
+        let tmp_path = path.with_extension("json.tmp");
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(&tmp_path, content)
+            .with_context(|| format!("failed to write monitor locks: {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("failed to write monitor locks: {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Returns `false` when nothing changed. Without `renderer` the monitor
+    /// keeps whichever renderer it had.
+    pub fn lock(&mut self, monitor: &str, release_on_rotate: bool, renderer: Option<u32>) -> bool {
+        let inserted = self.locked.insert(monitor.to_string());
+        let release_changed = if release_on_rotate {
+            self.release_on_rotate.insert(monitor.to_string())
+        } else {
+            self.release_on_rotate.remove(monitor)
+        };
+        let renderer_changed = renderer
+            .is_some_and(|pid| self.renderers.insert(monitor.to_string(), pid) != Some(pid));
+        inserted || release_changed || renderer_changed
+    }
+
+    /// Like `--monitor`, a lock matches its monitor in any case.
+    pub fn is_locked(&self, monitor: &str) -> bool {
+        self.locked.iter().any(|name| name.eq_ignore_ascii_case(monitor))
+    }
+
+    /// The process showing the wallpaper set by hand on `monitor`, if any.
+    pub fn renderer(&self, monitor: &str) -> Option<u32> {
+        self.renderers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(monitor))
+            .map(|(_, pid)| *pid)
+    }
+
+    pub fn unlock(&mut self, monitor: &str) -> bool {
+        let other = |name: &String| !name.eq_ignore_ascii_case(monitor);
+        self.release_on_rotate.retain(other);
+        self.renderers.retain(|name, _| other(name));
+        let before = self.locked.len();
+        self.locked.retain(other);
+        self.locked.len() != before
+    }
+
+    /// Drops the locks marked `release_on_rotate`; returns whether any were.
+    pub fn release_for_rotation(&mut self) -> bool {
+        let released = std::mem::take(&mut self.release_on_rotate);
+        self.locked.retain(|monitor| !released.contains(monitor));
+        self.renderers.retain(|monitor, _| !released.contains(monitor));
+        !released.is_empty()
+    }
+
+    /// Drops the locks whose renderer is no longer `alive` and returns their
+    /// monitors, which would otherwise stay blank.
+    pub fn release_exited_renderers(&mut self, alive: impl Fn(u32) -> bool) -> Vec<String> {
+        let exited: Vec<String> = self
+            .renderers
+            .iter()
+            .filter(|(_, pid)| !alive(**pid))
+            .map(|(monitor, _)| monitor.clone())
+            .collect();
+        for monitor in &exited {
+            self.unlock(monitor);
+        }
+        exited
+    }
+}
+
+pub fn locked_path() -> PathBuf {
+    runtime_dir().join(LOCKED_FILE)
+}
+
+pub fn daemon_state_path() -> PathBuf {
+    runtime_dir().join(DAEMON_STATE_FILE)
+}
+
+/// Written by the daemon on power changes: video renderers cap their frame
+/// rate to this until it changes again, or until the daemon that wrote it is
+/// gone.
+pub fn fps_limit_path() -> PathBuf {
+    runtime_dir().join(FPS_LIMIT_FILE)
+}
+
+/// Writes `<fps> <pid>`, the pid being this daemon's.
+pub fn set_fps_limit(fps: u32) -> Result<()> {
+    let path = fps_limit_path();
+    fs::write(&path, format!("{} {}", fps, std::process::id()))
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Called when the daemon exits, so renderers it leaves behind play at full rate.
+pub fn remove_fps_limit() {
+    let _ = fs::remove_file(fps_limit_path());
+}
+
+pub fn fps_limit() -> Option<u32> {
+    parse_fps_limit(&fs::read_to_string(fps_limit_path()).ok()?)
+}
+
+fn parse_fps_limit(content: &str) -> Option<u32> {
+    let mut fields = content.split_whitespace();
+    let fps: u32 = fields.next()?.parse().ok().filter(|fps| *fps > 0)?;
+    let pid: u32 = fields.next()?.parse().ok()?;
+    Path::new(&format!("/proc/{}", pid)).exists().then_some(fps)
+}
+
+/// Flag file whose presence pauses video on `monitor`, or on every monitor for `None`.
+pub fn pause_flag_path(monitor: Option<&str>) -> PathBuf {
+    match monitor {
+        Some(monitor) => runtime_dir().join(format!(
+            "{}-{}",
+            PAUSE_FLAG_FILE,
+            monitor.trim().replace('/', "_")
+        )),
+        None => runtime_dir().join(PAUSE_FLAG_FILE),
+    }
+}
+
+pub fn is_paused(monitor: Option<&str>) -> bool {
+    pause_flag_path(None).exists()
+        || monitor
+            .map(|monitor| pause_flag_path(Some(monitor)).exists())
+            .unwrap_or(false)
+}
+
+/// Removes the global flag and every per-monitor flag.
+pub fn clear_pause_flags() -> Result<()> {
+    let prefix = format!("{}-", PAUSE_FLAG_FILE);
+    let per_monitor = fs::read_dir(runtime_dir())?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map(|name| name.starts_with(&prefix))
+                .unwrap_or(false)
+        });
+    for path in per_monitor.chain([pause_flag_path(None)]) {
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("failed to remove {}", path.display()))
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn profile_request_path() -> PathBuf {
+    runtime_dir().join(PROFILE_REQUEST_FILE)
+}
+
+/// Asks the running daemon to switch to profile `name`.
+pub fn request_profile(name: &str) -> Result<()> {
+    let path = profile_request_path();
+    fs::write(&path, name)
+        .with_context(|| format!("failed to write profile request: {}", path.display()))
+}
+
+/// The pending profile request, removed so it is handled once.
+pub fn take_profile_request() -> Option<String> {
+    let path = profile_request_path();
+    let name = fs::read_to_string(&path).ok()?;
+    let _ = fs::remove_file(&path);
+    Some(name.trim().to_string()).filter(|name| !name.is_empty())
+}
+
+/// What a `rotate-now` request asks the daemon to rotate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RotateSignal {
+    All,
+    Monitor(String),
+}
+
+pub fn rotate_request_path() -> PathBuf {
+    runtime_dir().join(ROTATE_REQUEST_FILE)
+}
+
+/// Asks the running daemon to rotate now, appending
+/// `{"cmd":"rotate-now","monitor":"all"}` (or a monitor name) as one line so
+/// requests made within the same second are all handled.
+pub fn request_rotation(signal: &RotateSignal) -> Result<()> {
+    let monitor = match signal {
+        RotateSignal::All => "all",
+        RotateSignal::Monitor(name) => name,
+    };
+    let line = serde_json::json!({"cmd": "rotate-now", "monitor": monitor});
+    let path = rotate_request_path();
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("failed to write rotate request: {}", path.display()))?;
+    writeln!(file, "{}", line)
+        .with_context(|| format!("failed to write rotate request: {}", path.display()))
+}
+
+/// Pending rotate requests, removed so each is handled once.
+pub fn take_rotate_requests() -> Vec<RotateSignal> {
+    let path = rotate_request_path();
+    let Ok(content) = fs::read_to_string(&path) else {
+        return Vec::new();
+    };
+    let _ = fs::remove_file(&path);
+    parse_rotate_requests(&content)
+}
+
+fn parse_rotate_requests(content: &str) -> Vec<RotateSignal> {
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|request| request["cmd"] == "rotate-now")
+        .map(|request| match request["monitor"].as_str() {
+            None | Some("all") => RotateSignal::All,
+            Some(monitor) => RotateSignal::Monitor(monitor.to_string()),
+        })
+        .collect()
+}
+
+pub fn pinned_path() -> PathBuf {
+    runtime_dir().join(PINNED_FILE)
+}
+
+/// Monitors the daemon leaves on their current wallpaper instead of rotating.
+pub fn load_pinned() -> BTreeSet<String> {
+    fs::read_to_string(pinned_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Like `--monitor`, a pin matches its monitor in any case.
+pub fn is_pinned(pinned: &BTreeSet<String>, monitor: &str) -> bool {
+    pinned.iter().any(|name| name.eq_ignore_ascii_case(monitor))
+}
+
+pub fn save_pinned(pinned: &BTreeSet<String>) -> Result<()> {
+    let path = pinned_path();
+    let tmp_path = path.with_extension("json.tmp");
+    let content = serde_json::to_string_pretty(pinned)?;
+    fs::write(&tmp_path, content)
+        .with_context(|| format!("failed to write pinned monitors: {}", tmp_path.display()))?;
+    fs::rename(&tmp_path, &path)
+        .with_context(|| format!("failed to write pinned monitors: {}", path.display()))?;
+    Ok(())
+}
+
+/// Written next to each renderer process started for a monitor, so that
+/// `daemon --watch-pidfile` can take the process over instead of starting a
+/// second renderer on the same monitor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WallpaperAdoptionRecord {
+    pub monitor: String,
+    pub path: PathBuf,
+    pub pid: u32,
+    pub spawned_at: u64,
+}
+
+impl WallpaperAdoptionRecord {
+    pub fn new(monitor: &str, path: &Path, pid: u32) -> Self {
+        Self {
+            monitor: monitor.to_string(),
+            path: path.to_path_buf(),
+            pid,
+            spawned_at: unix_now(),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.save_in(&runtime_dir())
+    }
+
+    fn save_in(&self, dir: &Path) -> Result<()> {
+        let path = renderer_record_path(dir, &self.monitor);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write renderer record: {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("failed to write renderer record: {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Removes the monitor's record if it still belongs to `pid`; a newer
+    /// renderer on the monitor may have replaced it since.
+    pub fn remove(monitor: &str, pid: u32) {
+        Self::remove_in(&runtime_dir(), monitor, pid);
+    }
+
+    fn remove_in(dir: &Path, monitor: &str, pid: u32) {
+        let path = renderer_record_path(dir, monitor);
+        let recorded = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<Self>(&content).ok());
+        if recorded.is_some_and(|record| record.pid == pid) {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Every record in the runtime directory; unreadable ones are skipped.
+    pub fn load_all() -> Vec<Self> {
+        Self::load_all_in(&runtime_dir())
+    }
+
+    fn load_all_in(dir: &Path) -> Vec<Self> {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut records: Vec<Self> = entries
+            .flatten()
+            .filter(|entry| {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                name.starts_with(RENDERER_RECORD_PREFIX) && name.ends_with(".json")
+            })
+            .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+            .filter_map(|content| serde_json::from_str(&content).ok())
+            .collect();
+        records.sort_by(|a, b| a.monitor.cmp(&b.monitor));
+        records
+    }
+}
+
+/// Renderers a stopping daemon leaves on its monitors, by monitor. The next
+/// daemon stops exactly these once its own wallpaper is up, and leaves any
+/// other renderer on the monitor alone.
+pub fn save_shutdown_renderers(pids: &BTreeMap<String, u32>) -> Result<()> {
+    save_shutdown_renderers_in(&runtime_dir(), pids)
+}
+
+fn save_shutdown_renderers_in(dir: &Path, pids: &BTreeMap<String, u32>) -> Result<()> {
+    let path = dir.join(SHUTDOWN_RENDERERS_FILE);
+    if pids.is_empty() {
+        let _ = fs::remove_file(&path);
+        return Ok(());
+    }
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_string_pretty(pids)?)
+        .with_context(|| format!("failed to write shutdown renderers: {}", tmp_path.display()))?;
+    fs::rename(&tmp_path, &path)
+        .with_context(|| format!("failed to write shutdown renderers: {}", path.display()))?;
+    Ok(())
+}
+
+/// Removes and returns the shutdown renderer recorded for `monitor`.
+pub fn take_shutdown_renderer(monitor: &str) -> Option<u32> {
+    take_shutdown_renderer_in(&runtime_dir(), monitor)
+}
+
+fn take_shutdown_renderer_in(dir: &Path, monitor: &str) -> Option<u32> {
+    let mut pids: BTreeMap<String, u32> = fs::read_to_string(dir.join(SHUTDOWN_RENDERERS_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())?;
+    let pid = pids.remove(monitor)?;
+    let _ = save_shutdown_renderers_in(dir, &pids);
+    Some(pid)
+}
+
+fn renderer_record_path(dir: &Path, monitor: &str) -> PathBuf {
+    dir.join(format!(
+        "{}{}.json",
+        RENDERER_RECORD_PREFIX,
+        monitor.trim().replace('/', "_")
+    ))
+}
+
+/// `$XDG_RUNTIME_DIR`, falling back to `/tmp` outside a user session.
+pub fn runtime_dir() -> PathBuf {
+    env::var("XDG_RUNTIME_DIR")
+        .ok()
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_fps_limit, parse_manifest, parse_rotate_requests, save_shutdown_renderers_in,
+        take_shutdown_renderer_in, DaemonState, ManifestEntry, MonitorLocks, MonitorState,
+        RotateSignal, SessionManifest, WallpaperAdoptionRecord, SHUTDOWN_RENDERERS_FILE,
+    };
+    use crate::config::FitMode;
+    use std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    };
+
+    #[test]
+    fn manifest_accepts_entry_arrays_and_status_json() {
+        let entries = parse_manifest(
+            r#"[{"monitor":"DP-1","path":"/walls/left.jpg","fit":"cover","fps":60},{"monitor":"DP-2","path":"/walls/right.mp4"}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                ManifestEntry {
+                    monitor: "DP-1".to_string(),
+                    path: PathBuf::from("/walls/left.jpg"),
+                    fit: Some(FitMode::Cover),
+                    fps: Some(60),
+                },
+                ManifestEntry {
+                    monitor: "DP-2".to_string(),
+                    path: PathBuf::from("/walls/right.mp4"),
+                    fit: None,
+                    fps: None,
+                },
+            ]
+        );
+
+        let status = r#"{
+            "monitors": {
+                "HDMI-A-1": {"wallpaper": "/walls/b.png", "changed_unix": 1, "renderer_pid": 7, "fps": 30, "fit": "contain"},
+                "DP-1": {"wallpaper": "/walls/a.png", "changed_unix": 1, "renderer_pid": 7, "fps": 60, "fit": "cover"}
+            },
+            "pid": 7,
+            "started_unix": 1,
+            "pinned": []
+        }"#;
+        let entries = parse_manifest(status).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].monitor, "DP-1");
+        assert_eq!(entries[1].path, PathBuf::from("/walls/b.png"));
+        assert_eq!(entries[1].fit, Some(FitMode::Contain));
+
+        assert!(parse_manifest("null").is_err());
+    }
+
+    #[test]
+    fn exported_sessions_restore_the_same_wallpapers() {
+        let mut state = DaemonState::new(7);
+        for (monitor, wallpaper, fps, fit) in [
+            ("HDMI-A-1", "/walls/b.mp4", 30, FitMode::Contain),
+            ("DP-1", "/walls/a.png", 60, FitMode::Cover),
+        ] {
+            let monitor_state = MonitorState {
+                wallpaper: PathBuf::from(wallpaper),
+                changed_unix: 1,
+                renderer_pid: 7,
+                fps,
+                fit,
+            };
+            state.monitors.insert(monitor.to_string(), monitor_state);
+        }
+
+        let manifest = SessionManifest::from_state(&state, "2025-01-15T09:30:00+01:00".into());
+        let exported = serde_json::to_string_pretty(&manifest).unwrap();
+        assert!(exported.contains(r#""generated_at": "2025-01-15T09:30:00+01:00""#));
+        let restored = parse_manifest(&exported).unwrap();
+        assert_eq!(restored, manifest.entries());
+        assert_eq!(restored[0].monitor, "DP-1");
+        assert_eq!(restored[0].path, PathBuf::from("/walls/a.png"));
+        assert_eq!((restored[1].fit, restored[1].fps), (Some(FitMode::Contain), Some(30)));
+
+        let status = serde_json::to_string(&state).unwrap();
+        assert_eq!(parse_manifest(&status).unwrap(), restored);
+    }
+
+    #[test]
+    fn release_on_rotate_locks_are_dropped_at_the_next_rotation() {
+        let mut locks: MonitorLocks = serde_json::from_str(r#"{"locked":["DP-1"]}"#).unwrap();
+        assert!(locks.lock("DP-2", true, None));
+        assert!(!locks.lock("DP-2", true, None));
+        assert_eq!(
+            serde_json::to_string(&locks).unwrap(),
+            r#"{"locked":["DP-1","DP-2"],"release_on_rotate":["DP-2"]}"#
+        );
+
+        assert!(locks.release_for_rotation());
+        assert!(!locks.release_for_rotation());
+        assert_eq!(locks.locked.iter().collect::<Vec<_>>(), ["DP-1"]);
+        assert!(locks.unlock("DP-1"));
+        assert!(!locks.unlock("DP-1"));
+    }
+
+    #[test]
+    fn locks_go_away_with_the_renderer_that_set_them() {
+        let mut locks = MonitorLocks::default();
+        assert!(locks.lock("DP-1", false, Some(41)));
+        assert!(locks.lock("DP-2", false, Some(42)));
+        assert!(locks.lock("HDMI-A-1", false, None));
+        // `lock` on a monitor set by hand keeps its renderer.
+        assert!(!locks.lock("DP-1", false, None));
+        assert_eq!(
+            serde_json::to_string(&locks).unwrap(),
+            r#"{"locked":["DP-1","DP-2","HDMI-A-1"],"renderers":{"DP-1":41,"DP-2":42}}"#
+        );
+
+        assert_eq!(locks.release_exited_renderers(|pid| pid == 42), ["DP-1"]);
+        assert!(locks.release_exited_renderers(|pid| pid == 42).is_empty());
+        assert_eq!(locks.locked.iter().collect::<Vec<_>>(), ["DP-2", "HDMI-A-1"]);
+        assert_eq!(locks.renderers, BTreeMap::from([("DP-2".to_string(), 42)]));
+
+        // Monitor names compare in any case, as `--monitor` matches them.
+        assert!(locks.is_locked("dp-2") && locks.is_locked("hdmi-a-1"));
+        assert_eq!(locks.renderer("dp-2"), Some(42));
+        assert_eq!(locks.renderer("HDMI-A-1"), None);
+        assert!(locks.unlock("dp-2"));
+        assert!(!locks.is_locked("DP-2") && locks.renderers.is_empty());
+    }
+
+    #[test]
+    fn rotate_requests_are_read_line_by_line() {
+        let content = "{\"cmd\":\"rotate-now\",\"monitor\":\"all\"}\n\
+                       {\"cmd\":\"rotate-now\",\"monitor\":\"DP-1\"}\n\
+                       {\"cmd\":\"something-else\"}\n\
+                       not json\n\
+                       {\"cmd\":\"rotate-now\"}\n";
+        assert_eq!(
+            parse_rotate_requests(content),
+            [
+                RotateSignal::All,
+                RotateSignal::Monitor("DP-1".to_string()),
+                RotateSignal::All
+            ]
+        );
+    }
+
+    #[test]
+    fn renderer_records_round_trip_per_monitor() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let first = WallpaperAdoptionRecord::new("DP-1", Path::new("/walls/a.mp4"), 41);
+        let second = WallpaperAdoptionRecord::new("HDMI-A-1", Path::new("/walls/b.png"), 42);
+        second.save_in(dir).unwrap();
+        first.save_in(dir).unwrap();
+        std::fs::write(dir.join("papdieo-renderer-broken.json"), "not json").unwrap();
+        std::fs::write(dir.join("papdieo-state.json"), "{}").unwrap();
+        assert_eq!(
+            WallpaperAdoptionRecord::load_all_in(dir),
+            [first.clone(), second.clone()]
+        );
+
+        // A newer renderer on the same monitor replaces the record.
+        let replaced = WallpaperAdoptionRecord::new("DP-1", Path::new("/walls/c.gif"), 43);
+        replaced.save_in(dir).unwrap();
+        assert_eq!(WallpaperAdoptionRecord::load_all_in(dir), [replaced.clone(), second.clone()]);
+
+        // The replaced renderer's pid no longer owns the record.
+        WallpaperAdoptionRecord::remove_in(dir, "DP-1", 41);
+        assert_eq!(WallpaperAdoptionRecord::load_all_in(dir), [replaced, second.clone()]);
+        WallpaperAdoptionRecord::remove_in(dir, "DP-1", 43);
+        assert_eq!(WallpaperAdoptionRecord::load_all_in(dir), [second]);
+    }
+
+    #[test]
+    fn fps_limit_needs_a_live_writer() {
+        assert_eq!(parse_fps_limit(&format!("24 {}\n", std::process::id())), Some(24));
+        assert_eq!(parse_fps_limit(&format!("0 {}", std::process::id())), None);
+        // Without a pid, or written by a daemon that has exited.
+        assert_eq!(parse_fps_limit("24"), None);
+        assert_eq!(parse_fps_limit(&format!("24 {}", u32::MAX)), None);
+    }
+
+    #[test]
+    fn shutdown_renderers_are_taken_once_per_monitor() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        assert_eq!(take_shutdown_renderer_in(dir, "DP-1"), None);
+
+        let pids = BTreeMap::from([("DP-1".to_string(), 41), ("HDMI-A-1".to_string(), 42)]);
+        save_shutdown_renderers_in(dir, &pids).unwrap();
+        assert_eq!(take_shutdown_renderer_in(dir, "DP-2"), None);
+        assert_eq!(take_shutdown_renderer_in(dir, "DP-1"), Some(41));
+        assert_eq!(take_shutdown_renderer_in(dir, "DP-1"), None);
+        assert_eq!(take_shutdown_renderer_in(dir, "HDMI-A-1"), Some(42));
+        assert!(!dir.join(SHUTDOWN_RENDERERS_FILE).exists());
+    }
+}
@@ -0,0 +1,203 @@
+//! CPU and memory use of renderer processes the daemon watches, read from
+//! `/proc`, for `max_renderer_cpu_percent_sustained` and `max_renderer_rss_mb`.
+//!
+//! CPU use is the process's user plus system time between two checks, as a
+//! percentage of one core; memory is its resident set (`VmRSS`). A renderer
+//! is only reported once it has stayed over a limit for the whole grace
+//! period, so a burst while a video starts does not count.
+
+use anyhow::{anyhow, Context, Result};
+use std::{
+    collections::HashMap,
+    fs,
+    time::{Duration, Instant},
+};
+
+/// Default `resource_check_interval_seconds`.
+pub const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Default `resource_violation_grace_seconds`.
+pub const DEFAULT_VIOLATION_GRACE: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessResources {
+    /// User plus system time, in clock ticks.
+    pub cpu_ticks: u64,
+    pub rss_kb: u64,
+}
+
+/// Current counters of process `pid`.
+pub fn check_child_resources(pid: u32) -> Result<ProcessResources> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid))
+        .with_context(|| format!("failed to read /proc/{}/stat", pid))?;
+    let status = fs::read_to_string(format!("/proc/{}/status", pid))
+        .with_context(|| format!("failed to read /proc/{}/status", pid))?;
+    Ok(ProcessResources {
+        cpu_ticks: parse_cpu_ticks(&stat)
+            .ok_or_else(|| anyhow!("unexpected /proc/{}/stat format", pid))?,
+        rss_kb: parse_rss_kb(&status).unwrap_or(0),
+    })
+}
+
+/// `utime` + `stime`, the 14th and 15th fields. The command name before them
+/// may contain spaces and parentheses, so fields are counted from the last `)`.
+fn parse_cpu_ticks(stat: &str) -> Option<u64> {
+    let mut fields = stat.get(stat.rfind(')')? + 1..)?.split_whitespace().skip(11);
+    let utime: u64 = fields.next()?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+    Some(utime + stime)
+}
+
+fn parse_rss_kb(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+fn ticks_per_second() -> u64 {
+    match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+        ticks if ticks > 0 => ticks as u64,
+        _ => 100,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResourceCaps {
+    pub cpu_percent: Option<f32>,
+    pub memory_mb: Option<u64>,
+}
+
+/// A renderer over its limits for longer than the grace period.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResourceViolation {
+    pub cpu_percent: f32,
+    pub rss_mb: u64,
+    pub over_for: Duration,
+}
+
+#[derive(Debug)]
+struct Sample {
+    at: Instant,
+    cpu_ticks: u64,
+    over_since: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+pub struct ResourceWatch {
+    samples: HashMap<u32, Sample>,
+}
+
+impl ResourceWatch {
+    /// Reads `pid` from `/proc` and records it; see [`ResourceWatch::record`].
+    pub fn check(
+        &mut self,
+        pid: u32,
+        caps: ResourceCaps,
+        grace: Duration,
+    ) -> Result<Option<ResourceViolation>> {
+        let resources = check_child_resources(pid)?;
+        Ok(self.record(pid, resources, Instant::now(), ticks_per_second(), caps, grace))
+    }
+
+    /// Compares `resources` with the last sample of `pid`. The first sample of
+    /// a process only starts the CPU measurement.
+    pub fn record(
+        &mut self,
+        pid: u32,
+        resources: ProcessResources,
+        now: Instant,
+        ticks_per_second: u64,
+        caps: ResourceCaps,
+        grace: Duration,
+    ) -> Option<ResourceViolation> {
+        let previous = self.samples.remove(&pid);
+        let cpu_percent = previous
+            .as_ref()
+            .map(|sample| {
+                let seconds = now.duration_since(sample.at).as_secs_f32();
+                let ticks = resources.cpu_ticks.saturating_sub(sample.cpu_ticks);
+                if seconds > 0.0 {
+                    ticks as f32 / ticks_per_second as f32 / seconds * 100.0
+                } else {
+                    0.0
+                }
+            })
+            .unwrap_or(0.0);
+        let rss_mb = resources.rss_kb / 1024;
+        let over = caps
+            .cpu_percent
+            .is_some_and(|limit| previous.is_some() && cpu_percent > limit)
+            || caps.memory_mb.is_some_and(|limit| rss_mb > limit);
+        let over_since = match (over, previous.and_then(|sample| sample.over_since)) {
+            (false, _) => None,
+            (true, Some(since)) => Some(since),
+            (true, None) => Some(now),
+        };
+        self.samples.insert(
+            pid,
+            Sample {
+                at: now,
+                cpu_ticks: resources.cpu_ticks,
+                over_since,
+            },
+        );
+        let over_for = now.duration_since(over_since?);
+        (over_for >= grace).then_some(ResourceViolation {
+            cpu_percent,
+            rss_mb,
+            over_for,
+        })
+    }
+
+    /// Drops the samples of processes that are no longer watched.
+    pub fn retain(&mut self, pids: &[u32]) {
+        self.samples.retain(|pid, _| pids.contains(pid));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_cpu_ticks, parse_rss_kb, ProcessResources, ResourceCaps, ResourceWatch};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn sustained_use_over_a_limit_is_reported_after_the_grace_period() {
+        let stat = "4242 (papdieo (gst)) S 1 4242 4242 0 -1 4194304 1 0 0 0 250 50 0 0 20 0 4";
+        assert_eq!(parse_cpu_ticks(stat), Some(300));
+        assert_eq!(parse_rss_kb("Name:\tpapdieo\nVmRSS:\t  204800 kB\n"), Some(204800));
+
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+        let caps = ResourceCaps {
+            cpu_percent: Some(80.0),
+            memory_mb: Some(500),
+        };
+        let grace = Duration::from_secs(60);
+        let usage = |cpu_ticks, rss_mb: u64| ProcessResources {
+            cpu_ticks,
+            rss_kb: rss_mb * 1024,
+        };
+        let mut watch = ResourceWatch::default();
+
+        // 90% of a core from the second check on; reported once it has lasted 60s.
+        assert_eq!(watch.record(7, usage(0, 100), at(0), 100, caps, grace), None);
+        assert_eq!(watch.record(7, usage(2700, 100), at(30), 100, caps, grace), None);
+        assert_eq!(watch.record(7, usage(5400, 100), at(60), 100, caps, grace), None);
+        let violation = watch.record(7, usage(8100, 100), at(90), 100, caps, grace).unwrap();
+        assert!((violation.cpu_percent - 90.0).abs() < 0.01, "{:?}", violation);
+        assert_eq!((violation.rss_mb, violation.over_for), (100, grace));
+
+        // Dropping back under the limits starts the grace period again.
+        assert_eq!(watch.record(7, usage(8400, 100), at(120), 100, caps, grace), None);
+        assert_eq!(watch.record(7, usage(8700, 600), at(150), 100, caps, grace), None);
+        assert_eq!(watch.record(7, usage(9000, 600), at(180), 100, caps, grace), None);
+        let violation = watch.record(7, usage(9300, 600), at(210), 100, caps, grace).unwrap();
+        assert_eq!((violation.rss_mb, violation.over_for), (600, grace));
+
+        watch.retain(&[]);
+        assert_eq!(watch.record(7, usage(9600, 600), at(240), 100, caps, grace), None);
+    }
+}
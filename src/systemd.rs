@@ -0,0 +1,87 @@
+//! `sd_notify_ready`: the systemd notification protocol for `Type=notify` units.
+//!
+//! Each message is one datagram to the socket named by `$NOTIFY_SOCKET`; a
+//! leading `@` names an abstract socket. Outside systemd the variable is unset
+//! and every message is dropped.
+
+use anyhow::{Context, Result};
+use std::{
+    env,
+    ffi::OsStr,
+    os::{
+        linux::net::SocketAddrExt,
+        unix::{
+            ffi::OsStrExt,
+            net::{SocketAddr, UnixDatagram},
+        },
+    },
+    path::Path,
+    process,
+    time::Duration,
+};
+
+pub fn sd_notify(message: &str) -> Result<()> {
+    match env::var_os("NOTIFY_SOCKET") {
+        Some(socket) => notify_socket(&socket, message),
+        None => Ok(()),
+    }
+}
+
+fn notify_socket(socket: &OsStr, message: &str) -> Result<()> {
+    let sender = UnixDatagram::unbound().context("failed to create notify socket")?;
+    let sent = match socket.as_bytes().strip_prefix(b"@") {
+        Some(name) => SocketAddr::from_abstract_name(name)
+            .and_then(|address| sender.send_to_addr(message.as_bytes(), &address)),
+        None => sender.send_to(message.as_bytes(), Path::new(socket)),
+    };
+    sent.with_context(|| format!("failed to notify {}", Path::new(socket).display()))?;
+    Ok(())
+}
+
+/// How often to send `WATCHDOG=1`: half of `$WATCHDOG_USEC`, or `None` when
+/// the unit has no watchdog or `$WATCHDOG_PID` names another process.
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec = env::var("WATCHDOG_USEC").ok();
+    let pid = env::var("WATCHDOG_PID").ok();
+    watchdog_interval_from(usec.as_deref(), pid.as_deref(), process::id())
+}
+
+fn watchdog_interval_from(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    if pid.is_some_and(|pid| pid.trim().parse() != Ok(own_pid)) {
+        return None;
+    }
+    let usec: u64 = usec?.trim().parse().ok().filter(|&usec| usec > 0)?;
+    Some(Duration::from_micros(usec / 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{notify_socket, watchdog_interval_from};
+    use std::{os::unix::net::UnixDatagram, time::Duration};
+
+    #[test]
+    fn messages_reach_the_notify_socket() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let path = dir.join("notify");
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        notify_socket(path.as_os_str(), "READY=1\n").unwrap();
+        let mut buffer = [0; 64];
+        let received = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..received], b"READY=1\n");
+        assert!(notify_socket(dir.join("missing").as_os_str(), "STOPPING=1\n").is_err());
+
+        assert_eq!(
+            watchdog_interval_from(Some("30000000"), None, 7),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            watchdog_interval_from(Some("30000000"), Some("7"), 7),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(watchdog_interval_from(Some("30000000"), Some("8"), 7), None);
+        assert_eq!(watchdog_interval_from(Some("0"), None, 7), None);
+        assert_eq!(watchdog_interval_from(None, None, 7), None);
+    }
+}
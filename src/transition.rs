@@ -0,0 +1,218 @@
+//! Daemon cross-fades done by the compositor instead of in CPU memory.
+//!
+//! On rotation the daemon starts the next renderer fully transparent over the
+//! old one, and [`TransitionCoordinator`] ramps the new surface's
+//! `wp_alpha_modifier_v1` multiplier up before stopping the old renderer. The
+//! old surface stays opaque underneath, so the composite never lets the
+//! background through mid-fade (two surfaces at 50% each would show only 75%).
+//! Each buffer is committed once and never rewritten mid-fade, so the
+//! compositor cannot sample a half-blended frame.
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long the ramp waits for the new renderer's first frame; a renderer that
+/// fails to start must not keep the old one up forever.
+const FIRST_FRAME_TIMEOUT: Duration = Duration::from_secs(10);
+const FIRST_FRAME_POLL: Duration = Duration::from_millis(10);
+
+/// Shape of a fade's opacity ramp (`--opacity-curve` / `transition_curve`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum EasingCurve {
+    Linear,
+    EaseIn,
+    EaseOut,
+    #[default]
+    EaseInOut,
+}
+
+impl EasingCurve {
+    pub fn name(self) -> &'static str {
+        match self {
+            EasingCurve::Linear => "linear",
+            EasingCurve::EaseIn => "ease-in",
+            EasingCurve::EaseOut => "ease-out",
+            EasingCurve::EaseInOut => "ease-in-out",
+        }
+    }
+}
+
+/// Opacity at fade progress `t` (0.0 to 1.0). Every curve starts at 0 and ends at 1.
+pub fn easing_function(t: f32, curve: EasingCurve) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    match curve {
+        EasingCurve::Linear => t,
+        EasingCurve::EaseIn => t * t,
+        EasingCurve::EaseOut => t * (2.0 - t),
+        // Cubic Hermite (smoothstep): flat at both ends.
+        EasingCurve::EaseInOut => t * t * (3.0 - 2.0 * t),
+    }
+}
+
+/// Opacity of one renderer's surface, written by the daemon and applied by the
+/// renderer thread on its next loop iteration.
+#[derive(Debug)]
+pub struct SurfaceAlpha {
+    factor: AtomicU32,
+    shown: AtomicBool,
+    output: Mutex<Option<String>>,
+}
+
+impl SurfaceAlpha {
+    pub fn new(opacity: f32) -> Arc<Self> {
+        Arc::new(Self {
+            factor: AtomicU32::new(opacity_to_factor(opacity)),
+            shown: AtomicBool::new(false),
+            output: Mutex::new(None),
+        })
+    }
+
+    pub fn set(&self, opacity: f32) {
+        self.factor.store(opacity_to_factor(opacity), Ordering::Relaxed);
+    }
+
+    /// The value for `wp_alpha_modifier_surface_v1.set_multiplier`.
+    pub fn factor(&self) -> u32 {
+        self.factor.load(Ordering::Relaxed)
+    }
+
+    /// Called by the renderer once its first frame is committed.
+    pub fn mark_shown(&self) {
+        self.shown.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_shown(&self) -> bool {
+        self.shown.load(Ordering::Relaxed)
+    }
+
+    /// Called by the renderer with the connector name of the output it picked,
+    /// however `--monitor` named it.
+    pub fn set_output(&self, name: &str) {
+        *self.output.lock().unwrap_or_else(|e| e.into_inner()) = Some(name.to_string());
+    }
+
+    pub fn output(&self) -> Option<String> {
+        self.output.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+fn opacity_to_factor(opacity: f32) -> u32 {
+    (opacity.clamp(0.0, 1.0) as f64 * u32::MAX as f64).round() as u32
+}
+
+/// Fades `incoming` in over the opaque old surface along `curve` on a timer
+/// thread, then runs `on_finished` (which stops the old renderer).
+pub struct TransitionCoordinator {
+    skip: Arc<AtomicBool>,
+    handle: thread::JoinHandle<()>,
+}
+
+impl TransitionCoordinator {
+    /// The ramp is split into this many ticks of `duration / STEPS`.
+    pub const STEPS: u32 = 60;
+
+    pub fn start(
+        duration: Duration,
+        curve: EasingCurve,
+        incoming: Arc<SurfaceAlpha>,
+        on_finished: impl FnOnce() + Send + 'static,
+    ) -> Self {
+        let skip = Arc::new(AtomicBool::new(false));
+        let skip_ramp = Arc::clone(&skip);
+        let handle = thread::spawn(move || {
+            let waiting_since = Instant::now();
+            while !incoming.is_shown()
+                && !skip_ramp.load(Ordering::Relaxed)
+                && waiting_since.elapsed() < FIRST_FRAME_TIMEOUT
+            {
+                thread::sleep(FIRST_FRAME_POLL);
+            }
+
+            let tick = duration / Self::STEPS;
+            for step in 1..=Self::STEPS {
+                if skip_ramp.load(Ordering::Relaxed) {
+                    break;
+                }
+                incoming.set(easing_function(step as f32 / Self::STEPS as f32, curve));
+                thread::sleep(tick);
+            }
+            incoming.set(1.0);
+            on_finished();
+        });
+
+        Self { skip, handle }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Jumps to the end of the ramp and waits until the old renderer is stopped.
+    pub fn finish(self) {
+        self.skip.store(true, Ordering::Relaxed);
+        let _ = self.handle.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{easing_function, EasingCurve, SurfaceAlpha, TransitionCoordinator};
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    #[test]
+    fn ramp_waits_for_the_first_frame_and_ends_fully_swapped() {
+        let incoming = SurfaceAlpha::new(0.0);
+        assert_eq!(incoming.factor(), 0);
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopped_flag = Arc::clone(&stopped);
+        let coordinator = TransitionCoordinator::start(
+            Duration::from_millis(60),
+            EasingCurve::EaseInOut,
+            Arc::clone(&incoming),
+            move || stopped_flag.store(true, Ordering::Relaxed),
+        );
+
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(incoming.factor(), 0, "ramp started before the first frame");
+
+        incoming.mark_shown();
+        while !coordinator.is_finished() {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        coordinator.finish();
+        assert_eq!(incoming.factor(), u32::MAX);
+        assert!(stopped.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn easing_curves_match_their_formulas() {
+        let expected = [
+            (EasingCurve::Linear, [0.0, 0.25, 0.5, 0.75, 1.0]),
+            (EasingCurve::EaseIn, [0.0, 0.0625, 0.25, 0.5625, 1.0]),
+            (EasingCurve::EaseOut, [0.0, 0.4375, 0.75, 0.9375, 1.0]),
+            (EasingCurve::EaseInOut, [0.0, 0.15625, 0.5, 0.84375, 1.0]),
+        ];
+        for (curve, values) in expected {
+            for (t, value) in [0.0, 0.25, 0.5, 0.75, 1.0].into_iter().zip(values) {
+                let eased = easing_function(t, curve);
+                assert!((eased - value).abs() < 1e-6, "{:?} at {}: {}", curve, t, eased);
+            }
+        }
+    }
+}
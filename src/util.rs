@@ -0,0 +1,47 @@
+//! Small parsers shared by the CLI and the config.
+
+use anyhow::{anyhow, Result};
+
+/// A byte count such as `1048576`, `500KB`, `1.5MB` or `2GiB`. Suffixes are
+/// case-insensitive and binary (`1KB` is 1024 bytes), as in `du -h`.
+pub fn parse_size(s: &str) -> Result<u64> {
+    let value = s.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, suffix) = value.split_at(split);
+    let multiplier: u64 = match suffix.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        other => return Err(anyhow!("unknown size suffix '{}' in '{}'", other, s)),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow!("'{}' is not a size like 500KB or 2GB", s))?;
+    let bytes = number * multiplier as f64;
+    if !bytes.is_finite() || bytes > u64::MAX as f64 {
+        return Err(anyhow!("size '{}' is too large", s));
+    }
+    Ok(bytes.round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_size;
+
+    #[test]
+    fn sizes_take_binary_suffixes() {
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert_eq!(parse_size("12b").unwrap(), 12);
+        assert_eq!(parse_size("500KB").unwrap(), 500 * 1024);
+        assert_eq!(parse_size(" 1.5 MiB ").unwrap(), 1_572_864);
+        assert_eq!(parse_size("2gb").unwrap(), 2 << 30);
+        assert_eq!(parse_size("1M").unwrap(), 1 << 20);
+        for invalid in ["", "MB", "1.2.3MB", "10 parsecs", "-5KB"] {
+            assert!(parse_size(invalid).is_err(), "{:?} parsed", invalid);
+        }
+    }
+}
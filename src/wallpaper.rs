@@ -1,59 +1,229 @@
 use anyhow::{anyhow, Context, Result};
-use crate::config::FitMode;
+use crate::colorspace;
+use crate::config::{CompositorType, FitMode, ScaleFilter, SurfaceAnchor, Transition};
+use crate::dmabuf::{DmabufRenderer, Presented, DRM_FORMAT_MOD_INVALID};
+use crate::effects::{self, Effect, WatermarkConfig};
+use crate::formats;
+use crate::gpu;
+use crate::hyprland_ipc::CompositorVisibility;
+use crate::limits::ResourceLimits;
+use crate::monitors::MonitorGeometry;
+use crate::output::{debug, trace, warning};
+use crate::picker;
+use crate::shm::{ShmBuffer, ShmPoolRegistry};
+use crate::shm_cache;
+use crate::source::WallpaperSource;
+use crate::state;
+use crate::transition::{self, EasingCurve, SurfaceAlpha};
 use gstreamer as gst;
 use gstreamer::prelude::*;
 use gstreamer_app as gst_app;
+use gstreamer_pbutils as gst_pbutils;
 use gstreamer_video as gst_video;
-use image::{imageops, imageops::FilterType, DynamicImage, RgbaImage};
-use memmap2::MmapMut;
+use image::{
+    codecs::webp::WebPDecoder, imageops, imageops::FilterType, AnimationDecoder, DynamicImage,
+    RgbaImage,
+};
 use std::{
+    cell::OnceCell,
     fs::File,
-    fs::OpenOptions,
-    os::fd::AsFd,
-    process,
-    process::Command,
+    io::{BufReader, Read},
+    os::{fd::AsFd, unix::net::UnixStream},
     path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicBool, AtomicU64, Ordering},
+        atomic::{AtomicBool, Ordering},
         Arc,
     },
     time::Instant,
     time::Duration,
 };
 use wayland_client::{
-    globals::{registry_queue_init, GlobalListContents},
+    backend::WaylandError,
+    globals::{registry_queue_init, GlobalList, GlobalListContents},
     protocol::{
         wl_buffer, wl_compositor, wl_output, wl_registry, wl_shm, wl_shm_pool, wl_surface,
     },
-    Connection, Dispatch, EventQueue, QueueHandle,
+    Connection, Dispatch, EventQueue, QueueHandle, WEnum,
+};
+use wayland_protocols::wp::{
+    alpha_modifier::v1::client::{wp_alpha_modifier_surface_v1, wp_alpha_modifier_v1},
+    linux_dmabuf::zv1::client::{zwp_linux_buffer_params_v1, zwp_linux_dmabuf_v1},
+    viewporter::client::{wp_viewport, wp_viewporter},
 };
-use wayland_protocols_wlr::layer_shell::v1::client::{
-    zwlr_layer_shell_v1, zwlr_layer_surface_v1,
+use wayland_protocols_wlr::{
+    layer_shell::v1::client::{zwlr_layer_shell_v1, zwlr_layer_surface_v1},
+    output_power_management::v1::client::{zwlr_output_power_manager_v1, zwlr_output_power_v1},
 };
 
-static BUFFER_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+const FADE_TRANSITION_STEPS: u32 = 12;
+/// Idle image renderers poll a daemon fade this often instead of every 50ms.
+const ALPHA_POLL_INTERVAL: Duration = Duration::from_millis(8);
+const FILE_STARTUP_TIMEOUT: Duration = Duration::from_secs(2);
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How often `set --delay` checks whether the renderer was stopped meanwhile.
+const STARTUP_DELAY_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Streams need time to fetch a manifest and the first segment.
+const STREAM_STARTUP_TIMEOUT: Duration = Duration::from_secs(15);
+
+pub const DEFAULT_LAYER_NAMESPACE: &str = "papdieo";
+pub const DEFAULT_EXCLUSIVE_ZONE: i32 = -1;
+pub const DEFAULT_TRANSITION_DURATION: Duration = Duration::from_millis(300);
+pub const DEFAULT_VIDEO_RETRIES: u32 = 3;
+/// First retry delay; it doubles with every further retry, up to the max.
+const VIDEO_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+const VIDEO_RETRY_MAX_BACKOFF: Duration = Duration::from_secs(8);
+/// A file that grew since the last try is likely still downloading: it gets
+/// this many times the retries, at a short fixed delay.
+const PARTIAL_FILE_RETRY_FACTOR: u32 = 4;
+const PARTIAL_FILE_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// Per-renderer settings resolved from CLI flags and config.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    pub fps: u32,
+    pub fit_mode: FitMode,
+    pub transition: Transition,
+    pub scale_filter: Option<ScaleFilter>,
+    /// Pixel shift applied to images: moves the crop window for cover/fill and
+    /// the placed image for contain/fit/center. Positive values go right/down.
+    pub offset: (i32, i32),
+    /// Size images are scaled to before `tile` repeats them; a 0 side keeps the
+    /// aspect ratio, `None` or 0x0 tiles at native size.
+    pub tile_size: Option<(u32, u32)>,
+    /// `[x, y, w, h]` as fractions of the source image that cover/fill crops keep
+    /// in view instead of centering.
+    pub focus_area: Option<[f32; 4]>,
+    pub compositor: CompositorType,
+    /// wlr-layer-shell namespace, matchable by compositor layer rules.
+    pub layer_namespace: String,
+    /// -1 draws under panels edge-to-edge; a positive value reserves that many pixels.
+    pub exclusive_zone: i32,
+    pub anchor: SurfaceAnchor,
+    /// Only applied to renderers started as child processes.
+    pub resource_limits: ResourceLimits,
+    /// Where video files start and loop back to; live streams ignore it.
+    pub video_start: Duration,
+    /// Pause between the surface being configured and the first frame (`set --delay`).
+    pub startup_delay: Duration,
+    /// Restarts of a pipeline that fails mid-playback.
+    pub video_retries: u32,
+    /// Passes through a video before it stops; 0 loops forever.
+    pub video_loops: u32,
+    /// Shown instead of the last frame once `video_loops` passes have played.
+    pub loop_fallback: Option<PathBuf>,
+    /// Shown when no video pipeline can play the file; `None` shows neutral grey.
+    pub video_fallback: Option<PathBuf>,
+    /// Redraw at the new size when the output's transform turns it (`set --auto-rotate`).
+    pub auto_rotate: bool,
+    /// Logo composited over the wallpaper after the effects.
+    pub watermark: Option<WatermarkConfig>,
+    /// GPU that decodes video, in DRM render node order; `None` lets GStreamer choose.
+    pub gpu_index: Option<u32>,
+    /// Channel multiplier, 0.0 to 1.0; 1.0 leaves wallpapers as they are.
+    pub brightness: f32,
+    /// Stretch around mid-grey, 0.0 to 2.0; 1.0 leaves wallpapers as they are.
+    pub contrast: f32,
+    /// Applied in order after brightness and contrast.
+    pub effects: Vec<Effect>,
+    /// Gaussian sigma of the `blur` effect, in pixels, for images and videos alike.
+    pub blur_sigma: f32,
+    /// Videos play their audio track only when this is false.
+    pub mute_audio: bool,
+    pub transition_duration: Duration,
+    pub transition_curve: EasingCurve,
+    /// Time each page of a multi-page TIFF is shown.
+    pub slideshow_delay: Duration,
+    /// Draw a still image on every output from this one renderer (`set --all-outputs`).
+    pub all_outputs: bool,
+    /// How long a video keeps its render state after windows cover or uncover
+    /// the desktop before following again.
+    pub visibility_hysteresis: Duration,
+    /// Share fitted still frames with other renderers through [`shm_cache`] (`set --cache-key`).
+    pub frame_cache_key: Option<String>,
+    /// Set by the daemon so it can fade this surface through wp_alpha_modifier_v1.
+    pub surface_alpha: Option<Arc<SurfaceAlpha>>,
+    /// Compositor socket from `wayland_display`/`xdg_runtime_dir`; `None`
+    /// connects through `WAYLAND_DISPLAY` as usual.
+    pub wayland_socket: Option<PathBuf>,
+}
+
+impl RenderOptions {
+    /// Images default to Lanczos3 for quality.
+    fn image_filter(&self) -> FilterType {
+        self.scale_filter
+            .map(ScaleFilter::filter_type)
+            .unwrap_or(FilterType::Lanczos3)
+    }
+
+    /// Video frames are rescaled every frame, so default to a cheap bilinear filter.
+    fn video_filter(&self) -> FilterType {
+        self.scale_filter
+            .map(ScaleFilter::filter_type)
+            .unwrap_or(FilterType::Triangle)
+    }
+}
+
+/// Socket the renderers connect to when the config names a display or runtime
+/// dir. Whatever it leaves out comes from the environment, as for
+/// `wl_display_connect`; `None` when neither is configured.
+pub fn wayland_socket_path(display: Option<&str>, runtime_dir: Option<&Path>) -> Option<PathBuf> {
+    if display.is_none() && runtime_dir.is_none() {
+        return None;
+    }
+    let display = display
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("WAYLAND_DISPLAY").map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("wayland-0"));
+    if display.is_absolute() {
+        return Some(display);
+    }
+    let runtime_dir = runtime_dir
+        .map(Path::to_path_buf)
+        .or_else(|| std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from))?;
+    Some(runtime_dir.join(display))
+}
+
+fn connect_wayland(socket: Option<&Path>) -> Result<Connection> {
+    let Some(socket) = socket else {
+        return Connection::connect_to_env().context("failed to connect to Wayland");
+    };
+    let stream = UnixStream::connect(socket)
+        .with_context(|| format!("failed to connect to Wayland at {}", socket.display()))?;
+    Connection::from_socket(stream).context("failed to connect to Wayland")
+}
 
 pub fn run_wallpaper(
     path: PathBuf,
     monitor_name: Option<&str>,
-    fps: u32,
-    fit_mode: FitMode,
+    options: &RenderOptions,
 ) -> Result<()> {
-    run_wallpaper_with_stop(path, monitor_name, fps, fit_mode, None)
+    run_wallpaper_with_stop(path, monitor_name, options, None)
 }
 
 pub fn run_wallpaper_with_stop(
     path: PathBuf,
     monitor_name: Option<&str>,
-    fps: u32,
-    fit_mode: FitMode,
+    options: &RenderOptions,
     stop_signal: Option<&AtomicBool>,
 ) -> Result<()> {
-    if !path.exists() {
+    let fps = options.fps;
+    let fit_mode = options.fit_mode;
+
+    let source = WallpaperSource::parse(&path)?;
+    if source.is_stream() {
+        source.check_reachable()?;
+    } else if !path.exists() {
         return Err(anyhow!("wallpaper does not exist: {}", path.display()));
     }
+    let is_video = source.is_stream() || is_video_file(&path);
+    if options.all_outputs {
+        if is_video {
+            return Err(anyhow!("--all-outputs only draws still images, not {}", path.display()));
+        }
+        return run_still_on_all_outputs(&path, options, stop_signal);
+    }
 
-    let connection = Connection::connect_to_env().context("failed to connect to Wayland")?;
+    let connection = connect_wayland(options.wayland_socket.as_deref())?;
     let (globals, mut event_queue) =
         registry_queue_init::<AppState>(&connection).context("failed to init globals")?;
     let qh = event_queue.handle();
@@ -66,11 +236,399 @@ pub fn run_wallpaper_with_stop(
         .bind(&qh, 1..=4, ())
         .context("missing zwlr_layer_shell_v1 (wlr-layer-shell)")?;
 
+    // Bound before the output roundtrips so the compositor's format list arrives with them.
+    let (dmabuf, viewporter) = if is_video {
+        (
+            globals
+                .bind::<zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1, _, _>(&qh, 3..=3, ())
+                .ok(),
+            globals
+                .bind::<wp_viewporter::WpViewporter, _, _>(&qh, 1..=1, ())
+                .ok(),
+        )
+    } else {
+        (None, None)
+    };
+    let alpha_modifier = options.surface_alpha.as_ref().and_then(|_| {
+        globals
+            .bind::<wp_alpha_modifier_v1::WpAlphaModifierV1, _, _>(&qh, 1..=1, ())
+            .ok()
+    });
+    // Only videos have decoding to pause while their monitor is powered off.
+    let power_manager = is_video
+        .then(|| {
+            globals
+                .bind::<zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1, _, _>(
+                    &qh,
+                    1..=1,
+                    (),
+                )
+                .ok()
+        })
+        .flatten();
+
     let mut state = AppState::new(path.clone(), monitor_name.map(str::to_string));
+    state.loop_count_remaining = (options.video_loops > 0).then_some(options.video_loops);
+    state.loop_fallback = options.loop_fallback.clone();
+    state.auto_rotate = options.auto_rotate;
 
-    let output_globals: Vec<_> = globals
-        .contents()
-        .clone_list()
+    discover_outputs(&globals, &mut event_queue, &mut state)?;
+
+    let selected_output = state.select_output()?;
+    let selected = state.outputs.iter().find(|out| out.output == selected_output);
+    state.selected_output = selected.map(|out| out.global_name);
+    if let (Some(alpha), Some(name)) = (
+        &options.surface_alpha,
+        selected.and_then(|out| out.name.as_deref()),
+    ) {
+        alpha.set_output(name);
+    }
+    state.monitor_power = power_manager
+        .as_ref()
+        .map(|manager| manager.get_output_power(&selected_output, &qh, ()));
+    let output_scale = state.output_scale(&selected_output);
+    if let Some((width, height)) = state.output_resolution(&selected_output) {
+        // The mode is in physical pixels; surface sizes are logical.
+        state.width = width / output_scale as u32;
+        state.height = height / output_scale as u32;
+    }
+
+    let surface = compositor.create_surface(&qh, ());
+    // On an integer-scaled output, shm frames are drawn at the physical size so
+    // they are shown 1:1. DMA-BUF frames are sized by the viewport instead, and
+    // the DMA-BUF renderer switches the scale whenever it takes over or hands back.
+    if output_scale > 1 {
+        debug!("output scale {}, drawing at {}x the surface size", output_scale, output_scale);
+        surface.set_buffer_scale(output_scale);
+        state.buffer_scale = output_scale as u32;
+    }
+    state.alpha = options.surface_alpha.clone().map(|shared| {
+        let modifier = alpha_modifier.as_ref().map(|manager| {
+            let modifier = manager.get_surface(&surface, &qh, ());
+            // Applied by the first commit, so a faded-in surface never shows opaque.
+            modifier.set_multiplier(shared.factor());
+            modifier
+        });
+        SurfaceAlphaSync {
+            applied: shared.factor(),
+            shared,
+            modifier,
+        }
+    });
+    // The daemon fades in surfaces it starts transparent; everything else keeps
+    // the CPU fade from black.
+    let transition = match &state.alpha {
+        Some(alpha) if alpha.modifier.is_some() && alpha.applied == 0 => Transition::None,
+        _ => options.transition,
+    };
+    let layer_surface = layer_shell.get_layer_surface(
+        &surface,
+        Some(&selected_output),
+        zwlr_layer_shell_v1::Layer::Background,
+        options.layer_namespace.clone(),
+        &qh,
+        (),
+    );
+
+    layer_surface.set_anchor(anchor_edges(options.anchor));
+    let (surface_width, surface_height) = match options.anchor {
+        SurfaceAnchor::AllEdges => (0, 0),
+        _ => anchored_surface_size(&path, is_video, (state.width.max(1), state.height.max(1))),
+    };
+    layer_surface.set_size(surface_width, surface_height);
+    warn_unused_exclusive_zone(options);
+    layer_surface.set_exclusive_zone(options.exclusive_zone);
+    surface.commit();
+
+    while !state.configured {
+        if stop_signal
+            .map(|signal| signal.load(Ordering::Relaxed))
+            .unwrap_or(false)
+        {
+            state.exit = true;
+            break;
+        }
+        event_queue
+            .blocking_dispatch(&mut state)
+            .context("failed during initial Wayland dispatch")?;
+    }
+    if !state.exit {
+        wait_for_startup_delay(options.startup_delay, stop_signal);
+    }
+
+    if stop_signal
+        .map(|signal| signal.load(Ordering::Relaxed))
+        .unwrap_or(false)
+    {
+        return Ok(());
+    }
+
+    let mut frame_renderer = FrameRenderer::new(
+        state.width.max(1),
+        state.height.max(1),
+        state.requested_monitor.as_deref(),
+        &shm,
+        &qh,
+    )?;
+
+    if is_video {
+        let mut dmabuf_renderer = dmabuf.map(|dmabuf| {
+            DmabufRenderer::new(
+                dmabuf,
+                viewporter.as_ref(),
+                std::mem::take(&mut state.dmabuf_formats),
+                &surface,
+                (state.surface_size(), state.buffer_scale as i32),
+                fit_mode,
+                &qh,
+            )
+        });
+        let played = loop {
+            let output = (state.width.max(1), state.height.max(1));
+            let filter_stage = video_filter_stage(options, output);
+            let played = play_video_loop(
+                &path,
+                &surface,
+                &mut frame_renderer,
+                dmabuf_renderer.as_mut(),
+                &mut event_queue,
+                &mut state,
+                fps.max(1),
+                fit_mode,
+                options.video_filter(),
+                (options.compositor, options.visibility_hysteresis),
+                options.video_start,
+                options.video_retries,
+                options.gpu_index,
+                &filter_stage,
+                !options.mute_audio,
+                stop_signal,
+            );
+            if played.is_err() || !state.take_resize() {
+                break played;
+            }
+            // The pipeline's caps carry the output size, so it starts over at the new one.
+            let size = (state.width.max(1), state.height.max(1));
+            debug!("output rotated, restarting the video at {}x{}", size.0, size.1);
+            frame_renderer.resize(size, &shm, &qh)?;
+            if let Some(dmabuf) = dmabuf_renderer.as_mut() {
+                dmabuf.set_output_size(state.surface_size());
+            }
+        };
+        let still = match played {
+            Ok(()) if state.loops_exhausted() => state.loop_fallback.clone(),
+            Ok(()) => None,
+            // Exiting would only get the same undecodable file restarted; a still keeps the
+            // monitor covered. Streams, Wayland and setup failures still exit, so the daemon
+            // backs off and counts them.
+            Err(error) if falls_back_to_still(&error, source.is_stream()) => {
+                warning!("warning: {:#}", error);
+                Some(video_error_fallback(options)?)
+            }
+            Err(error) => return Err(error),
+        };
+        if !state.exit {
+            if let Some(fallback) = still {
+                debug!("video stopped, showing {}", fallback.display());
+                if let Some(dmabuf) = dmabuf_renderer.as_mut() {
+                    dmabuf.reset_viewport(&surface);
+                }
+                state.path = fallback;
+                show_still_image(
+                    &mut state,
+                    &surface,
+                    &mut frame_renderer,
+                    &mut event_queue,
+                    (&shm, &qh),
+                    options,
+                    Transition::None,
+                    stop_signal,
+                )?;
+            }
+        }
+    } else if let Some(mut frames) = decode_animated_frames(&path, options.slideshow_delay)? {
+        for (frame, _) in &mut frames {
+            colorspace::apply_image_adjustments(frame, options.brightness, options.contrast);
+            effects::apply_effects(frame, &options.effects, options.blur_sigma);
+        }
+        play_animated_loop(
+            frames,
+            &surface,
+            &mut frame_renderer,
+            &mut event_queue,
+            &mut state,
+            fps.max(1),
+            fit_mode,
+            options.image_filter(),
+            options.offset,
+            load_watermark(options),
+            stop_signal,
+        )?;
+    } else {
+        show_still_image(
+            &mut state,
+            &surface,
+            &mut frame_renderer,
+            &mut event_queue,
+            (&shm, &qh),
+            options,
+            transition,
+            stop_signal,
+        )?;
+    }
+
+    release_surfaces(&[(surface, layer_surface)], &mut event_queue, &mut state);
+    drop(frame_renderer);
+    Ok(())
+}
+
+/// Destroys the surfaces and waits for the compositor to release their
+/// buffers, so the renderer can pool them.
+fn release_surfaces(
+    surfaces: &[(wl_surface::WlSurface, zwlr_layer_surface_v1::ZwlrLayerSurfaceV1)],
+    event_queue: &mut EventQueue<AppState>,
+    state: &mut AppState,
+) {
+    for (surface, layer_surface) in surfaces {
+        layer_surface.destroy();
+        surface.destroy();
+    }
+    if let Err(error) = event_queue.roundtrip(state) {
+        debug!("no buffer release before exiting: {}", error);
+    }
+}
+
+/// One layer surface per output, all showing the same image decoded once.
+/// Outputs that appear later are not covered, and `auto_rotate`, transitions
+/// and the daemon's fades do not apply.
+fn run_still_on_all_outputs(
+    path: &Path,
+    options: &RenderOptions,
+    stop_signal: Option<&AtomicBool>,
+) -> Result<()> {
+    if decode_animated_frames(path, options.slideshow_delay)?.is_some() {
+        return Err(anyhow!("--all-outputs only draws still images, not {}", path.display()));
+    }
+    let filter = options.image_filter();
+    let image = formats::open_image(path)?;
+    let image = match (options.fit_mode, options.tile_size) {
+        (FitMode::Tile, Some(size)) => scale_tile(image, size, filter),
+        _ => image,
+    };
+    let watermark = load_watermark(options);
+
+    let connection = connect_wayland(options.wayland_socket.as_deref())?;
+    let (globals, mut event_queue) =
+        registry_queue_init::<AppState>(&connection).context("failed to init globals")?;
+    let qh = event_queue.handle();
+    let compositor: wl_compositor::WlCompositor = globals
+        .bind(&qh, 4..=6, ())
+        .context("missing wl_compositor")?;
+    let shm: wl_shm::WlShm = globals.bind(&qh, 1..=1, ()).context("missing wl_shm")?;
+    let layer_shell: zwlr_layer_shell_v1::ZwlrLayerShellV1 = globals
+        .bind(&qh, 1..=4, ())
+        .context("missing zwlr_layer_shell_v1 (wlr-layer-shell)")?;
+
+    let mut state = AppState::new(path.to_path_buf(), None);
+    discover_outputs(&globals, &mut event_queue, &mut state)?;
+
+    let mut surfaces = Vec::with_capacity(state.outputs.len());
+    for (index, out) in state.outputs.iter().enumerate() {
+        let scale = out.scale_factor.max(1);
+        let (width, height) = out
+            .resolution
+            .map(|(width, height)| (width / scale as u32, height / scale as u32))
+            .unwrap_or((state.width, state.height));
+        let surface = compositor.create_surface(&qh, ());
+        if scale > 1 {
+            surface.set_buffer_scale(scale);
+        }
+        let layer_surface = layer_shell.get_layer_surface(
+            &surface,
+            Some(&out.output),
+            zwlr_layer_shell_v1::Layer::Background,
+            options.layer_namespace.clone(),
+            &qh,
+            index,
+        );
+        layer_surface.set_anchor(anchor_edges(options.anchor));
+        let (surface_width, surface_height) = match options.anchor {
+            SurfaceAnchor::AllEdges => (0, 0),
+            _ => anchored_surface_size(path, false, (width.max(1), height.max(1))),
+        };
+        layer_surface.set_size(surface_width, surface_height);
+        layer_surface.set_exclusive_zone(options.exclusive_zone);
+        surface.commit();
+        surfaces.push((surface, layer_surface));
+        state.output_surfaces.push(OutputSurface {
+            width,
+            height,
+            buffer_scale: scale as u32,
+            configured: false,
+            closed: false,
+        });
+    }
+
+    warn_unused_exclusive_zone(options);
+    let stopped = || stop_signal.is_some_and(|signal| signal.load(Ordering::Relaxed));
+    while !state.output_surfaces.iter().all(|out| out.configured || out.closed) {
+        if stopped() {
+            return Ok(());
+        }
+        event_queue
+            .blocking_dispatch(&mut state)
+            .context("failed during initial Wayland dispatch")?;
+    }
+    wait_for_startup_delay(options.startup_delay, stop_signal);
+    if stopped() {
+        return Ok(());
+    }
+
+    // The buffers must outlive the hold loop, or the compositor loses the frames.
+    let mut renderers = Vec::with_capacity(surfaces.len());
+    for ((surface, _), out) in surfaces.iter().zip(&state.output_surfaces) {
+        if out.closed {
+            continue;
+        }
+        let (width, height) = (out.width.max(1), out.height.max(1));
+        let (fit_mode, offset, focus) = (options.fit_mode, options.offset, options.focus_area);
+        let mut rendered = render_image_fit(&image, width, height, fit_mode, filter, offset, focus);
+        colorspace::apply_image_adjustments(&mut rendered, options.brightness, options.contrast);
+        effects::apply_effects(&mut rendered, &options.effects, options.blur_sigma);
+        if let Some((mark, watermark)) = &watermark {
+            effects::apply_watermark(&mut rendered, mark, watermark);
+        }
+        let mut renderer = FrameRenderer::new(width, height, None, &shm, &qh)?;
+        draw_image_frame(rendered.as_raw(), surface, &mut renderer)?;
+        renderers.push(renderer);
+    }
+    debug!("showing {} on {} outputs", path.display(), renderers.len());
+
+    while !stopped() && !state.output_surfaces.iter().all(|out| out.closed) {
+        event_queue
+            .dispatch_pending(&mut state)
+            .context("failed during Wayland event dispatch")?;
+        event_queue.flush().ok();
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    release_surfaces(&surfaces, &mut event_queue, &mut state);
+    drop(renderers);
+    Ok(())
+}
+
+/// Binds every wl_output and waits for their names, modes and scales.
+fn discover_outputs(
+    globals: &GlobalList,
+    event_queue: &mut EventQueue<AppState>,
+    state: &mut AppState,
+) -> Result<()> {
+    let qh = event_queue.handle();
+    let global_list = globals.contents().clone_list();
+    for g in global_list.iter() {
+        trace!("wayland global: {} v{} (name {})", g.interface, g.version, g.name);
+    }
+
+    let output_globals: Vec<_> = global_list
         .into_iter()
         .filter(|g| g.interface == "wl_output")
         .collect();
@@ -87,48 +645,114 @@ pub fn run_wallpaper_with_stop(
                 .bind::<wl_output::WlOutput, _, _>(g.name, version, &qh, g.name);
         state.outputs.push(OutputBinding {
             global_name: g.name,
+            version,
             output,
             name: None,
             description: None,
+            resolution: None,
+            scale_factor: 1,
+            transform: wl_output::Transform::Normal,
         });
     }
 
     event_queue
-        .roundtrip(&mut state)
+        .roundtrip(state)
         .context("failed to discover monitor names")?;
 
-    for _ in 0..6 {
+    // wl_output v3 has no name/description events; metadata comes from the
+    // geometry event instead, which some compositors send late.
+    let metadata_rounds = if state.outputs.iter().all(|out| out.version < 4) {
+        debug!("all wl_output globals are below v4, matching monitors by make/model");
+        12
+    } else {
+        6
+    };
+    for _ in 0..metadata_rounds {
         if state.has_resolved_requested_output() || state.all_outputs_have_metadata() {
             break;
         }
         event_queue
-            .roundtrip(&mut state)
+            .roundtrip(state)
             .context("failed while waiting for monitor metadata")?;
     }
 
-    let selected_output = state.select_output()?;
+    for out in state.outputs.iter() {
+        debug!(
+            "wayland output {} (v{}): name={}, description={}, mode={}, scale={}",
+            out.global_name,
+            out.version,
+            out.name.as_deref().unwrap_or("unknown"),
+            out.description.as_deref().unwrap_or("unknown"),
+            out.resolution
+                .map(|(w, h)| format!("{}x{}", w, h))
+                .unwrap_or_else(|| "unknown".to_string()),
+            out.scale_factor
+        );
+    }
+    Ok(())
+}
 
-    let surface = compositor.create_surface(&qh, ());
-    let layer_surface = layer_shell.get_layer_surface(
-        &surface,
-        Some(&selected_output),
-        zwlr_layer_shell_v1::Layer::Background,
-        "papdieo".into(),
-        &qh,
-        (),
-    );
+/// Only a local file that could not be decoded gets a still instead of the
+/// renderer exiting.
+fn falls_back_to_still(error: &anyhow::Error, is_stream: bool) -> bool {
+    !is_stream && error.is::<VideoDecodeError>()
+}
 
-    layer_surface.set_anchor(
-        zwlr_layer_surface_v1::Anchor::Top
-            | zwlr_layer_surface_v1::Anchor::Bottom
-            | zwlr_layer_surface_v1::Anchor::Left
-            | zwlr_layer_surface_v1::Anchor::Right,
-    );
-    layer_surface.set_size(0, 0);
-    layer_surface.set_exclusive_zone(-1);
-    surface.commit();
+/// `video_fallback_image` when it exists, otherwise a neutral grey.
+fn video_error_fallback(options: &RenderOptions) -> Result<PathBuf> {
+    match options.video_fallback.as_ref().filter(|path| path.exists()) {
+        Some(path) => Ok(path.clone()),
+        None => picker::gray_wallpaper(),
+    }
+}
 
-    while !state.configured {
+/// Draws `state.path` and keeps it up until the renderer is stopped, drawing
+/// it again at the new size whenever `auto_rotate` sees the output turn.
+#[allow(clippy::too_many_arguments)]
+fn show_still_image(
+    state: &mut AppState,
+    surface: &wl_surface::WlSurface,
+    renderer: &mut FrameRenderer,
+    event_queue: &mut EventQueue<AppState>,
+    (shm, qh): (&wl_shm::WlShm, &QueueHandle<AppState>),
+    options: &RenderOptions,
+    mut transition: Transition,
+    stop_signal: Option<&AtomicBool>,
+) -> Result<()> {
+    loop {
+        draw_image(
+            state,
+            surface,
+            renderer,
+            event_queue,
+            options.fit_mode,
+            options.image_filter(),
+            options.offset,
+            options,
+            transition,
+            options.transition_duration,
+        )?;
+        hold_still_frame(state, surface, event_queue, stop_signal)?;
+        if !state.take_resize() {
+            return Ok(());
+        }
+        let size = (state.width.max(1), state.height.max(1));
+        debug!("output rotated, redrawing at {}x{}", size.0, size.1);
+        renderer.resize(size, shm, qh)?;
+        transition = Transition::None;
+    }
+}
+
+/// Keeps a committed still frame on screen until the renderer is stopped or,
+/// with `auto_rotate`, the output turns.
+fn hold_still_frame(
+    state: &mut AppState,
+    surface: &wl_surface::WlSurface,
+    event_queue: &mut EventQueue<AppState>,
+    stop_signal: Option<&AtomicBool>,
+) -> Result<()> {
+    state.sync_alpha(surface);
+    while !state.exit && !state.resize_needed {
         if stop_signal
             .map(|signal| signal.load(Ordering::Relaxed))
             .unwrap_or(false)
@@ -136,112 +760,433 @@ pub fn run_wallpaper_with_stop(
             state.exit = true;
             break;
         }
+        if state.auto_rotate {
+            read_wayland_events(event_queue)?;
+        }
         event_queue
-            .blocking_dispatch(&mut state)
-            .context("failed during initial Wayland dispatch")?;
+            .dispatch_pending(state)
+            .context("failed during Wayland event dispatch")?;
+        state.sync_alpha(surface);
+        event_queue.flush().ok();
+        std::thread::sleep(if state.alpha_is_fading() {
+            ALPHA_POLL_INTERVAL
+        } else {
+            Duration::from_millis(50)
+        });
     }
+    Ok(())
+}
 
-    if stop_signal
-        .map(|signal| signal.load(Ordering::Relaxed))
-        .unwrap_or(false)
-    {
+/// Queues whatever events the compositor has sent, without blocking.
+fn read_wayland_events(event_queue: &EventQueue<AppState>) -> Result<()> {
+    let Some(guard) = event_queue.prepare_read() else {
         return Ok(());
+    };
+    match guard.read() {
+        Err(WaylandError::Io(error)) if error.kind() == std::io::ErrorKind::WouldBlock => Ok(()),
+        result => result.map(drop).context("failed reading Wayland events"),
     }
+}
 
-    let mut frame_renderer = FrameRenderer::new(state.width.max(1), state.height.max(1), &shm, &qh)?;
+fn anchor_edges(anchor: SurfaceAnchor) -> zwlr_layer_surface_v1::Anchor {
+    use zwlr_layer_surface_v1::Anchor;
+    match anchor {
+        SurfaceAnchor::AllEdges => Anchor::Top | Anchor::Bottom | Anchor::Left | Anchor::Right,
+        SurfaceAnchor::TopLeft => Anchor::Top | Anchor::Left,
+        SurfaceAnchor::TopRight => Anchor::Top | Anchor::Right,
+        SurfaceAnchor::BottomLeft => Anchor::Bottom | Anchor::Left,
+        SurfaceAnchor::BottomRight => Anchor::Bottom | Anchor::Right,
+        // Layer shell centers a surface that is not anchored to any edge.
+        SurfaceAnchor::Center => Anchor::empty(),
+    }
+}
 
-    if is_video_file(&path) {
-        play_video_loop(
-            &path,
-            &surface,
-            &mut frame_renderer,
-            &mut event_queue,
-            &mut state,
-            fps.max(1),
-            fit_mode,
-            stop_signal,
+/// wlr-layer-shell only reserves space for a surface anchored to one edge, or
+/// to one edge and both of its neighbours; any other surface gets 0.
+fn reserves_space(edges: zwlr_layer_surface_v1::Anchor) -> bool {
+    matches!(edges.bits().count_ones(), 1 | 3)
+}
+
+/// No `--anchor` is a single edge, so a positive `--exclusive-zone` is ignored
+/// by the compositor; say so rather than let it do nothing quietly.
+fn warn_unused_exclusive_zone(options: &RenderOptions) {
+    if options.exclusive_zone > 0 && !reserves_space(anchor_edges(options.anchor)) {
+        warning!(
+            "warning: --exclusive-zone {} has no effect: the compositor only reserves space for \
+             a surface anchored to a single edge",
+            options.exclusive_zone
+        );
+    }
+}
+
+/// An anchored surface is as large as the image, shrunk to fit the output.
+/// Videos and images whose size cannot be read get half the output.
+fn anchored_surface_size(path: &Path, is_video: bool, output: (u32, u32)) -> (u32, u32) {
+    let natural = (!is_video)
+        .then(|| image::image_dimensions(path).ok())
+        .flatten()
+        .unwrap_or((output.0 / 2, output.1 / 2));
+    fit_within(natural, output)
+}
+
+fn fit_within((width, height): (u32, u32), (max_width, max_height): (u32, u32)) -> (u32, u32) {
+    let scale = (max_width as f64 / width.max(1) as f64)
+        .min(max_height as f64 / height.max(1) as f64)
+        .min(1.0);
+    (
+        ((width as f64 * scale).round() as u32).max(1),
+        ((height as f64 * scale).round() as u32).max(1),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_image(
+    state: &mut AppState,
+    surface: &wl_surface::WlSurface,
+    renderer: &mut FrameRenderer,
+    event_queue: &mut EventQueue<AppState>,
+    fit_mode: FitMode,
+    filter: FilterType,
+    offset: (i32, i32),
+    options: &RenderOptions,
+    transition: Transition,
+    transition_duration: Duration,
+) -> Result<()> {
+    let width = state.width.max(1);
+    let height = state.height.max(1);
+
+    let cache_key = options.frame_cache_key.as_deref();
+    let cached = cache_key.and_then(|key| shm_cache::load(key, width, height, fit_mode));
+    let rendered;
+    let rgba = match &cached {
+        Some(frame) => {
+            debug!("using the cached {}x{} frame", width, height);
+            frame.rgba()
+        }
+        None => {
+            let image = formats::open_image(&state.path)?;
+            let image = match (fit_mode, options.tile_size) {
+                (FitMode::Tile, Some(size)) => scale_tile(image, size, filter),
+                _ => image,
+            };
+            let focus = options.focus_area;
+            let mut frame =
+                render_image_fit(&image, width, height, fit_mode, filter, offset, focus);
+            colorspace::apply_image_adjustments(&mut frame, options.brightness, options.contrast);
+            effects::apply_effects(&mut frame, &options.effects, options.blur_sigma);
+            let mark = state
+                .watermark
+                .get_or_init(|| load_watermark(options).map(|(mark, _)| mark));
+            if let (Some(mark), Some(watermark)) = (mark, &options.watermark) {
+                effects::apply_watermark(&mut frame, mark, watermark);
+            }
+            if let Some(key) = cache_key {
+                if let Err(error) = shm_cache::store(key, fit_mode, &frame) {
+                    warning!("warning: {:#}", error);
+                }
+            }
+            rendered = frame;
+            rendered.as_raw()
+        }
+    };
+
+    if transition == Transition::Fade {
+        play_fade_in(
+            rgba,
+            surface,
+            renderer,
+            event_queue,
+            state,
+            transition_duration,
+            options.transition_curve,
         )?;
-    } else {
-        draw_image(&state, &surface, &mut frame_renderer, fit_mode)?;
-        while !state.exit {
+    }
+
+    draw_image_frame(rgba, surface, renderer)
+}
+
+/// Frames of an animated WebP or JPEG XL, or the pages of a multi-page TIFF;
+/// `None` sends still images down the single-frame path.
+fn decode_animated_frames(
+    path: &Path,
+    slideshow_delay: Duration,
+) -> Result<Option<Vec<(RgbaImage, Duration)>>> {
+    if formats::is_tiff(path) {
+        return formats::decode_tiff_pages(path, slideshow_delay);
+    }
+    #[cfg(feature = "jxl")]
+    if formats::is_jxl(path) {
+        if !formats::jxl_is_animated(path)? {
+            return Ok(None);
+        }
+        return formats::decode_jxl_animated(path).map(Some);
+    }
+    decode_animated_webp(path)
+}
+
+/// Decodes every frame of an animated WebP. Static WebP files (and any other
+/// format) return `None` so they keep using the single-frame image path.
+fn decode_animated_webp(path: &Path) -> Result<Option<Vec<(RgbaImage, Duration)>>> {
+    let is_webp = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("webp"))
+        .unwrap_or(false);
+    if !is_webp {
+        return Ok(None);
+    }
+
+    let file = File::open(path)
+        .with_context(|| format!("failed to open image: {}", path.display()))?;
+    let decoder = WebPDecoder::new(BufReader::new(file))
+        .with_context(|| format!("failed to decode WebP: {}", path.display()))?;
+    if !decoder.has_animation() {
+        return Ok(None);
+    }
+
+    let frames = decoder
+        .into_frames()
+        .collect_frames()
+        .with_context(|| format!("failed to decode WebP animation: {}", path.display()))?;
+
+    Ok(Some(
+        frames
+            .into_iter()
+            .map(|frame| {
+                let delay = Duration::from(frame.delay());
+                (frame.into_buffer(), delay)
+            })
+            .collect(),
+    ))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn play_animated_loop(
+    frames: Vec<(RgbaImage, Duration)>,
+    surface: &wl_surface::WlSurface,
+    renderer: &mut FrameRenderer,
+    event_queue: &mut EventQueue<AppState>,
+    state: &mut AppState,
+    fps: u32,
+    fit_mode: FitMode,
+    filter: FilterType,
+    offset: (i32, i32),
+    watermark: Option<(RgbaImage, &WatermarkConfig)>,
+    stop_signal: Option<&AtomicBool>,
+) -> Result<()> {
+    if frames.is_empty() {
+        return Err(anyhow!("animation has no frames: {}", state.path.display()));
+    }
+
+    let width = state.width.max(1);
+    let height = state.height.max(1);
+    let min_frame_time = Duration::from_millis((1000 / fps.max(1)) as u64);
+    let rendered: Vec<(Vec<u8>, Duration)> = frames
+        .iter()
+        .map(|(frame, delay)| {
+            let mut fitted = render_rgba_fit(frame, width, height, fit_mode, filter, offset);
+            if let Some((mark, watermark)) = &watermark {
+                effects::apply_watermark(&mut fitted, mark, watermark);
+            }
+            (
+                colorspace::rgba_to_xrgb(fitted.as_raw()),
+                (*delay).max(min_frame_time),
+            )
+        })
+        .collect();
+
+    while !state.exit {
+        for (frame, delay) in rendered.iter() {
             if stop_signal
                 .map(|signal| signal.load(Ordering::Relaxed))
                 .unwrap_or(false)
             {
                 state.exit = true;
+            }
+            if state.exit {
                 break;
             }
+
+            let frame_started = Instant::now();
+            while renderer.all_slots_in_use() {
+                event_queue
+                    .blocking_dispatch(state)
+                    .context("failed while waiting for Wayland frame release")?;
+            }
+            draw_xrgb_frame(frame, surface, renderer)?;
+            state.sync_alpha(surface);
+            event_queue.flush().ok();
+
+            std::thread::sleep(delay.saturating_sub(frame_started.elapsed()));
             event_queue
-                .dispatch_pending(&mut state)
+                .dispatch_pending(state)
                 .context("failed during Wayland event dispatch")?;
-            event_queue.flush().ok();
-            std::thread::sleep(Duration::from_millis(50));
         }
     }
 
-    drop(layer_surface);
     Ok(())
 }
 
-fn draw_image(
-    state: &AppState,
+fn play_fade_in(
+    rgba_bytes: &[u8],
     surface: &wl_surface::WlSurface,
     renderer: &mut FrameRenderer,
-    fit_mode: FitMode,
+    event_queue: &mut EventQueue<AppState>,
+    state: &mut AppState,
+    duration: Duration,
+    curve: EasingCurve,
 ) -> Result<()> {
-    let width = state.width.max(1);
-    let height = state.height.max(1);
+    // Fading only scales the color bytes, so it can work on the converted frame.
+    let xrgb = colorspace::rgba_to_xrgb(rgba_bytes);
+    let mut faded = vec![0u8; xrgb.len()];
+
+    for step in 1..FADE_TRANSITION_STEPS {
+        if state.exit {
+            break;
+        }
+
+        let progress = step as f32 / FADE_TRANSITION_STEPS as f32;
+        let factor = transition::easing_function(progress, curve);
+        fade_rgba_frame(&xrgb, &mut faded, factor);
+
+        // The compositor holds on to the previously committed buffer until the
+        // next one is attached, so wait for a release instead of failing.
+        while renderer.all_slots_in_use() {
+            event_queue
+                .blocking_dispatch(state)
+                .context("failed while waiting for Wayland frame release")?;
+        }
+
+        draw_xrgb_frame(&faded, surface, renderer)?;
+        event_queue.flush().ok();
+        std::thread::sleep(duration / FADE_TRANSITION_STEPS);
+        event_queue
+            .dispatch_pending(state)
+            .context("failed during Wayland event dispatch")?;
+    }
+
+    while renderer.all_slots_in_use() {
+        event_queue
+            .blocking_dispatch(state)
+            .context("failed while waiting for Wayland frame release")?;
+    }
 
-    let image = image::open(&state.path)
-        .with_context(|| format!("failed to load image: {}", state.path.display()))?;
-    let rendered = render_image_fit(&image, width, height, fit_mode);
+    Ok(())
+}
 
-    draw_image_frame(rendered.as_raw(), surface, renderer)
+fn fade_rgba_frame(src: &[u8], dst: &mut [u8], factor: f32) {
+    let factor = factor.clamp(0.0, 1.0);
+    for (out, px) in dst.chunks_exact_mut(4).zip(src.chunks_exact(4)) {
+        out[0] = (px[0] as f32 * factor).round() as u8;
+        out[1] = (px[1] as f32 * factor).round() as u8;
+        out[2] = (px[2] as f32 * factor).round() as u8;
+        out[3] = px[3];
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn play_video_loop(
     path: &Path,
     surface: &wl_surface::WlSurface,
     renderer: &mut FrameRenderer,
+    mut dmabuf: Option<&mut DmabufRenderer>,
     event_queue: &mut EventQueue<AppState>,
     state: &mut AppState,
     fps: u32,
     fit_mode: FitMode,
+    filter: FilterType,
+    (compositor, visibility_hysteresis): (CompositorType, Duration),
+    video_start: Duration,
+    video_retries: u32,
+    gpu_index: Option<u32>,
+    balance_stage: &str,
+    play_audio: bool,
     stop_signal: Option<&AtomicBool>,
 ) -> Result<()> {
+    let decoders = gpu::decoders_for(gpu_index);
     gst::init().context("failed to initialize gstreamer")?;
 
     let width = state.width.max(1);
     let height = state.height.max(1);
 
-    let location = path
-        .to_str()
-        .ok_or_else(|| anyhow!("video path contains invalid UTF-8"))?
-        .replace('\\', "\\\\")
-        .replace('"', "\\\"");
-
-    let visibility = HyprlandVisibility::new(state.requested_monitor.as_deref());
+    let visibility = CompositorVisibility::new(
+        compositor,
+        state.requested_monitor.as_deref(),
+        visibility_hysteresis,
+    );
     let frame_timeout_ms = (1000 / fps.max(1)).max(4) as u64;
 
-    let descriptions = build_video_pipeline_descriptions(&location, width, height, fps, fit_mode);
+    let mut descriptions = Vec::new();
+    let mut start = video_start;
+    let mut retry = VideoRetry {
+        max_retries: video_retries,
+        file: Some(path.to_path_buf()),
+    };
+    let startup_timeout = if let Some(stream) = WallpaperSource::parse(path)?.pipeline_source() {
+        descriptions.push(stream_pipeline_description(
+            &stream,
+            width,
+            height,
+            fps,
+            fit_mode,
+            balance_stage,
+        ));
+        start = Duration::ZERO;
+        retry.file = None;
+        STREAM_STARTUP_TIMEOUT
+    } else {
+        let location = path
+            .to_str()
+            .ok_or_else(|| anyhow!("video path contains invalid UTF-8"))?
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"");
+        // The DMA-BUF pipeline never maps frames, so videobalance has nothing to work on.
+        let zero_copy = balance_stage.is_empty();
+        if zero_copy && dmabuf.as_deref().map(DmabufRenderer::can_scale).unwrap_or(false) {
+            descriptions.push(dmabuf_video_pipeline_description(&location, fps));
+        }
+        descriptions.extend(build_video_pipeline_descriptions(
+            &location,
+            width,
+            height,
+            fps,
+            fit_mode,
+            decoders.nvdec_element.as_deref(),
+            balance_stage,
+        ));
+        // A sink that never gets a stream would stall preroll, so only files with audio get one.
+        if play_audio && has_audio_stream(path) {
+            for description in &mut descriptions {
+                *description = with_audio_branch(description);
+            }
+        }
+        FILE_STARTUP_TIMEOUT
+    };
 
     let mut last_error: Option<anyhow::Error> = None;
     for pipeline_desc in descriptions {
+        debug!("trying video pipeline: {}", pipeline_desc);
         match run_video_pipeline(
             &pipeline_desc,
             width,
             height,
             fit_mode,
+            filter,
             surface,
             renderer,
+            dmabuf.as_deref_mut(),
             event_queue,
             state,
             visibility.as_ref(),
+            startup_timeout,
             frame_timeout_ms,
+            start,
+            &retry,
             stop_signal,
         ) {
             Ok(()) => return Ok(()),
             Err(err) => {
+                debug!("video pipeline failed: {:#}", err);
                 last_error = Some(err);
                 if state.exit {
                     return Ok(());
@@ -254,46 +1199,147 @@ fn play_video_loop(
         anyhow!(
             "no video frames decoded; install GStreamer codec plugins (gst-plugins-good, gst-plugins-bad, gst-plugins-ugly, gst-libav)"
         )
+        .context(VideoDecodeError)
     }))
 }
 
+/// Network sources decode through `decodebin`; there is no hardware-specific fallback list.
+fn stream_pipeline_description(
+    source: &str,
+    width: u32,
+    height: u32,
+    fps: u32,
+    fit_mode: FitMode,
+    balance_stage: &str,
+) -> String {
+    format!(
+        "{} ! videoconvert{}{} ! videorate ! {} ! appsink name=sink sync=true max-buffers=1 drop=true",
+        source,
+        video_scale_stage(fit_mode),
+        balance_stage,
+        video_output_caps(fit_mode, width, height, fps)
+    )
+}
+
+/// VA-API decode straight to DMA-BUF at the video's own size; the compositor
+/// scales it through the viewport, so no scaling or conversion stage here.
+fn dmabuf_video_pipeline_description(location: &str, fps: u32) -> String {
+    format!(
+        "filesrc location=\"{}\" ! qtdemux ! h264parse ! vaapih264dec ! vaapipostproc ! video/x-raw(memory:DMABuf),format=BGRx ! videorate ! video/x-raw(memory:DMABuf),framerate={}/1 ! appsink name=sink sync=true max-buffers=1 drop=true",
+        location, fps
+    )
+}
+
 fn build_video_pipeline_descriptions(
     location: &str,
     width: u32,
     height: u32,
     fps: u32,
     fit_mode: FitMode,
-) -> [String; 4] {
-    let scale_stage = video_scale_stage(fit_mode);
+    nvdec_element: Option<&str>,
+    balance_stage: &str,
+) -> Vec<String> {
+    let scale_stage = format!("{}{}", video_scale_stage(fit_mode), balance_stage);
     let output_caps = video_output_caps(fit_mode, width, height, fps);
 
-    [
-        // NVIDIA fast path
-        format!(
-            "filesrc location=\"{}\" ! qtdemux ! h264parse ! nvh264dec ! videoconvert{} ! videorate ! {} ! appsink name=sink sync=true max-buffers=1 drop=true",
-            location, scale_stage, output_caps
-        ),
-        // Intel/AMD VA-API decode to lower CPU usage on laptops while preserving contain/fit behavior.
+    // NVIDIA fast path, unless another GPU was selected.
+    let nvdec = nvdec_element.map(|element| {
         format!(
-            "filesrc location=\"{}\" ! qtdemux ! h264parse ! vaapih264dec ! vaapipostproc ! videoconvert{} ! videorate ! {} ! appsink name=sink sync=true max-buffers=1 drop=true",
-            location, scale_stage, output_caps
-        ),
-        // Generic Vulkan decode
+            "filesrc location=\"{}\" ! qtdemux ! h264parse ! {} ! videoconvert{} ! videorate ! {} ! appsink name=sink sync=true max-buffers=1 drop=true",
+            location, element, scale_stage, output_caps
+        )
+    });
+    nvdec
+        .into_iter()
+        .chain([
+            // Intel/AMD VA-API decode to lower CPU usage on laptops while preserving contain/fit behavior.
+            format!(
+                "filesrc location=\"{}\" ! qtdemux ! h264parse ! vaapih264dec ! vaapipostproc ! videoconvert{} ! videorate ! {} ! appsink name=sink sync=true max-buffers=1 drop=true",
+                location, scale_stage, output_caps
+            ),
+            // Generic Vulkan decode
+            format!(
+                "filesrc location=\"{}\" ! qtdemux ! h264parse ! vulkanh264dec ! videoconvert{} ! videorate ! {} ! appsink name=sink sync=true max-buffers=1 drop=true",
+                location, scale_stage, output_caps
+            ),
+            // Fallback software decode
+            format!(
+                "filesrc location=\"{}\" ! decodebin ! videoconvert{} ! videorate ! {} ! appsink name=sink sync=true max-buffers=1 drop=true",
+                location, scale_stage, output_caps
+            ),
+        ])
+        .collect()
+}
+
+/// Adds a branch that plays the file's first audio stream to a pipeline from
+/// [`build_video_pipeline_descriptions`], off the demuxer or `decodebin` the
+/// video comes from. Each branch starts with a queue, so neither stalls the
+/// other while the pipeline prerolls.
+fn with_audio_branch(description: &str) -> String {
+    const AUDIO_SINK: &str = "audioconvert ! audioresample ! autoaudiosink";
+    if let Some((source, rest)) = description.split_once(" ! qtdemux ! ") {
         format!(
-            "filesrc location=\"{}\" ! qtdemux ! h264parse ! vulkanh264dec ! videoconvert{} ! videorate ! {} ! appsink name=sink sync=true max-buffers=1 drop=true",
-            location, scale_stage, output_caps
-        ),
-        // Fallback software decode
+            "{} ! qtdemux name=demux demux.video_0 ! queue ! {} demux.audio_0 ! queue ! decodebin ! {}",
+            source, rest, AUDIO_SINK
+        )
+    } else if let Some((source, rest)) = description.split_once(" ! decodebin ! ") {
         format!(
-            "filesrc location=\"{}\" ! decodebin ! videoconvert{} ! videorate ! {} ! appsink name=sink sync=true max-buffers=1 drop=true",
-            location, scale_stage, output_caps
-        ),
-    ]
+            "{} ! decodebin name=dec dec. ! video/x-raw(ANY) ! queue ! {} dec. ! audio/x-raw(ANY) ! queue ! {}",
+            source, rest, AUDIO_SINK
+        )
+    } else {
+        description.to_string()
+    }
+}
+
+/// [`video_balance_stage`] followed by the `--effect` elements and the
+/// watermark, sized for frames of `output` size.
+fn video_filter_stage(options: &RenderOptions, output: (u32, u32)) -> String {
+    if options.effects.contains(&Effect::Invert) {
+        warning!("warning: the invert effect only applies to images; ignoring it for video");
+    }
+    let mut stage = video_balance_stage(options.brightness, options.contrast)
+        + &effects::video_effect_stage(&options.effects, options.blur_sigma);
+    if let Some(watermark) = &options.watermark {
+        match formats::image_dimensions(&watermark.path) {
+            Ok(size) => stage += &effects::video_watermark_stage(watermark, size, output),
+            Err(error) => warning!("warning: ignoring the watermark: {:#}", error),
+        }
+    }
+    stage
+}
+
+/// The `--watermark` image, or `None` when there is none or it cannot be
+/// read; a broken watermark only costs a warning, not the wallpaper.
+fn load_watermark(options: &RenderOptions) -> Option<(RgbaImage, &WatermarkConfig)> {
+    let watermark = options.watermark.as_ref()?;
+    match effects::load_watermark(watermark) {
+        Ok(mark) => Some((mark, watermark)),
+        Err(error) => {
+            warning!("warning: ignoring the watermark: {:#}", error);
+            None
+        }
+    }
+}
+
+/// `videobalance` for `--brightness`/`--contrast`, or nothing when both are
+/// neutral. Its brightness is an offset (-1.0 to 1.0) rather than a multiplier,
+/// so 0.0 maps to -1.0 (black) as it does for images.
+fn video_balance_stage(brightness: f32, contrast: f32) -> String {
+    if brightness == 1.0 && contrast == 1.0 {
+        return String::new();
+    }
+    format!(
+        " ! videobalance brightness={:.3} contrast={:.3}",
+        brightness.clamp(0.0, 1.0) - 1.0,
+        contrast.clamp(0.0, 2.0)
+    )
 }
 
 fn video_scale_stage(fit_mode: FitMode) -> &'static str {
     match fit_mode {
-        FitMode::Center | FitMode::ScaleDown => "",
+        // Tiling is image-only; video frames keep their size and are tiled on the CPU.
+        FitMode::Center | FitMode::ScaleDown | FitMode::Tile => "",
         FitMode::Fit | FitMode::Contain => " ! videoscale add-borders=true",
         _ => " ! videoscale",
     }
@@ -301,7 +1347,7 @@ fn video_scale_stage(fit_mode: FitMode) -> &'static str {
 
 fn video_output_caps(fit_mode: FitMode, width: u32, height: u32, fps: u32) -> String {
     match fit_mode {
-        FitMode::Center | FitMode::ScaleDown => {
+        FitMode::Center | FitMode::ScaleDown | FitMode::Tile => {
             format!("video/x-raw,format=BGRx,framerate={}/1", fps)
         }
         _ => format!(
@@ -311,41 +1357,202 @@ fn video_output_caps(fit_mode: FitMode, width: u32, height: u32, fps: u32) -> St
     }
 }
 
+/// The caps filter right in front of the appsink, which fixes the frame rate
+/// `videorate` produces.
+fn rate_caps_filter(pipeline: &gst::Pipeline) -> Option<gst::Element> {
+    pipeline
+        .by_name("sink")?
+        .static_pad("sink")?
+        .peer()?
+        .parent_element()
+        .filter(|element| element.factory().is_some_and(|f| f.name() == "capsfilter"))
+}
+
+fn pipeline_fps(pipeline: &gst::Pipeline) -> Option<u32> {
+    let caps = rate_caps_filter(pipeline)?.property::<gst::Caps>("caps");
+    let rate = caps.structure(0)?.get::<gst::Fraction>("framerate").ok()?;
+    u32::try_from(rate.numer() / rate.denom().max(1)).ok()
+}
+
+/// Changes the frame rate of a running video pipeline by rewriting the caps
+/// after `videorate`, which then renegotiates to drop or duplicate frames.
+fn set_pipeline_fps(pipeline: &gst::Pipeline, fps: u32) -> Result<()> {
+    let filter = rate_caps_filter(pipeline)
+        .ok_or_else(|| anyhow!("video pipeline has no frame rate caps to change"))?;
+    let mut caps = filter.property::<gst::Caps>("caps");
+    for structure in caps.make_mut().iter_mut() {
+        structure.set("framerate", gst::Fraction::new(fps as i32, 1));
+    }
+    filter.set_property("caps", &caps);
+    Ok(())
+}
+
+/// `set --span-monitors`: fits `image` to the bounding box of `monitors` in
+/// layout coordinates, then cuts out each monitor's part at its pixel size.
+pub fn compute_span_crop(
+    monitors: &[MonitorGeometry],
+    image: &DynamicImage,
+    fit: FitMode,
+) -> Vec<(String, RgbaImage)> {
+    if monitors.is_empty() {
+        return Vec::new();
+    }
+    let left = monitors.iter().map(|m| m.x).min().unwrap_or(0) as f64;
+    let top = monitors.iter().map(|m| m.y).min().unwrap_or(0) as f64;
+    let right = monitors
+        .iter()
+        .map(|m| m.x as f64 + m.layout_size().0)
+        .fold(f64::MIN, f64::max);
+    let bottom = monitors
+        .iter()
+        .map(|m| m.y as f64 + m.layout_size().1)
+        .fold(f64::MIN, f64::max);
+    // At the density of the sharpest monitor, so none of them gets an upscaled part.
+    let density = monitors
+        .iter()
+        .map(|m| m.width as f64 / m.layout_size().0)
+        .fold(1.0, f64::max);
+    let to_pixels = |length: f64| (length * density).round() as u32;
+    let canvas_width = to_pixels(right - left).max(1);
+    let canvas_height = to_pixels(bottom - top).max(1);
+    let canvas = render_image_fit(
+        image,
+        canvas_width,
+        canvas_height,
+        fit,
+        FilterType::Lanczos3,
+        (0, 0),
+        None,
+    );
+
+    monitors
+        .iter()
+        .map(|monitor| {
+            let (layout_width, layout_height) = monitor.layout_size();
+            let x = to_pixels(monitor.x as f64 - left).min(canvas_width - 1);
+            let y = to_pixels(monitor.y as f64 - top).min(canvas_height - 1);
+            let width = to_pixels(layout_width).clamp(1, canvas_width - x);
+            let height = to_pixels(layout_height).clamp(1, canvas_height - y);
+            let part = imageops::crop_imm(&canvas, x, y, width, height).to_image();
+            let part = if part.dimensions() == (monitor.width, monitor.height) {
+                part
+            } else {
+                imageops::resize(&part, monitor.width, monitor.height, FilterType::Lanczos3)
+            };
+            (monitor.name.clone(), part)
+        })
+        .collect()
+}
+
 fn render_image_fit(
     image: &DynamicImage,
     out_w: u32,
     out_h: u32,
     fit_mode: FitMode,
+    filter: FilterType,
+    offset: (i32, i32),
+    focus: Option<[f32; 4]>,
 ) -> RgbaImage {
-    render_rgba_fit(&image.to_rgba8(), out_w, out_h, fit_mode)
+    match (fit_mode, focus) {
+        (FitMode::Fill | FitMode::Cover, Some(focus)) => {
+            render_cover_rgba(&image.to_rgba8(), out_w, out_h, filter, offset, Some(focus))
+        }
+        _ => render_rgba_fit(&image.to_rgba8(), out_w, out_h, fit_mode, filter, offset),
+    }
 }
 
-fn render_rgba_fit(image: &RgbaImage, out_w: u32, out_h: u32, fit_mode: FitMode) -> RgbaImage {
+fn render_rgba_fit(
+    image: &RgbaImage,
+    out_w: u32,
+    out_h: u32,
+    fit_mode: FitMode,
+    filter: FilterType,
+    offset: (i32, i32),
+) -> RgbaImage {
     match fit_mode {
-        FitMode::Stretch => imageops::resize(image, out_w, out_h, FilterType::Lanczos3),
-        FitMode::Fit | FitMode::Contain => render_contained_rgba(image, out_w, out_h, true),
-        FitMode::Center => render_centered_rgba(image, out_w, out_h),
-        FitMode::ScaleDown => render_contained_rgba(image, out_w, out_h, false),
+        FitMode::Stretch => imageops::resize(image, out_w, out_h, filter),
+        FitMode::Fit | FitMode::Contain => {
+            render_contained_rgba(image, out_w, out_h, true, filter, offset)
+        }
+        FitMode::Center => render_centered_rgba(image, out_w, out_h, offset),
+        FitMode::ScaleDown => render_contained_rgba(image, out_w, out_h, false, filter, offset),
+        FitMode::Tile => render_tiled_rgba(image, out_w, out_h, offset),
         FitMode::Fill | FitMode::Cover => {
-            let scale = f64::max(
-                out_w as f64 / image.width() as f64,
-                out_h as f64 / image.height() as f64,
-            );
-            let rw = (image.width() as f64 * scale).round().max(out_w as f64) as u32;
-            let rh = (image.height() as f64 * scale).round().max(out_h as f64) as u32;
-            let resized = imageops::resize(image, rw, rh, FilterType::Lanczos3);
-            let x = (rw.saturating_sub(out_w)) / 2;
-            let y = (rh.saturating_sub(out_h)) / 2;
-            imageops::crop_imm(&resized, x, y, out_w, out_h).to_image()
+            render_cover_rgba(image, out_w, out_h, filter, offset, None)
         }
     }
 }
 
+/// Scales the image to cover the output and crops the overflow, centered or
+/// around `focus`.
+fn render_cover_rgba(
+    image: &RgbaImage,
+    out_w: u32,
+    out_h: u32,
+    filter: FilterType,
+    offset: (i32, i32),
+    focus: Option<[f32; 4]>,
+) -> RgbaImage {
+    let scale = f64::max(
+        out_w as f64 / image.width() as f64,
+        out_h as f64 / image.height() as f64,
+    );
+    let rw = (image.width() as f64 * scale).round().max(out_w as f64) as u32;
+    let rh = (image.height() as f64 * scale).round().max(out_h as f64) as u32;
+    let resized = imageops::resize(image, rw, rh, filter);
+    let (x, y) = match focus {
+        Some(focus) => {
+            let (x, y) = compute_cover_crop_offset_with_focus(rw, rh, out_w, out_h, focus);
+            let shift = |origin: u32, offset: i32, slack: u32| {
+                (origin as i64 + offset as i64).clamp(0, slack as i64) as u32
+            };
+            (
+                shift(x, offset.0, rw.saturating_sub(out_w)),
+                shift(y, offset.1, rh.saturating_sub(out_h)),
+            )
+        }
+        None => (
+            shifted_origin(rw.saturating_sub(out_w), offset.0),
+            shifted_origin(rh.saturating_sub(out_h), offset.1),
+        ),
+    };
+    imageops::crop_imm(&resized, x, y, out_w, out_h).to_image()
+}
+
+/// Origin of an `out_w`x`out_h` crop of an `rw`x`rh` image, centered on the
+/// `focus` rectangle (`[x, y, w, h]` fractions) and clamped to the image. A focus
+/// area that fits in the crop stays fully visible.
+pub fn compute_cover_crop_offset_with_focus(
+    rw: u32,
+    rh: u32,
+    out_w: u32,
+    out_h: u32,
+    focus: [f32; 4],
+) -> (u32, u32) {
+    let origin = |size: u32, out: u32, start: f32, length: f32| {
+        let center = (start + length / 2.0) as f64 * size as f64;
+        let slack = size.saturating_sub(out) as f64;
+        (center - out as f64 / 2.0).round().clamp(0.0, slack) as u32
+    };
+    (
+        origin(rw, out_w, focus[0], focus[2]),
+        origin(rh, out_h, focus[1], focus[3]),
+    )
+}
+
+/// Centers within `slack` pixels of free space, shifted by `offset` and
+/// clamped so the image never leaves the screen.
+fn shifted_origin(slack: u32, offset: i32) -> u32 {
+    (slack as i64 / 2 + offset as i64).clamp(0, slack as i64) as u32
+}
+
 fn render_contained_rgba(
     image: &RgbaImage,
     out_w: u32,
     out_h: u32,
     allow_upscale: bool,
+    filter: FilterType,
+    offset: (i32, i32),
 ) -> RgbaImage {
     let scale = f64::min(
         out_w as f64 / image.width() as f64,
@@ -357,44 +1564,104 @@ fn render_contained_rgba(
     let resized = if rw == image.width() && rh == image.height() {
         image.clone()
     } else {
-        imageops::resize(image, rw, rh, FilterType::Lanczos3)
+        imageops::resize(image, rw, rh, filter)
     };
 
     let mut canvas = RgbaImage::new(out_w, out_h);
-    let x = ((out_w as i64 - resized.width() as i64) / 2).max(0) as u32;
-    let y = ((out_h as i64 - resized.height() as i64) / 2).max(0) as u32;
+    let x = shifted_origin(out_w.saturating_sub(resized.width()), offset.0);
+    let y = shifted_origin(out_h.saturating_sub(resized.height()), offset.1);
     imageops::overlay(&mut canvas, &resized, x as i64, y as i64);
     canvas
 }
 
-fn render_centered_rgba(image: &RgbaImage, out_w: u32, out_h: u32) -> RgbaImage {
+/// The image at `tile_size` for tiling. A 0 side follows the other one's scale;
+/// 0x0 leaves the image as it is.
+fn scale_tile(
+    image: DynamicImage,
+    (width, height): (u32, u32),
+    filter: FilterType,
+) -> DynamicImage {
+    let (native_w, native_h) = (image.width().max(1), image.height().max(1));
+    let (width, height) = match (width, height) {
+        (0, 0) => return image,
+        (0, height) => ((native_w as u64 * height as u64 / native_h as u64) as u32, height),
+        (width, 0) => (width, (native_h as u64 * width as u64 / native_w as u64) as u32),
+        size => size,
+    };
+    let (width, height) = (width.max(1), height.max(1));
+    if (width, height) == (native_w, native_h) {
+        return image;
+    }
+    image.resize_exact(width, height, filter)
+}
+
+/// Repeats the image at its native size from the top-left corner; the offset
+/// shifts the grid.
+fn render_tiled_rgba(image: &RgbaImage, out_w: u32, out_h: u32, offset: (i32, i32)) -> RgbaImage {
+    let mut canvas = RgbaImage::new(out_w, out_h);
+    let tile_w = image.width().max(1) as i64;
+    let tile_h = image.height().max(1) as i64;
+    let first = |offset: i32, tile: i64| {
+        let start = (offset as i64).rem_euclid(tile);
+        if start > 0 {
+            start - tile
+        } else {
+            start
+        }
+    };
+
+    let mut y = first(offset.1, tile_h);
+    while y < out_h as i64 {
+        let mut x = first(offset.0, tile_w);
+        while x < out_w as i64 {
+            imageops::overlay(&mut canvas, image, x, y);
+            x += tile_w;
+        }
+        y += tile_h;
+    }
+    canvas
+}
+
+fn render_centered_rgba(
+    image: &RgbaImage,
+    out_w: u32,
+    out_h: u32,
+    offset: (i32, i32),
+) -> RgbaImage {
     let crop_w = image.width().min(out_w);
     let crop_h = image.height().min(out_h);
-    let src_x = image.width().saturating_sub(crop_w) / 2;
-    let src_y = image.height().saturating_sub(crop_h) / 2;
-    let dst_x = out_w.saturating_sub(crop_w) / 2;
-    let dst_y = out_h.saturating_sub(crop_h) / 2;
+    let src_x = shifted_origin(image.width().saturating_sub(crop_w), offset.0);
+    let src_y = shifted_origin(image.height().saturating_sub(crop_h), offset.1);
+    let dst_x = shifted_origin(out_w.saturating_sub(crop_w), offset.0);
+    let dst_y = shifted_origin(out_h.saturating_sub(crop_h), offset.1);
     let cropped = imageops::crop_imm(image, src_x, src_y, crop_w, crop_h).to_image();
     let mut canvas = RgbaImage::new(out_w, out_h);
     imageops::overlay(&mut canvas, &cropped, dst_x as i64, dst_y as i64);
     canvas
 }
 
+#[allow(clippy::too_many_arguments)]
 fn run_video_pipeline(
     pipeline_desc: &str,
     width: u32,
     height: u32,
     fit_mode: FitMode,
+    filter: FilterType,
     surface: &wl_surface::WlSurface,
     renderer: &mut FrameRenderer,
+    mut dmabuf: Option<&mut DmabufRenderer>,
     event_queue: &mut EventQueue<AppState>,
     state: &mut AppState,
-    visibility: Option<&HyprlandVisibility>,
+    visibility: Option<&CompositorVisibility>,
+    startup_timeout: Duration,
     frame_timeout_ms: u64,
+    mut start: Duration,
+    retry: &VideoRetry,
     stop_signal: Option<&AtomicBool>,
 ) -> Result<()> {
     let pipeline = gst::parse::launch(pipeline_desc)
-        .context("failed to build gstreamer pipeline")?
+        .context("failed to build gstreamer pipeline")
+        .context(VideoDecodeError)?
         .downcast::<gst::Pipeline>()
         .map_err(|_| anyhow!("gstreamer element is not a pipeline"))?;
 
@@ -410,19 +1677,48 @@ fn run_video_pipeline(
 
     pipeline
         .set_state(gst::State::Playing)
-        .context("failed to start video pipeline")?;
+        .context("failed to start video pipeline")
+        .context(VideoDecodeError)?;
+
+    let mut pending_seek = None;
+    if !start.is_zero() {
+        // Seeking needs a prerolled pipeline.
+        let _ = pipeline.state(gst::ClockTime::from_mseconds(startup_timeout.as_millis() as u64));
+        let duration = pipeline
+            .query_duration::<gst::ClockTime>()
+            .map(|duration| Duration::from_nanos(duration.nseconds()));
+        start = video_start_within(start, duration);
+    }
+    if !start.is_zero() && !seek_video(&pipeline, start) {
+        pending_seek = Some(start);
+    }
 
-    let Some(initial_sample) = sink.try_pull_sample(gst::ClockTime::from_seconds(2)) else {
+    let Some(initial_sample) = sink.try_pull_sample(gst::ClockTime::from_mseconds(
+        startup_timeout.as_millis() as u64,
+    )) else {
         pipeline.set_state(gst::State::Null).ok();
-        return Err(anyhow!("no initial video frame from pipeline"));
+        return Err(anyhow!("no initial video frame from pipeline").context(VideoDecodeError));
     };
+    // Some hardware decoders only accept a seek once the first frame is out.
+    if let Some(position) = pending_seek {
+        if !seek_video(&pipeline, position) {
+            debug!("video decoder rejected seek to {:?}, starting from 0", position);
+        }
+    }
 
     let mut last_visibility_refresh = Instant::now();
     let mut render_enabled = visibility.map(|v| v.should_render()).unwrap_or(true);
-    let mut pending_render_state: Option<(bool, Instant)> = None;
     let mut primed_sample = Some(initial_sample);
-
-    while !state.exit {
+    let mut last_pause_check = Instant::now();
+    let mut user_paused = false;
+    // The daemon's battery_fps/ac_fps cap; the pipeline never goes above its own rate.
+    let own_fps = pipeline_fps(&pipeline);
+    let mut fps_limit = None;
+    let mut retry_count = 0;
+    let mut file_len = retry.file_len();
+    let mut loops_done = false;
+
+    while !state.exit && !state.resize_needed {
         if stop_signal
             .map(|signal| signal.load(Ordering::Relaxed))
             .unwrap_or(false)
@@ -436,22 +1732,48 @@ fn run_video_pipeline(
                 v.refresh_now();
                 last_visibility_refresh = Instant::now();
             }
+            render_enabled = v.should_render();
+        }
 
-            let observed = v.should_render();
-            if observed == render_enabled {
-                pending_render_state = None;
-            } else {
-                match pending_render_state {
-                    Some((pending, since)) if pending == observed => {
-                        if since.elapsed() >= Duration::from_millis(750) {
-                            render_enabled = observed;
-                            pending_render_state = None;
-                        }
+        if !loops_done && last_pause_check.elapsed() >= PAUSE_POLL_INTERVAL {
+            last_pause_check = Instant::now();
+            let paused = state::is_paused(state.requested_monitor.as_deref()) || state.monitor_off;
+            if paused != user_paused {
+                user_paused = paused;
+                let target = if paused {
+                    gst::State::Paused
+                } else {
+                    gst::State::Playing
+                };
+                pipeline
+                    .set_state(target)
+                    .context("failed to change video pipeline state")?;
+                debug!("video {}", if paused { "paused" } else { "resumed" });
+            }
+            let limit = state::fps_limit();
+            if limit != fps_limit {
+                fps_limit = limit;
+                if let Some(own) = own_fps {
+                    let fps = limit.map_or(own, |limit| limit.min(own));
+                    match set_pipeline_fps(&pipeline, fps) {
+                        Ok(()) => debug!("video frame rate set to {} fps", fps),
+                        Err(error) => warning!("warning: {:#}", error),
                     }
-                    _ => pending_render_state = Some((observed, Instant::now())),
                 }
             }
         }
+        if user_paused || loops_done {
+            std::thread::sleep(Duration::from_millis(50));
+            // Nothing else reads the socket while paused, and the monitor's power-on
+            // event has to get through.
+            read_wayland_events(event_queue)?;
+            event_queue
+                .dispatch_pending(state)
+                .context("failed dispatching Wayland events")?;
+            state.sync_alpha(surface);
+            event_queue.flush().ok();
+            continue;
+        }
 
         let should_render = render_enabled;
 
@@ -463,14 +1785,30 @@ fn run_video_pipeline(
 
         if let Some(sample) = sample {
             if should_render {
-                let wrote_frame = write_sample_frame(
-                    &sample,
-                    surface,
-                    renderer,
-                    width as usize,
-                    height as usize,
-                    fit_mode,
-                )?;
+                let presented = match dmabuf.as_deref_mut() {
+                    Some(dmabuf) => {
+                        dmabuf.present_sample(&sample, surface, &event_queue.handle())?
+                    }
+                    None => Presented::Unsupported,
+                };
+                let wrote_frame = match presented {
+                    Presented::Frame => true,
+                    Presented::Busy => false,
+                    Presented::Unsupported => {
+                        if let Some(dmabuf) = dmabuf.as_deref_mut() {
+                            dmabuf.reset_viewport(surface);
+                        }
+                        write_sample_frame(
+                            &sample,
+                            surface,
+                            renderer,
+                            width as usize,
+                            height as usize,
+                            fit_mode,
+                            filter,
+                        )?
+                    }
+                };
 
                 if !wrote_frame {
                     // All shm buffers are currently held by the compositor.
@@ -486,16 +1824,75 @@ fn run_video_pipeline(
         }
 
         if let Some(msg) = bus.pop_filtered(&[gst::MessageType::Error, gst::MessageType::Eos]) {
-            match msg.type_() {
-                gst::MessageType::Error => {
+            match msg.view() {
+                gst::MessageView::Error(error) => {
+                    let len = retry.file_len();
+                    let growing = len.is_some() && len != file_len;
+                    file_len = len;
+                    let limit = if growing {
+                        retry.max_retries.saturating_mul(PARTIAL_FILE_RETRY_FACTOR)
+                    } else {
+                        retry.max_retries
+                    };
+                    if retry_count >= limit {
+                        pipeline.set_state(gst::State::Null).ok();
+                        return Err(anyhow!(
+                            "video pipeline error after {} retries: {}",
+                            retry_count,
+                            error.error()
+                        )
+                        .context(VideoDecodeError));
+                    }
+
+                    retry_count += 1;
+                    let delay = retry_backoff(retry_count, growing);
+                    warning!(
+                        "warning: video pipeline error ({}), retry {}/{} in {:?}",
+                        error.error(),
+                        retry_count,
+                        limit,
+                        delay
+                    );
                     pipeline.set_state(gst::State::Null).ok();
-                    return Err(anyhow!("video pipeline error"));
+                    let resume_at = Instant::now() + delay;
+                    while Instant::now() < resume_at
+                        && !stop_signal
+                            .map(|signal| signal.load(Ordering::Relaxed))
+                            .unwrap_or(false)
+                    {
+                        std::thread::sleep(Duration::from_millis(50));
+                    }
+                    let target = if user_paused {
+                        gst::State::Paused
+                    } else {
+                        gst::State::Playing
+                    };
+                    pipeline
+                        .set_state(target)
+                        .context("failed to restart video pipeline")?;
+                    if !start.is_zero() {
+                        let _ = pipeline.state(gst::ClockTime::from_mseconds(
+                            startup_timeout.as_millis() as u64,
+                        ));
+                        seek_video(&pipeline, start);
+                    }
                 }
-                gst::MessageType::Eos => {
-                    let _ = pipeline.seek_simple(
-                        gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT,
-                        gst::ClockTime::from_seconds(0),
-                    );
+                gst::MessageView::Eos(_) => {
+                    // A clean pass through the file earns the retries back.
+                    retry_count = 0;
+                    if state.finish_loop() {
+                        if state.loop_fallback.is_some() {
+                            pipeline.set_state(gst::State::Null).ok();
+                            return Ok(());
+                        }
+                        debug!("video loops done, holding the last frame");
+                        pipeline
+                            .set_state(gst::State::Paused)
+                            .context("failed to pause video pipeline")?;
+                        loops_done = true;
+                    } else if !seek_video(&pipeline, start) {
+                        seek_video(&pipeline, Duration::ZERO);
+                    }
                 }
                 _ => {}
             }
@@ -506,11 +1903,86 @@ fn run_video_pipeline(
                 .dispatch_pending(state)
                 .context("failed dispatching Wayland events")?;
         }
+        state.sync_alpha(surface);
         event_queue.flush().ok();
     }
 
-    pipeline.set_state(gst::State::Null).ok();
-    Ok(())
+    pipeline.set_state(gst::State::Null).ok();
+    Ok(())
+}
+
+/// Mid-playback recovery for one pipeline: an error restarts the same
+/// pipeline instead of falling through to the next decoder.
+struct VideoRetry {
+    max_retries: u32,
+    /// The local file behind the pipeline, `None` for streams.
+    file: Option<PathBuf>,
+}
+
+impl VideoRetry {
+    fn file_len(&self) -> Option<u64> {
+        let file = self.file.as_ref()?;
+        std::fs::metadata(file).ok().map(|metadata| metadata.len())
+    }
+}
+
+/// Marks a video the pipeline could not decode, or whose frames came with
+/// caps the renderer cannot use: the failures a still fallback is for.
+#[derive(Debug)]
+struct VideoDecodeError;
+
+impl std::fmt::Display for VideoDecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("video could not be decoded")
+    }
+}
+
+/// Sleeps for `set --delay`, returning early once `stop_signal` is set.
+fn wait_for_startup_delay(delay: Duration, stop_signal: Option<&AtomicBool>) {
+    if delay.is_zero() {
+        return;
+    }
+    debug!("waiting {:?} before the first frame", delay);
+    let deadline = Instant::now() + delay;
+    while !stop_signal.is_some_and(|signal| signal.load(Ordering::Relaxed)) {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return;
+        }
+        std::thread::sleep(left.min(STARTUP_DELAY_POLL_INTERVAL));
+    }
+}
+
+/// Delay before retry number `attempt` (counting from 1).
+fn retry_backoff(attempt: u32, growing: bool) -> Duration {
+    if growing {
+        return PARTIAL_FILE_RETRY_DELAY;
+    }
+    let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+    VIDEO_RETRY_BACKOFF
+        .saturating_mul(factor)
+        .min(VIDEO_RETRY_MAX_BACKOFF)
+}
+
+/// `video_start_time_ms` applies to every video, so one shorter than the start
+/// plays from the beginning instead of ending, and looping, at once.
+fn video_start_within(start: Duration, duration: Option<Duration>) -> Duration {
+    match duration {
+        Some(duration) if start >= duration => {
+            debug!("video is {:?} long, starting at 0 instead of {:?}", duration, start);
+            Duration::ZERO
+        }
+        _ => start,
+    }
+}
+
+fn seek_video(pipeline: &gst::Pipeline, position: Duration) -> bool {
+    pipeline
+        .seek_simple(
+            gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT,
+            gst::ClockTime::from_mseconds(position.as_millis() as u64),
+        )
+        .is_ok()
 }
 
 fn write_sample_frame(
@@ -520,14 +1992,15 @@ fn write_sample_frame(
     width: usize,
     height: usize,
     fit_mode: FitMode,
+    filter: FilterType,
 ) -> Result<bool> {
     let Some(slot) = renderer.acquire_slot() else {
         return Ok(false);
     };
 
-    if let Err(error) = renderer.write_sample_bgrx(slot, sample, width, height, fit_mode) {
+    if let Err(error) = renderer.write_sample_bgrx(slot, sample, width, height, fit_mode, filter) {
         renderer.release_slot(slot);
-        return Err(error);
+        return Err(error.context(VideoDecodeError));
     }
 
     surface.attach(Some(renderer.buffer(slot)), 0, 0);
@@ -558,11 +2031,31 @@ fn draw_image_frame(
     Ok(())
 }
 
+fn draw_xrgb_frame(
+    xrgb: &[u8],
+    surface: &wl_surface::WlSurface,
+    renderer: &mut FrameRenderer,
+) -> Result<()> {
+    let slot = renderer
+        .acquire_slot()
+        .ok_or_else(|| anyhow!("no free Wayland frame buffer for image frame"))?;
+
+    if let Err(error) = renderer.write_xrgb_frame(slot, xrgb) {
+        renderer.release_slot(slot);
+        return Err(error);
+    }
+
+    surface.attach(Some(renderer.buffer(slot)), 0, 0);
+    surface.damage_buffer(0, 0, renderer.width as i32, renderer.height as i32);
+    surface.commit();
+
+    Ok(())
+}
+
 struct FrameSlot {
     frame_size: usize,
-    mmap: MmapMut,
-    _file: File,
-    _pool: wl_shm_pool::WlShmPool,
+    memory: ShmBuffer,
+    pool: wl_shm_pool::WlShmPool,
     buffer: wl_buffer::WlBuffer,
     in_use: Arc<AtomicBool>,
 }
@@ -570,6 +2063,8 @@ struct FrameSlot {
 struct FrameRenderer {
     width: u32,
     height: u32,
+    /// Whose buffers these are in the pool, so they go when the monitor does.
+    monitor: Option<String>,
     slots: Vec<FrameSlot>,
     next_slot: usize,
 }
@@ -578,6 +2073,7 @@ impl FrameRenderer {
     fn new(
         width: u32,
         height: u32,
+        monitor: Option<&str>,
         shm: &wl_shm::WlShm,
         qh: &QueueHandle<AppState>,
     ) -> Result<Self> {
@@ -585,26 +2081,9 @@ impl FrameRenderer {
         let frame_size = (height as i32 * stride) as usize;
         let mut slots = Vec::with_capacity(2);
 
-        for index in 0..2 {
-            let unique_id = BUFFER_FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
-            let buffer_path = std::env::temp_dir()
-                .join(format!("papdieo-buffer-{}-{}-{}", process::id(), unique_id, index));
-
-            let file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(&buffer_path)
-                .context("failed to create shared memory buffer file")?;
-            file.set_len(frame_size as u64)?;
-            let _ = std::fs::remove_file(&buffer_path);
-
-            let mmap =
-                unsafe { MmapMut::map_mut(&file) }.context("failed to map shared memory")?;
-
+        for memory in ShmPoolRegistry::global().take((width, height), 2)? {
             let in_use = Arc::new(AtomicBool::new(false));
-            let pool = shm.create_pool(file.as_fd(), frame_size as i32, qh, ());
+            let pool = shm.create_pool(memory.file.as_fd(), frame_size as i32, qh, ());
             let buffer = pool.create_buffer(
                 0,
                 width as i32,
@@ -617,9 +2096,8 @@ impl FrameRenderer {
 
             slots.push(FrameSlot {
                 frame_size,
-                mmap,
-                _file: file,
-                _pool: pool,
+                memory,
+                pool,
                 buffer,
                 in_use,
             });
@@ -628,11 +2106,26 @@ impl FrameRenderer {
         Ok(Self {
             width,
             height,
+            monitor: monitor.map(str::to_string),
             slots,
             next_slot: 0,
         })
     }
 
+    /// Replaces the buffers when the output size changed; the old ones go
+    /// back to the pool.
+    fn resize(
+        &mut self,
+        (width, height): (u32, u32),
+        shm: &wl_shm::WlShm,
+        qh: &QueueHandle<AppState>,
+    ) -> Result<()> {
+        if (width, height) != (self.width, self.height) {
+            *self = Self::new(width, height, self.monitor.as_deref(), shm, qh)?;
+        }
+        Ok(())
+    }
+
     fn acquire_slot(&mut self) -> Option<usize> {
         for offset in 0..self.slots.len() {
             let idx = (self.next_slot + offset) % self.slots.len();
@@ -647,6 +2140,12 @@ impl FrameRenderer {
         None
     }
 
+    fn all_slots_in_use(&self) -> bool {
+        self.slots
+            .iter()
+            .all(|slot| slot.in_use.load(Ordering::Acquire))
+    }
+
     fn release_slot(&self, slot_idx: usize) {
         if let Some(slot) = self.slots.get(slot_idx) {
             slot.in_use.store(false, Ordering::Release);
@@ -664,6 +2163,7 @@ impl FrameRenderer {
         width: usize,
         height: usize,
         fit_mode: FitMode,
+        filter: FilterType,
     ) -> Result<()> {
         let buffer = sample
             .buffer()
@@ -678,16 +2178,24 @@ impl FrameRenderer {
         let info = gst_video::VideoInfo::from_caps(caps)
             .map_err(|_| anyhow!("failed to parse video caps"))?;
 
-        let stride = info.stride()[0] as usize;
-        let src = map.as_slice();
+        let converted;
+        let (src, stride) = match info.format() {
+            gst_video::VideoFormat::Bgrx => (map.as_slice(), info.stride()[0] as usize),
+            gst_video::VideoFormat::Nv12 | gst_video::VideoFormat::I420 => {
+                converted = bgrx_from_yuv_frame(&info, map.as_slice())?;
+                (converted.as_slice(), info.width() as usize * 4)
+            }
+            other => return Err(anyhow!("unsupported video format: {:?}", other)),
+        };
         let row_bytes = width * 4;
 
         if info.width() != width as u32
             || info.height() != height as u32
-            || matches!(fit_mode, FitMode::Center | FitMode::ScaleDown)
+            || matches!(fit_mode, FitMode::Center | FitMode::ScaleDown | FitMode::Tile)
         {
             let rgba = rgba_from_bgrx_frame(src, stride, info.width(), info.height())?;
-            let rendered = render_rgba_fit(&rgba, width as u32, height as u32, fit_mode);
+            let rendered =
+                render_rgba_fit(&rgba, width as u32, height as u32, fit_mode, filter, (0, 0));
             return self.write_rgba_image_frame(slot_idx, rendered.as_raw());
         }
 
@@ -703,7 +2211,7 @@ impl FrameRenderer {
             if src_end > src.len() {
                 return Err(anyhow!("video frame stride exceeds buffer"));
             }
-            self.slots[slot_idx].mmap[dst_start..dst_end].copy_from_slice(&src[src_start..src_end]);
+            self.slots[slot_idx].memory.mmap[dst_start..dst_end].copy_from_slice(&src[src_start..src_end]);
         }
 
         Ok(())
@@ -714,19 +2222,77 @@ impl FrameRenderer {
             return Err(anyhow!("image frame is larger than renderer buffer"));
         }
 
-        for (dst, px) in self.slots[slot_idx].mmap[..rgba.len()]
-            .chunks_exact_mut(4)
-            .zip(rgba.chunks_exact(4))
-        {
-            dst[0] = px[2];
-            dst[1] = px[1];
-            dst[2] = px[0];
-            dst[3] = 255;
+        colorspace::rgba_to_xrgb_into(rgba, &mut self.slots[slot_idx].memory.mmap[..rgba.len()]);
+        Ok(())
+    }
+
+    /// Copies a frame that is already XRGB8888, e.g. from `colorspace::rgba_to_xrgb`.
+    fn write_xrgb_frame(&mut self, slot_idx: usize, xrgb: &[u8]) -> Result<()> {
+        if xrgb.len() > self.slots[slot_idx].frame_size {
+            return Err(anyhow!("image frame is larger than renderer buffer"));
         }
+
+        self.slots[slot_idx].memory.mmap[..xrgb.len()].copy_from_slice(xrgb);
         Ok(())
     }
 }
 
+impl Drop for FrameRenderer {
+    /// Hands the buffer memory to the next renderer of this size. A buffer the
+    /// compositor has not released yet may still be read, so it is freed
+    /// instead; destroying the surface and a roundtrip first gets them released.
+    fn drop(&mut self) {
+        let memory = self
+            .slots
+            .drain(..)
+            .filter_map(|slot| {
+                slot.buffer.destroy();
+                slot.pool.destroy();
+                (!slot.in_use.load(Ordering::Acquire)).then_some(slot.memory)
+            })
+            .collect();
+        ShmPoolRegistry::global().park((self.width, self.height), self.monitor.as_deref(), memory);
+    }
+}
+
+/// Converts an NV12/I420 frame to tightly packed BGRx. Only the plane
+/// layout the `colorspace` converters expect is accepted.
+fn bgrx_from_yuv_frame(info: &gst_video::VideoInfo, src: &[u8]) -> Result<Vec<u8>> {
+    let width = info.width() as usize;
+    let height = info.height() as usize;
+    let stride = info.stride()[0] as usize;
+    let offsets = info.offset();
+    let strides = info.stride();
+
+    let (expected_len, layout_matches) = match info.format() {
+        gst_video::VideoFormat::Nv12 => (
+            colorspace::nv12_frame_len(stride, height),
+            offsets[1] == stride * height && strides[1] as usize == stride,
+        ),
+        _ => (
+            colorspace::yuv420p_frame_len(stride, height),
+            offsets[1] == stride * height
+                && strides[1] as usize == stride / 2
+                && offsets[2] == offsets[1] + (stride / 2) * height.div_ceil(2),
+        ),
+    };
+    if offsets[0] != 0 || !layout_matches {
+        return Err(anyhow!("unsupported {:?} plane layout", info.format()));
+    }
+    if src.len() < expected_len {
+        return Err(anyhow!("video frame stride exceeds buffer"));
+    }
+
+    let mut bgrx = vec![0u8; width * height * 4];
+    match info.format() {
+        gst_video::VideoFormat::Nv12 => {
+            colorspace::convert_nv12_to_xrgb(src, stride, width, height, &mut bgrx)
+        }
+        _ => colorspace::convert_yuv420p_to_xrgb(src, stride, width, height, &mut bgrx),
+    }
+    Ok(bgrx)
+}
+
 fn rgba_from_bgrx_frame(src: &[u8], stride: usize, width: u32, height: u32) -> Result<RgbaImage> {
     let row_bytes = width as usize * 4;
     let mut rgba = RgbaImage::new(width, height);
@@ -750,128 +2316,65 @@ fn rgba_from_bgrx_frame(src: &[u8], stride: usize, width: u32, height: u32) -> R
     Ok(rgba)
 }
 
-fn is_video_file(path: &Path) -> bool {
-    path.extension()
-        .and_then(|e| e.to_str())
-        .map(|ext| {
-            matches!(
-                ext.to_ascii_lowercase().as_str(),
-                "mp4" | "mkv" | "webm" | "mov" | "avi"
-            )
-        })
-        .unwrap_or(false)
-}
-
-struct HyprlandVisibility {
-    should_render: Arc<AtomicBool>,
-    target_monitor_id: Option<i64>,
-}
-
-impl HyprlandVisibility {
-    fn new(target_monitor_name: Option<&str>) -> Option<Self> {
-        let target_monitor_id = resolve_monitor_id(target_monitor_name);
-        let initial_should_render = query_should_render(target_monitor_id).unwrap_or(true);
-
-        Some(Self {
-            should_render: Arc::new(AtomicBool::new(initial_should_render)),
-            target_monitor_id,
-        })
-    }
-
-    fn should_render(&self) -> bool {
-        self.should_render.load(Ordering::Relaxed)
-    }
-
-    fn refresh_now(&self) {
-        if let Some(should_render) = query_should_render(self.target_monitor_id) {
-            self.should_render.store(should_render, Ordering::Relaxed);
-        }
+/// `.gifv` is MP4 under another name. `.ts` is shared with TypeScript sources, so
+/// it only counts as video when the file starts with the MPEG-TS sync byte.
+pub fn is_video_file(path: &Path) -> bool {
+    let Some(ext) = path.extension().and_then(|e| e.to_str()) else {
+        return false;
+    };
+    match ext.to_ascii_lowercase().as_str() {
+        "ts" => starts_with_mpeg_ts_sync_byte(path),
+        ext => picker::SUPPORTED_VIDEO_EXTS.contains(&ext),
     }
 }
 
-fn resolve_monitor_id(target_monitor_name: Option<&str>) -> Option<i64> {
-    let name = target_monitor_name?;
-    let output = Command::new("hyprctl")
-        .args(["-j", "monitors"])
-        .output()
-        .ok()?;
-    if !output.status.success() {
-        return None;
-    }
+/// Streams of a media file, read by GStreamer's discoverer without playing it.
+fn discover(path: &Path) -> Result<gst_pbutils::DiscovererInfo> {
+    const DISCOVER_TIMEOUT: gst::ClockTime = gst::ClockTime::from_seconds(5);
 
-    let value: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
-    value
-        .as_array()?
-        .iter()
-        .find(|m| m.get("name").and_then(|v| v.as_str()) == Some(name))
-        .and_then(|m| m.get("id").and_then(|v| v.as_i64()))
+    gst::init().context("failed to initialize gstreamer")?;
+    let absolute = std::path::absolute(path)?;
+    let uri = gst::glib::filename_to_uri(&absolute, None)
+        .with_context(|| format!("no file URI for {}", path.display()))?;
+    gst_pbutils::Discoverer::new(DISCOVER_TIMEOUT)
+        .context("failed to create a GStreamer discoverer")?
+        .discover_uri(&uri)
+        .with_context(|| format!("failed to probe video: {}", path.display()))
 }
 
-fn query_should_render(target_monitor_id: Option<i64>) -> Option<bool> {
-    let active_workspace_id = active_workspace_id(target_monitor_id)?;
-
-    let output = Command::new("hyprctl")
-        .args(["-j", "clients"])
-        .output()
-        .ok()?;
-    if !output.status.success() {
-        return None;
-    }
+/// Files that cannot be probed count as silent.
+fn has_audio_stream(path: &Path) -> bool {
+    discover(path)
+        .map(|info| !info.audio_streams().is_empty())
+        .unwrap_or(false)
+}
 
-    let value: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
-    let clients = value.as_array()?;
+/// Display size of the first video stream.
+pub fn video_dimensions(path: &Path) -> Result<(u32, u32)> {
+    let info = discover(path)?;
+    let stream = info
+        .video_streams()
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("no video stream in {}", path.display()))?;
 
-    let has_window_on_active_workspace = clients.iter().any(|client| {
-        let mapped = client
-            .get("mapped")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false);
-        let hidden = client
-            .get("hidden")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false);
-        if !mapped || hidden {
-            return false;
+    let par = stream.par();
+    let width = match (par.numer(), par.denom()) {
+        (numer, denom) if numer > 0 && denom > 0 && numer != denom => {
+            (stream.width() as u64 * numer as u64 / denom as u64) as u32
         }
-
-        let workspace_id = client
-            .get("workspace")
-            .and_then(|ws| ws.get("id"))
-            .and_then(|id| id.as_i64());
-
-        workspace_id == Some(active_workspace_id)
-    });
-
-    Some(!has_window_on_active_workspace)
+        _ => stream.width(),
+    };
+    Ok((width, stream.height()))
 }
 
-fn active_workspace_id(target_monitor_id: Option<i64>) -> Option<i64> {
-    let output = Command::new("hyprctl")
-        .args(["-j", "monitors"])
-        .output()
-        .ok()?;
-    if !output.status.success() {
-        return None;
-    }
-
-    let value: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
-    let monitors = value.as_array()?;
-
-    let monitor = if let Some(target_id) = target_monitor_id {
-        monitors
-            .iter()
-            .find(|m| m.get("id").and_then(|v| v.as_i64()) == Some(target_id))
-    } else {
-        monitors
-            .iter()
-            .find(|m| m.get("focused").and_then(|v| v.as_bool()) == Some(true))
-            .or_else(|| monitors.first())
-    }?;
-
-    monitor
-        .get("activeWorkspace")
-        .and_then(|ws| ws.get("id"))
-        .and_then(|id| id.as_i64())
+fn starts_with_mpeg_ts_sync_byte(path: &Path) -> bool {
+    const MPEG_TS_SYNC_BYTE: u8 = 0x47;
+    let mut first = [0u8; 1];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut first))
+        .map(|_| first[0] == MPEG_TS_SYNC_BYTE)
+        .unwrap_or(false)
 }
 
 struct AppState {
@@ -882,6 +2385,43 @@ struct AppState {
     height: u32,
     configured: bool,
     exit: bool,
+    dmabuf_formats: Vec<(u32, u64)>,
+    alpha: Option<SurfaceAlphaSync>,
+    /// Buffer pixels per surface unit; `width`/`height` are in buffer pixels
+    /// once the layer surface is configured.
+    buffer_scale: u32,
+    /// Video passes left before playback stops (`set --loop`); `None` loops forever.
+    loop_count_remaining: Option<u32>,
+    loop_fallback: Option<PathBuf>,
+    /// DPMS state of the output from wlr-output-power-management; videos pause while it is off.
+    monitor_power: Option<zwlr_output_power_v1::ZwlrOutputPowerV1>,
+    monitor_off: bool,
+    /// Global name of the output the surface is on, once selected.
+    selected_output: Option<u32>,
+    auto_rotate: bool,
+    /// Set by `auto_rotate` when the output turned and the frame must be redrawn.
+    resize_needed: bool,
+    /// Surfaces of an `--all-outputs` renderer, indexed like `outputs`.
+    output_surfaces: Vec<OutputSurface>,
+    /// The `--watermark` image, decoded by the first draw and reused by redraws.
+    watermark: OnceCell<Option<RgbaImage>>,
+}
+
+/// Size and state of one `--all-outputs` layer surface.
+struct OutputSurface {
+    width: u32,
+    height: u32,
+    buffer_scale: u32,
+    configured: bool,
+    closed: bool,
+}
+
+/// Mirrors the daemon's [`SurfaceAlpha`] onto the surface. Without
+/// wp_alpha_modifier_v1 it only reports that the first frame is up.
+struct SurfaceAlphaSync {
+    shared: Arc<SurfaceAlpha>,
+    modifier: Option<wp_alpha_modifier_surface_v1::WpAlphaModifierSurfaceV1>,
+    applied: u32,
 }
 
 impl AppState {
@@ -896,14 +2436,78 @@ impl AppState {
             height: 1080,
             configured: false,
             exit: false,
+            dmabuf_formats: Vec::new(),
+            alpha: None,
+            buffer_scale: 1,
+            loop_count_remaining: None,
+            loop_fallback: None,
+            monitor_power: None,
+            monitor_off: false,
+            selected_output: None,
+            auto_rotate: false,
+            resize_needed: false,
+            output_surfaces: Vec::new(),
+            watermark: OnceCell::new(),
+        }
+    }
+
+    /// Counts a finished pass through the video; true once the last one has played.
+    fn finish_loop(&mut self) -> bool {
+        match self.loop_count_remaining.as_mut() {
+            Some(remaining) => {
+                *remaining = remaining.saturating_sub(1);
+                *remaining == 0
+            }
+            None => false,
+        }
+    }
+
+    fn loops_exhausted(&self) -> bool {
+        self.loop_count_remaining == Some(0)
+    }
+
+    /// The configured surface size in logical pixels; `width` and `height` are
+    /// the physical size shm frames are drawn at.
+    fn surface_size(&self) -> (u32, u32) {
+        (
+            (self.width / self.buffer_scale).max(1),
+            (self.height / self.buffer_scale).max(1),
+        )
+    }
+
+    /// Whether the output turned since the last call, unless the renderer is stopping.
+    fn take_resize(&mut self) -> bool {
+        std::mem::take(&mut self.resize_needed) && !self.exit
+    }
+
+    /// Called after a frame is committed; picks up any change the daemon made.
+    fn sync_alpha(&mut self, surface: &wl_surface::WlSurface) {
+        let Some(alpha) = self.alpha.as_mut() else {
+            return;
+        };
+        alpha.shared.mark_shown();
+        let Some(modifier) = &alpha.modifier else {
+            return;
+        };
+        let factor = alpha.shared.factor();
+        if factor != alpha.applied {
+            modifier.set_multiplier(factor);
+            surface.commit();
+            alpha.applied = factor;
         }
     }
 
+    fn alpha_is_fading(&self) -> bool {
+        self.alpha.as_ref().is_some_and(|alpha| {
+            alpha.modifier.is_some() && alpha.applied != 0 && alpha.applied != u32::MAX
+        })
+    }
+
     fn has_resolved_requested_output(&self) -> bool {
         let Some(requested) = self.requested_monitor.as_deref() else {
             return true;
         };
-        self.outputs.iter().any(|out| output_matches_monitor(out, requested))
+        find_requested_output(&self.outputs, requested).is_some()
     }
 
     fn all_outputs_have_metadata(&self) -> bool {
@@ -912,15 +2516,39 @@ impl AppState {
             .all(|out| out.name.is_some() || out.description.is_some())
     }
 
+    /// Current mode of a bound output, used when the layer surface configure reports `(0, 0)`.
+    fn output_resolution(&self, output: &wl_output::WlOutput) -> Option<(u32, u32)> {
+        self.outputs
+            .iter()
+            .find(|out| &out.output == output)
+            .and_then(|out| out.resolution)
+    }
+
+    fn output_scale(&self, output: &wl_output::WlOutput) -> i32 {
+        self.outputs
+            .iter()
+            .find(|out| &out.output == output)
+            .map(|out| out.scale_factor.max(1))
+            .unwrap_or(1)
+    }
+
     fn select_output(&self) -> Result<wl_output::WlOutput> {
         if let Some(requested) = &self.requested_monitor {
-            if let Some(found) = self
-                .outputs
-                .iter()
-                .find(|out| output_matches_monitor(out, requested))
-            {
+            if let Some(found) = find_requested_output(&self.outputs, requested) {
                 return Ok(found.output.clone());
             }
+            if let Some(index) = monitor_index(requested) {
+                let numbered: Vec<String> = outputs_by_global_name(&self.outputs)
+                    .iter()
+                    .enumerate()
+                    .map(|(i, out)| format!("{}={}", i, out.name.as_deref().unwrap_or("unknown")))
+                    .collect();
+                return Err(anyhow!(
+                    "monitor index {} is out of range (outputs: {})",
+                    index,
+                    numbered.join(", ")
+                ));
+            }
 
             let available: Vec<String> = self
                 .outputs
@@ -955,48 +2583,280 @@ impl AppState {
 
 struct OutputBinding {
     global_name: u32,
+    version: u32,
     output: wl_output::WlOutput,
     name: Option<String>,
     description: Option<String>,
+    resolution: Option<(u32, u32)>,
+    /// Integer HiDPI scale from `wl_output.scale`.
+    scale_factor: i32,
+    /// Rotation and flip from `wl_output.geometry`.
+    transform: wl_output::Transform,
+}
+
+/// The surface size once the output is turned from `previous` to `transform`:
+/// swapped when its orientation no longer matches the output mode (as before,
+/// if the mode is unknown) turned that way.
+fn turned_size(
+    (width, height): (u32, u32),
+    mode: Option<(u32, u32)>,
+    previous: wl_output::Transform,
+    transform: wl_output::Transform,
+) -> (u32, u32) {
+    use wl_output::Transform;
+    let quarter_turn = |transform| {
+        matches!(
+            transform,
+            Transform::_90 | Transform::_270 | Transform::Flipped90 | Transform::Flipped270
+        )
+    };
+    let mode_landscape = match mode {
+        Some((mode_width, mode_height)) => mode_width >= mode_height,
+        None => (width >= height) != quarter_turn(previous),
+    };
+    if (width >= height) == (mode_landscape != quarter_turn(transform)) {
+        (width, height)
+    } else {
+        (height, width)
+    }
+}
+
+/// Stand-in description for wl_output v3, built from the geometry event.
+fn legacy_output_description(make: &str, model: &str) -> Option<String> {
+    let description = format!("{} {}", make.trim(), model.trim());
+    let description = description.trim();
+    (!description.is_empty()).then(|| description.to_string())
+}
+
+/// The connector name of the output `requested` picks, matched as `--monitor`
+/// is: a name in any case, a description or an index. `None` when none matches.
+pub fn resolve_output_name(requested: &str, socket: Option<&Path>) -> Result<Option<String>> {
+    let connection = connect_wayland(socket)?;
+    let (globals, mut event_queue) =
+        registry_queue_init::<AppState>(&connection).context("failed to init globals")?;
+    let mut state = AppState::new(PathBuf::new(), Some(requested.to_string()));
+    discover_outputs(&globals, &mut event_queue, &mut state)?;
+    Ok(find_requested_output(&state.outputs, requested).and_then(|out| out.name.clone()))
+}
+
+/// A number picks the output at that index in global-name order, which stays
+/// the same across restarts even when connector names do not. Anything else
+/// is matched by name or description.
+fn find_requested_output<'a>(
+    outputs: &'a [OutputBinding],
+    requested: &str,
+) -> Option<&'a OutputBinding> {
+    // Checked first: a description such as "Dell U2720Q" would otherwise
+    // swallow "2" as a substring.
+    if let Some(index) = monitor_index(requested) {
+        return outputs_by_global_name(outputs).get(index).copied();
+    }
+    outputs.iter().find(|out| output_matches_monitor(out, requested))
+}
+
+/// `--monitor 1`, with the whitespace trimmed as for names.
+fn monitor_index(requested: &str) -> Option<usize> {
+    requested.trim().parse().ok()
+}
+
+fn outputs_by_global_name(outputs: &[OutputBinding]) -> Vec<&OutputBinding> {
+    let mut sorted: Vec<&OutputBinding> = outputs.iter().collect();
+    sorted.sort_by_key(|out| out.global_name);
+    sorted
+}
+
+fn output_matches_monitor(output: &OutputBinding, requested: &str) -> bool {
+    let requested = requested.trim();
+
+    if let Some(name) = output.name.as_deref() {
+        if name == requested || name.eq_ignore_ascii_case(requested) {
+            return true;
+        }
+    }
+
+    if let Some(description) = output.description.as_deref() {
+        let requested_lower = requested.to_ascii_lowercase();
+        let desc_lower = description.to_ascii_lowercase();
+        if desc_lower == requested_lower || desc_lower.contains(&requested_lower) {
+            return true;
+        }
+    }
+
+    false
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for AppState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &wl_registry::WlRegistry,
+        _event: wl_registry::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<wl_compositor::WlCompositor, ()> for AppState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &wl_compositor::WlCompositor,
+        _event: wl_compositor::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<wl_surface::WlSurface, ()> for AppState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &wl_surface::WlSurface,
+        _event: wl_surface::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, u32> for AppState {
+    fn event(
+        state: &mut Self,
+        _proxy: &wl_output::WlOutput,
+        event: wl_output::Event,
+        data: &u32,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_output::Event::Name { name } => {
+                if let Some(output) = state.outputs.iter_mut().find(|o| o.global_name == *data) {
+                    output.name = Some(name);
+                }
+            }
+            wl_output::Event::Description { description } => {
+                if let Some(output) = state.outputs.iter_mut().find(|o| o.global_name == *data) {
+                    output.description = Some(description);
+                }
+            }
+            wl_output::Event::Geometry {
+                make,
+                model,
+                transform,
+                ..
+            } => {
+                let rotating = state.auto_rotate && state.selected_output == Some(*data);
+                if let Some(output) = state.outputs.iter_mut().find(|o| o.global_name == *data) {
+                    if output.version < 4 && output.description.is_none() {
+                        output.description = legacy_output_description(&make, &model);
+                    }
+                    if let WEnum::Value(transform) = transform {
+                        let previous = std::mem::replace(&mut output.transform, transform);
+                        let size = (state.width, state.height);
+                        let turned = turned_size(size, output.resolution, previous, transform);
+                        if rotating && turned != size {
+                            debug!("output transform {:?} -> {:?}", previous, transform);
+                            (state.width, state.height) = turned;
+                            state.resize_needed = true;
+                        }
+                    }
+                }
+            }
+            wl_output::Event::Mode {
+                flags,
+                width,
+                height,
+                ..
+            } => {
+                let is_current = matches!(
+                    flags,
+                    WEnum::Value(mode) if mode.contains(wl_output::Mode::Current)
+                );
+                if is_current && width > 0 && height > 0 {
+                    if let Some(output) = state.outputs.iter_mut().find(|o| o.global_name == *data) {
+                        output.resolution = Some((width as u32, height as u32));
+                    }
+                }
+            }
+            wl_output::Event::Scale { factor } => {
+                if let Some(output) = state.outputs.iter_mut().find(|o| o.global_name == *data) {
+                    output.scale_factor = factor;
+                }
+            }
+            _ => {}
+        }
+    }
 }
 
-fn output_matches_monitor(output: &OutputBinding, requested: &str) -> bool {
-    let requested = requested.trim();
-
-    if let Some(name) = output.name.as_deref() {
-        if name == requested || name.eq_ignore_ascii_case(requested) {
-            return true;
+impl Dispatch<zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        _proxy: &zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1,
+        event: zwp_linux_dmabuf_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwp_linux_dmabuf_v1::Event::Format { format } => {
+                state.dmabuf_formats.push((format, DRM_FORMAT_MOD_INVALID));
+            }
+            zwp_linux_dmabuf_v1::Event::Modifier {
+                format,
+                modifier_hi,
+                modifier_lo,
+            } => {
+                let modifier = ((modifier_hi as u64) << 32) | modifier_lo as u64;
+                state.dmabuf_formats.push((format, modifier));
+            }
+            _ => {}
         }
     }
+}
 
-    if let Some(description) = output.description.as_deref() {
-        let requested_lower = requested.to_ascii_lowercase();
-        let desc_lower = description.to_ascii_lowercase();
-        if desc_lower == requested_lower || desc_lower.contains(&requested_lower) {
-            return true;
-        }
+impl Dispatch<zwp_linux_buffer_params_v1::ZwpLinuxBufferParamsV1, ()> for AppState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &zwp_linux_buffer_params_v1::ZwpLinuxBufferParamsV1,
+        _event: zwp_linux_buffer_params_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
     }
+}
 
-    false
+impl Dispatch<wp_viewporter::WpViewporter, ()> for AppState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &wp_viewporter::WpViewporter,
+        _event: wp_viewporter::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
 }
 
-impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for AppState {
+impl Dispatch<wp_alpha_modifier_v1::WpAlphaModifierV1, ()> for AppState {
     fn event(
         _state: &mut Self,
-        _proxy: &wl_registry::WlRegistry,
-        _event: wl_registry::Event,
-        _data: &GlobalListContents,
+        _proxy: &wp_alpha_modifier_v1::WpAlphaModifierV1,
+        _event: wp_alpha_modifier_v1::Event,
+        _data: &(),
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
     ) {
     }
 }
 
-impl Dispatch<wl_compositor::WlCompositor, ()> for AppState {
+impl Dispatch<wp_alpha_modifier_surface_v1::WpAlphaModifierSurfaceV1, ()> for AppState {
     fn event(
         _state: &mut Self,
-        _proxy: &wl_compositor::WlCompositor,
-        _event: wl_compositor::Event,
+        _proxy: &wp_alpha_modifier_surface_v1::WpAlphaModifierSurfaceV1,
+        _event: wp_alpha_modifier_surface_v1::Event,
         _data: &(),
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
@@ -1004,11 +2864,11 @@ impl Dispatch<wl_compositor::WlCompositor, ()> for AppState {
     }
 }
 
-impl Dispatch<wl_surface::WlSurface, ()> for AppState {
+impl Dispatch<zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1, ()> for AppState {
     fn event(
         _state: &mut Self,
-        _proxy: &wl_surface::WlSurface,
-        _event: wl_surface::Event,
+        _proxy: &zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1,
+        _event: zwlr_output_power_manager_v1::Event,
         _data: &(),
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
@@ -1016,31 +2876,47 @@ impl Dispatch<wl_surface::WlSurface, ()> for AppState {
     }
 }
 
-impl Dispatch<wl_output::WlOutput, u32> for AppState {
+impl Dispatch<zwlr_output_power_v1::ZwlrOutputPowerV1, ()> for AppState {
     fn event(
         state: &mut Self,
-        _proxy: &wl_output::WlOutput,
-        event: wl_output::Event,
-        data: &u32,
+        proxy: &zwlr_output_power_v1::ZwlrOutputPowerV1,
+        event: zwlr_output_power_v1::Event,
+        _data: &(),
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
     ) {
         match event {
-            wl_output::Event::Name { name } => {
-                if let Some(output) = state.outputs.iter_mut().find(|o| o.global_name == *data) {
-                    output.name = Some(name);
+            zwlr_output_power_v1::Event::Mode { mode } => {
+                let off = mode == WEnum::Value(zwlr_output_power_v1::Mode::Off);
+                if off != state.monitor_off {
+                    debug!("monitor powered {}", if off { "off" } else { "on" });
                 }
+                state.monitor_off = off;
             }
-            wl_output::Event::Description { description } => {
-                if let Some(output) = state.outputs.iter_mut().find(|o| o.global_name == *data) {
-                    output.description = Some(description);
-                }
+            zwlr_output_power_v1::Event::Failed => {
+                // Another client controls the output's power, or the output is gone.
+                debug!("output power state unavailable, rendering regardless");
+                proxy.destroy();
+                state.monitor_power = None;
+                state.monitor_off = false;
             }
             _ => {}
         }
     }
 }
 
+impl Dispatch<wp_viewport::WpViewport, ()> for AppState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &wp_viewport::WpViewport,
+        _event: wp_viewport::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
 impl Dispatch<wl_shm::WlShm, ()> for AppState {
     fn event(
         _state: &mut Self,
@@ -1108,11 +2984,16 @@ impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, ()> for AppState {
                 height,
             } => {
                 proxy.ack_configure(serial);
+                let size = (state.width, state.height);
                 if width > 0 {
-                    state.width = width;
+                    state.width = width * state.buffer_scale;
                 }
                 if height > 0 {
-                    state.height = height;
+                    state.height = height * state.buffer_scale;
+                }
+                // The compositor may resize the surface for a rotation before the output says so.
+                if state.configured && state.auto_rotate && size != (state.width, state.height) {
+                    state.resize_needed = true;
                 }
                 state.configured = true;
             }
@@ -1124,26 +3005,168 @@ impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, ()> for AppState {
     }
 }
 
+impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, usize> for AppState {
+    fn event(
+        state: &mut Self,
+        proxy: &zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
+        event: zwlr_layer_surface_v1::Event,
+        index: &usize,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let Some(out) = state.output_surfaces.get_mut(*index) else {
+            return;
+        };
+        match event {
+            zwlr_layer_surface_v1::Event::Configure {
+                serial,
+                width,
+                height,
+            } => {
+                proxy.ack_configure(serial);
+                if width > 0 {
+                    out.width = width * out.buffer_scale;
+                }
+                if height > 0 {
+                    out.height = height * out.buffer_scale;
+                }
+                out.configured = true;
+            }
+            zwlr_layer_surface_v1::Event::Closed => {
+                out.closed = true;
+            }
+            _ => {}
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{build_video_pipeline_descriptions, render_rgba_fit};
-    use crate::config::FitMode;
-    use image::RgbaImage;
+    use super::{
+        anchor_edges, build_video_pipeline_descriptions, compute_cover_crop_offset_with_focus,
+        compute_span_crop, decode_animated_webp, fade_rgba_frame, falls_back_to_still,
+        find_requested_output, fit_within, is_video_file, legacy_output_description,
+        render_image_fit, render_rgba_fit, reserves_space, retry_backoff, scale_tile, turned_size,
+        video_balance_stage, video_start_within, wait_for_startup_delay, wayland_socket_path,
+        with_audio_branch, OutputBinding, VideoDecodeError,
+    };
+    use crate::config::{FitMode, SurfaceAnchor};
+    use crate::monitors::MonitorGeometry;
+    use image::{codecs::webp::WebPEncoder, imageops::FilterType, ExtendedColorType, RgbaImage};
+    use std::{
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    #[test]
+    fn configured_display_names_the_socket_directly() {
+        assert_eq!(wayland_socket_path(None, None), None);
+        assert_eq!(
+            wayland_socket_path(Some("wayland-1"), Some(Path::new("/run/user/1000"))),
+            Some(PathBuf::from("/run/user/1000/wayland-1"))
+        );
+        assert_eq!(
+            wayland_socket_path(Some("/tmp/compositor.sock"), Some(Path::new("/run/user/1000"))),
+            Some(PathBuf::from("/tmp/compositor.sock"))
+        );
+    }
+
+    fn riff_chunk(fourcc: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut chunk = fourcc.to_vec();
+        chunk.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(payload);
+        if payload.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn u24(value: u32) -> [u8; 3] {
+        let bytes = value.to_le_bytes();
+        [bytes[0], bytes[1], bytes[2]]
+    }
+
+    /// Builds a 1x1 animated WebP by wrapping losslessly encoded frames in
+    /// ANMF chunks, since the `image` crate can only encode static WebP.
+    fn synthetic_animated_webp(colors: &[[u8; 4]], delay_ms: u32) -> Vec<u8> {
+        let mut body = b"WEBP".to_vec();
+
+        let mut vp8x = vec![0x10 | 0x02, 0, 0, 0];
+        vp8x.extend_from_slice(&u24(0));
+        vp8x.extend_from_slice(&u24(0));
+        body.extend(riff_chunk(b"VP8X", &vp8x));
+        body.extend(riff_chunk(b"ANIM", &[0, 0, 0, 0, 0, 0]));
+
+        for color in colors {
+            let mut encoded = Vec::new();
+            WebPEncoder::new_lossless(&mut encoded)
+                .encode(color, 1, 1, ExtendedColorType::Rgba8)
+                .expect("1x1 frame should encode");
+            // Skip the RIFF/WEBP header and keep the VP8L chunk.
+            let frame_data = &encoded[12..];
+
+            let mut anmf = Vec::new();
+            anmf.extend_from_slice(&u24(0));
+            anmf.extend_from_slice(&u24(0));
+            anmf.extend_from_slice(&u24(0));
+            anmf.extend_from_slice(&u24(0));
+            anmf.extend_from_slice(&u24(delay_ms));
+            anmf.push(0x02);
+            anmf.extend_from_slice(frame_data);
+            body.extend(riff_chunk(b"ANMF", &anmf));
+        }
+
+        let mut file = b"RIFF".to_vec();
+        file.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        file.extend(body);
+        file
+    }
 
     #[test]
     fn contain_enables_borders_for_all_video_pipelines() {
-        let descriptions =
-            build_video_pipeline_descriptions("/tmp/demo.mp4", 1920, 1080, 60, FitMode::Contain);
+        let descriptions = build_video_pipeline_descriptions(
+            "/tmp/demo.mp4",
+            1920,
+            1080,
+            60,
+            FitMode::Contain,
+            Some("nvh264device1dec"),
+            "",
+        );
 
         assert!(descriptions
             .iter()
             .all(|pipeline| pipeline.contains("videoscale add-borders=true")));
+        assert!(descriptions[0].contains("! nvh264device1dec !"));
+    }
+
+    #[test]
+    fn nvdec_pipeline_is_skipped_for_other_gpus() {
+        let descriptions = build_video_pipeline_descriptions(
+            "/tmp/demo.mp4",
+            1920,
+            1080,
+            60,
+            FitMode::Cover,
+            None,
+            "",
+        );
+
+        assert_eq!(descriptions.len(), 3);
+        assert!(descriptions.iter().all(|pipeline| !pipeline.contains("nvh264")));
     }
 
     #[test]
     fn cover_does_not_enable_video_borders() {
-        let descriptions =
-            build_video_pipeline_descriptions("/tmp/demo.mp4", 1920, 1080, 60, FitMode::Cover);
+        let descriptions = build_video_pipeline_descriptions(
+            "/tmp/demo.mp4",
+            1920,
+            1080,
+            60,
+            FitMode::Cover,
+            Some("nvh264dec"),
+            "",
+        );
 
         assert!(descriptions
             .iter()
@@ -1152,8 +3175,15 @@ mod tests {
 
     #[test]
     fn center_video_pipelines_keep_source_dimensions() {
-        let descriptions =
-            build_video_pipeline_descriptions("/tmp/demo.mp4", 1920, 1080, 60, FitMode::Center);
+        let descriptions = build_video_pipeline_descriptions(
+            "/tmp/demo.mp4",
+            1920,
+            1080,
+            60,
+            FitMode::Center,
+            Some("nvh264dec"),
+            "",
+        );
 
         assert!(descriptions.iter().all(|pipeline| !pipeline.contains("videoscale")));
         assert!(descriptions
@@ -1161,15 +3191,594 @@ mod tests {
             .all(|pipeline| !pipeline.contains("width=1920") && !pipeline.contains("height=1080")));
     }
 
+    #[test]
+    fn span_crop_gives_each_monitor_its_part_of_the_layout() {
+        // A 4x1 image: red, green, blue, white columns.
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [255; 4]];
+        let image = image::DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 1, |x, _| {
+            image::Rgba(colors[x as usize])
+        }));
+        let monitor = |name: &str, x, width, scale| MonitorGeometry {
+            name: name.to_string(),
+            x,
+            y: 0,
+            width,
+            height: 2,
+            scale,
+        };
+        // The right monitor is HiDPI: 8 pixels wide but only 4 layout units.
+        let monitors = [monitor("LEFT", 0, 4, 1.0), monitor("RIGHT", 4, 8, 2.0)];
+
+        let parts = compute_span_crop(&monitors, &image, FitMode::Stretch);
+
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].0, "LEFT");
+        assert_eq!(parts[0].1.dimensions(), (4, 2));
+        assert_eq!(parts[1].1.dimensions(), (8, 2));
+        let red = parts[0].1.get_pixel(0, 0).0;
+        let white = parts[1].1.get_pixel(7, 0).0;
+        assert!(red[0] > 200 && red[2] < 50, "{:?}", red);
+        assert!(white.iter().all(|&c| c > 200), "{:?}", white);
+    }
+
+    #[test]
+    fn video_balance_is_added_only_for_adjusted_videos() {
+        assert_eq!(video_balance_stage(1.0, 1.0), "");
+        let stage = video_balance_stage(0.6, 1.2);
+        assert_eq!(stage, " ! videobalance brightness=-0.400 contrast=1.200");
+
+        let descriptions = build_video_pipeline_descriptions(
+            "/tmp/demo.mp4",
+            1920,
+            1080,
+            60,
+            FitMode::Cover,
+            None,
+            &stage,
+        );
+        assert!(descriptions
+            .iter()
+            .all(|pipeline| pipeline.contains("videoscale ! videobalance brightness=-0.400")));
+    }
+
+    #[test]
+    fn audio_branch_queues_both_streams_off_the_demuxer_or_decodebin() {
+        let descriptions = build_video_pipeline_descriptions(
+            "/tmp/demo.mp4",
+            1920,
+            1080,
+            60,
+            FitMode::Cover,
+            None,
+            "",
+        );
+        let with_audio: Vec<String> =
+            descriptions.iter().map(|d| with_audio_branch(d)).collect();
+        let audio = "audioconvert ! audioresample ! autoaudiosink";
+        assert_eq!(
+            with_audio_branch(
+                "filesrc location=\"/tmp/demo.mp4\" ! qtdemux ! h264parse ! avdec_h264 ! appsink"
+            ),
+            format!(
+                "filesrc location=\"/tmp/demo.mp4\" ! qtdemux name=demux \
+                 demux.video_0 ! queue ! h264parse ! avdec_h264 ! appsink \
+                 demux.audio_0 ! queue ! decodebin ! {}",
+                audio
+            )
+        );
+        assert_eq!(
+            with_audio_branch(
+                "filesrc location=\"/tmp/demo.mp4\" ! decodebin ! videoconvert ! appsink"
+            ),
+            format!(
+                "filesrc location=\"/tmp/demo.mp4\" ! decodebin name=dec \
+                 dec. ! video/x-raw(ANY) ! queue ! videoconvert ! appsink \
+                 dec. ! audio/x-raw(ANY) ! queue ! {}",
+                audio
+            )
+        );
+        // Every pipeline gets exactly one queued branch per stream.
+        for pipeline in &with_audio {
+            assert_eq!(pipeline.matches("! queue !").count(), 2, "{}", pipeline);
+            assert_eq!(pipeline.matches(audio).count(), 1, "{}", pipeline);
+        }
+        assert_eq!(with_audio_branch("videotestsrc ! appsink"), "videotestsrc ! appsink");
+    }
+
     #[test]
     fn scale_down_does_not_upscale_smaller_images() {
         let mut image = RgbaImage::new(1, 1);
         image.put_pixel(0, 0, image::Rgba([255, 255, 255, 255]));
 
-        let rendered = render_rgba_fit(&image, 3, 3, FitMode::ScaleDown);
+        let rendered =
+            render_rgba_fit(&image, 3, 3, FitMode::ScaleDown, FilterType::Lanczos3, (0, 0));
 
         assert_eq!(rendered.get_pixel(1, 1).0, [255, 255, 255, 255]);
         assert_eq!(rendered.get_pixel(0, 0).0, [0, 0, 0, 0]);
         assert_eq!(rendered.get_pixel(2, 2).0, [0, 0, 0, 0]);
     }
+
+    #[test]
+    fn cover_offset_shifts_crop_window_and_clamps() {
+        // 1x5 column, cropped to 1x1 by cover: the offset picks which row shows.
+        let mut image = RgbaImage::new(1, 5);
+        for y in 0..5 {
+            image.put_pixel(0, y, image::Rgba([y as u8 * 60, 0, 0, 255]));
+        }
+
+        let centered = render_rgba_fit(&image, 1, 1, FitMode::Cover, FilterType::Nearest, (0, 0));
+        let bottom = render_rgba_fit(&image, 1, 1, FitMode::Cover, FilterType::Nearest, (0, 100));
+        let top = render_rgba_fit(&image, 1, 1, FitMode::Cover, FilterType::Nearest, (0, -100));
+
+        assert_eq!(centered.get_pixel(0, 0).0[0], 120);
+        assert_eq!(bottom.get_pixel(0, 0).0[0], 240);
+        assert_eq!(top.get_pixel(0, 0).0[0], 0);
+    }
+
+    #[test]
+    fn cover_crop_keeps_the_focus_area_in_view() {
+        // Portrait 1920x2880 on a 1920x1080 screen, with a face near the top.
+        let face = [0.3, 0.1, 0.4, 0.2];
+        let crop = |rw, rh, focus| compute_cover_crop_offset_with_focus(rw, rh, 1920, 1080, focus);
+        assert_eq!(crop(1920, 2880, face), (0, 36));
+        assert_eq!(crop(1920, 2880, [0.0, 0.9, 1.0, 0.1]), (0, 1800));
+        assert_eq!(crop(3000, 1080, [0.0, 0.0, 0.1, 1.0]), (0, 0));
+        assert_eq!(crop(3000, 1080, [0.45, 0.0, 0.1, 1.0]), (540, 0));
+
+        let mut column = RgbaImage::new(1, 5);
+        for y in 0..5 {
+            column.put_pixel(0, y, image::Rgba([y as u8 * 60, 0, 0, 255]));
+        }
+        let column = image::DynamicImage::ImageRgba8(column);
+        let render = |focus| {
+            render_image_fit(&column, 1, 1, FitMode::Cover, FilterType::Nearest, (0, 0), focus)
+        };
+        assert_eq!(render(None).get_pixel(0, 0).0[0], 120);
+        assert_eq!(render(Some([0.0, 0.0, 1.0, 0.2])).get_pixel(0, 0).0[0], 0);
+        assert_eq!(render(Some([0.0, 0.8, 1.0, 0.2])).get_pixel(0, 0).0[0], 240);
+    }
+
+    #[test]
+    fn contain_offset_moves_image_within_canvas() {
+        let mut image = RgbaImage::new(1, 1);
+        image.put_pixel(0, 0, image::Rgba([255, 255, 255, 255]));
+
+        let rendered =
+            render_rgba_fit(&image, 3, 1, FitMode::ScaleDown, FilterType::Nearest, (-1, 0));
+
+        assert_eq!(rendered.get_pixel(0, 0).0, [255, 255, 255, 255]);
+        assert_eq!(rendered.get_pixel(1, 0).0, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn tile_repeats_image_and_offset_shifts_grid() {
+        let mut image = RgbaImage::new(2, 1);
+        image.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        image.put_pixel(1, 0, image::Rgba([0, 0, 255, 255]));
+
+        let tiled = render_rgba_fit(&image, 5, 2, FitMode::Tile, FilterType::Nearest, (0, 0));
+        let shifted = render_rgba_fit(&image, 5, 2, FitMode::Tile, FilterType::Nearest, (1, 0));
+
+        let reds: Vec<u32> = (0..5).filter(|&x| tiled.get_pixel(x, 1).0[0] == 255).collect();
+        assert_eq!(reds, vec![0, 2, 4]);
+        assert_eq!(shifted.get_pixel(0, 0).0, [0, 0, 255, 255]);
+        assert_eq!(shifted.get_pixel(1, 0).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn tile_size_scales_the_image_before_tiling() {
+        let source = RgbaImage::from_fn(40, 20, |x, y| image::Rgba([x as u8, y as u8, 0, 255]));
+        let source = image::DynamicImage::ImageRgba8(source);
+        let size = |tile: (u32, u32)| {
+            let scaled = scale_tile(source.clone(), tile, FilterType::Nearest);
+            (scaled.width(), scaled.height())
+        };
+        assert_eq!(size((0, 0)), (40, 20));
+        assert_eq!(size((0, 10)), (20, 10));
+        assert_eq!(size((10, 0)), (10, 5));
+
+        let tile = scale_tile(source, (10, 10), FilterType::Nearest).to_rgba8();
+        assert_eq!(tile.dimensions(), (10, 10));
+        let tiled = render_rgba_fit(&tile, 1920, 1080, FitMode::Tile, FilterType::Nearest, (0, 0));
+        assert_eq!(tiled.dimensions(), (1920, 1080));
+        // The last of 192 columns by 108 rows shows the scaled tile, not a crop of the source.
+        let corner = *tiled.get_pixel(1913, 1077);
+        assert_eq!(corner, *tile.get_pixel(3, 7));
+        assert_ne!(corner, image::Rgba([3, 7, 0, 255]));
+        // Every pixel repeats the tile.
+        assert!(tiled
+            .enumerate_pixels()
+            .all(|(x, y, pixel)| pixel == tile.get_pixel(x % 10, y % 10)));
+    }
+
+    #[test]
+    fn center_keeps_native_pixels_and_crops_from_the_middle() {
+        let image = RgbaImage::from_fn(3, 3, |x, y| image::Rgba([x as u8, y as u8, 0, 255]));
+
+        let framed = render_rgba_fit(&image, 5, 5, FitMode::Center, FilterType::Nearest, (0, 0));
+        assert_eq!(framed.get_pixel(0, 0).0, [0, 0, 0, 0]);
+        assert_eq!(framed.get_pixel(1, 1).0, [0, 0, 0, 255]);
+        assert_eq!(framed.get_pixel(3, 3).0, [2, 2, 0, 255]);
+
+        let cropped = render_rgba_fit(&image, 1, 1, FitMode::Center, FilterType::Nearest, (0, 0));
+        assert_eq!(cropped.get_pixel(0, 0).0, [1, 1, 0, 255]);
+    }
+
+    #[test]
+    fn legacy_output_description_joins_make_and_model() {
+        assert_eq!(
+            legacy_output_description("Dell Inc. ", "U2720Q"),
+            Some("Dell Inc. U2720Q".to_string())
+        );
+        assert_eq!(legacy_output_description("", "  "), None);
+    }
+
+    #[test]
+    fn fade_scales_color_channels_and_keeps_alpha() {
+        let src = [200u8, 100, 50, 255];
+        let mut dst = [0u8; 4];
+
+        fade_rgba_frame(&src, &mut dst, 0.5);
+
+        assert_eq!(dst, [100, 50, 25, 255]);
+    }
+
+    #[test]
+    fn animated_webp_decodes_all_frames_with_delays() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("animated.webp");
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+        std::fs::write(&path, synthetic_animated_webp(&colors, 40)).unwrap();
+
+        let frames = decode_animated_webp(&path).unwrap();
+        let frames = frames.expect("animated WebP should use the animation path");
+        assert_eq!(frames.len(), 3);
+        for ((frame, delay), color) in frames.iter().zip(colors) {
+            assert_eq!(frame.get_pixel(0, 0).0, color);
+            assert_eq!(*delay, Duration::from_millis(40));
+        }
+    }
+
+    #[test]
+    fn static_webp_stays_on_image_path() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("static.webp");
+        let mut encoded = Vec::new();
+        WebPEncoder::new_lossless(&mut encoded)
+            .encode(&[1, 2, 3, 255], 1, 1, ExtendedColorType::Rgba8)
+            .unwrap();
+        std::fs::write(&path, encoded).unwrap();
+
+        let frames = decode_animated_webp(&path).unwrap();
+        assert!(frames.is_none());
+    }
+
+    #[test]
+    fn legacy_video_extensions_and_ts_sync_byte() {
+        for ext in ["flv", "3gp", "rm", "gifv", "m4v", "FLV"] {
+            assert!(is_video_file(std::path::Path::new(&format!("clip.{}", ext))), "{}", ext);
+        }
+
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let stream = dir.join("stream.ts");
+        let source = dir.join("source.ts");
+        std::fs::write(&stream, [0x47, 0x40, 0x00, 0x10]).unwrap();
+        std::fs::write(&source, "export const x = 1;\n").unwrap();
+
+        assert!(is_video_file(&stream));
+        assert!(!is_video_file(&source));
+        assert!(!is_video_file(&dir.join("missing.ts")));
+    }
+
+    #[test]
+    fn corner_anchors_use_two_edges_and_shrink_to_the_output() {
+        use clap::ValueEnum;
+        use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_surface_v1::Anchor;
+
+        assert_eq!(anchor_edges(SurfaceAnchor::TopRight), Anchor::Top | Anchor::Right);
+        assert_eq!(anchor_edges(SurfaceAnchor::AllEdges).bits(), Anchor::all().bits());
+        assert!(anchor_edges(SurfaceAnchor::Center).is_empty());
+        // None of them gets a positive exclusive zone honoured.
+        for anchor in SurfaceAnchor::value_variants() {
+            assert!(!reserves_space(anchor_edges(*anchor)), "{:?}", anchor);
+        }
+        assert!(reserves_space(Anchor::Top));
+        assert!(reserves_space(Anchor::Bottom | Anchor::Left | Anchor::Right));
+
+        assert_eq!(fit_within((800, 600), (1920, 1080)), (800, 600));
+        assert_eq!(fit_within((3840, 1080), (1920, 1080)), (1920, 540));
+    }
+
+    #[test]
+    fn retry_backoff_doubles_up_to_the_cap_unless_the_file_is_growing() {
+        let delays: Vec<u64> = (1..=6)
+            .map(|attempt| retry_backoff(attempt, false).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, [500, 1000, 2000, 4000, 8000, 8000]);
+        assert_eq!(retry_backoff(9, true), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn a_start_past_the_end_of_the_video_starts_at_zero() {
+        let secs = Duration::from_secs;
+        assert_eq!(video_start_within(secs(30), Some(secs(10))), Duration::ZERO);
+        assert_eq!(video_start_within(secs(10), Some(secs(10))), Duration::ZERO);
+        assert_eq!(video_start_within(secs(5), Some(secs(10))), secs(5));
+        // Live streams and files that report no duration keep the start.
+        assert_eq!(video_start_within(secs(30), None), secs(30));
+    }
+
+    #[test]
+    fn startup_delay_ends_when_the_renderer_is_stopped() {
+        use std::{
+            sync::atomic::{AtomicBool, Ordering},
+            thread,
+            time::Instant,
+        };
+
+        let stop = AtomicBool::new(false);
+        let started = Instant::now();
+        thread::scope(|scope| {
+            scope.spawn(|| wait_for_startup_delay(Duration::from_secs(30), Some(&stop)));
+            stop.store(true, Ordering::Relaxed);
+        });
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let started = Instant::now();
+        wait_for_startup_delay(Duration::from_millis(120), None);
+        assert!(started.elapsed() >= Duration::from_millis(120));
+    }
+
+    #[test]
+    fn video_stops_after_the_requested_number_of_loops() {
+        use super::AppState;
+
+        let mut forever = AppState::new("/walls/a.mp4".into(), None);
+        assert!((0..100).all(|_| !forever.finish_loop()));
+        assert!(!forever.loops_exhausted());
+
+        let mut state = AppState::new("/walls/a.mp4".into(), None);
+        state.loop_count_remaining = Some(3);
+        assert!(!state.finish_loop());
+        assert!(!state.finish_loop());
+        assert!(!state.loops_exhausted());
+        assert!(state.finish_loop());
+        assert!(state.loops_exhausted());
+    }
+
+    #[test]
+    fn scale_event_doubles_the_buffer_of_a_hidpi_output() {
+        use super::AppState;
+        use std::os::unix::net::UnixStream;
+        use wayland_client::{
+            backend::Backend,
+            protocol::wl_output::{self, WlOutput},
+            Connection, Dispatch, Proxy, WEnum,
+        };
+        use wayland_protocols_wlr::layer_shell::v1::client::zwlr_layer_surface_v1::{
+            self, ZwlrLayerSurfaceV1,
+        };
+
+        let (stream, _peer) = UnixStream::pair().unwrap();
+        let backend = Backend::connect(stream).unwrap();
+        let conn = Connection::from_backend(backend.clone());
+        let queue = conn.new_event_queue::<AppState>();
+        let qh = queue.handle();
+        let output = WlOutput::inert(backend.downgrade());
+        let mut state = AppState::new("/walls/a.png".into(), None);
+        state.outputs.push(OutputBinding {
+            global_name: 5,
+            version: 4,
+            output: output.clone(),
+            name: None,
+            description: None,
+            resolution: None,
+            scale_factor: 1,
+            transform: wl_output::Transform::Normal,
+        });
+
+        for event in [
+            wl_output::Event::Mode {
+                flags: WEnum::Value(wl_output::Mode::Current),
+                width: 3840,
+                height: 2160,
+                refresh: 60000,
+            },
+            wl_output::Event::Scale { factor: 2 },
+            wl_output::Event::Done,
+        ] {
+            <AppState as Dispatch<WlOutput, u32>>::event(
+                &mut state,
+                &output,
+                event,
+                &5,
+                &conn,
+                &qh,
+            );
+        }
+        assert_eq!(state.output_scale(&output), 2);
+        assert_eq!(state.output_resolution(&output), Some((3840, 2160)));
+
+        state.buffer_scale = state.output_scale(&output) as u32;
+        let configure = zwlr_layer_surface_v1::Event::Configure {
+            serial: 1,
+            width: 1920,
+            height: 1080,
+        };
+        let layer_surface = ZwlrLayerSurfaceV1::inert(backend.downgrade());
+        <AppState as Dispatch<ZwlrLayerSurfaceV1, ()>>::event(
+            &mut state,
+            &layer_surface,
+            configure,
+            &(),
+            &conn,
+            &qh,
+        );
+        assert_eq!((state.width, state.height), (3840, 2160));
+        // DMA-BUF frames are scaled by the viewport to the logical size.
+        assert_eq!(state.surface_size(), (1920, 1080));
+    }
+
+    #[test]
+    fn rotated_output_redraws_at_the_turned_size() {
+        use super::AppState;
+        use std::os::unix::net::UnixStream;
+        use wayland_client::{
+            backend::Backend,
+            protocol::wl_output::{self, Transform, WlOutput},
+            Connection, Dispatch, Proxy, WEnum,
+        };
+
+        let turned = |size, mode, transform| turned_size(size, mode, Transform::Normal, transform);
+        assert_eq!(turned((1920, 1080), None, Transform::_90), (1080, 1920));
+        assert_eq!(turned((1920, 1080), None, Transform::Flipped180), (1920, 1080));
+        // Already resized by a configure: the mode says portrait is right.
+        assert_eq!(turned((1440, 2560), Some((2560, 1440)), Transform::_270), (1440, 2560));
+        // Without a mode, the previous transform tells the mode's orientation.
+        let unturned = turned_size((1080, 1920), None, Transform::_90, Transform::Flipped);
+        assert_eq!(unturned, (1920, 1080));
+
+        let (stream, _peer) = UnixStream::pair().unwrap();
+        let backend = Backend::connect(stream).unwrap();
+        let conn = Connection::from_backend(backend.clone());
+        let qh = conn.new_event_queue::<AppState>().handle();
+        let output = WlOutput::inert(backend.downgrade());
+        let mut state = AppState::new("/walls/a.png".into(), None);
+        state.outputs.push(OutputBinding {
+            global_name: 5,
+            version: 4,
+            output: output.clone(),
+            name: None,
+            description: None,
+            resolution: Some((2560, 1440)),
+            scale_factor: 1,
+            transform: Transform::Normal,
+        });
+        (state.width, state.height) = (2560, 1440);
+        state.selected_output = Some(5);
+        let turn = |state: &mut AppState, transform| {
+            let geometry = wl_output::Event::Geometry {
+                x: 0,
+                y: 0,
+                physical_width: 600,
+                physical_height: 340,
+                subpixel: WEnum::Value(wl_output::Subpixel::Unknown),
+                make: "Dell".to_string(),
+                model: "U2720Q".to_string(),
+                transform: WEnum::Value(transform),
+            };
+            <AppState as Dispatch<WlOutput, u32>>::event(state, &output, geometry, &5, &conn, &qh);
+        };
+
+        turn(&mut state, Transform::_90);
+        assert!(!state.take_resize());
+        assert_eq!((state.width, state.height), (2560, 1440));
+
+        state.auto_rotate = true;
+        turn(&mut state, Transform::Normal);
+        turn(&mut state, Transform::_270);
+        assert_eq!((state.width, state.height), (1440, 2560));
+        assert!(state.take_resize());
+        assert!(!state.take_resize());
+        // The compositor turns the frame itself; only a new aspect needs a redraw.
+        turn(&mut state, Transform::_90);
+        assert!(!state.take_resize());
+        turn(&mut state, Transform::_180);
+        assert_eq!((state.width, state.height), (2560, 1440));
+        state.exit = true;
+        assert!(!state.take_resize());
+    }
+
+    #[test]
+    fn powered_off_monitor_pauses_until_it_is_back_on() {
+        use super::AppState;
+        use std::os::unix::net::UnixStream;
+        use wayland_client::{backend::Backend, Connection, Dispatch, Proxy, WEnum};
+        use wayland_protocols_wlr::output_power_management::v1::client::zwlr_output_power_v1::{
+            self, ZwlrOutputPowerV1,
+        };
+
+        let (stream, _peer) = UnixStream::pair().unwrap();
+        let backend = Backend::connect(stream).unwrap();
+        let conn = Connection::from_backend(backend.clone());
+        let qh = conn.new_event_queue::<AppState>().handle();
+        let power = ZwlrOutputPowerV1::inert(backend.downgrade());
+        let mut state = AppState::new("/walls/a.mp4".into(), None);
+        state.monitor_power = Some(power.clone());
+        let send = |state: &mut AppState, event| {
+            <AppState as Dispatch<ZwlrOutputPowerV1, ()>>::event(
+                state,
+                &power,
+                event,
+                &(),
+                &conn,
+                &qh,
+            )
+        };
+        let mode = |mode| zwlr_output_power_v1::Event::Mode { mode: WEnum::Value(mode) };
+
+        send(&mut state, mode(zwlr_output_power_v1::Mode::Off));
+        assert!(state.monitor_off);
+        send(&mut state, mode(zwlr_output_power_v1::Mode::On));
+        assert!(!state.monitor_off);
+        send(&mut state, mode(zwlr_output_power_v1::Mode::Off));
+        send(&mut state, zwlr_output_power_v1::Event::Failed);
+        assert!(!state.monitor_off);
+        assert!(state.monitor_power.is_none());
+    }
+
+    #[test]
+    fn numeric_monitor_picks_output_by_global_name_order() {
+        use std::os::unix::net::UnixStream;
+        use wayland_client::{
+            backend::Backend,
+            protocol::wl_output::{Transform, WlOutput},
+            Proxy,
+        };
+
+        let (stream, _peer) = UnixStream::pair().unwrap();
+        let backend = Backend::connect(stream).unwrap();
+        let output = |global_name: u32, name: &str, description: &str| OutputBinding {
+            global_name,
+            version: 4,
+            output: WlOutput::inert(backend.downgrade()),
+            name: Some(name.to_string()),
+            description: Some(description.to_string()),
+            resolution: None,
+            scale_factor: 1,
+            transform: Transform::Normal,
+        };
+        let outputs = [
+            output(42, "DP-1", "Dell Inc. DELL U2720Q 2"),
+            output(7, "HDMI-A-1", "LG Electronics 27GL850"),
+            output(13, "DP-2", "BNQ BenQ GW2480"),
+        ];
+        let pick = |requested: &str| {
+            find_requested_output(&outputs, requested).and_then(|out| out.name.as_deref())
+        };
+
+        assert_eq!(pick("0"), Some("HDMI-A-1"));
+        assert_eq!(pick("1"), Some("DP-2"));
+        assert_eq!(pick(" 1\n"), Some("DP-2"));
+        assert_eq!(pick("2"), Some("DP-1"));
+        assert_eq!(pick("3"), None);
+        assert_eq!(pick("DP-1"), Some("DP-1"));
+        assert_eq!(pick("benq"), Some("DP-2"));
+    }
+
+    #[test]
+    fn only_undecodable_local_videos_fall_back_to_a_still() {
+        let decode = || {
+            anyhow::anyhow!("no initial video frame from pipeline")
+                .context(VideoDecodeError)
+                .context("DP-1")
+        };
+        let other = || anyhow::anyhow!("failed dispatching Wayland events");
+
+        assert!(falls_back_to_still(&decode(), false));
+        assert!(!falls_back_to_still(&decode(), true));
+        assert!(!falls_back_to_still(&other(), false));
+    }
+
 }
@@ -0,0 +1,192 @@
+//! Watches the daemon's wallpaper directories so new files show up at once.
+//!
+//! Every directory a monitor draws from gets one inotify watch, however many
+//! monitors share it. A media file finished writing (`IN_CLOSE_WRITE`) or
+//! moved in (`IN_MOVED_TO`) rotates only the monitors that use that
+//! directory. A directory that is deleted or moved away loses its watch with
+//! a warning.
+
+use crate::{output::warning, picker};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask, Watches};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+/// Which monitors draw from each watched directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirectoryWatchMap {
+    dirs: BTreeMap<PathBuf, Vec<String>>,
+}
+
+impl DirectoryWatchMap {
+    pub fn new(monitor_dirs: impl IntoIterator<Item = (String, Vec<PathBuf>)>) -> Self {
+        let mut dirs: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+        for (monitor, monitor_dirs) in monitor_dirs {
+            for dir in monitor_dirs {
+                let monitors = dirs.entry(dir).or_default();
+                if !monitors.contains(&monitor) {
+                    monitors.push(monitor.clone());
+                }
+            }
+        }
+        Self { dirs }
+    }
+
+    pub fn monitors_for(&self, dir: &Path) -> &[String] {
+        self.dirs.get(dir).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dirs.is_empty()
+    }
+}
+
+pub struct DirectoryWatcher {
+    map: DirectoryWatchMap,
+    watches: Watches,
+    descriptors: Vec<WatchDescriptor>,
+    events: Receiver<Vec<String>>,
+}
+
+impl DirectoryWatcher {
+    /// Watches every directory in `map`; directories that cannot be watched
+    /// (usually because they do not exist) are skipped with a warning.
+    pub fn spawn(map: DirectoryWatchMap) -> io::Result<Self> {
+        let mut inotify = Inotify::init()?;
+        let mut watches = inotify.watches();
+        let mut watched: HashMap<WatchDescriptor, (PathBuf, Vec<String>)> = HashMap::new();
+        for dir in map.dirs.keys() {
+            let mask = WatchMask::CLOSE_WRITE
+                | WatchMask::MOVED_TO
+                | WatchMask::DELETE_SELF
+                | WatchMask::MOVE_SELF;
+            match watches.add(dir, mask) {
+                Ok(descriptor) => {
+                    watched.insert(descriptor, (dir.clone(), map.monitors_for(dir).to_vec()));
+                }
+                Err(error) => {
+                    warning!("warning: cannot watch {}: {}", dir.display(), error);
+                }
+            }
+        }
+        let descriptors = watched.keys().cloned().collect();
+
+        let (sender, events) = mpsc::channel();
+        let mut thread_watches = watches.clone();
+        thread::spawn(move || {
+            let mut buffer = [0u8; 4096];
+            while !watched.is_empty() {
+                let Ok(events) = inotify.read_events_blocking(&mut buffer) else {
+                    return;
+                };
+                for event in events {
+                    if event.mask.contains(EventMask::IGNORED) {
+                        watched.remove(&event.wd);
+                        continue;
+                    }
+                    let Some((dir, monitors)) = watched.get(&event.wd) else {
+                        continue;
+                    };
+                    if event
+                        .mask
+                        .intersects(EventMask::DELETE_SELF | EventMask::MOVE_SELF)
+                    {
+                        warning!(
+                            "warning: wallpaper directory {} was removed; no longer watching it",
+                            dir.display()
+                        );
+                        let _ = thread_watches.remove(event.wd.clone());
+                        watched.remove(&event.wd);
+                        continue;
+                    }
+                    let added = event
+                        .name
+                        .is_some_and(|name| picker::is_supported_media(Path::new(name)));
+                    if added && sender.send(monitors.clone()).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            map,
+            watches,
+            descriptors,
+            events,
+        })
+    }
+
+    pub fn watches(&self) -> &DirectoryWatchMap {
+        &self.map
+    }
+
+    /// Monitors that got a new wallpaper file since the last call.
+    pub fn take_rotations(&self) -> Vec<String> {
+        let mut monitors: Vec<String> = Vec::new();
+        for batch in self.events.try_iter() {
+            for monitor in batch {
+                if !monitors.contains(&monitor) {
+                    monitors.push(monitor);
+                }
+            }
+        }
+        monitors
+    }
+}
+
+impl Drop for DirectoryWatcher {
+    /// Removing the watches wakes the reader thread with `IN_IGNORED`, so it exits.
+    fn drop(&mut self) {
+        for descriptor in self.descriptors.drain(..) {
+            let _ = self.watches.remove(descriptor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DirectoryWatchMap, DirectoryWatcher};
+    use std::{fs, path::PathBuf, time::Duration};
+
+    #[test]
+    fn new_media_rotates_only_the_monitors_using_that_directory() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let (shared, own) = (root.join("shared"), root.join("own"));
+        fs::create_dir_all(&shared).unwrap();
+        fs::create_dir_all(&own).unwrap();
+
+        let map = DirectoryWatchMap::new([
+            ("DP-1".to_string(), vec![shared.clone(), own.clone()]),
+            ("DP-2".to_string(), vec![shared.clone()]),
+            ("HDMI-A-1".to_string(), vec![shared.clone(), root.join("missing")]),
+        ]);
+        assert_eq!(map.monitors_for(&shared), ["DP-1", "DP-2", "HDMI-A-1"]);
+        assert_eq!(map.monitors_for(&own), ["DP-1"]);
+        assert!(map.monitors_for(&PathBuf::from("/elsewhere")).is_empty());
+
+        let watcher = DirectoryWatcher::spawn(map.clone()).unwrap();
+        assert_eq!(watcher.watches(), &map);
+
+        // Each media file sends one batch, in the order the files were written.
+        let next_batch = || watcher.events.recv_timeout(Duration::from_secs(5)).unwrap();
+        fs::write(own.join("notes.txt"), "").unwrap();
+        fs::write(own.join("new.png"), "").unwrap();
+        assert_eq!(next_batch(), ["DP-1"]);
+        assert!(watcher.take_rotations().is_empty());
+
+        fs::remove_dir_all(&own).unwrap();
+        fs::write(shared.join("a.jpg"), "").unwrap();
+        fs::write(shared.join("b.mp4"), "").unwrap();
+        assert_eq!(next_batch(), ["DP-1", "DP-2", "HDMI-A-1"]);
+        assert_eq!(next_batch(), ["DP-1", "DP-2", "HDMI-A-1"]);
+        assert!(watcher.take_rotations().is_empty());
+
+        drop(watcher);
+    }
+}